@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use axum::http::{HeaderMap, header::AUTHORIZATION};
+use ring::digest::{SHA256, digest};
+use serde::Deserialize;
+
+use crate::{
+    config::{ApiKeyConfig, JwtConfig, ServerAuthConfig},
+    jwt::{JwtValidator, looks_like_jwt},
+};
+
+/// Caller identity established by inbound authentication, stored in request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedIdentity {
+    pub identity: String,
+    pub groups: Vec<String>,
+}
+
+impl AuthenticatedIdentity {
+    pub fn new(identity: impl Into<String>) -> Self {
+        Self {
+            identity: identity.into(),
+            groups: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiKeysFile {
+    #[serde(default)]
+    keys: Vec<ApiKeyConfig>,
+}
+
+/// Validates inbound API keys or bearer JWTs and maps them to named identities.
+#[derive(Clone)]
+pub struct InboundAuth {
+    api_key_header: String,
+    /// Keyed by the SHA-256 digest of each API key, so lookups never compare raw secrets.
+    identities_by_key: HashMap<Vec<u8>, AuthenticatedIdentity>,
+    jwt: Option<JwtValidator>,
+}
+
+impl InboundAuth {
+    pub async fn from_config(
+        config: &ServerAuthConfig,
+        jwt_config: &JwtConfig,
+    ) -> Result<Option<Self>> {
+        let jwt = JwtValidator::from_config(jwt_config)
+            .await
+            .context("failed to configure server.jwt")?;
+        if !config.enabled && jwt.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            api_key_header: config.api_key_header.clone(),
+            identities_by_key: if config.enabled {
+                load_identities_by_key(config)?
+            } else {
+                HashMap::new()
+            },
+            jwt,
+        }))
+    }
+
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<AuthenticatedIdentity, String> {
+        if let Some(jwt) = self.jwt.as_ref() {
+            match extract_bearer(headers) {
+                Some(token) if looks_like_jwt(token) || self.identities_by_key.is_empty() => {
+                    return jwt.validate(token).await;
+                }
+                None if self.identities_by_key.is_empty() => {
+                    return Err(
+                        "missing credentials: send `Authorization: Bearer <jwt>`".to_string()
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let Some(credential) = extract_credential(headers, &self.api_key_header) else {
+            return Err(format!(
+                "missing credentials: send `Authorization: Bearer <key>` or `{}: <key>`",
+                self.api_key_header
+            ));
+        };
+
+        self.identities_by_key
+            .get(&key_digest(&credential))
+            .cloned()
+            .ok_or_else(|| "invalid credentials".to_string())
+    }
+}
+
+fn load_identities_by_key(
+    config: &ServerAuthConfig,
+) -> Result<HashMap<Vec<u8>, AuthenticatedIdentity>> {
+    let mut keys = config.keys.clone();
+    if let Some(path) = config.keys_file.as_deref() {
+        keys.extend(load_keys_file(path)?);
+    }
+
+    let mut identities_by_key = HashMap::new();
+    for api_key in keys {
+        let identity = api_key.identity.trim().to_string();
+        let key = api_key.key.trim().to_string();
+        if identity.is_empty() || key.is_empty() {
+            bail!("server.auth keys must have a non-empty identity and key");
+        }
+        let groups = api_key
+            .groups
+            .iter()
+            .map(|group| group.trim().to_string())
+            .filter(|group| !group.is_empty())
+            .collect();
+        let authenticated = AuthenticatedIdentity {
+            identity: identity.clone(),
+            groups,
+        };
+        if identities_by_key
+            .insert(key_digest(&key), authenticated)
+            .is_some()
+        {
+            bail!("duplicate server.auth key configured (identity={identity})");
+        }
+    }
+
+    if identities_by_key.is_empty() {
+        bail!("server.auth.enabled=true but no API keys were configured");
+    }
+
+    Ok(identities_by_key)
+}
+
+fn key_digest(key: &str) -> Vec<u8> {
+    digest(&SHA256, key.as_bytes()).as_ref().to_vec()
+}
+
+fn extract_bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let (scheme, token) = value.trim().split_once(' ')?;
+            scheme
+                .eq_ignore_ascii_case("bearer")
+                .then_some(token.trim())
+        })
+        .filter(|token| !token.is_empty())
+}
+
+fn extract_credential(headers: &HeaderMap, api_key_header: &str) -> Option<String> {
+    extract_bearer(headers)
+        .or_else(|| {
+            headers
+                .get(api_key_header)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn load_keys_file(path: &str) -> Result<Vec<ApiKeyConfig>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read server.auth.keys_file from {path}"))?;
+    let parsed: ApiKeysFile = toml::from_str(&contents)
+        .with_context(|| format!("invalid server.auth.keys_file at {path}"))?;
+
+    Ok(parsed.keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use crate::{
+        auth::{AuthenticatedIdentity, InboundAuth},
+        config::{ApiKeyConfig, JwtConfig, ServerAuthConfig},
+    };
+
+    fn auth_config() -> ServerAuthConfig {
+        ServerAuthConfig {
+            enabled: true,
+            keys: vec![ApiKeyConfig {
+                identity: "alice".to_string(),
+                key: "alice-secret".to_string(),
+                groups: vec!["sre".to_string()],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_auth_builds_nothing() {
+        let auth = InboundAuth::from_config(&ServerAuthConfig::default(), &JwtConfig::default())
+            .await
+            .expect("config");
+        assert!(auth.is_none());
+    }
+
+    #[tokio::test]
+    async fn authenticates_bearer_and_api_key_headers() {
+        let auth = InboundAuth::from_config(&auth_config(), &JwtConfig::default())
+            .await
+            .expect("config")
+            .expect("auth enabled");
+
+        let mut bearer = HeaderMap::new();
+        bearer.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer alice-secret"),
+        );
+        assert_eq!(
+            auth.authenticate(&bearer).await,
+            Ok(AuthenticatedIdentity {
+                identity: "alice".to_string(),
+                groups: vec!["sre".to_string()],
+            })
+        );
+
+        let mut api_key = HeaderMap::new();
+        api_key.insert("x-api-key", HeaderValue::from_static("alice-secret"));
+        assert_eq!(
+            auth.authenticate(&api_key).await,
+            Ok(AuthenticatedIdentity {
+                identity: "alice".to_string(),
+                groups: vec!["sre".to_string()],
+            })
+        );
+
+        let mut wrong = HeaderMap::new();
+        wrong.insert("x-api-key", HeaderValue::from_static("nope"));
+        assert!(auth.authenticate(&wrong).await.is_err());
+        assert!(auth.authenticate(&HeaderMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn loads_keys_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("loki-mcp-auth-keys-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[[keys]]\nidentity = \"ci-bot\"\nkey = \"bot-secret\"\n",
+        )
+        .expect("write keys file");
+
+        let config = ServerAuthConfig {
+            keys_file: Some(path.display().to_string()),
+            ..auth_config()
+        };
+        let auth = InboundAuth::from_config(&config, &JwtConfig::default())
+            .await
+            .expect("config")
+            .expect("auth enabled");
+        let _ = std::fs::remove_file(&path);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("bot-secret"));
+        assert_eq!(
+            auth.authenticate(&headers).await,
+            Ok(AuthenticatedIdentity::new("ci-bot"))
+        );
+    }
+}
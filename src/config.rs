@@ -1,4 +1,10 @@
-use std::{collections::BTreeMap, fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+};
 
 use anyhow::{Context, Result, anyhow, bail};
 use clap::Parser;
@@ -8,7 +14,11 @@ use figment::{
 };
 use serde::{Deserialize, Serialize, de::Deserializer};
 
-use crate::time::parse_std_duration;
+use crate::{
+    prompts::template_placeholders, response::ResponseMode, time::parse_std_duration, tools::saved,
+};
+
+pub const DEFAULT_DATASOURCE_NAME: &str = "default";
 
 #[derive(Debug, Clone, Parser)]
 #[command(author, version, about)]
@@ -24,6 +34,20 @@ pub struct Cli {
     pub log_level: Option<String>,
     #[arg(long)]
     pub identity_header: Option<String>,
+    #[arg(long)]
+    pub transport: Option<String>,
+    #[arg(long)]
+    pub static_identity: Option<String>,
+    #[arg(long)]
+    pub auth_enabled: Option<bool>,
+    #[arg(long)]
+    pub auth_keys_file: Option<String>,
+    #[arg(long)]
+    pub tls_cert: Option<String>,
+    #[arg(long)]
+    pub tls_key: Option<String>,
+    #[arg(long)]
+    pub tls_client_ca: Option<String>,
 
     #[arg(long)]
     pub loki_url: Option<String>,
@@ -40,7 +64,13 @@ pub struct Cli {
     #[arg(long)]
     pub loki_ca_cert: Option<String>,
     #[arg(long)]
+    pub loki_client_cert: Option<String>,
+    #[arg(long)]
+    pub loki_client_key: Option<String>,
+    #[arg(long)]
     pub loki_timeout: Option<String>,
+    #[arg(long)]
+    pub loki_max_response_bytes: Option<String>,
 
     #[arg(long)]
     pub cache_enabled: Option<bool>,
@@ -89,14 +119,23 @@ pub struct Config {
     pub cache: CacheConfig,
     pub guardrails: GuardrailsConfig,
     pub rate_limit: RateLimitConfig,
+    pub query_split: QuerySplitConfig,
+    pub lint: LintConfig,
     pub metrics: MetricsConfig,
     pub recent_actions: RecentActionsConfig,
+    pub tenants: TenantsConfig,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub default_datasource: Option<String>,
+    #[serde(default)]
+    pub datasources: Vec<DatasourceConfig>,
     #[serde(default)]
     pub labels: Vec<SchemaField>,
     #[serde(default)]
     pub structured_metadata: Vec<SchemaField>,
     #[serde(default)]
     pub saved_queries: Vec<SavedQuery>,
+    #[serde(default)]
+    pub prompts: Vec<PromptTemplate>,
 }
 
 impl Config {
@@ -105,27 +144,52 @@ impl Config {
         self.server.timezone = self.server.timezone.trim().to_string();
         self.server.log_level = self.server.log_level.trim().to_string();
         normalize_optional_string(&mut self.server.identity_header);
+        self.server.transport = self.server.transport.trim().to_ascii_lowercase();
+        normalize_optional_string(&mut self.server.static_identity);
+        normalize_optional_string(&mut self.server.tls_cert);
+        normalize_optional_string(&mut self.server.tls_key);
+        normalize_optional_string(&mut self.server.tls_client_ca);
+        self.server.tls_reload_interval = self.server.tls_reload_interval.trim().to_string();
+        self.server.auth.api_key_header =
+            self.server.auth.api_key_header.trim().to_ascii_lowercase();
+        normalize_optional_string(&mut self.server.auth.keys_file);
+        for api_key in &mut self.server.auth.keys {
+            api_key.identity = api_key.identity.trim().to_string();
+            api_key.key = api_key.key.trim().to_string();
+            normalize_string_list(&mut api_key.groups);
+        }
+        normalize_optional_string(&mut self.server.jwt.jwks_file);
+        normalize_optional_string(&mut self.server.jwt.jwks_url);
+        normalize_optional_string(&mut self.server.jwt.issuer);
+        normalize_optional_string(&mut self.server.jwt.audience);
+        self.server.jwt.identity_claim = self.server.jwt.identity_claim.trim().to_string();
+        self.server.jwt.leeway = self.server.jwt.leeway.trim().to_string();
+        normalize_optional_string(&mut self.server.jwt.groups_claim);
+
+        normalize_loki(&mut self.loki);
+        normalize_cache(&mut self.cache);
+        normalize_guardrails(&mut self.guardrails);
+
+        normalize_optional_string(&mut self.default_datasource);
+        for datasource in &mut self.datasources {
+            datasource.name = datasource.name.trim().to_string();
+            datasource.description = datasource.description.trim().to_string();
+            normalize_loki(&mut datasource.loki);
+            if let Some(cache) = datasource.cache.as_mut() {
+                normalize_cache(cache);
+            }
+            if let Some(guardrails) = datasource.guardrails.as_mut() {
+                normalize_guardrails(guardrails);
+            }
+        }
 
-        self.loki.url = self.loki.url.trim().to_string();
-        self.loki.auth_type = self.loki.auth_type.trim().to_ascii_lowercase();
-        self.loki.timeout = self.loki.timeout.trim().to_string();
-        normalize_optional_string(&mut self.loki.tenant_id);
-        normalize_optional_string(&mut self.loki.username);
-        normalize_optional_string(&mut self.loki.password);
-        normalize_optional_string(&mut self.loki.token);
-        normalize_optional_string(&mut self.loki.ca_cert);
-
-        self.cache.ttl = self.cache.ttl.trim().to_string();
-        self.cache.skip_if_range_shorter_than =
-            self.cache.skip_if_range_shorter_than.trim().to_string();
-
-        self.guardrails.max_bytes_scanned = self.guardrails.max_bytes_scanned.trim().to_string();
-        self.guardrails.skip_stats_if_range_shorter_than = self
-            .guardrails
-            .skip_stats_if_range_shorter_than
-            .trim()
-            .to_string();
+        for mapping in &mut self.tenants.mappings {
+            normalize_string_list(&mut mapping.identities);
+            normalize_string_list(&mut mapping.groups);
+            normalize_string_list(&mut mapping.tenants);
+        }
 
+        self.query_split.interval = self.query_split.interval.trim().to_string();
         self.metrics.prefix = self.metrics.prefix.trim().to_string();
         self.recent_actions.ttl = self.recent_actions.ttl.trim().to_string();
     }
@@ -147,58 +211,96 @@ impl Config {
 
         ensure_non_empty("server.log_level", &self.server.log_level)?;
 
-        ensure_non_empty("loki.url", &self.loki.url)?;
-        reqwest::Url::parse(&self.loki.url)
-            .with_context(|| format!("invalid loki.url: {}", self.loki.url))?;
+        match self.server.transport.as_str() {
+            "http" | "stdio" => {}
+            other => {
+                bail!("unsupported server.transport: {other}. expected one of http/stdio");
+            }
+        }
 
-        match self.loki.auth_type.as_str() {
-            "none" => {}
-            "basic" => {
-                if self.loki.username.is_none() {
-                    bail!("loki.username is required when loki.auth_type=basic");
-                }
-                if self.loki.password.is_none() {
-                    bail!("loki.password is required when loki.auth_type=basic");
-                }
+        match (&self.server.tls_cert, &self.server.tls_key) {
+            (Some(_), Some(_)) => {
+                parse_std_duration(&self.server.tls_reload_interval).with_context(|| {
+                    format!(
+                        "invalid server.tls_reload_interval: {}",
+                        self.server.tls_reload_interval
+                    )
+                })?;
             }
-            "bearer" => {
-                if self.loki.token.is_none() {
-                    bail!("loki.token is required when loki.auth_type=bearer");
+            (None, None) => {
+                if self.server.tls_client_ca.is_some() {
+                    bail!("server.tls_client_ca requires server.tls_cert and server.tls_key");
                 }
             }
-            other => {
-                bail!("unsupported loki.auth_type: {other}. expected one of none/basic/bearer");
+            _ => bail!("server.tls_cert and server.tls_key must be set together"),
+        }
+
+        if self.server.auth.enabled {
+            ensure_non_empty(
+                "server.auth.api_key_header",
+                &self.server.auth.api_key_header,
+            )?;
+            axum::http::HeaderName::from_bytes(self.server.auth.api_key_header.as_bytes())
+                .with_context(|| {
+                    format!(
+                        "invalid server.auth.api_key_header: {}",
+                        self.server.auth.api_key_header
+                    )
+                })?;
+            if self.server.auth.keys.is_empty() && self.server.auth.keys_file.is_none() {
+                bail!(
+                    "server.auth.keys or server.auth.keys_file is required when server.auth.enabled=true"
+                );
+            }
+            for api_key in &self.server.auth.keys {
+                ensure_non_empty("server.auth.keys.identity", &api_key.identity)?;
+                ensure_non_empty("server.auth.keys.key", &api_key.key)?;
             }
         }
 
-        parse_std_duration(&self.loki.timeout)
-            .with_context(|| format!("invalid loki.timeout: {}", self.loki.timeout))?;
-        parse_std_duration(&self.cache.ttl)
-            .with_context(|| format!("invalid cache.ttl: {}", self.cache.ttl))?;
-        parse_std_duration(&self.cache.skip_if_range_shorter_than).with_context(|| {
-            format!(
-                "invalid cache.skip_if_range_shorter_than: {}",
-                self.cache.skip_if_range_shorter_than
-            )
-        })?;
-        parse_std_duration(&self.guardrails.skip_stats_if_range_shorter_than).with_context(
-            || {
-                format!(
-                    "invalid guardrails.skip_stats_if_range_shorter_than: {}",
-                    self.guardrails.skip_stats_if_range_shorter_than
-                )
-            },
-        )?;
+        if self.server.jwt.enabled {
+            match (&self.server.jwt.jwks_file, &self.server.jwt.jwks_url) {
+                (Some(_), None) => {}
+                (None, Some(url)) => {
+                    reqwest::Url::parse(url)
+                        .with_context(|| format!("invalid server.jwt.jwks_url: {url}"))?;
+                }
+                _ => bail!(
+                    "exactly one of server.jwt.jwks_file or server.jwt.jwks_url is required when server.jwt.enabled=true"
+                ),
+            }
+            ensure_non_empty("server.jwt.identity_claim", &self.server.jwt.identity_claim)?;
+            parse_std_duration(&self.server.jwt.leeway).with_context(|| {
+                format!("invalid server.jwt.leeway: {}", self.server.jwt.leeway)
+            })?;
+        }
 
-        parse_byte_size(&self.guardrails.max_bytes_scanned).with_context(|| {
-            format!(
-                "invalid guardrails.max_bytes_scanned: {}",
-                self.guardrails.max_bytes_scanned
-            )
-        })?;
+        validate_loki("loki", &self.loki)?;
+        validate_cache("cache", &self.cache)?;
+        validate_guardrails("guardrails", &self.guardrails)?;
+        self.validate_datasources()?;
 
-        if self.cache.max_entries == 0 {
-            bail!("cache.max_entries must be greater than zero");
+        if self.tenants.enabled {
+            if self.tenants.mappings.is_empty() {
+                bail!("tenants.mappings must not be empty when tenants.enabled=true");
+            }
+            for mapping in &self.tenants.mappings {
+                if mapping.identities.is_empty() && mapping.groups.is_empty() {
+                    bail!("each tenants.mappings entry needs at least one identity or group");
+                }
+                if mapping.tenants.is_empty() {
+                    bail!("each tenants.mappings entry needs at least one tenant");
+                }
+            }
+            // Over HTTP only an authenticated identity can be trusted to pick a tenant.
+            if self.server.transport == "http"
+                && !self.server.auth.enabled
+                && !self.server.jwt.enabled
+            {
+                bail!(
+                    "tenants.enabled=true over http requires server.auth.enabled or server.jwt.enabled"
+                );
+            }
         }
 
         if self.rate_limit.enabled {
@@ -210,6 +312,21 @@ impl Config {
             }
         }
 
+        if self.query_split.enabled {
+            let interval = parse_std_duration(&self.query_split.interval).with_context(|| {
+                format!(
+                    "invalid query_split.interval: {}",
+                    self.query_split.interval
+                )
+            })?;
+            if interval.is_zero() {
+                bail!("query_split.interval must be greater than zero");
+            }
+            if self.query_split.max_concurrency == 0 {
+                bail!("query_split.max_concurrency must be greater than zero");
+            }
+        }
+
         ensure_non_empty("metrics.prefix", &self.metrics.prefix)?;
         parse_std_duration(&self.recent_actions.ttl)
             .with_context(|| format!("invalid recent_actions.ttl: {}", self.recent_actions.ttl))?;
@@ -217,6 +334,331 @@ impl Config {
             bail!("recent_actions.max_entries must be greater than zero");
         }
 
+        self.validate_saved_queries()?;
+        self.validate_prompts()?;
+
+        Ok(())
+    }
+
+    fn validate_datasources(&self) -> Result<()> {
+        let mut seen = BTreeSet::new();
+        for datasource in &self.datasources {
+            ensure_non_empty("datasources.name", &datasource.name)?;
+            if !seen.insert(datasource.name.as_str()) {
+                bail!("duplicate datasource name: {}", datasource.name);
+            }
+
+            let prefix = format!("datasources.{}", datasource.name);
+            validate_loki(&prefix, &datasource.loki)?;
+            if let Some(cache) = datasource.cache.as_ref() {
+                validate_cache(&format!("{prefix}.cache"), cache)?;
+            }
+            if let Some(guardrails) = datasource.guardrails.as_ref() {
+                validate_guardrails(&format!("{prefix}.guardrails"), guardrails)?;
+            }
+        }
+
+        if let Some(default_datasource) = self.default_datasource.as_deref()
+            && !self
+                .resolved_datasources()
+                .iter()
+                .any(|datasource| datasource.name == default_datasource)
+        {
+            bail!("default_datasource references unknown datasource: {default_datasource}");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configured `[[datasources]]` with inherited `[cache]`/`[guardrails]` applied,
+    /// or a single `default` datasource built from `[loki]` when none are configured.
+    pub fn resolved_datasources(&self) -> Vec<ResolvedDatasource> {
+        if self.datasources.is_empty() {
+            return vec![ResolvedDatasource {
+                name: DEFAULT_DATASOURCE_NAME.to_string(),
+                description: String::new(),
+                loki: self.loki.clone(),
+                cache: self.cache.clone(),
+                guardrails: self.guardrails.clone(),
+            }];
+        }
+
+        self.datasources
+            .iter()
+            .map(|datasource| ResolvedDatasource {
+                name: datasource.name.clone(),
+                description: datasource.description.clone(),
+                loki: datasource.loki.clone(),
+                cache: datasource
+                    .cache
+                    .clone()
+                    .unwrap_or_else(|| self.cache.clone()),
+                guardrails: datasource
+                    .guardrails
+                    .clone()
+                    .unwrap_or_else(|| self.guardrails.clone()),
+            })
+            .collect()
+    }
+
+    /// Name of the datasource used when a tool call omits `datasource`.
+    pub fn default_datasource_name(&self) -> String {
+        self.default_datasource
+            .clone()
+            .or_else(|| {
+                self.datasources
+                    .first()
+                    .map(|datasource| datasource.name.clone())
+            })
+            .unwrap_or_else(|| DEFAULT_DATASOURCE_NAME.to_string())
+    }
+
+    /// The resolved datasource used when a tool call omits `datasource`.
+    pub fn default_resolved_datasource(&self) -> ResolvedDatasource {
+        let name = self.default_datasource_name();
+        let mut datasources = self.resolved_datasources();
+        let index = datasources
+            .iter()
+            .position(|datasource| datasource.name == name)
+            .unwrap_or_default();
+        datasources.swap_remove(index)
+    }
+
+    fn validate_saved_queries(&self) -> Result<()> {
+        let mut seen = BTreeSet::new();
+        for saved_query in &self.saved_queries {
+            ensure_non_empty("saved_queries.name", &saved_query.name)?;
+            if !seen.insert(saved_query.name.as_str()) {
+                bail!("duplicate saved query name: {}", saved_query.name);
+            }
+            ensure_non_empty(
+                &format!("saved_queries.{}.query", saved_query.name),
+                &saved_query.query,
+            )?;
+            self.validate_saved_query_options(saved_query)?;
+
+            let mut params = BTreeMap::new();
+            for param in &saved_query.params {
+                ensure_non_empty(
+                    &format!("saved_queries.{}.params.name", saved_query.name),
+                    &param.name,
+                )?;
+                if params.insert(param.name.as_str(), param).is_some() {
+                    bail!(
+                        "duplicate param {} in saved query {}",
+                        param.name,
+                        saved_query.name
+                    );
+                }
+                if !saved::PARAM_TYPES.contains(&param.kind.as_str()) {
+                    bail!(
+                        "unsupported type {} for param {} in saved query {}. expected one of {}",
+                        param.kind,
+                        param.name,
+                        saved_query.name,
+                        saved::PARAM_TYPES.join("/")
+                    );
+                }
+                if let Some(label) = &param.label {
+                    if param.kind != "string" {
+                        bail!(
+                            "param {} in saved query {} sets label but is not a string",
+                            param.name,
+                            saved_query.name
+                        );
+                    }
+                    if !self
+                        .labels
+                        .iter()
+                        .any(|field| &field.name == label && !field.common_values.is_empty())
+                    {
+                        bail!(
+                            "param {} in saved query {} references label {label}, which has no configured common_values",
+                            param.name,
+                            saved_query.name
+                        );
+                    }
+                }
+                if let Some(default) = &param.default {
+                    saved::check_value(param, &default.as_str().into(), &self.labels)
+                        .with_context(|| {
+                            format!(
+                                "invalid default for param {} in saved query {}",
+                                param.name, saved_query.name
+                            )
+                        })?;
+                }
+            }
+
+            let placeholders = saved::placeholders(&saved_query.query);
+            for placeholder in &placeholders {
+                let Some(param) = params.get(placeholder.name.as_str()) else {
+                    bail!(
+                        "saved query {} references undeclared param ${{{}}}",
+                        saved_query.name,
+                        placeholder.name
+                    );
+                };
+                if placeholder.context == saved::PlaceholderContext::Bare && param.kind != "number"
+                {
+                    bail!(
+                        "saved query {} uses {} param ${{{}}} outside a string literal. only number params may be unquoted",
+                        saved_query.name,
+                        param.kind,
+                        placeholder.name
+                    );
+                }
+            }
+            if let Some(unused) = params.keys().find(|name| {
+                !placeholders
+                    .iter()
+                    .any(|placeholder| &placeholder.name == *name)
+            }) {
+                bail!(
+                    "saved query {} declares param {unused} but never references ${{{unused}}}",
+                    saved_query.name
+                );
+            }
+
+            if saved_query
+                .params
+                .iter()
+                .all(|param| param.default.is_some())
+            {
+                saved::render(self, saved_query, None)
+                    .with_context(|| format!("invalid saved query {}", saved_query.name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_saved_query_options(&self, saved_query: &SavedQuery) -> Result<()> {
+        let name = &saved_query.name;
+        let kind = saved_query.kind.as_str();
+        if !saved::KINDS.contains(&kind) {
+            bail!(
+                "unsupported kind {kind} for saved query {name}. expected one of {}",
+                saved::KINDS.join("/")
+            );
+        }
+
+        if kind == "instant" {
+            if !saved_query.range.trim().is_empty() {
+                bail!("saved query {name} is an instant query and must not set range");
+            }
+        } else {
+            ensure_non_empty(&format!("saved_queries.{name}.range"), &saved_query.range)?;
+        }
+
+        let unsupported = [
+            ("step", saved_query.step.is_some(), kind == "metrics"),
+            ("limit", saved_query.limit.is_some(), kind != "metrics"),
+            ("direction", saved_query.direction.is_some(), kind == "logs"),
+            (
+                "response_mode",
+                saved_query.response_mode.is_some(),
+                kind == "logs",
+            ),
+        ];
+        if let Some((option, _, _)) = unsupported
+            .iter()
+            .find(|(_, set, supported)| *set && !supported)
+        {
+            bail!("saved query {name} sets {option}, which does not apply to {kind} queries");
+        }
+        if saved_query.limit == Some(0) {
+            bail!("saved query {name} limit must be greater than zero");
+        }
+        if let Some(step) = &saved_query.step {
+            ensure_non_empty(&format!("saved_queries.{name}.step"), step)?;
+        }
+        if let Some(direction) = &saved_query.direction
+            && !matches!(direction.as_str(), "forward" | "backward")
+        {
+            bail!(
+                "unsupported direction {direction} for saved query {name}. expected one of forward/backward"
+            );
+        }
+        for tag in &saved_query.tags {
+            ensure_non_empty(&format!("saved_queries.{name}.tags"), tag)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_prompts(&self) -> Result<()> {
+        let mut seen = BTreeSet::new();
+        for prompt in &self.prompts {
+            ensure_non_empty("prompts.name", &prompt.name)?;
+            if !seen.insert(prompt.name.as_str()) {
+                bail!("duplicate prompt name: {}", prompt.name);
+            }
+            ensure_non_empty(
+                &format!("prompts.{}.template", prompt.name),
+                &prompt.template,
+            )?;
+
+            let mut argument_names = BTreeSet::new();
+            for argument in &prompt.arguments {
+                ensure_non_empty(
+                    &format!("prompts.{}.arguments.name", prompt.name),
+                    &argument.name,
+                )?;
+                if !argument_names.insert(argument.name.as_str()) {
+                    bail!(
+                        "duplicate argument {} in prompt {}",
+                        argument.name,
+                        prompt.name
+                    );
+                }
+                match argument.kind.as_str() {
+                    "string" | "number" | "boolean" => {}
+                    other => bail!(
+                        "unsupported type {other} for argument {} in prompt {}. expected one of string/number/boolean",
+                        argument.name,
+                        prompt.name
+                    ),
+                }
+            }
+
+            for placeholder in template_placeholders(&prompt.template) {
+                if !argument_names.contains(placeholder.as_str()) {
+                    bail!(
+                        "prompt {} references undeclared argument {{{{{placeholder}}}}}",
+                        prompt.name
+                    );
+                }
+            }
+
+            for saved_query in &prompt.saved_queries {
+                if !self
+                    .saved_queries
+                    .iter()
+                    .any(|candidate| &candidate.name == saved_query)
+                {
+                    bail!(
+                        "prompt {} references unknown saved query: {saved_query}",
+                        prompt.name
+                    );
+                }
+            }
+
+            for field in &prompt.fields {
+                if !self
+                    .labels
+                    .iter()
+                    .chain(self.structured_metadata.iter())
+                    .any(|candidate| &candidate.name == field)
+                {
+                    bail!(
+                        "prompt {} references unknown label or structured metadata field: {field}",
+                        prompt.name
+                    );
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -228,6 +670,18 @@ pub struct ServerConfig {
     pub log_level: String,
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub identity_header: Option<String>,
+    pub transport: String,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub static_identity: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub tls_cert: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub tls_key: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub tls_client_ca: Option<String>,
+    pub tls_reload_interval: String,
+    pub auth: ServerAuthConfig,
+    pub jwt: JwtConfig,
 }
 
 impl Default for ServerConfig {
@@ -237,11 +691,81 @@ impl Default for ServerConfig {
             timezone: "America/New_York".to_string(),
             log_level: "info".to_string(),
             identity_header: None,
+            transport: "http".to_string(),
+            static_identity: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            tls_reload_interval: "30s".to_string(),
+            auth: ServerAuthConfig::default(),
+            jwt: JwtConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerAuthConfig {
+    pub enabled: bool,
+    pub api_key_header: String,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub keys_file: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<ApiKeyConfig>,
+}
+
+impl Default for ServerAuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_key_header: "x-api-key".to_string(),
+            keys_file: None,
+            keys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtConfig {
+    pub enabled: bool,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub jwks_file: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub jwks_url: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub issuer: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub audience: Option<String>,
+    pub identity_claim: String,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub groups_claim: Option<String>,
+    pub leeway: String,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            jwks_file: None,
+            jwks_url: None,
+            issuer: None,
+            audience: None,
+            identity_claim: "sub".to_string(),
+            groups_claim: Some("groups".to_string()),
+            leeway: "60s".to_string(),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    pub identity: String,
+    pub key: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LokiConfig {
     pub url: String,
     #[serde(default, deserialize_with = "empty_string_as_none")]
@@ -255,7 +779,23 @@ pub struct LokiConfig {
     pub token: Option<String>,
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub ca_cert: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub client_cert: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub client_key: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub forward_headers: Vec<String>,
+    #[serde(default)]
+    pub oauth2: Option<LokiOAuth2Config>,
     pub timeout: String,
+    /// Largest response body read from Loki, e.g. `100MB`; longer bodies are cut off with an error.
+    pub max_response_bytes: String,
+    #[serde(default)]
+    pub retry: LokiRetryConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
 }
 
 impl Default for LokiConfig {
@@ -268,12 +808,92 @@ impl Default for LokiConfig {
             password: None,
             token: None,
             ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            headers: BTreeMap::new(),
+            forward_headers: Vec::new(),
+            oauth2: None,
             timeout: "30s".to_string(),
+            max_response_bytes: "100MB".to_string(),
+            retry: LokiRetryConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+/// OAuth2 client-credentials grant used when `auth_type = "oauth2"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LokiOAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Retries for idempotent Loki GETs that fail with 429/502/503/504 or a dropped connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LokiRetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff: String,
+    pub max_backoff: String,
+}
+
+impl Default for LokiRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: "200ms".to_string(),
+            max_backoff: "5s".to_string(),
+        }
+    }
+}
+
+/// Per-endpoint breaker that fails fast after consecutive Loki failures.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    pub failure_threshold: u32,
+    pub open_duration: String,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 5,
+            open_duration: "30s".to_string(),
         }
     }
 }
 
+/// A named Loki backend. Omitted `cache`/`guardrails` tables inherit the top-level sections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasourceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(flatten)]
+    pub loki: LokiConfig,
+    #[serde(default)]
+    pub cache: Option<CacheConfig>,
+    #[serde(default)]
+    pub guardrails: Option<GuardrailsConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedDatasource {
+    pub name: String,
+    pub description: String,
+    pub loki: LokiConfig,
+    pub cache: CacheConfig,
+    pub guardrails: GuardrailsConfig,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CacheConfig {
     pub enabled: bool,
     pub ttl: String,
@@ -293,6 +913,7 @@ impl Default for CacheConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GuardrailsConfig {
     pub max_bytes_scanned: String,
     pub max_streams: u64,
@@ -328,6 +949,47 @@ impl Default for RateLimitConfig {
     }
 }
 
+/// Splits long `query_range` windows into sub-intervals that run with bounded concurrency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuerySplitConfig {
+    pub enabled: bool,
+    pub interval: String,
+    pub max_concurrency: usize,
+}
+
+impl Default for QuerySplitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: "24h".to_string(),
+            max_concurrency: 4,
+        }
+    }
+}
+
+/// Runs the LogQL linter over query tool calls and attaches its findings to the response.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    pub pre_check: bool,
+}
+
+/// Maps caller identities and groups to the Loki tenants (`X-Scope-OrgID`) they may query.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantsConfig {
+    pub enabled: bool,
+    pub mappings: Vec<TenantMappingConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantMappingConfig {
+    pub identities: Vec<String>,
+    pub groups: Vec<String>,
+    pub tenants: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricsConfig {
     pub prefix: String,
@@ -375,7 +1037,96 @@ pub struct SavedQuery {
     pub name: String,
     pub description: String,
     pub query: String,
+    /// `logs` (default), `metrics` or `instant`.
+    #[serde(default = "default_saved_query_kind")]
+    pub kind: String,
+    /// Lookback for `logs` and `metrics` queries. Instant queries run at the current time.
+    #[serde(default)]
     pub range: String,
+    /// Resolution step of a `metrics` query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<String>,
+    /// Lines returned by a `logs` query, or series kept by an `instant` one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// `forward` or `backward` for a `logs` query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+    /// Response mode of a `logs` query when the caller does not pick one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_mode: Option<ResponseMode>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Typed parameters referenced from `query` as `${name}`.
+    #[serde(default)]
+    pub params: Vec<SavedQueryParam>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQueryParam {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_prompt_argument_kind", rename = "type")]
+    pub kind: String,
+    /// Restricts a string parameter to the `common_values` of this configured label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Used when the caller omits the parameter. Parameters without a default are required.
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub description: String,
+    pub template: String,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    #[serde(default)]
+    pub saved_queries: Vec<String>,
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_prompt_argument_kind", rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+impl Default for SavedQuery {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: String::new(),
+            query: String::new(),
+            kind: default_saved_query_kind(),
+            range: String::new(),
+            step: None,
+            limit: None,
+            direction: None,
+            response_mode: None,
+            tags: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+}
+
+fn default_saved_query_kind() -> String {
+    "logs".to_string()
+}
+
+fn default_prompt_argument_kind() -> String {
+    "string".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Default)]
@@ -403,6 +1154,15 @@ impl ConfigOverrides {
             timezone: normalized(cli.timezone.clone()),
             log_level: normalized(cli.log_level.clone()),
             identity_header: normalized(cli.identity_header.clone()),
+            transport: normalized(cli.transport.clone()),
+            static_identity: normalized(cli.static_identity.clone()),
+            tls_cert: normalized(cli.tls_cert.clone()),
+            tls_key: normalized(cli.tls_key.clone()),
+            tls_client_ca: normalized(cli.tls_client_ca.clone()),
+            auth: option_if_not_empty(ServerAuthOverrides {
+                enabled: cli.auth_enabled,
+                keys_file: normalized(cli.auth_keys_file.clone()),
+            }),
         };
 
         let loki = LokiOverrides {
@@ -413,7 +1173,10 @@ impl ConfigOverrides {
             password: normalized(cli.loki_password.clone()),
             token: normalized(cli.loki_token.clone()),
             ca_cert: normalized(cli.loki_ca_cert.clone()),
+            client_cert: normalized(cli.loki_client_cert.clone()),
+            client_key: normalized(cli.loki_client_key.clone()),
             timeout: normalized(cli.loki_timeout.clone()),
+            max_response_bytes: normalized(cli.loki_max_response_bytes.clone()),
         };
 
         let cache = CacheOverrides {
@@ -472,6 +1235,18 @@ struct ServerOverrides {
     log_level: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     identity_header: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transport: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    static_identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_client_ca: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth: Option<ServerAuthOverrides>,
 }
 
 impl IsEmpty for ServerOverrides {
@@ -480,6 +1255,26 @@ impl IsEmpty for ServerOverrides {
             && self.timezone.is_none()
             && self.log_level.is_none()
             && self.identity_header.is_none()
+            && self.transport.is_none()
+            && self.static_identity.is_none()
+            && self.tls_cert.is_none()
+            && self.tls_key.is_none()
+            && self.tls_client_ca.is_none()
+            && self.auth.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+struct ServerAuthOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys_file: Option<String>,
+}
+
+impl IsEmpty for ServerAuthOverrides {
+    fn is_empty(&self) -> bool {
+        self.enabled.is_none() && self.keys_file.is_none()
     }
 }
 
@@ -500,7 +1295,13 @@ struct LokiOverrides {
     #[serde(skip_serializing_if = "Option::is_none")]
     ca_cert: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    client_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_response_bytes: Option<String>,
 }
 
 impl IsEmpty for LokiOverrides {
@@ -512,7 +1313,10 @@ impl IsEmpty for LokiOverrides {
             && self.password.is_none()
             && self.token.is_none()
             && self.ca_cert.is_none()
+            && self.client_cert.is_none()
+            && self.client_key.is_none()
             && self.timeout.is_none()
+            && self.max_response_bytes.is_none()
     }
 }
 
@@ -629,6 +1433,210 @@ fn normalized(value: Option<String>) -> Option<String> {
     })
 }
 
+fn normalize_loki(loki: &mut LokiConfig) {
+    loki.url = loki.url.trim().to_string();
+    loki.auth_type = loki.auth_type.trim().to_ascii_lowercase();
+    loki.timeout = loki.timeout.trim().to_string();
+    loki.max_response_bytes = loki.max_response_bytes.trim().to_string();
+    normalize_optional_string(&mut loki.tenant_id);
+    normalize_optional_string(&mut loki.username);
+    normalize_optional_string(&mut loki.password);
+    normalize_optional_string(&mut loki.token);
+    normalize_optional_string(&mut loki.ca_cert);
+    normalize_optional_string(&mut loki.client_cert);
+    normalize_optional_string(&mut loki.client_key);
+    loki.headers = std::mem::take(&mut loki.headers)
+        .into_iter()
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    loki.forward_headers = loki
+        .forward_headers
+        .iter()
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if let Some(oauth2) = loki.oauth2.as_mut() {
+        oauth2.token_url = oauth2.token_url.trim().to_string();
+        oauth2.client_id = oauth2.client_id.trim().to_string();
+        oauth2.client_secret = oauth2.client_secret.trim().to_string();
+        oauth2.scopes = oauth2
+            .scopes
+            .iter()
+            .map(|scope| scope.trim().to_string())
+            .filter(|scope| !scope.is_empty())
+            .collect();
+    }
+    loki.retry.initial_backoff = loki.retry.initial_backoff.trim().to_string();
+    loki.retry.max_backoff = loki.retry.max_backoff.trim().to_string();
+    loki.circuit_breaker.open_duration = loki.circuit_breaker.open_duration.trim().to_string();
+}
+
+fn normalize_cache(cache: &mut CacheConfig) {
+    cache.ttl = cache.ttl.trim().to_string();
+    cache.skip_if_range_shorter_than = cache.skip_if_range_shorter_than.trim().to_string();
+}
+
+fn normalize_guardrails(guardrails: &mut GuardrailsConfig) {
+    guardrails.max_bytes_scanned = guardrails.max_bytes_scanned.trim().to_string();
+    guardrails.skip_stats_if_range_shorter_than = guardrails
+        .skip_stats_if_range_shorter_than
+        .trim()
+        .to_string();
+}
+
+fn validate_loki(prefix: &str, loki: &LokiConfig) -> Result<()> {
+    ensure_non_empty(&format!("{prefix}.url"), &loki.url)?;
+    reqwest::Url::parse(&loki.url)
+        .with_context(|| format!("invalid {prefix}.url: {}", loki.url))?;
+
+    match loki.auth_type.as_str() {
+        "none" => {}
+        "basic" => {
+            if loki.username.is_none() {
+                bail!("{prefix}.username is required when {prefix}.auth_type=basic");
+            }
+            if loki.password.is_none() {
+                bail!("{prefix}.password is required when {prefix}.auth_type=basic");
+            }
+        }
+        "bearer" => {
+            if loki.token.is_none() {
+                bail!("{prefix}.token is required when {prefix}.auth_type=bearer");
+            }
+        }
+        "oauth2" => {
+            let Some(oauth2) = loki.oauth2.as_ref() else {
+                bail!("{prefix}.oauth2 is required when {prefix}.auth_type=oauth2");
+            };
+            ensure_non_empty(&format!("{prefix}.oauth2.token_url"), &oauth2.token_url)?;
+            reqwest::Url::parse(&oauth2.token_url).with_context(|| {
+                format!("invalid {prefix}.oauth2.token_url: {}", oauth2.token_url)
+            })?;
+            ensure_non_empty(&format!("{prefix}.oauth2.client_id"), &oauth2.client_id)?;
+            ensure_non_empty(
+                &format!("{prefix}.oauth2.client_secret"),
+                &oauth2.client_secret,
+            )?;
+        }
+        other => {
+            bail!(
+                "unsupported {prefix}.auth_type: {other}. expected one of none/basic/bearer/oauth2"
+            );
+        }
+    }
+
+    if loki.client_cert.is_some() != loki.client_key.is_some() {
+        bail!("{prefix}.client_cert and {prefix}.client_key must be set together");
+    }
+    for (name, value) in &loki.headers {
+        validate_loki_header_name(&format!("{prefix}.headers"), name)?;
+        reqwest::header::HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for {prefix}.headers.{name}"))?;
+    }
+    for name in &loki.forward_headers {
+        validate_loki_header_name(&format!("{prefix}.forward_headers"), name)?;
+    }
+
+    parse_std_duration(&loki.timeout)
+        .with_context(|| format!("invalid {prefix}.timeout: {}", loki.timeout))?;
+    let max_response_bytes = parse_byte_size(&loki.max_response_bytes).with_context(|| {
+        format!(
+            "invalid {prefix}.max_response_bytes: {}",
+            loki.max_response_bytes
+        )
+    })?;
+    if max_response_bytes == 0 {
+        bail!("{prefix}.max_response_bytes must be greater than zero");
+    }
+
+    let initial_backoff = parse_std_duration(&loki.retry.initial_backoff).with_context(|| {
+        format!(
+            "invalid {prefix}.retry.initial_backoff: {}",
+            loki.retry.initial_backoff
+        )
+    })?;
+    let max_backoff = parse_std_duration(&loki.retry.max_backoff).with_context(|| {
+        format!(
+            "invalid {prefix}.retry.max_backoff: {}",
+            loki.retry.max_backoff
+        )
+    })?;
+    if max_backoff < initial_backoff {
+        bail!("{prefix}.retry.max_backoff must be at least {prefix}.retry.initial_backoff");
+    }
+
+    if loki.circuit_breaker.enabled {
+        if loki.circuit_breaker.failure_threshold == 0 {
+            bail!("{prefix}.circuit_breaker.failure_threshold must be greater than zero");
+        }
+        let open_duration =
+            parse_std_duration(&loki.circuit_breaker.open_duration).with_context(|| {
+                format!(
+                    "invalid {prefix}.circuit_breaker.open_duration: {}",
+                    loki.circuit_breaker.open_duration
+                )
+            })?;
+        if open_duration.is_zero() {
+            bail!("{prefix}.circuit_breaker.open_duration must be greater than zero");
+        }
+    }
+
+    Ok(())
+}
+
+/// Headers loki-mcp sets itself from `auth_type`/`tenant_id`, or that belong to the transport.
+const RESERVED_LOKI_HEADERS: [&str; 6] = [
+    "authorization",
+    "x-scope-orgid",
+    "host",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+];
+
+fn validate_loki_header_name(key: &str, name: &str) -> Result<()> {
+    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+        .with_context(|| format!("invalid header name in {key}: {name}"))?;
+    if RESERVED_LOKI_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+        bail!("{key} must not set {name}; it is managed by loki-mcp (see auth_type and tenant_id)");
+    }
+
+    Ok(())
+}
+
+fn validate_cache(prefix: &str, cache: &CacheConfig) -> Result<()> {
+    parse_std_duration(&cache.ttl)
+        .with_context(|| format!("invalid {prefix}.ttl: {}", cache.ttl))?;
+    parse_std_duration(&cache.skip_if_range_shorter_than).with_context(|| {
+        format!(
+            "invalid {prefix}.skip_if_range_shorter_than: {}",
+            cache.skip_if_range_shorter_than
+        )
+    })?;
+    if cache.max_entries == 0 {
+        bail!("{prefix}.max_entries must be greater than zero");
+    }
+
+    Ok(())
+}
+
+fn validate_guardrails(prefix: &str, guardrails: &GuardrailsConfig) -> Result<()> {
+    parse_std_duration(&guardrails.skip_stats_if_range_shorter_than).with_context(|| {
+        format!(
+            "invalid {prefix}.skip_stats_if_range_shorter_than: {}",
+            guardrails.skip_stats_if_range_shorter_than
+        )
+    })?;
+    parse_byte_size(&guardrails.max_bytes_scanned).with_context(|| {
+        format!(
+            "invalid {prefix}.max_bytes_scanned: {}",
+            guardrails.max_bytes_scanned
+        )
+    })?;
+
+    Ok(())
+}
+
 fn normalize_optional_string(value: &mut Option<String>) {
     if let Some(inner) = value {
         let trimmed = inner.trim().to_string();
@@ -640,6 +1648,13 @@ fn normalize_optional_string(value: &mut Option<String>) {
     }
 }
 
+fn normalize_string_list(values: &mut Vec<String>) {
+    for value in values.iter_mut() {
+        *value = value.trim().to_string();
+    }
+    values.retain(|value| !value.is_empty());
+}
+
 fn empty_string_as_none<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>,
@@ -690,6 +1705,15 @@ fn flat_env_overrides_from_map(vars: &BTreeMap<String, String>) -> Result<Config
         timezone: env_string(vars, "LOKI_MCP_TIMEZONE"),
         log_level: env_string(vars, "LOKI_MCP_LOG_LEVEL"),
         identity_header: env_string(vars, "LOKI_MCP_IDENTITY_HEADER"),
+        transport: env_string(vars, "LOKI_MCP_TRANSPORT"),
+        static_identity: env_string(vars, "LOKI_MCP_STATIC_IDENTITY"),
+        tls_cert: env_string(vars, "LOKI_MCP_TLS_CERT"),
+        tls_key: env_string(vars, "LOKI_MCP_TLS_KEY"),
+        tls_client_ca: env_string(vars, "LOKI_MCP_TLS_CLIENT_CA"),
+        auth: option_if_not_empty(ServerAuthOverrides {
+            enabled: env_parse(vars, "LOKI_MCP_AUTH_ENABLED")?,
+            keys_file: env_string(vars, "LOKI_MCP_AUTH_KEYS_FILE"),
+        }),
     };
 
     let loki = LokiOverrides {
@@ -700,7 +1724,10 @@ fn flat_env_overrides_from_map(vars: &BTreeMap<String, String>) -> Result<Config
         password: env_string(vars, "LOKI_MCP_LOKI_PASSWORD"),
         token: env_string(vars, "LOKI_MCP_LOKI_TOKEN"),
         ca_cert: env_string(vars, "LOKI_MCP_LOKI_CA_CERT"),
+        client_cert: env_string(vars, "LOKI_MCP_LOKI_CLIENT_CERT"),
+        client_key: env_string(vars, "LOKI_MCP_LOKI_CLIENT_KEY"),
         timeout: env_string(vars, "LOKI_MCP_LOKI_TIMEOUT"),
+        max_response_bytes: env_string(vars, "LOKI_MCP_LOKI_MAX_RESPONSE_BYTES"),
     };
 
     let cache = CacheOverrides {
@@ -845,7 +1872,13 @@ fn parse_byte_size(input: &str) -> Result<u64> {
 mod tests {
     use std::collections::BTreeMap;
 
-    use crate::config::{Config, ConfigOverrides, flat_env_overrides_from_map, parse_byte_size};
+    use clap::Parser;
+
+    use crate::config::{
+        Cli, Config, ConfigOverrides, LokiOAuth2Config, PromptArgument, PromptTemplate, SavedQuery,
+        SavedQueryParam, SchemaField, TenantMappingConfig, TenantsConfig,
+        flat_env_overrides_from_map, load, parse_byte_size,
+    };
 
     #[test]
     fn default_config_has_expected_values() {
@@ -853,6 +1886,7 @@ mod tests {
         assert_eq!(config.server.listen, "0.0.0.0:8080");
         assert_eq!(config.server.log_level, "info");
         assert_eq!(config.loki.timeout, "30s");
+        assert_eq!(config.loki.max_response_bytes, "100MB");
     }
 
     #[test]
@@ -895,5 +1929,341 @@ mod tests {
                 .to_string()
                 .contains("loki.username is required when loki.auth_type=basic")
         );
+
+        config.loki.auth_type = "oauth2".to_string();
+        let error = config
+            .validate()
+            .expect_err("oauth2 without a table should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("loki.oauth2 is required when loki.auth_type=oauth2")
+        );
+
+        config.loki.oauth2 = Some(LokiOAuth2Config {
+            token_url: "https://idp.example/token".to_string(),
+            client_id: "loki-mcp".to_string(),
+            client_secret: String::new(),
+            scopes: Vec::new(),
+        });
+        let error = config.validate().expect_err("missing secret should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("loki.oauth2.client_secret must not be empty")
+        );
+
+        config.loki.auth_type = "none".to_string();
+        config.loki.client_cert = Some("client.crt".to_string());
+        let error = config
+            .validate()
+            .expect_err("certificate without key should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("loki.client_cert and loki.client_key must be set together")
+        );
+
+        config.loki.client_cert = None;
+        config.loki.forward_headers = vec!["x-scope-orgid".to_string()];
+        let error = config
+            .validate()
+            .expect_err("forwarding the tenant header should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("loki.forward_headers must not set x-scope-orgid")
+        );
+
+        config.loki.forward_headers = Vec::new();
+        config.loki.max_response_bytes = "0".to_string();
+        let error = config
+            .validate()
+            .expect_err("a zero response limit should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("loki.max_response_bytes must be greater than zero")
+        );
+    }
+
+    #[test]
+    fn validation_rejects_prompts_with_unknown_references() {
+        let mut config = Config {
+            prompts: vec![PromptTemplate {
+                name: "triage".to_string(),
+                description: "Investigate errors".to_string(),
+                template: "Investigate {{namespace}}".to_string(),
+                arguments: Vec::new(),
+                saved_queries: Vec::new(),
+                fields: Vec::new(),
+            }],
+            ..Default::default()
+        };
+
+        let error = config
+            .validate()
+            .expect_err("undeclared argument should fail");
+        assert!(error.to_string().contains("undeclared argument"));
+
+        config.prompts[0].arguments = vec![PromptArgument {
+            name: "namespace".to_string(),
+            description: String::new(),
+            kind: "string".to_string(),
+            required: true,
+            default: None,
+        }];
+        config.prompts[0].saved_queries = vec!["missing".to_string()];
+        let error = config
+            .validate()
+            .expect_err("unknown saved query should fail");
+        assert!(error.to_string().contains("unknown saved query: missing"));
+
+        config.prompts[0].saved_queries.clear();
+        config.validate().expect("prompt should be valid");
+    }
+
+    #[test]
+    fn validation_rejects_saved_queries_with_invalid_params() {
+        let mut config = Config {
+            labels: vec![SchemaField {
+                name: "namespace".to_string(),
+                description: "Kubernetes namespace".to_string(),
+                common_values: vec!["checkout".to_string()],
+            }],
+            saved_queries: vec![SavedQuery {
+                name: "slow".to_string(),
+                description: "Slow requests".to_string(),
+                query: r#"{namespace="${namespace}"} | logfmt | duration > ${min}"#.to_string(),
+                range: "1h".to_string(),
+                params: vec![SavedQueryParam {
+                    name: "namespace".to_string(),
+                    description: String::new(),
+                    kind: "string".to_string(),
+                    label: Some("namespace".to_string()),
+                    default: Some("checkout".to_string()),
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let error = config.validate().expect_err("undeclared param should fail");
+        assert!(error.to_string().contains("undeclared param ${min}"));
+
+        config.saved_queries[0].params.push(SavedQueryParam {
+            name: "min".to_string(),
+            description: String::new(),
+            kind: "string".to_string(),
+            label: None,
+            default: None,
+        });
+        let error = config
+            .validate()
+            .expect_err("bare string param should fail");
+        assert!(error.to_string().contains("outside a string literal"));
+
+        config.saved_queries[0].params[1].kind = "number".to_string();
+        config.saved_queries[0].params[1].default = Some("fast".to_string());
+        let error = config.validate().expect_err("invalid default should fail");
+        assert!(error.to_string().contains("invalid default for param min"));
+
+        config.saved_queries[0].params[1].default = Some("2".to_string());
+        config.validate().expect("saved query should be valid");
+
+        // Forms the LogQL parser lacks are logged at startup, not rejected.
+        config.saved_queries[0].query = r#"{namespace="${namespace}"} | sample ${min}"#.to_string();
+        config
+            .validate()
+            .expect("an unparsable template is not fatal");
+    }
+
+    #[test]
+    fn validation_checks_saved_query_kind_and_options() {
+        let mut config = Config {
+            saved_queries: vec![SavedQuery {
+                name: "errors".to_string(),
+                description: "Errors".to_string(),
+                query: r#"{level="error"}"#.to_string(),
+                kind: "metrics".to_string(),
+                range: "1h".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let error = config
+            .validate()
+            .expect_err("log query as metrics should fail");
+        assert!(format!("{error:#}").contains("has kind metrics but its query is a log query"));
+
+        // A query the parser cannot read is trusted to match its declared kind.
+        config.saved_queries[0].query =
+            r#"sum(count_over_time({level="error"} | sample 0.5 [5m]))"#.to_string();
+        config
+            .validate()
+            .expect("kind is only checked on queries that parse");
+        config.saved_queries[0].query = r#"{level="error"}"#.to_string();
+
+        config.saved_queries[0].kind = "logs".to_string();
+        config.saved_queries[0].step = Some("1m".to_string());
+        let error = config
+            .validate()
+            .expect_err("step on a log query should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("sets step, which does not apply to logs")
+        );
+
+        config.saved_queries[0].step = None;
+        config.saved_queries[0].kind = "instant".to_string();
+        config.saved_queries[0].query = r#"count_over_time({level="error"}[1h])"#.to_string();
+        let error = config
+            .validate()
+            .expect_err("range on an instant query should fail");
+        assert!(error.to_string().contains("must not set range"));
+
+        config.saved_queries[0].range.clear();
+        config.saved_queries[0].limit = Some(5);
+        config
+            .validate()
+            .expect("instant saved query should be valid");
+    }
+
+    #[test]
+    fn validation_rejects_unknown_transport() {
+        let mut config = Config::default();
+        config.server.transport = "websocket".to_string();
+
+        let error = config
+            .validate()
+            .expect_err("unknown transport should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("unsupported server.transport: websocket")
+        );
+
+        config.server.transport = "stdio".to_string();
+        config.validate().expect("stdio transport should be valid");
+    }
+
+    #[test]
+    fn validation_requires_tls_cert_and_key_together() {
+        let mut config = Config::default();
+        config.server.tls_cert = Some("/etc/loki-mcp/tls.crt".to_string());
+
+        let error = config.validate().expect_err("cert without key should fail");
+        assert!(error.to_string().contains("must be set together"));
+
+        config.server.tls_key = Some("/etc/loki-mcp/tls.key".to_string());
+        config.server.tls_client_ca = Some("/etc/loki-mcp/ca.crt".to_string());
+        config
+            .validate()
+            .expect("cert, key and client ca should be valid");
+    }
+
+    #[test]
+    fn validation_requires_single_jwks_source_when_jwt_enabled() {
+        let mut config = Config::default();
+        config.server.jwt.enabled = true;
+
+        let error = config
+            .validate()
+            .expect_err("jwt without a jwks source should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("exactly one of server.jwt.jwks_file")
+        );
+
+        config.server.jwt.jwks_url = Some("https://idp.example.com/jwks.json".to_string());
+        config.validate().expect("jwks url should be valid");
+
+        config.server.jwt.jwks_file = Some("/etc/loki-mcp/jwks.json".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn loads_datasources_with_inherited_settings() {
+        let path =
+            std::env::temp_dir().join(format!("loki-mcp-datasources-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+default_datasource = "staging"
+
+[guardrails]
+max_streams = 100
+
+[[datasources]]
+name = "prod"
+url = "https://loki-prod.internal:3100"
+tenant_id = "prod"
+
+[[datasources]]
+name = "staging"
+url = "https://loki-staging.internal:3100"
+auth_type = "bearer"
+token = "staging-token"
+
+[datasources.guardrails]
+max_streams = 20
+"#,
+        )
+        .expect("write config");
+
+        let cli = Cli::parse_from(["loki-mcp", "--config", &path.display().to_string()]);
+        let config = load(&cli).expect("config should load");
+        let _ = std::fs::remove_file(&path);
+
+        let datasources = config.resolved_datasources();
+        assert_eq!(datasources.len(), 2);
+        assert_eq!(datasources[0].loki.tenant_id.as_deref(), Some("prod"));
+        assert_eq!(datasources[0].loki.timeout, "30s");
+        assert_eq!(datasources[0].guardrails.max_streams, 100);
+        assert_eq!(datasources[1].guardrails.max_streams, 20);
+        assert_eq!(config.default_resolved_datasource().name, "staging");
+    }
+
+    #[test]
+    fn validation_rejects_unknown_default_datasource() {
+        let config = Config {
+            default_datasource: Some("edge".to_string()),
+            ..Default::default()
+        };
+
+        let error = config
+            .validate()
+            .expect_err("unknown default datasource should fail");
+        assert!(error.to_string().contains("unknown datasource: edge"));
+    }
+
+    #[test]
+    fn validation_requires_complete_tenant_mappings_when_enabled() {
+        let mut config = Config {
+            tenants: TenantsConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.tenants.mappings.push(TenantMappingConfig {
+            groups: vec!["sre".to_string()],
+            ..Default::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.tenants.mappings[0].tenants = vec!["payments".to_string()];
+        let error = config
+            .validate()
+            .expect_err("unauthenticated http tenant mapping should fail");
+        assert!(error.to_string().contains("requires server.auth.enabled"));
+
+        config.server.transport = "stdio".to_string();
+        config.validate().expect("complete mapping should validate");
     }
 }
@@ -0,0 +1,374 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{AlgorithmParameters, Jwk, JwkSet},
+};
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{auth::AuthenticatedIdentity, config::JwtConfig, time::parse_std_duration};
+
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+enum JwksSource {
+    File(String),
+    Url(String),
+}
+
+struct JwksState {
+    keys: JwkSet,
+    loaded_at: Instant,
+}
+
+/// Verifies bearer JWTs against a JWKS document and extracts the caller identity from a claim.
+#[derive(Clone)]
+pub struct JwtValidator {
+    source: JwksSource,
+    state: Arc<RwLock<JwksState>>,
+    http_client: reqwest::Client,
+    issuer: Option<String>,
+    audience: Option<String>,
+    identity_claim: String,
+    groups_claim: Option<String>,
+    leeway: u64,
+}
+
+impl JwtValidator {
+    pub async fn from_config(config: &JwtConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let source = match (config.jwks_file.as_deref(), config.jwks_url.as_deref()) {
+            (Some(path), None) => JwksSource::File(path.to_string()),
+            (None, Some(url)) => JwksSource::Url(url.to_string()),
+            _ => bail!("exactly one of server.jwt.jwks_file or server.jwt.jwks_url is required"),
+        };
+        let leeway = parse_std_duration(&config.leeway)
+            .with_context(|| format!("invalid server.jwt.leeway: {}", config.leeway))?
+            .as_secs();
+        let http_client = reqwest::Client::builder()
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .build()
+            .context("failed to build JWKS HTTP client")?;
+
+        let keys = load_jwks(&source, &http_client).await?;
+
+        Ok(Some(Self {
+            source,
+            state: Arc::new(RwLock::new(JwksState {
+                keys,
+                loaded_at: Instant::now(),
+            })),
+            http_client,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            identity_claim: config.identity_claim.clone(),
+            groups_claim: config.groups_claim.clone(),
+            leeway,
+        }))
+    }
+
+    pub async fn validate(&self, token: &str) -> Result<AuthenticatedIdentity, String> {
+        let header =
+            decode_header(token).map_err(|error| format!("invalid JWT header: {error}"))?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(format!(
+                "unsupported JWT algorithm {:?}: only asymmetric algorithms are accepted",
+                header.alg
+            ));
+        }
+
+        let jwk = self.find_key(header.kid.as_deref()).await?;
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err("symmetric JWKS keys are not accepted".to_string());
+        }
+        let decoding_key =
+            DecodingKey::from_jwk(&jwk).map_err(|error| format!("invalid JWKS key: {error}"))?;
+
+        let claims =
+            decode::<Map<String, Value>>(token, &decoding_key, &self.validation(header.alg))
+                .map_err(|error| format!("invalid JWT: {error}"))?
+                .claims;
+
+        let identity = match claims.get(&self.identity_claim) {
+            Some(Value::String(identity)) if !identity.trim().is_empty() => {
+                identity.trim().to_string()
+            }
+            Some(Value::Number(identity)) => identity.to_string(),
+            _ => {
+                return Err(format!(
+                    "JWT is missing identity claim `{}`",
+                    self.identity_claim
+                ));
+            }
+        };
+        let groups = self
+            .groups_claim
+            .as_deref()
+            .and_then(|claim| claims.get(claim))
+            .map(claim_strings)
+            .unwrap_or_default();
+
+        Ok(AuthenticatedIdentity { identity, groups })
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+
+        let mut required = vec!["exp"];
+        if let Some(issuer) = self.issuer.as_deref() {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        match self.audience.as_deref() {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                required.push("aud");
+            }
+            None => validation.validate_aud = false,
+        }
+        validation.set_required_spec_claims(&required);
+
+        validation
+    }
+
+    async fn find_key(&self, kid: Option<&str>) -> Result<Jwk, String> {
+        if let Some(jwk) = select_key(&self.state.read().await.keys, kid) {
+            return Ok(jwk);
+        }
+
+        // Unknown key ids usually mean the issuer rotated keys; reload, but not on every request.
+        let mut state = self.state.write().await;
+        if state.loaded_at.elapsed() >= JWKS_REFRESH_INTERVAL {
+            match load_jwks(&self.source, &self.http_client).await {
+                Ok(keys) => state.keys = keys,
+                Err(error) => warn!(error = %error, "failed to refresh JWKS"),
+            }
+            state.loaded_at = Instant::now();
+        }
+
+        select_key(&state.keys, kid).ok_or_else(|| match kid {
+            Some(kid) => format!("no JWKS key matches kid {kid}"),
+            None => "JWT has no kid and the JWKS does not contain exactly one key".to_string(),
+        })
+    }
+}
+
+/// Returns true when a bearer credential has the three-segment compact JWT shape.
+pub fn looks_like_jwt(token: &str) -> bool {
+    let segments = token.split('.').collect::<Vec<&str>>();
+    segments.len() == 3 && segments.iter().all(|segment| !segment.is_empty())
+}
+
+/// Reads a group claim that is either a single string or an array of strings.
+fn claim_strings(value: &Value) -> Vec<String> {
+    let values = match value {
+        Value::Array(values) => values.iter().collect::<Vec<&Value>>(),
+        other => vec![other],
+    };
+
+    values
+        .into_iter()
+        .filter_map(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn select_key(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid).cloned(),
+        None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+        None => None,
+    }
+}
+
+async fn load_jwks(source: &JwksSource, http_client: &reqwest::Client) -> Result<JwkSet> {
+    match source {
+        JwksSource::File(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read server.jwt.jwks_file from {path}"))?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("invalid JWKS document at {path}"))
+        }
+        JwksSource::Url(url) => http_client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("failed to fetch JWKS from {url}"))?
+            .json::<JwkSet>()
+            .await
+            .with_context(|| format!("invalid JWKS document at {url}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+    use ring::{
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{
+        config::JwtConfig,
+        jwt::{JwtValidator, looks_like_jwt},
+    };
+
+    struct TestIssuer {
+        encoding_key: EncodingKey,
+        jwks: Value,
+    }
+
+    impl TestIssuer {
+        fn generate() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .expect("generate key");
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .expect("parse key");
+
+            // Uncompressed SEC1 point: 0x04 || x || y.
+            let public_key = key_pair.public_key().as_ref();
+            let jwks = json!({
+                "keys": [{
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "kid": "test-key",
+                    "alg": "ES256",
+                    "use": "sig",
+                    "x": URL_SAFE_NO_PAD.encode(&public_key[1..33]),
+                    "y": URL_SAFE_NO_PAD.encode(&public_key[33..65]),
+                }]
+            });
+
+            Self {
+                encoding_key: EncodingKey::from_ec_der(pkcs8.as_ref()),
+                jwks,
+            }
+        }
+
+        fn sign(&self, claims: Value) -> String {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some("test-key".to_string());
+            encode(&header, &claims, &self.encoding_key).expect("sign token")
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock")
+            .as_secs()
+    }
+
+    static JWKS_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    async fn validator(issuer: &TestIssuer, identity_claim: &str) -> JwtValidator {
+        let path = std::env::temp_dir().join(format!(
+            "loki-mcp-jwks-{}-{}.json",
+            std::process::id(),
+            JWKS_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, issuer.jwks.to_string()).expect("write jwks");
+
+        let validator = JwtValidator::from_config(&JwtConfig {
+            enabled: true,
+            jwks_file: Some(path.display().to_string()),
+            issuer: Some("https://idp.example.com".to_string()),
+            audience: Some("loki-mcp".to_string()),
+            identity_claim: identity_claim.to_string(),
+            leeway: "0s".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("config")
+        .expect("jwt enabled");
+        let _ = std::fs::remove_file(&path);
+
+        validator
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_token_and_reads_identity_claim() {
+        let issuer = TestIssuer::generate();
+        let validator = validator(&issuer, "email").await;
+
+        let token = issuer.sign(json!({
+            "sub": "user-123",
+            "email": "alice@example.com",
+            "groups": ["sre", "payments"],
+            "iss": "https://idp.example.com",
+            "aud": "loki-mcp",
+            "exp": now() + 300,
+        }));
+        assert!(looks_like_jwt(&token));
+        let caller = validator.validate(&token).await.expect("valid token");
+        assert_eq!(caller.identity, "alice@example.com");
+        assert_eq!(caller.groups, vec!["sre", "payments"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_issuer_audience_and_expired_tokens() {
+        let issuer = TestIssuer::generate();
+        let validator = validator(&issuer, "sub").await;
+
+        let claims = |iss: &str, aud: &str, exp: u64| json!({"sub": "alice", "iss": iss, "aud": aud, "exp": exp});
+
+        let wrong_issuer = issuer.sign(claims("https://evil.example.com", "loki-mcp", now() + 300));
+        assert!(validator.validate(&wrong_issuer).await.is_err());
+
+        let wrong_audience = issuer.sign(claims("https://idp.example.com", "other", now() + 300));
+        assert!(validator.validate(&wrong_audience).await.is_err());
+
+        let expired = issuer.sign(claims("https://idp.example.com", "loki-mcp", now() - 300));
+        assert!(validator.validate(&expired).await.is_err());
+
+        let valid = issuer.sign(claims("https://idp.example.com", "loki-mcp", now() + 300));
+        assert_eq!(
+            validator
+                .validate(&valid)
+                .await
+                .map(|caller| caller.identity),
+            Ok("alice".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_signed_by_unknown_keys() {
+        let trusted = TestIssuer::generate();
+        let untrusted = TestIssuer::generate();
+        let validator = validator(&trusted, "sub").await;
+
+        let token = untrusted.sign(json!({
+            "sub": "mallory",
+            "iss": "https://idp.example.com",
+            "aud": "loki-mcp",
+            "exp": now() + 300,
+        }));
+        assert!(validator.validate(&token).await.is_err());
+    }
+}
@@ -1,13 +1,20 @@
+pub mod auth;
 pub mod cache;
 pub mod config;
 pub mod error;
 pub mod guardrails;
+pub mod jwt;
+pub mod logql;
 pub mod loki;
 pub mod mcp;
 pub mod metrics;
+pub mod prompts;
 pub mod rate_limit;
 pub mod recent_actions;
+pub mod resources;
 pub mod response;
 pub mod server;
+pub mod tenants;
 pub mod time;
+pub mod tls;
 pub mod tools;
@@ -0,0 +1,604 @@
+use crate::logql::Span;
+
+/// A complete query: either a log query or a metric query built from range aggregations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Log(LogExpr),
+    RangeAggregation(RangeAggregation),
+    VectorAggregation(VectorAggregation),
+    Binary(BinaryExpr),
+    Literal {
+        value: f64,
+        span: Span,
+    },
+    /// `vector(1)`
+    Vector {
+        value: f64,
+        span: Span,
+    },
+    LabelReplace(LabelReplace),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Log(log) => log.span,
+            Self::RangeAggregation(aggregation) => aggregation.span,
+            Self::VectorAggregation(aggregation) => aggregation.span,
+            Self::Binary(binary) => binary.span,
+            Self::Literal { span, .. } | Self::Vector { span, .. } => *span,
+            Self::LabelReplace(replace) => replace.span,
+        }
+    }
+
+    pub fn is_log(&self) -> bool {
+        matches!(self, Self::Log(_))
+    }
+
+    /// Every log query in the expression, left to right.
+    pub fn log_exprs(&self) -> Vec<&LogExpr> {
+        let mut logs = Vec::new();
+        self.collect_logs(&mut logs);
+        logs
+    }
+
+    fn collect_logs<'a>(&'a self, logs: &mut Vec<&'a LogExpr>) {
+        match self {
+            Self::Log(log) => logs.push(log),
+            Self::RangeAggregation(aggregation) => logs.push(&aggregation.range.log),
+            Self::VectorAggregation(aggregation) => aggregation.expr.collect_logs(logs),
+            Self::Binary(binary) => {
+                binary.left.collect_logs(logs);
+                binary.right.collect_logs(logs);
+            }
+            Self::LabelReplace(replace) => replace.expr.collect_logs(logs),
+            Self::Literal { .. } | Self::Vector { .. } => {}
+        }
+    }
+
+    /// Every range aggregation in the expression, left to right.
+    pub fn range_aggregations(&self) -> Vec<&RangeAggregation> {
+        let mut aggregations = Vec::new();
+        self.collect_ranges(&mut aggregations);
+        aggregations
+    }
+
+    fn collect_ranges<'a>(&'a self, aggregations: &mut Vec<&'a RangeAggregation>) {
+        match self {
+            Self::RangeAggregation(aggregation) => aggregations.push(aggregation),
+            Self::VectorAggregation(aggregation) => aggregation.expr.collect_ranges(aggregations),
+            Self::Binary(binary) => {
+                binary.left.collect_ranges(aggregations);
+                binary.right.collect_ranges(aggregations);
+            }
+            Self::LabelReplace(replace) => replace.expr.collect_ranges(aggregations),
+            Self::Log(_) | Self::Literal { .. } | Self::Vector { .. } => {}
+        }
+    }
+}
+
+/// A stream selector and its pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogExpr {
+    pub selector: Selector,
+    pub pipeline: Vec<Stage>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub matchers: Vec<Matcher>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matcher {
+    pub label: String,
+    pub op: MatchOp,
+    pub value: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOp {
+    Eq,
+    NotEq,
+    Regex,
+    NotRegex,
+}
+
+impl MatchOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::NotEq => "!=",
+            Self::Regex => "=~",
+            Self::NotRegex => "!~",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub kind: StageKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageKind {
+    LineFilter(LineFilter),
+    Parser(ParserStage),
+    LabelFilter(LabelFilter),
+    LineFormat {
+        template: String,
+    },
+    LabelFormat(Vec<LabelFormatOp>),
+    Decolorize,
+    Drop(Vec<LabelSelection>),
+    Keep(Vec<LabelSelection>),
+    /// `distinct level, app`: keeps the first line for each combination of the labels.
+    Distinct(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineFilter {
+    pub op: LineFilterOp,
+    /// Alternatives joined with `or`; a line passes when any of them matches.
+    pub values: Vec<LineFilterValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineFilterOp {
+    Contains,
+    NotContains,
+    Regex,
+    NotRegex,
+    Pattern,
+    NotPattern,
+}
+
+impl LineFilterOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Contains => "|=",
+            Self::NotContains => "!=",
+            Self::Regex => "|~",
+            Self::NotRegex => "!~",
+            Self::Pattern => "|>",
+            Self::NotPattern => "!>",
+        }
+    }
+
+    pub fn is_negative(self) -> bool {
+        matches!(self, Self::NotContains | Self::NotRegex | Self::NotPattern)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineFilterValue {
+    Text {
+        value: String,
+        span: Span,
+    },
+    /// `ip("10.0.0.0/8")`
+    Ip {
+        cidr: String,
+        span: Span,
+    },
+}
+
+impl LineFilterValue {
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Text { value, .. } => value,
+            Self::Ip { cidr, .. } => cidr,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Text { span, .. } | Self::Ip { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParserStage {
+    /// `| json` or `| json status="response.code", method`
+    Json(Vec<ExtractParam>),
+    /// `| logfmt --strict level, msg="message"`
+    Logfmt {
+        flags: Vec<String>,
+        params: Vec<ExtractParam>,
+    },
+    Regexp {
+        expression: String,
+    },
+    Pattern {
+        expression: String,
+    },
+    Unpack,
+}
+
+impl ParserStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json(_) => "json",
+            Self::Logfmt { .. } => "logfmt",
+            Self::Regexp { .. } => "regexp",
+            Self::Pattern { .. } => "pattern",
+            Self::Unpack => "unpack",
+        }
+    }
+}
+
+/// One label a parser extracts: `label` from `expression`, or from the same-named key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractParam {
+    pub label: String,
+    pub expression: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelFilter {
+    And(Box<LabelFilter>, Box<LabelFilter>),
+    Or(Box<LabelFilter>, Box<LabelFilter>),
+    Comparison(LabelComparison),
+}
+
+impl LabelFilter {
+    /// Every comparison in the filter, left to right.
+    pub fn comparisons(&self) -> Vec<&LabelComparison> {
+        match self {
+            Self::And(left, right) | Self::Or(left, right) => {
+                let mut comparisons = left.comparisons();
+                comparisons.extend(right.comparisons());
+                comparisons
+            }
+            Self::Comparison(comparison) => vec![comparison],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelComparison {
+    pub label: String,
+    pub op: CompareOp,
+    pub value: FilterValue,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Regex,
+    NotRegex,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl CompareOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::NotEq => "!=",
+            Self::Regex => "=~",
+            Self::NotRegex => "!~",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    String(String),
+    Number(f64),
+    /// As written, e.g. `250ms`.
+    Duration(String),
+    /// As written, e.g. `20KB`.
+    Bytes(String),
+    Ip(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelFormatOp {
+    /// `dst=src`
+    Rename { label: String, from: String },
+    /// `dst="{{.template}}"`
+    Template { label: String, template: String },
+}
+
+/// A label named by `drop`/`keep`, optionally only when it matches a value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelSelection {
+    pub label: String,
+    pub matcher: Option<(MatchOp, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeAggregation {
+    pub op: RangeOp,
+    /// The quantile of `quantile_over_time`.
+    pub parameter: Option<f64>,
+    pub range: LogRange,
+    pub grouping: Option<Grouping>,
+    pub span: Span,
+}
+
+/// `{...} | pipeline | unwrap label [5m] offset 1h`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRange {
+    pub log: LogExpr,
+    pub unwrap: Option<Unwrap>,
+    /// As written, e.g. `5m`.
+    pub range: String,
+    pub offset: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unwrap {
+    pub label: String,
+    /// `bytes`, `duration` or `duration_seconds`.
+    pub conversion: Option<String>,
+    /// Label filters after the unwrap, usually `__error__=""`.
+    pub filters: Vec<LabelFilter>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOp {
+    Rate,
+    RateCounter,
+    CountOverTime,
+    BytesRate,
+    BytesOverTime,
+    AbsentOverTime,
+    SumOverTime,
+    AvgOverTime,
+    MaxOverTime,
+    MinOverTime,
+    StddevOverTime,
+    StdvarOverTime,
+    QuantileOverTime,
+    FirstOverTime,
+    LastOverTime,
+}
+
+impl RangeOp {
+    pub const ALL: [Self; 15] = [
+        Self::Rate,
+        Self::RateCounter,
+        Self::CountOverTime,
+        Self::BytesRate,
+        Self::BytesOverTime,
+        Self::AbsentOverTime,
+        Self::SumOverTime,
+        Self::AvgOverTime,
+        Self::MaxOverTime,
+        Self::MinOverTime,
+        Self::StddevOverTime,
+        Self::StdvarOverTime,
+        Self::QuantileOverTime,
+        Self::FirstOverTime,
+        Self::LastOverTime,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rate => "rate",
+            Self::RateCounter => "rate_counter",
+            Self::CountOverTime => "count_over_time",
+            Self::BytesRate => "bytes_rate",
+            Self::BytesOverTime => "bytes_over_time",
+            Self::AbsentOverTime => "absent_over_time",
+            Self::SumOverTime => "sum_over_time",
+            Self::AvgOverTime => "avg_over_time",
+            Self::MaxOverTime => "max_over_time",
+            Self::MinOverTime => "min_over_time",
+            Self::StddevOverTime => "stddev_over_time",
+            Self::StdvarOverTime => "stdvar_over_time",
+            Self::QuantileOverTime => "quantile_over_time",
+            Self::FirstOverTime => "first_over_time",
+            Self::LastOverTime => "last_over_time",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    /// Whether the aggregation reads an unwrapped label rather than counting lines or bytes.
+    pub fn requires_unwrap(self) -> bool {
+        !matches!(
+            self,
+            Self::Rate
+                | Self::CountOverTime
+                | Self::BytesRate
+                | Self::BytesOverTime
+                | Self::AbsentOverTime
+        )
+    }
+
+    /// Whether the aggregation accepts an unwrapped label at all.
+    pub fn allows_unwrap(self) -> bool {
+        self == Self::Rate || self.requires_unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorAggregation {
+    pub op: VectorOp,
+    /// `k` of `topk`/`bottomk`/`approx_topk`.
+    pub parameter: Option<f64>,
+    pub expr: Box<Expr>,
+    pub grouping: Option<Grouping>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+    Stddev,
+    Stdvar,
+    Topk,
+    Bottomk,
+    ApproxTopk,
+    Sort,
+    SortDesc,
+}
+
+impl VectorOp {
+    pub const ALL: [Self; 12] = [
+        Self::Sum,
+        Self::Avg,
+        Self::Min,
+        Self::Max,
+        Self::Count,
+        Self::Stddev,
+        Self::Stdvar,
+        Self::Topk,
+        Self::Bottomk,
+        Self::ApproxTopk,
+        Self::Sort,
+        Self::SortDesc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Count => "count",
+            Self::Stddev => "stddev",
+            Self::Stdvar => "stdvar",
+            Self::Topk => "topk",
+            Self::Bottomk => "bottomk",
+            Self::ApproxTopk => "approx_topk",
+            Self::Sort => "sort",
+            Self::SortDesc => "sort_desc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    pub fn takes_parameter(self) -> bool {
+        matches!(self, Self::Topk | Self::Bottomk | Self::ApproxTopk)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grouping {
+    pub without: bool,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryExpr {
+    pub op: BinaryOp,
+    /// `bool` on a comparison: return 0/1 instead of filtering.
+    pub return_bool: bool,
+    pub matching: Option<VectorMatching>,
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Eq,
+    NotEq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    And,
+    Or,
+    Unless,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::Pow => "^",
+            Self::Eq => "==",
+            Self::NotEq => "!=",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Unless => "unless",
+        }
+    }
+
+    /// Binding strength; higher binds tighter.
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And | Self::Unless => 2,
+            Self::Eq | Self::NotEq | Self::Gt | Self::Gte | Self::Lt | Self::Lte => 3,
+            Self::Add | Self::Sub => 4,
+            Self::Mul | Self::Div | Self::Mod => 5,
+            Self::Pow => 6,
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        self.precedence() == 3
+    }
+
+    pub fn is_set_operator(self) -> bool {
+        matches!(self, Self::And | Self::Or | Self::Unless)
+    }
+}
+
+/// `on(...)`/`ignoring(...)` with an optional `group_left(...)`/`group_right(...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatching {
+    pub on: bool,
+    pub labels: Vec<String>,
+    pub group: Option<(GroupSide, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupSide {
+    Left,
+    Right,
+}
+
+/// `label_replace(expr, "dst", "replacement", "src", "regex")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelReplace {
+    pub expr: Box<Expr>,
+    pub destination: String,
+    pub replacement: String,
+    pub source: String,
+    pub regex: String,
+    pub span: Span,
+}
@@ -0,0 +1,541 @@
+use serde_json::{Map, Value, json};
+
+use crate::logql::ast::{
+    BinaryExpr, BinaryOp, CompareOp, Expr, ExtractParam, FilterValue, GroupSide, Grouping,
+    LabelComparison, LabelFilter, LabelFormatOp, LabelSelection, LineFilter, LineFilterOp,
+    LineFilterValue, LogExpr, MatchOp, Matcher, ParserStage, RangeAggregation, RangeOp, Selector,
+    Stage, StageKind, Unwrap, VectorAggregation, VectorOp,
+};
+
+/// Renders a parsed query as a JSON tree. Every node has a `type`, the `text` it was parsed
+/// from and a one-sentence `description`.
+pub fn explain(expr: &Expr, source: &str) -> Value {
+    match expr {
+        Expr::Log(log) => log_node(log, source),
+        Expr::RangeAggregation(aggregation) => range_node(aggregation, source),
+        Expr::VectorAggregation(aggregation) => vector_node(aggregation, source),
+        Expr::Binary(binary) => binary_node(binary, source),
+        Expr::Literal { value, span } => json!({
+            "type": "number",
+            "text": span.text(source),
+            "value": value,
+            "description": format!("The constant {}.", format_number(*value)),
+        }),
+        Expr::Vector { value, span } => json!({
+            "type": "vector",
+            "text": span.text(source),
+            "value": value,
+            "description": format!(
+                "A single series with the constant value {} and no labels, often used as a fallback with `or`.",
+                format_number(*value)
+            ),
+        }),
+        Expr::LabelReplace(replace) => json!({
+            "type": "label_replace",
+            "text": replace.span.text(source),
+            "destination": replace.destination,
+            "replacement": replace.replacement,
+            "source": replace.source,
+            "regex": replace.regex,
+            "expr": explain(&replace.expr, source),
+            "description": format!(
+                "Sets label `{}` to \"{}\" on series whose `{}` label matches the regex \"{}\".",
+                replace.destination, replace.replacement, replace.source, replace.regex
+            ),
+        }),
+    }
+}
+
+fn log_node(log: &LogExpr, source: &str) -> Value {
+    let description = match log.pipeline.len() {
+        0 => format!(
+            "Returns log lines from streams matching {}.",
+            log.selector.span.text(source)
+        ),
+        stages => format!(
+            "Returns log lines from streams matching {} after {stages} pipeline stage{}.",
+            log.selector.span.text(source),
+            if stages == 1 { "" } else { "s" }
+        ),
+    };
+
+    json!({
+        "type": "log_query",
+        "text": log.span.text(source),
+        "selector": selector_node(&log.selector, source),
+        "pipeline": log.pipeline.iter().map(|stage| stage_node(stage, source)).collect::<Vec<_>>(),
+        "description": description,
+    })
+}
+
+fn selector_node(selector: &Selector, source: &str) -> Value {
+    let matchers = selector
+        .matchers
+        .iter()
+        .map(|matcher| {
+            json!({
+                "label": matcher.label,
+                "operator": matcher.op.symbol(),
+                "value": matcher.value,
+                "description": describe_matcher(matcher),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "type": "stream_selector",
+        "text": selector.span.text(source),
+        "matchers": matchers,
+        "description": format!(
+            "Selects streams where {}.",
+            join_and(selector.matchers.iter().map(describe_matcher).collect())
+        ),
+    })
+}
+
+fn describe_matcher(matcher: &Matcher) -> String {
+    let verb = match matcher.op {
+        MatchOp::Eq => "is",
+        MatchOp::NotEq => "is not",
+        MatchOp::Regex => "matches the regex",
+        MatchOp::NotRegex => "does not match the regex",
+    };
+    format!("`{}` {verb} \"{}\"", matcher.label, matcher.value)
+}
+
+fn stage_node(stage: &Stage, source: &str) -> Value {
+    let mut node = Map::new();
+    let (stage_type, description) = match &stage.kind {
+        StageKind::LineFilter(filter) => {
+            node.insert("operator".into(), json!(filter.op.symbol()));
+            node.insert(
+                "values".into(),
+                json!(
+                    filter
+                        .values
+                        .iter()
+                        .map(LineFilterValue::text)
+                        .collect::<Vec<_>>()
+                ),
+            );
+            ("line_filter", describe_line_filter(filter))
+        }
+        StageKind::Parser(parser) => {
+            node.insert("parser".into(), json!(parser.name()));
+            ("parser", describe_parser(parser))
+        }
+        StageKind::LabelFilter(filter) => (
+            "label_filter",
+            format!("Keeps lines where {}.", describe_label_filter(filter)),
+        ),
+        StageKind::LineFormat { template } => {
+            node.insert("template".into(), json!(template));
+            (
+                "line_format",
+                "Rewrites each line from the template, using extracted labels.".to_string(),
+            )
+        }
+        StageKind::LabelFormat(operations) => {
+            let changes = operations
+                .iter()
+                .map(|operation| match operation {
+                    LabelFormatOp::Rename { label, from } => {
+                        format!("renames `{from}` to `{label}`")
+                    }
+                    LabelFormatOp::Template { label, template } => {
+                        format!("sets `{label}` from the template \"{template}\"")
+                    }
+                })
+                .collect();
+            (
+                "label_format",
+                format!("{}.", capitalize(&join_and(changes))),
+            )
+        }
+        StageKind::Decolorize => (
+            "decolorize",
+            "Strips ANSI color codes from each line.".to_string(),
+        ),
+        StageKind::Drop(selections) => (
+            "drop",
+            format!("Drops the labels {}.", describe_selections(selections)),
+        ),
+        StageKind::Keep(selections) => (
+            "keep",
+            format!(
+                "Keeps only the labels {} and drops the rest.",
+                describe_selections(selections)
+            ),
+        ),
+        StageKind::Distinct(labels) => (
+            "distinct",
+            format!(
+                "Keeps the first line for each distinct value of {}.",
+                join_and(labels.iter().map(|label| format!("`{label}`")).collect())
+            ),
+        ),
+    };
+
+    node.insert("type".into(), json!(stage_type));
+    node.insert("text".into(), json!(stage.span.text(source)));
+    node.insert("description".into(), json!(description));
+    Value::Object(node)
+}
+
+fn describe_line_filter(filter: &LineFilter) -> String {
+    let verb = match filter.op {
+        LineFilterOp::Contains => "Keeps lines containing",
+        LineFilterOp::NotContains => "Drops lines containing",
+        LineFilterOp::Regex => "Keeps lines matching the regex",
+        LineFilterOp::NotRegex => "Drops lines matching the regex",
+        LineFilterOp::Pattern => "Keeps lines matching the pattern",
+        LineFilterOp::NotPattern => "Drops lines matching the pattern",
+    };
+    let values = filter
+        .values
+        .iter()
+        .map(|value| match value {
+            LineFilterValue::Text { value, .. } => format!("\"{value}\""),
+            LineFilterValue::Ip { cidr, .. } => format!("the IP address or range {cidr}"),
+        })
+        .collect::<Vec<_>>()
+        .join(" or ");
+    format!("{verb} {values}.")
+}
+
+fn describe_parser(parser: &ParserStage) -> String {
+    let extracted = |params: &[ExtractParam], format: &str| {
+        if params.is_empty() {
+            return format!("Parses each line as {format} and extracts every field as a label.");
+        }
+        let labels = params
+            .iter()
+            .map(|param| match &param.expression {
+                Some(expression) => format!("`{}` from {expression}", param.label),
+                None => format!("`{}`", param.label),
+            })
+            .collect();
+        format!(
+            "Parses each line as {format} and extracts only {}.",
+            join_and(labels)
+        )
+    };
+
+    match parser {
+        ParserStage::Json(params) => extracted(params, "JSON"),
+        ParserStage::Logfmt { flags, params } => {
+            let mut description = extracted(params, "logfmt");
+            if !flags.is_empty() {
+                description.push_str(&format!(
+                    " Flags: {}.",
+                    flags
+                        .iter()
+                        .map(|flag| format!("--{flag}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            description
+        }
+        ParserStage::Regexp { .. } => {
+            "Extracts the named capture groups of the regex as labels.".to_string()
+        }
+        ParserStage::Pattern { .. } => {
+            "Extracts the named `<captures>` of the pattern as labels.".to_string()
+        }
+        ParserStage::Unpack => {
+            "Unpacks labels and the original line from lines written by Promtail's pack stage."
+                .to_string()
+        }
+    }
+}
+
+fn describe_label_filter(filter: &LabelFilter) -> String {
+    match filter {
+        LabelFilter::And(left, right) => format!(
+            "{} and {}",
+            describe_label_filter(left),
+            describe_label_filter(right)
+        ),
+        LabelFilter::Or(left, right) => format!(
+            "({} or {})",
+            describe_label_filter(left),
+            describe_label_filter(right)
+        ),
+        LabelFilter::Comparison(comparison) => describe_comparison(comparison),
+    }
+}
+
+fn describe_comparison(comparison: &LabelComparison) -> String {
+    let verb = match comparison.op {
+        CompareOp::Eq => "is",
+        CompareOp::NotEq => "is not",
+        CompareOp::Regex => "matches the regex",
+        CompareOp::NotRegex => "does not match the regex",
+        CompareOp::Gt => "is greater than",
+        CompareOp::Gte => "is at least",
+        CompareOp::Lt => "is less than",
+        CompareOp::Lte => "is at most",
+    };
+    let value = match &comparison.value {
+        FilterValue::String(value) if value.is_empty() => "empty".to_string(),
+        FilterValue::String(value) => format!("\"{value}\""),
+        FilterValue::Number(value) => format_number(*value),
+        FilterValue::Duration(value) | FilterValue::Bytes(value) => value.clone(),
+        FilterValue::Ip(cidr) => format!("in the IP range {cidr}"),
+    };
+    format!("`{}` {verb} {value}", comparison.label)
+}
+
+fn describe_selections(selections: &[LabelSelection]) -> String {
+    join_and(
+        selections
+            .iter()
+            .map(|selection| match &selection.matcher {
+                Some((op, value)) => format!(
+                    "`{}` (when it {} \"{value}\")",
+                    selection.label,
+                    match op {
+                        MatchOp::Eq => "is",
+                        MatchOp::NotEq => "is not",
+                        MatchOp::Regex => "matches",
+                        MatchOp::NotRegex => "does not match",
+                    }
+                ),
+                None => format!("`{}`", selection.label),
+            })
+            .collect(),
+    )
+}
+
+fn range_node(aggregation: &RangeAggregation, source: &str) -> Value {
+    let range = &aggregation.range;
+    let mut description = format!(
+        "{} per stream over each {} window",
+        describe_range_op(aggregation.op, aggregation.parameter, range.unwrap.as_ref()),
+        range.range
+    );
+    if let Some(offset) = &range.offset {
+        description.push_str(&format!(", shifted {offset} into the past"));
+    }
+    if let Some(grouping) = &aggregation.grouping {
+        description = format!("{description}, {}", describe_grouping(grouping));
+    }
+    description.push('.');
+
+    json!({
+        "type": "range_aggregation",
+        "text": aggregation.span.text(source),
+        "operation": aggregation.op.name(),
+        "parameter": aggregation.parameter,
+        "range": range.range,
+        "offset": range.offset,
+        "log": log_node(&range.log, source),
+        "unwrap": range.unwrap.as_ref().map(|unwrap| unwrap_node(unwrap, source)),
+        "grouping": aggregation.grouping.as_ref().map(grouping_node),
+        "description": description,
+    })
+}
+
+fn describe_range_op(op: RangeOp, parameter: Option<f64>, unwrap: Option<&Unwrap>) -> String {
+    let values = unwrap.map_or_else(
+        || "the unwrapped values".to_string(),
+        |unwrap| format!("the `{}` values", unwrap.label),
+    );
+    match op {
+        RangeOp::Rate => "Per-second rate of log lines".to_string(),
+        RangeOp::RateCounter => format!("Per-second rate of {values}, treated as a counter"),
+        RangeOp::CountOverTime => "Number of log lines".to_string(),
+        RangeOp::BytesRate => "Per-second rate of log bytes".to_string(),
+        RangeOp::BytesOverTime => "Total log bytes".to_string(),
+        RangeOp::AbsentOverTime => {
+            "1 when no log lines were found (for alerting on missing logs)".to_string()
+        }
+        RangeOp::SumOverTime => format!("Sum of {values}"),
+        RangeOp::AvgOverTime => format!("Average of {values}"),
+        RangeOp::MaxOverTime => format!("Maximum of {values}"),
+        RangeOp::MinOverTime => format!("Minimum of {values}"),
+        RangeOp::StddevOverTime => format!("Standard deviation of {values}"),
+        RangeOp::StdvarOverTime => format!("Variance of {values}"),
+        RangeOp::QuantileOverTime => format!(
+            "The {} quantile of {values}",
+            parameter.map_or_else(|| "requested".to_string(), format_number)
+        ),
+        RangeOp::FirstOverTime => format!("First of {values}"),
+        RangeOp::LastOverTime => format!("Last of {values}"),
+    }
+}
+
+fn unwrap_node(unwrap: &Unwrap, source: &str) -> Value {
+    let mut description = format!("Uses the `{}` label as the sample value", unwrap.label);
+    if let Some(conversion) = &unwrap.conversion {
+        description.push_str(&format!(", converted with {conversion}()"));
+    }
+    if !unwrap.filters.is_empty() {
+        let filters = unwrap.filters.iter().map(describe_label_filter).collect();
+        description.push_str(&format!(", keeping samples where {}", join_and(filters)));
+    }
+    description.push('.');
+
+    json!({
+        "type": "unwrap",
+        "text": unwrap.span.text(source),
+        "label": unwrap.label,
+        "conversion": unwrap.conversion,
+        "description": description,
+    })
+}
+
+fn vector_node(aggregation: &VectorAggregation, source: &str) -> Value {
+    let what = match aggregation.op {
+        VectorOp::Sum => "Sums".to_string(),
+        VectorOp::Avg => "Averages".to_string(),
+        VectorOp::Min => "Takes the minimum of".to_string(),
+        VectorOp::Max => "Takes the maximum of".to_string(),
+        VectorOp::Count => "Counts".to_string(),
+        VectorOp::Stddev => "Takes the standard deviation of".to_string(),
+        VectorOp::Stdvar => "Takes the variance of".to_string(),
+        VectorOp::Topk => format!(
+            "Keeps the {} largest of",
+            aggregation
+                .parameter
+                .map_or_else(|| "k".to_string(), format_number)
+        ),
+        VectorOp::Bottomk => format!(
+            "Keeps the {} smallest of",
+            aggregation
+                .parameter
+                .map_or_else(|| "k".to_string(), format_number)
+        ),
+        VectorOp::ApproxTopk => format!(
+            "Estimates the {} largest of",
+            aggregation
+                .parameter
+                .map_or_else(|| "k".to_string(), format_number)
+        ),
+        VectorOp::Sort => "Sorts ascending".to_string(),
+        VectorOp::SortDesc => "Sorts descending".to_string(),
+    };
+    let description = match &aggregation.grouping {
+        Some(grouping) => format!("{what} the series, {}.", describe_grouping(grouping)),
+        None if matches!(aggregation.op, VectorOp::Sort | VectorOp::SortDesc) => {
+            format!("{what} the series by value.")
+        }
+        None => format!("{what} the series into one result."),
+    };
+
+    json!({
+        "type": "vector_aggregation",
+        "text": aggregation.span.text(source),
+        "operation": aggregation.op.name(),
+        "parameter": aggregation.parameter,
+        "grouping": aggregation.grouping.as_ref().map(grouping_node),
+        "expr": explain(&aggregation.expr, source),
+        "description": description,
+    })
+}
+
+fn grouping_node(grouping: &Grouping) -> Value {
+    json!({
+        "mode": if grouping.without { "without" } else { "by" },
+        "labels": grouping.labels,
+    })
+}
+
+fn describe_grouping(grouping: &Grouping) -> String {
+    let labels = grouping
+        .labels
+        .iter()
+        .map(|label| format!("`{label}`"))
+        .collect::<Vec<_>>();
+    match (grouping.without, labels.is_empty()) {
+        (false, true) => "into a single series".to_string(),
+        (false, false) => format!("grouped by {}", join_and(labels)),
+        (true, true) => "keeping every label".to_string(),
+        (true, false) => format!("grouped by every label except {}", join_and(labels)),
+    }
+}
+
+fn binary_node(binary: &BinaryExpr, source: &str) -> Value {
+    let verb = match binary.op {
+        BinaryOp::Add => "Adds",
+        BinaryOp::Sub => "Subtracts",
+        BinaryOp::Mul => "Multiplies",
+        BinaryOp::Div => "Divides",
+        BinaryOp::Mod => "Takes the remainder of",
+        BinaryOp::Pow => "Raises",
+        BinaryOp::And => "Keeps left-hand series that also exist on the right",
+        BinaryOp::Or => "Combines series from both sides, preferring the left",
+        BinaryOp::Unless => "Keeps left-hand series that have no match on the right",
+        _ if binary.return_bool => "Compares (returning 0 or 1)",
+        _ => "Filters with",
+    };
+    let mut description = if binary.op.is_set_operator() {
+        verb.to_string()
+    } else {
+        format!("{verb} the two sides with `{}`", binary.op.symbol())
+    };
+    if let Some(matching) = &binary.matching {
+        let labels = join_and(
+            matching
+                .labels
+                .iter()
+                .map(|label| format!("`{label}`"))
+                .collect(),
+        );
+        if matching.on {
+            description.push_str(&format!(", matching series on {labels}"));
+        } else {
+            description.push_str(&format!(", matching series ignoring {labels}"));
+        }
+        if let Some((side, _)) = &matching.group {
+            description.push_str(match side {
+                GroupSide::Left => " (many-to-one)",
+                GroupSide::Right => " (one-to-many)",
+            });
+        }
+    }
+    description.push('.');
+
+    json!({
+        "type": "binary",
+        "text": binary.span.text(source),
+        "operator": binary.op.symbol(),
+        "bool": binary.return_bool,
+        "matching": binary.matching.as_ref().map(|matching| json!({
+            "mode": if matching.on { "on" } else { "ignoring" },
+            "labels": matching.labels,
+            "group": matching.group.as_ref().map(|(side, labels)| json!({
+                "side": match side { GroupSide::Left => "left", GroupSide::Right => "right" },
+                "labels": labels,
+            })),
+        })),
+        "left": explain(&binary.left, source),
+        "right": explain(&binary.right, source),
+        "description": description,
+    })
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+fn join_and(items: Vec<String>) -> String {
+    match items.as_slice() {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
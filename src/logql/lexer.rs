@@ -0,0 +1,501 @@
+use std::time::Duration;
+
+use crate::logql::{ParseError, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    /// A quoted string, already unescaped.
+    String(String),
+    Number(f64),
+    /// A number with a time unit, e.g. `5m`, `1h30m` or `250ms`.
+    Duration(String),
+    /// A number with a size unit, e.g. `20KB` or `1.5MiB`.
+    Bytes(String),
+    /// A parser flag such as `--strict`.
+    Flag(String),
+    LeftBrace,
+    RightBrace,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Pipe,
+    /// `|=`
+    PipeContains,
+    /// `|~`
+    PipeMatch,
+    /// `|>`
+    PipePattern,
+    /// `!>`
+    NotPattern,
+    /// `=`
+    Eq,
+    /// `==`
+    EqEq,
+    /// `!=`
+    NotEq,
+    /// `=~`
+    Match,
+    /// `!~`
+    NotMatch,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Eof,
+}
+
+impl TokenKind {
+    /// How the token reads in error messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Ident(name) => format!("`{name}`"),
+            Self::String(value) => format!("string {value:?}"),
+            Self::Number(value) => format!("number {value}"),
+            Self::Duration(value) => format!("duration {value}"),
+            Self::Bytes(value) => format!("size {value}"),
+            Self::Flag(flag) => format!("flag `--{flag}`"),
+            Self::Eof => "end of query".to_string(),
+            other => format!("`{}`", other.symbol()),
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::LeftBrace => "{",
+            Self::RightBrace => "}",
+            Self::LeftParen => "(",
+            Self::RightParen => ")",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Comma => ",",
+            Self::Pipe => "|",
+            Self::PipeContains => "|=",
+            Self::PipeMatch => "|~",
+            Self::PipePattern => "|>",
+            Self::NotPattern => "!>",
+            Self::Eq => "=",
+            Self::EqEq => "==",
+            Self::NotEq => "!=",
+            Self::Match => "=~",
+            Self::NotMatch => "!~",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::Pow => "^",
+            Self::Ident(_)
+            | Self::String(_)
+            | Self::Number(_)
+            | Self::Duration(_)
+            | Self::Bytes(_)
+            | Self::Flag(_)
+            | Self::Eof => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+const DURATION_UNITS: [&str; 10] = ["ns", "us", "µs", "ms", "s", "m", "h", "d", "w", "y"];
+const BYTES_UNITS: [&str; 13] = [
+    "b", "kb", "kib", "mb", "mib", "gb", "gib", "tb", "tib", "pb", "pib", "eb", "eib",
+];
+
+/// Splits a query into tokens, ending with [`TokenKind::Eof`]. `#` starts a comment that runs
+/// to the end of the line.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer {
+        source,
+        position: 0,
+    };
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        let done = token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.position..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.position += character.len_utf8();
+        Some(character)
+    }
+
+    fn error(&self, message: impl Into<String>, start: usize) -> ParseError {
+        ParseError::new(self.source, message, Span::new(start, self.position))
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(character) = self.peek() {
+            if character.is_whitespace() {
+                self.bump();
+            } else if character == '#' {
+                while self.peek().is_some_and(|character| character != '\n') {
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, ParseError> {
+        self.skip_trivia();
+        let start = self.position;
+        let Some(character) = self.peek() else {
+            return Ok(Token {
+                kind: TokenKind::Eof,
+                span: Span::new(start, start),
+            });
+        };
+
+        let kind = match character {
+            '"' => TokenKind::String(self.quoted_string()?),
+            '`' => TokenKind::String(self.raw_string()?),
+            '0'..='9' => self.number()?,
+            '.' if self.peek_second().is_some_and(|next| next.is_ascii_digit()) => self.number()?,
+            character if character.is_alphabetic() || character == '_' => {
+                TokenKind::Ident(self.identifier())
+            }
+            '-' if self.peek_second() == Some('-') => {
+                self.bump();
+                self.bump();
+                if !self
+                    .peek()
+                    .is_some_and(|next| next.is_alphabetic() || next == '_')
+                {
+                    return Err(self.error("expected a flag name after `--`", start));
+                }
+                let mut flag = String::new();
+                while let Some(next) = self
+                    .peek()
+                    .filter(|next| next.is_alphanumeric() || matches!(next, '_' | '-'))
+                {
+                    flag.push(next);
+                    self.bump();
+                }
+                TokenKind::Flag(flag)
+            }
+            _ => self.symbol(start)?,
+        };
+
+        Ok(Token {
+            kind,
+            span: Span::new(start, self.position),
+        })
+    }
+
+    fn symbol(&mut self, start: usize) -> Result<TokenKind, ParseError> {
+        let character = self.bump().unwrap_or_default();
+        let next = self.peek();
+        let mut take = |kind: TokenKind| {
+            self.bump();
+            kind
+        };
+
+        let kind = match (character, next) {
+            ('|', Some('=')) => take(TokenKind::PipeContains),
+            ('|', Some('~')) => take(TokenKind::PipeMatch),
+            ('|', Some('>')) => take(TokenKind::PipePattern),
+            ('|', _) => TokenKind::Pipe,
+            ('!', Some('=')) => take(TokenKind::NotEq),
+            ('!', Some('~')) => take(TokenKind::NotMatch),
+            ('!', Some('>')) => take(TokenKind::NotPattern),
+            ('=', Some('=')) => take(TokenKind::EqEq),
+            ('=', Some('~')) => take(TokenKind::Match),
+            ('=', _) => TokenKind::Eq,
+            ('>', Some('=')) => take(TokenKind::Gte),
+            ('>', _) => TokenKind::Gt,
+            ('<', Some('=')) => take(TokenKind::Lte),
+            ('<', _) => TokenKind::Lt,
+            ('{', _) => TokenKind::LeftBrace,
+            ('}', _) => TokenKind::RightBrace,
+            ('(', _) => TokenKind::LeftParen,
+            (')', _) => TokenKind::RightParen,
+            ('[', _) => TokenKind::LeftBracket,
+            (']', _) => TokenKind::RightBracket,
+            (',', _) => TokenKind::Comma,
+            ('+', _) => TokenKind::Add,
+            ('-', _) => TokenKind::Sub,
+            ('*', _) => TokenKind::Mul,
+            ('/', _) => TokenKind::Div,
+            ('%', _) => TokenKind::Mod,
+            ('^', _) => TokenKind::Pow,
+            ('!', _) => {
+                return Err(self.error("unexpected `!`; expected `!=`, `!~` or `!>`", start));
+            }
+            (other, _) => return Err(self.error(format!("unexpected character `{other}`"), start)),
+        };
+
+        Ok(kind)
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut identifier = String::new();
+        while let Some(character) = self
+            .peek()
+            .filter(|character| character.is_alphanumeric() || *character == '_')
+        {
+            identifier.push(character);
+            self.bump();
+        }
+        identifier
+    }
+
+    /// A number, or a duration or size when a unit follows it directly.
+    fn number(&mut self) -> Result<TokenKind, ParseError> {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|character| character.is_alphanumeric() || matches!(character, '.' | 'µ'))
+        {
+            self.bump();
+        }
+        let text = &self.source[start..self.position];
+
+        if let Ok(value) = text.parse::<f64>()
+            && text
+                .chars()
+                .all(|character| character.is_ascii_digit() || character == '.')
+        {
+            return Ok(TokenKind::Number(value));
+        }
+        if is_duration(text) {
+            return Ok(TokenKind::Duration(text.to_string()));
+        }
+        if is_bytes(text) {
+            return Ok(TokenKind::Bytes(text.to_string()));
+        }
+
+        Err(self.error(
+            format!(
+                "invalid number `{text}`; durations use units like 5m or 1h30m and sizes units like 20KB"
+            ),
+            start,
+        ))
+    }
+
+    fn quoted_string(&mut self) -> Result<String, ParseError> {
+        let start = self.position;
+        self.bump();
+        let mut value = String::new();
+        loop {
+            let Some(character) = self.bump() else {
+                return Err(self.error("unterminated string", start));
+            };
+            match character {
+                '"' => return Ok(value),
+                '\n' => return Err(self.error("unterminated string", start)),
+                '\\' => value.push(self.escape()?),
+                character => value.push(character),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let start = self.position - 1;
+        let Some(character) = self.bump() else {
+            return Err(self.error("unterminated string", start));
+        };
+        let escaped = match character {
+            '"' => '"',
+            '\\' => '\\',
+            '\'' => '\'',
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'a' => '\u{7}',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'v' => '\u{b}',
+            'x' => self.hex_escape(2, start)?,
+            'u' => self.hex_escape(4, start)?,
+            'U' => self.hex_escape(8, start)?,
+            other => {
+                return Err(self.error(
+                    format!(
+                        "unknown escape sequence `\\{other}`; write `\\\\{other}` or use a backtick string"
+                    ),
+                    start,
+                ));
+            }
+        };
+
+        Ok(escaped)
+    }
+
+    fn hex_escape(&mut self, digits: usize, start: usize) -> Result<char, ParseError> {
+        let mut code = 0_u32;
+        for _ in 0..digits {
+            let digit = self.bump().and_then(|character| character.to_digit(16));
+            let Some(digit) = digit else {
+                return Err(self.error("invalid hexadecimal escape sequence", start));
+            };
+            code = code * 16 + digit;
+        }
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape sequence", start))
+    }
+
+    fn raw_string(&mut self) -> Result<String, ParseError> {
+        let start = self.position;
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => return Ok(value),
+                Some(character) => value.push(character),
+                None => return Err(self.error("unterminated backtick string", start)),
+            }
+        }
+    }
+}
+
+/// `5m`, `1h30m`, `1.5s`: one or more number-unit pairs.
+fn is_duration(text: &str) -> bool {
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|character: char| !(character.is_ascii_digit() || character == '.'))
+            .unwrap_or(rest.len());
+        if digits == 0 || rest[..digits].parse::<f64>().is_err() {
+            return false;
+        }
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|character: char| character.is_ascii_digit() || character == '.')
+            .unwrap_or(rest.len());
+        if !DURATION_UNITS.contains(&&rest[..unit]) {
+            return false;
+        }
+        rest = &rest[unit..];
+    }
+
+    !text.is_empty()
+}
+
+/// The length of a LogQL duration such as `5m`, `1h30m` or `250ms`. `y` is 365 days.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if !is_duration(text) {
+        return None;
+    }
+
+    let mut total = 0.0_f64;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|character: char| !(character.is_ascii_digit() || character == '.'))
+            .unwrap_or(rest.len());
+        let amount = rest[..digits].parse::<f64>().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|character: char| character.is_ascii_digit() || character == '.')
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit] {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3_600.0,
+            "d" => 86_400.0,
+            "w" => 604_800.0,
+            _ => 31_536_000.0,
+        };
+        total += amount * seconds;
+        rest = &rest[unit..];
+    }
+
+    Duration::try_from_secs_f64(total).ok()
+}
+
+fn is_bytes(text: &str) -> bool {
+    let digits = text
+        .find(|character: char| !(character.is_ascii_digit() || character == '.'))
+        .unwrap_or(text.len());
+    digits > 0
+        && text[..digits].parse::<f64>().is_ok()
+        && BYTES_UNITS.contains(&text[digits..].to_ascii_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logql::lexer::{TokenKind, tokenize};
+
+    #[test]
+    fn tokenizes_operators_strings_and_units() {
+        let kinds = tokenize(r#"{app="a"} |= "x|=y" != `\d` | size > 1.5KiB # note"#)
+            .expect("valid")
+            .into_iter()
+            .map(|token| token.kind)
+            .collect::<Vec<TokenKind>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LeftBrace,
+                TokenKind::Ident("app".to_string()),
+                TokenKind::Eq,
+                TokenKind::String("a".to_string()),
+                TokenKind::RightBrace,
+                TokenKind::PipeContains,
+                TokenKind::String("x|=y".to_string()),
+                TokenKind::NotEq,
+                TokenKind::String("\\d".to_string()),
+                TokenKind::Pipe,
+                TokenKind::Ident("size".to_string()),
+                TokenKind::Gt,
+                TokenKind::Bytes("1.5KiB".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+
+        let durations = tokenize("[1h30m] 250ms 2")
+            .expect("valid")
+            .into_iter()
+            .map(|token| token.kind)
+            .collect::<Vec<TokenKind>>();
+        assert_eq!(durations[1], TokenKind::Duration("1h30m".to_string()));
+        assert_eq!(durations[3], TokenKind::Duration("250ms".to_string()));
+        assert_eq!(durations[4], TokenKind::Number(2.0));
+
+        let error = tokenize(r#"{app="a"} |~ "\d+""#).expect_err("bad escape");
+        assert_eq!((error.line, error.column), (1, 15));
+        assert!(error.message.contains("unknown escape sequence `\\d`"));
+    }
+}
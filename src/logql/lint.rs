@@ -0,0 +1,548 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::logql::{
+    Span,
+    ast::{
+        CompareOp, Expr, FilterValue, LabelFilter, LabelFormatOp, LineFilterOp, LineFilterValue,
+        LogExpr, MatchOp, Matcher, ParserStage, RangeOp, StageKind, Unwrap,
+    },
+};
+
+/// Labels Loki adds to every pipeline, so filtering on them never needs a parser.
+const BUILTIN_LABELS: [&str; 3] = ["__error__", "__error_details__", "__stream_shard__"];
+const WILDCARD_REGEXES: [&str; 4] = [".*", ".+", ".*?", ".+?"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A lint rule: a stable id, its severity and what it catches.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub summary: &'static str,
+}
+
+pub const EMPTY_COMPATIBLE_SELECTOR: Rule = Rule {
+    id: "empty-compatible-selector",
+    severity: Severity::Error,
+    summary: "every selector matcher also matches an empty label, which Loki rejects",
+};
+pub const BROAD_SELECTOR: Rule = Rule {
+    id: "broad-selector",
+    severity: Severity::Warning,
+    summary: "the selector has no specific matcher, so it reads every stream with the label",
+};
+pub const UNKNOWN_LABEL: Rule = Rule {
+    id: "unknown-label",
+    severity: Severity::Warning,
+    summary: "a selector label is not in the configured labels schema",
+};
+pub const LITERAL_REGEX: Rule = Rule {
+    id: "literal-regex",
+    severity: Severity::Warning,
+    summary: "a regex contains no regex syntax and could be a cheaper exact or substring match",
+};
+pub const UNANCHORED_WILDCARD: Rule = Rule {
+    id: "unanchored-wildcard",
+    severity: Severity::Info,
+    summary: "a line filter regex starts or ends with `.*`, which line filters already imply",
+};
+pub const PARSER_BEFORE_LINE_FILTER: Rule = Rule {
+    id: "parser-before-line-filter",
+    severity: Severity::Warning,
+    summary: "a line filter runs after a parser, so every line is parsed before it is dropped",
+};
+pub const UNEXTRACTED_LABEL: Rule = Rule {
+    id: "unextracted-label",
+    severity: Severity::Warning,
+    summary: "a label filter or unwrap names a label nothing in the query extracts",
+};
+pub const MISSING_ERROR_FILTER: Rule = Rule {
+    id: "missing-error-filter",
+    severity: Severity::Info,
+    summary: "an unwrap has no `__error__=\"\"` filter, so one unparsable value fails the query",
+};
+pub const QUANTILE_OUT_OF_RANGE: Rule = Rule {
+    id: "quantile-out-of-range",
+    severity: Severity::Error,
+    summary: "the quantile of quantile_over_time is outside 0..=1",
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+    pub span: Span,
+}
+
+/// The configured label names. Empty slices disable the checks that need them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LabelSchema<'a> {
+    pub stream_labels: &'a [String],
+    pub structured_metadata: &'a [String],
+}
+
+impl LabelSchema<'_> {
+    fn knows(&self, label: &str) -> bool {
+        self.stream_labels
+            .iter()
+            .chain(self.structured_metadata)
+            .any(|known| known == label)
+    }
+}
+
+/// Runs every rule over a parsed query; findings are ordered by position.
+pub fn lint(expr: &Expr, schema: LabelSchema<'_>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let ranges = expr.range_aggregations();
+
+    for log in expr.log_exprs() {
+        let unwrap = ranges
+            .iter()
+            .find(|range| std::ptr::eq(&range.range.log, log))
+            .and_then(|range| range.range.unwrap.as_ref());
+        lint_selector(log, schema, &mut findings);
+        lint_pipeline(log, unwrap, schema, &mut findings);
+    }
+    for range in &ranges {
+        if let (RangeOp::QuantileOverTime, Some(quantile)) = (range.op, range.parameter)
+            && !(0.0..=1.0).contains(&quantile)
+        {
+            findings.push(finding(
+                QUANTILE_OUT_OF_RANGE,
+                format!("quantile {quantile} is outside 0..=1"),
+                Some("use a value such as 0.99 for the 99th percentile".to_string()),
+                range.span,
+            ));
+        }
+    }
+
+    findings.sort_by_key(|finding| (finding.span.start, finding.span.end));
+    findings
+}
+
+fn lint_selector(log: &LogExpr, schema: LabelSchema<'_>, findings: &mut Vec<Finding>) {
+    let matchers = &log.selector.matchers;
+    if matchers.iter().all(matches_empty) {
+        findings.push(finding(
+            EMPTY_COMPATIBLE_SELECTOR,
+            "every matcher also matches streams without the label, so Loki rejects the selector"
+                .to_string(),
+            Some("add an equality matcher such as app=\"...\"".to_string()),
+            log.selector.span,
+        ));
+    } else if !matchers.iter().any(is_specific) {
+        findings.push(finding(
+            BROAD_SELECTOR,
+            "no matcher narrows the selector to specific streams, so the query reads every stream carrying the label"
+                .to_string(),
+            Some("match an exact value, e.g. namespace=\"payments\", or a short regex alternation".to_string()),
+            log.selector.span,
+        ));
+    }
+
+    for matcher in matchers {
+        if !schema.stream_labels.is_empty()
+            && !schema
+                .stream_labels
+                .iter()
+                .any(|label| label == &matcher.label)
+        {
+            findings.push(finding(
+                UNKNOWN_LABEL,
+                format!(
+                    "`{}` is not a configured stream label; known labels are {}",
+                    matcher.label,
+                    schema.stream_labels.join(", ")
+                ),
+                None,
+                matcher.span,
+            ));
+        }
+        if matches!(matcher.op, MatchOp::Regex | MatchOp::NotRegex)
+            && let Some(literals) = regex_literals(&matcher.value)
+            && literals.len() == 1
+        {
+            let op = if matcher.op == MatchOp::Regex {
+                "="
+            } else {
+                "!="
+            };
+            findings.push(finding(
+                LITERAL_REGEX,
+                format!("`{}` uses a regex for an exact value", matcher.label),
+                Some(format!("{}{op}{:?}", matcher.label, literals[0])),
+                matcher.span,
+            ));
+        }
+    }
+}
+
+fn lint_pipeline(
+    log: &LogExpr,
+    unwrap: Option<&Unwrap>,
+    schema: LabelSchema<'_>,
+    findings: &mut Vec<Finding>,
+) {
+    let mut available: HashSet<&str> = log
+        .selector
+        .matchers
+        .iter()
+        .map(|matcher| matcher.label.as_str())
+        .collect();
+    // Set once a parser may extract any key of the line.
+    let mut extracts_everything = false;
+    let mut parser_seen = None;
+    let mut line_rewritten = false;
+
+    for stage in &log.pipeline {
+        match &stage.kind {
+            StageKind::LineFilter(filter) => {
+                lint_line_filter(filter.op, &filter.values, findings);
+                if let Some(parser) = parser_seen
+                    && !line_rewritten
+                {
+                    findings.push(finding(
+                        PARSER_BEFORE_LINE_FILTER,
+                        format!(
+                            "this line filter runs after `| {parser}`, so every line is parsed before being filtered"
+                        ),
+                        Some("move line filters directly after the stream selector".to_string()),
+                        stage.span,
+                    ));
+                }
+            }
+            StageKind::Parser(parser) => {
+                parser_seen.get_or_insert(parser.name());
+                match parser {
+                    ParserStage::Json(params) | ParserStage::Logfmt { params, .. } => {
+                        extracts_everything |= params.is_empty();
+                        available.extend(params.iter().map(|param| param.label.as_str()));
+                    }
+                    ParserStage::Regexp { expression } => {
+                        available.extend(regexp_captures(expression));
+                    }
+                    ParserStage::Pattern { expression } => {
+                        available.extend(pattern_captures(expression));
+                    }
+                    ParserStage::Unpack => extracts_everything = true,
+                }
+            }
+            StageKind::LabelFilter(filter) => {
+                lint_label_filter(filter, &available, extracts_everything, schema, findings);
+            }
+            StageKind::LineFormat { .. } => line_rewritten = true,
+            StageKind::LabelFormat(operations) => {
+                available.extend(operations.iter().map(|operation| match operation {
+                    LabelFormatOp::Rename { label, .. } | LabelFormatOp::Template { label, .. } => {
+                        label.as_str()
+                    }
+                }));
+            }
+            StageKind::Decolorize
+            | StageKind::Drop(_)
+            | StageKind::Keep(_)
+            | StageKind::Distinct(_) => {}
+        }
+    }
+
+    let Some(unwrap) = unwrap else {
+        return;
+    };
+    if !extracts_everything
+        && !available.contains(unwrap.label.as_str())
+        && !schema.knows(&unwrap.label)
+    {
+        findings.push(unextracted(&unwrap.label, unwrap.span));
+    }
+    for filter in &unwrap.filters {
+        lint_label_filter(filter, &available, extracts_everything, schema, findings);
+    }
+    let filters_errors = unwrap
+        .filters
+        .iter()
+        .flat_map(LabelFilter::comparisons)
+        .any(|comparison| comparison.label == "__error__");
+    if !filters_errors {
+        findings.push(finding(
+            MISSING_ERROR_FILTER,
+            format!(
+                "values of `{}` that are not numbers make the whole query fail",
+                unwrap.label
+            ),
+            Some("add `| __error__=\"\"` after the unwrap to skip them".to_string()),
+            unwrap.span,
+        ));
+    }
+}
+
+fn lint_line_filter(op: LineFilterOp, values: &[LineFilterValue], findings: &mut Vec<Finding>) {
+    if !matches!(op, LineFilterOp::Regex | LineFilterOp::NotRegex) {
+        return;
+    }
+
+    for value in values {
+        let LineFilterValue::Text { value, span } = value else {
+            continue;
+        };
+        let substring_op = if op == LineFilterOp::Regex {
+            "|="
+        } else {
+            "!="
+        };
+        if let Some(literals) = regex_literals(value) {
+            let alternatives = literals
+                .iter()
+                .map(|literal| format!("{literal:?}"))
+                .collect::<Vec<_>>()
+                .join(" or ");
+            findings.push(finding(
+                LITERAL_REGEX,
+                format!("{value:?} has no regex syntax; a substring filter is much cheaper"),
+                Some(format!("{substring_op} {alternatives}")),
+                *span,
+            ));
+        } else if value.starts_with(".*") || (value.ends_with(".*") && !value.ends_with("\\.*")) {
+            let trimmed = value.trim_start_matches(".*");
+            let trimmed = trimmed.strip_suffix(".*").unwrap_or(trimmed);
+            findings.push(finding(
+                UNANCHORED_WILDCARD,
+                "line filter regexes match anywhere in the line, so a leading or trailing `.*` only adds work"
+                    .to_string(),
+                Some(format!("{} {trimmed:?}", op.symbol())),
+                *span,
+            ));
+        }
+    }
+}
+
+fn lint_label_filter(
+    filter: &LabelFilter,
+    available: &HashSet<&str>,
+    extracts_everything: bool,
+    schema: LabelSchema<'_>,
+    findings: &mut Vec<Finding>,
+) {
+    for comparison in filter.comparisons() {
+        let label = comparison.label.as_str();
+        if !extracts_everything
+            && !available.contains(label)
+            && !BUILTIN_LABELS.contains(&label)
+            && !schema.knows(label)
+        {
+            findings.push(unextracted(label, comparison.span));
+        }
+        if matches!(comparison.op, CompareOp::Regex | CompareOp::NotRegex)
+            && let FilterValue::String(value) = &comparison.value
+            && let Some(literals) = regex_literals(value)
+            && literals.len() == 1
+        {
+            let op = if comparison.op == CompareOp::Regex {
+                "="
+            } else {
+                "!="
+            };
+            findings.push(finding(
+                LITERAL_REGEX,
+                format!("`{label}` uses a regex for an exact value"),
+                Some(format!("{label}{op}{:?}", literals[0])),
+                comparison.span,
+            ));
+        }
+    }
+}
+
+fn unextracted(label: &str, span: Span) -> Finding {
+    finding(
+        UNEXTRACTED_LABEL,
+        format!(
+            "`{label}` is not a stream label and no parser before this stage extracts it, so the filter never matches"
+        ),
+        Some(format!(
+            "add a parser such as `| json` or `| logfmt` before filtering on `{label}`"
+        )),
+        span,
+    )
+}
+
+fn finding(rule: Rule, message: String, suggestion: Option<String>, span: Span) -> Finding {
+    Finding {
+        rule: rule.id,
+        severity: rule.severity,
+        message,
+        suggestion,
+        span,
+    }
+}
+
+/// Whether a stream matcher also selects streams that lack the label.
+fn matches_empty(matcher: &Matcher) -> bool {
+    let empty_regex = |value: &str| value.is_empty() || value == ".*" || value == ".*?";
+    match matcher.op {
+        MatchOp::Eq => matcher.value.is_empty(),
+        MatchOp::NotEq => !matcher.value.is_empty(),
+        MatchOp::Regex => empty_regex(&matcher.value),
+        MatchOp::NotRegex => !empty_regex(&matcher.value),
+    }
+}
+
+fn is_specific(matcher: &Matcher) -> bool {
+    match matcher.op {
+        MatchOp::Eq => !matcher.value.is_empty(),
+        MatchOp::Regex => !WILDCARD_REGEXES.contains(&matcher.value.as_str()),
+        MatchOp::NotEq | MatchOp::NotRegex => false,
+    }
+}
+
+/// The literal alternatives of a regex made only of escaped characters and top-level `|`,
+/// e.g. `timeout|refused` or `10\.0\.0\.1`.
+fn regex_literals(regex: &str) -> Option<Vec<String>> {
+    if regex.is_empty() {
+        return None;
+    }
+
+    let mut literals = vec![String::new()];
+    let mut chars = regex.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => {
+                    literals.last_mut()?.push(escaped);
+                }
+                _ => return None,
+            },
+            '|' => literals.push(String::new()),
+            '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '^' | '$' => return None,
+            _ => literals.last_mut()?.push(ch),
+        }
+    }
+
+    (!literals.iter().any(String::is_empty)).then_some(literals)
+}
+
+fn regexp_captures(expression: &str) -> impl Iterator<Item = &str> {
+    expression
+        .split("(?P<")
+        .skip(1)
+        .chain(expression.split("(?<").skip(1))
+        .filter_map(|rest| rest.split_once('>').map(|(name, _)| name))
+}
+
+fn pattern_captures(expression: &str) -> impl Iterator<Item = &str> {
+    expression
+        .split('<')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>').map(|(name, _)| name))
+        .filter(|name| *name != "_" && !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LabelSchema, Severity, lint};
+    use crate::logql::parse;
+
+    fn rules(query: &str, stream_labels: &[String]) -> Vec<(&'static str, Severity, String)> {
+        let expr = parse(query).expect("query should parse");
+        lint(
+            &expr,
+            LabelSchema {
+                stream_labels,
+                structured_metadata: &[],
+            },
+        )
+        .into_iter()
+        .map(|finding| {
+            (
+                finding.rule,
+                finding.severity,
+                finding.span.text(query).to_string(),
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn flags_expensive_and_incorrect_patterns() {
+        let schema = ["app".to_string(), "namespace".to_string()];
+        let query = r#"sum by (namespace) (count_over_time({namespace=~".+", team!="core"} | json |~ "timeout|refused" | status >= 500 [5m]))"#;
+        assert_eq!(
+            rules(query, &schema),
+            [
+                (
+                    "broad-selector",
+                    Severity::Warning,
+                    r#"{namespace=~".+", team!="core"}"#.to_string()
+                ),
+                (
+                    "unknown-label",
+                    Severity::Warning,
+                    r#"team!="core""#.to_string()
+                ),
+                (
+                    "parser-before-line-filter",
+                    Severity::Warning,
+                    r#"|~ "timeout|refused""#.to_string()
+                ),
+                (
+                    "literal-regex",
+                    Severity::Warning,
+                    r#""timeout|refused""#.to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            rules(r#"{app=~".*", env!="dev"}"#, &[]),
+            [(
+                "empty-compatible-selector",
+                Severity::Error,
+                r#"{app=~".*", env!="dev"}"#.to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn tracks_which_labels_the_pipeline_extracts() {
+        assert_eq!(
+            rules(r#"{app="api"} | latency > 1s"#, &[]),
+            [(
+                "unextracted-label",
+                Severity::Warning,
+                "latency > 1s".to_string()
+            )]
+        );
+        assert!(rules(r#"{app="api"} | logfmt | latency > 1s"#, &[]).is_empty());
+        assert!(
+            rules(
+                r#"{app="api"} | regexp "(?P<latency>\\d+)ms" | pattern "<_> <method> <_>" | latency > 1 and method="GET""#,
+                &[]
+            )
+            .is_empty()
+        );
+
+        let findings = rules(
+            r#"quantile_over_time(1.5, {app="api"} | json status | unwrap latency [5m]) by (app)"#,
+            &[],
+        );
+        assert_eq!(
+            findings
+                .iter()
+                .map(|(rule, _, _)| *rule)
+                .collect::<Vec<_>>(),
+            [
+                "quantile-out-of-range",
+                "unextracted-label",
+                "missing-error-filter"
+            ]
+        );
+    }
+}
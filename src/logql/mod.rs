@@ -0,0 +1,85 @@
+//! LogQL lexer, parser and typed syntax tree.
+//!
+//! [`parse`] turns a query into an [`Expr`]; syntax errors carry the byte offset, line and
+//! column of the offending token. [`explain`] renders a parsed query as a JSON tree with a
+//! plain-language description of every node. [`lint::lint`] flags expensive or incorrect patterns.
+
+pub mod ast;
+mod explain;
+mod lexer;
+pub mod lint;
+mod parser;
+
+use thiserror::Error;
+
+pub use ast::*;
+pub use explain::explain;
+pub use lexer::parse_duration;
+
+/// Parses a complete LogQL log or metric query.
+pub fn parse(query: &str) -> Result<Expr, ParseError> {
+    parser::Parser::new(query)?.parse_query()
+}
+
+/// A byte range in the parsed query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The smallest span covering both.
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn text(self, source: &str) -> &str {
+        source.get(self.start..self.end).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at line {line}, column {column}")]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    /// 1-based.
+    pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+    /// The offending line with a caret under the error.
+    pub excerpt: String,
+}
+
+impl ParseError {
+    pub fn new(source: &str, message: impl Into<String>, span: Span) -> Self {
+        let offset = span.start.min(source.len());
+        let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |index| offset + index);
+        let column = source[line_start..offset].chars().count() + 1;
+        let width = source
+            .get(offset..span.end.min(line_end))
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+
+        Self {
+            message: message.into(),
+            span,
+            line: source[..offset].matches('\n').count() + 1,
+            column,
+            excerpt: format!(
+                "{}\n{}{}",
+                &source[line_start..line_end],
+                " ".repeat(column - 1),
+                "^".repeat(width)
+            ),
+        }
+    }
+}
@@ -0,0 +1,1185 @@
+use crate::logql::{
+    ParseError, Span,
+    ast::{
+        BinaryExpr, BinaryOp, CompareOp, Expr, ExtractParam, FilterValue, GroupSide, Grouping,
+        LabelComparison, LabelFilter, LabelFormatOp, LabelReplace, LabelSelection, LineFilter,
+        LineFilterOp, LineFilterValue, LogExpr, LogRange, MatchOp, Matcher, ParserStage,
+        RangeAggregation, RangeOp, Selector, Stage, StageKind, Unwrap, VectorAggregation,
+        VectorMatching, VectorOp,
+    },
+    lexer::{Token, TokenKind, tokenize},
+};
+
+const LOGFMT_FLAGS: [&str; 2] = ["strict", "keep-empty"];
+const UNWRAP_CONVERSIONS: [&str; 3] = ["bytes", "duration", "duration_seconds"];
+
+/// Recursive-descent parser over the token stream; binary operators use precedence climbing.
+pub struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Result<Self, ParseError> {
+        Ok(Self {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+        })
+    }
+
+    pub fn parse_query(mut self) -> Result<Expr, ParseError> {
+        if self.peek() == &TokenKind::Eof {
+            return Err(self.error_here("query is empty"));
+        }
+
+        let expr = self.parse_binary(0)?;
+        match self.peek() {
+            TokenKind::Eof => Ok(expr),
+            TokenKind::Eq => Err(self.error_here("use `==` to compare values")),
+            TokenKind::LeftBracket if expr.is_log() => Err(self.error_here(
+                "a range like `[5m]` is only valid inside a range aggregation such as count_over_time(...)",
+            )),
+            _ => Err(self.unexpected("an operator or the end of the query")),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_binary_op() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.advance();
+
+            let return_bool = self.eat_keyword("bool");
+            if return_bool && !op.is_comparison() {
+                return Err(self.error(
+                    "`bool` only applies to comparison operators",
+                    self.previous_span(),
+                ));
+            }
+            let matching = self.parse_vector_matching()?;
+            // `^` is right-associative; everything else groups to the left.
+            let next_precedence = if op == BinaryOp::Pow {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let right = self.parse_binary(next_precedence)?;
+
+            for side in [&left, &right] {
+                if let Expr::Log(log) = side {
+                    return Err(self.error(
+                        format!(
+                            "`{}` needs metric operands; wrap the log query in a range aggregation such as count_over_time(... [5m])",
+                            op.symbol()
+                        ),
+                        log.span,
+                    ));
+                }
+            }
+            if op.is_set_operator()
+                && let Some(scalar) = [&left, &right]
+                    .into_iter()
+                    .find(|side| matches!(side, Expr::Literal { .. }))
+            {
+                return Err(self.error(
+                    format!("`{}` needs vector operands, not numbers", op.symbol()),
+                    scalar.span(),
+                ));
+            }
+
+            let span = left.span().to(right.span());
+            left = Expr::Binary(BinaryExpr {
+                op,
+                return_bool,
+                matching,
+                left: Box::new(left),
+                right: Box::new(right),
+                span,
+            });
+        }
+
+        Ok(left)
+    }
+
+    fn peek_binary_op(&self) -> Option<BinaryOp> {
+        let op = match self.peek() {
+            TokenKind::Add => BinaryOp::Add,
+            TokenKind::Sub => BinaryOp::Sub,
+            TokenKind::Mul => BinaryOp::Mul,
+            TokenKind::Div => BinaryOp::Div,
+            TokenKind::Mod => BinaryOp::Mod,
+            TokenKind::Pow => BinaryOp::Pow,
+            TokenKind::EqEq => BinaryOp::Eq,
+            TokenKind::NotEq => BinaryOp::NotEq,
+            TokenKind::Gt => BinaryOp::Gt,
+            TokenKind::Gte => BinaryOp::Gte,
+            TokenKind::Lt => BinaryOp::Lt,
+            TokenKind::Lte => BinaryOp::Lte,
+            TokenKind::Ident(name) => match name.as_str() {
+                "and" => BinaryOp::And,
+                "or" => BinaryOp::Or,
+                "unless" => BinaryOp::Unless,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    fn parse_vector_matching(&mut self) -> Result<Option<VectorMatching>, ParseError> {
+        let on = match self.peek() {
+            TokenKind::Ident(name) if name == "on" => true,
+            TokenKind::Ident(name) if name == "ignoring" => false,
+            _ => return Ok(None),
+        };
+        self.advance();
+        let labels = self.parse_label_list()?;
+
+        let side = match self.peek() {
+            TokenKind::Ident(name) if name == "group_left" => Some(GroupSide::Left),
+            TokenKind::Ident(name) if name == "group_right" => Some(GroupSide::Right),
+            _ => None,
+        };
+        let group = match side {
+            Some(side) => {
+                self.advance();
+                let labels = if self.check(&TokenKind::LeftParen) {
+                    self.parse_label_list()?
+                } else {
+                    Vec::new()
+                };
+                Some((side, labels))
+            }
+            None => None,
+        };
+
+        Ok(Some(VectorMatching { on, labels, group }))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if !matches!(self.peek(), TokenKind::Add | TokenKind::Sub) {
+            return self.parse_primary();
+        }
+
+        let sign = self.advance();
+        match self.peek().clone() {
+            TokenKind::Number(value) => {
+                let number = self.advance();
+                let value = if sign.kind == TokenKind::Sub {
+                    -value
+                } else {
+                    value
+                };
+                Ok(Expr::Literal {
+                    value,
+                    span: sign.span.to(number.span),
+                })
+            }
+            _ => Err(self.error(
+                format!(
+                    "unary `{}` is only supported before a number",
+                    sign.kind.describe().trim_matches('`')
+                ),
+                sign.span,
+            )),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek().clone() {
+            TokenKind::LeftParen => {
+                self.advance();
+                let inner = self.parse_binary(0)?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                Ok(inner)
+            }
+            TokenKind::LeftBrace => {
+                let log = self.parse_log_expr()?;
+                Ok(Expr::Log(log))
+            }
+            TokenKind::Number(value) => {
+                let span = self.advance().span;
+                Ok(Expr::Literal { value, span })
+            }
+            TokenKind::Ident(name) => {
+                if let Some(op) = RangeOp::from_name(&name) {
+                    return self.parse_range_aggregation(op);
+                }
+                if let Some(op) = VectorOp::from_name(&name) {
+                    return self.parse_vector_aggregation(op);
+                }
+                match name.as_str() {
+                    "vector" => self.parse_vector(),
+                    "label_replace" => self.parse_label_replace(),
+                    _ if self.peek_nth(1) == &TokenKind::LeftParen => {
+                        Err(self.error_here(format!("unknown function `{name}`")))
+                    }
+                    _ => Err(self.error_here(format!(
+                        "unexpected `{name}`; expected a stream selector `{{...}}`, a number or a function such as rate or sum"
+                    ))),
+                }
+            }
+            TokenKind::Eof => {
+                Err(self.error_here("unexpected end of query; expected an expression"))
+            }
+            _ => Err(self.unexpected("a stream selector `{...}`, a number or a function")),
+        }
+    }
+
+    fn parse_range_aggregation(&mut self, op: RangeOp) -> Result<Expr, ParseError> {
+        let name = self.advance().span;
+        self.expect(&TokenKind::LeftParen, &format!("`(` after {}", op.name()))?;
+        let parameter = if op == RangeOp::QuantileOverTime {
+            let quantile = self.expect_number("the quantile, e.g. 0.99")?;
+            self.expect(&TokenKind::Comma, "`,` after the quantile")?;
+            Some(quantile)
+        } else {
+            None
+        };
+        let range = self.parse_log_range(op)?;
+        self.expect(&TokenKind::RightParen, "`)`")?;
+        let grouping_start = self.peek_token().span;
+        let grouping = self.parse_grouping()?;
+
+        if grouping.is_some() && !op.requires_unwrap() {
+            return Err(self.error(
+                format!(
+                    "`{}` does not take by/without; group with an outer aggregation such as sum by (...)",
+                    op.name()
+                ),
+                grouping_start,
+            ));
+        }
+        match &range.unwrap {
+            None if op.requires_unwrap() => {
+                return Err(self.error(
+                    format!(
+                        "`{}` needs an `| unwrap <label>` stage before the range",
+                        op.name()
+                    ),
+                    name,
+                ));
+            }
+            Some(unwrap) if !op.allows_unwrap() => {
+                return Err(self.error(
+                    format!(
+                        "`{}` counts lines and cannot use unwrap; use an aggregation such as sum_over_time",
+                        op.name()
+                    ),
+                    unwrap.span,
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(Expr::RangeAggregation(RangeAggregation {
+            op,
+            parameter,
+            range,
+            grouping,
+            span: name.to(self.previous_span()),
+        }))
+    }
+
+    fn parse_log_range(&mut self, op: RangeOp) -> Result<LogRange, ParseError> {
+        if !self.check(&TokenKind::LeftBrace) {
+            return Err(self.unexpected(&format!(
+                "a stream selector `{{...}}` inside {}(...)",
+                op.name()
+            )));
+        }
+        let selector = self.parse_selector()?;
+        let mut pipeline = Vec::new();
+        let mut unwrap = None;
+        self.parse_pipeline(&mut pipeline, &mut unwrap, true)?;
+
+        if !self.check(&TokenKind::LeftBracket) {
+            return Err(self.unexpected("a range such as `[5m]` after the log query"));
+        }
+        let open = self.advance().span;
+        let range = self.expect_duration("a range such as 5m")?;
+        self.expect(&TokenKind::RightBracket, "`]`")?;
+        let offset = if self.eat_keyword("offset") {
+            Some(self.expect_duration("an offset such as 1h")?)
+        } else {
+            None
+        };
+        // Loki also accepts the pipeline after the range: `{app="api"}[5m] | json`.
+        if pipeline.is_empty() && unwrap.is_none() {
+            self.parse_pipeline(&mut pipeline, &mut unwrap, true)?;
+        }
+
+        let log_span = pipeline
+            .last()
+            .map_or(selector.span, |stage: &Stage| selector.span.to(stage.span));
+        let span = selector.span.to(self.previous_span()).to(open);
+
+        Ok(LogRange {
+            log: LogExpr {
+                selector,
+                pipeline,
+                span: log_span,
+            },
+            unwrap,
+            range,
+            offset,
+            span,
+        })
+    }
+
+    fn parse_vector_aggregation(&mut self, op: VectorOp) -> Result<Expr, ParseError> {
+        let name = self.advance().span;
+        let mut grouping = self.parse_grouping()?;
+        self.expect(&TokenKind::LeftParen, &format!("`(` after {}", op.name()))?;
+        let parameter = if op.takes_parameter() {
+            let count = self.expect_number("the number of series to keep")?;
+            self.expect(&TokenKind::Comma, "`,` after the series count")?;
+            Some(count)
+        } else {
+            None
+        };
+
+        let expr = self.parse_binary(0)?;
+        if let Expr::Log(log) = &expr {
+            return Err(self.error(
+                format!(
+                    "`{}` aggregates metric results; wrap the log query in a range aggregation such as count_over_time(... [5m])",
+                    op.name()
+                ),
+                log.span,
+            ));
+        }
+        self.expect(&TokenKind::RightParen, "`)`")?;
+
+        let trailing = self.peek_token().span;
+        if let Some(after) = self.parse_grouping()? {
+            if grouping.is_some() {
+                return Err(self.error("by/without is given twice", trailing));
+            }
+            grouping = Some(after);
+        }
+        if matches!(op, VectorOp::Sort | VectorOp::SortDesc) && grouping.is_some() {
+            return Err(self.error(format!("`{}` does not take by/without", op.name()), name));
+        }
+
+        Ok(Expr::VectorAggregation(VectorAggregation {
+            op,
+            parameter,
+            expr: Box::new(expr),
+            grouping,
+            span: name.to(self.previous_span()),
+        }))
+    }
+
+    fn parse_grouping(&mut self) -> Result<Option<Grouping>, ParseError> {
+        let without = match self.peek() {
+            TokenKind::Ident(name) if name == "by" => false,
+            TokenKind::Ident(name) if name == "without" => true,
+            _ => return Ok(None),
+        };
+        self.advance();
+
+        Ok(Some(Grouping {
+            without,
+            labels: self.parse_label_list()?,
+        }))
+    }
+
+    /// `(a, b)`, possibly empty.
+    fn parse_label_list(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect(&TokenKind::LeftParen, "`(` before the label list")?;
+        let mut labels = Vec::new();
+        while !self.check(&TokenKind::RightParen) {
+            labels.push(self.expect_ident("a label name")?.0);
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightParen, "`,` or `)`")?;
+
+        Ok(labels)
+    }
+
+    fn parse_vector(&mut self) -> Result<Expr, ParseError> {
+        let name = self.advance().span;
+        self.expect(&TokenKind::LeftParen, "`(` after vector")?;
+        let value = match self.parse_unary()? {
+            Expr::Literal { value, .. } => value,
+            other => {
+                return Err(self.error("vector(...) takes a number", other.span()));
+            }
+        };
+        self.expect(&TokenKind::RightParen, "`)`")?;
+
+        Ok(Expr::Vector {
+            value,
+            span: name.to(self.previous_span()),
+        })
+    }
+
+    fn parse_label_replace(&mut self) -> Result<Expr, ParseError> {
+        let name = self.advance().span;
+        self.expect(&TokenKind::LeftParen, "`(` after label_replace")?;
+        let expr = self.parse_binary(0)?;
+        if let Expr::Log(log) = &expr {
+            return Err(self.error("label_replace needs a metric expression", log.span));
+        }
+        let mut arguments = Vec::new();
+        for what in [
+            "the destination label",
+            "the replacement",
+            "the source label",
+            "the regular expression",
+        ] {
+            self.expect(&TokenKind::Comma, "`,`")?;
+            arguments.push(self.expect_string(what)?.0);
+        }
+        self.expect(&TokenKind::RightParen, "`)`")?;
+        let [destination, replacement, source, regex] =
+            <[String; 4]>::try_from(arguments).unwrap_or_default();
+
+        Ok(Expr::LabelReplace(LabelReplace {
+            expr: Box::new(expr),
+            destination,
+            replacement,
+            source,
+            regex,
+            span: name.to(self.previous_span()),
+        }))
+    }
+
+    fn parse_log_expr(&mut self) -> Result<LogExpr, ParseError> {
+        let selector = self.parse_selector()?;
+        let mut pipeline = Vec::new();
+        let mut unwrap = None;
+        self.parse_pipeline(&mut pipeline, &mut unwrap, false)?;
+        let span = pipeline
+            .last()
+            .map_or(selector.span, |stage| selector.span.to(stage.span));
+
+        Ok(LogExpr {
+            selector,
+            pipeline,
+            span,
+        })
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, ParseError> {
+        let open = self.expect(&TokenKind::LeftBrace, "`{`")?.span;
+        if self.check(&TokenKind::RightBrace) {
+            return Err(self.error(
+                "a stream selector needs at least one label matcher, e.g. {app=\"api\"}",
+                open.to(self.peek_token().span),
+            ));
+        }
+
+        let mut matchers = Vec::new();
+        loop {
+            let (label, label_span) = self.expect_ident("a label name")?;
+            let op = match self.peek() {
+                TokenKind::Eq => MatchOp::Eq,
+                TokenKind::NotEq => MatchOp::NotEq,
+                TokenKind::Match => MatchOp::Regex,
+                TokenKind::NotMatch => MatchOp::NotRegex,
+                TokenKind::EqEq => {
+                    return Err(self.error_here("use `=` in stream selectors"));
+                }
+                _ => {
+                    return Err(
+                        self.unexpected(&format!("`=`, `!=`, `=~` or `!~` after `{label}`"))
+                    );
+                }
+            };
+            self.advance();
+            if let TokenKind::Number(_) = self.peek() {
+                return Err(self.error_here("label values must be quoted, e.g. {status=\"500\"}"));
+            }
+            let (value, value_span) = self.expect_string("a quoted label value")?;
+            matchers.push(Matcher {
+                label,
+                op,
+                value,
+                span: label_span.to(value_span),
+            });
+
+            if self.eat(&TokenKind::Comma) && !self.check(&TokenKind::RightBrace) {
+                continue;
+            }
+            let close = self.expect(&TokenKind::RightBrace, "`,` or `}`")?.span;
+            return Ok(Selector {
+                matchers,
+                span: open.to(close),
+            });
+        }
+    }
+
+    fn parse_pipeline(
+        &mut self,
+        stages: &mut Vec<Stage>,
+        unwrap: &mut Option<Unwrap>,
+        allow_unwrap: bool,
+    ) -> Result<(), ParseError> {
+        loop {
+            let token = self.peek_token().clone();
+            match token.kind {
+                TokenKind::PipeContains
+                | TokenKind::NotEq
+                | TokenKind::PipeMatch
+                | TokenKind::NotMatch
+                | TokenKind::PipePattern
+                | TokenKind::NotPattern => {
+                    if unwrap.is_some() {
+                        return Err(self.error_here("only label filters may follow unwrap"));
+                    }
+                    let stage = self.parse_line_filter()?;
+                    stages.push(stage);
+                }
+                TokenKind::Pipe => {
+                    self.advance();
+                    if let Some(unwrap) = unwrap.as_mut() {
+                        if !self.starts_label_filter() {
+                            return Err(self.error_here("only label filters may follow unwrap"));
+                        }
+                        unwrap.filters.push(self.parse_label_filter()?);
+                        unwrap.span = unwrap.span.to(self.previous_span());
+                        continue;
+                    }
+                    if self.peek() == &TokenKind::Ident("unwrap".to_string()) {
+                        if !allow_unwrap {
+                            return Err(self.error_here(
+                                "unwrap is only valid inside a range aggregation such as sum_over_time(... | unwrap latency [5m])",
+                            ));
+                        }
+                        *unwrap = Some(self.parse_unwrap(token.span)?);
+                        continue;
+                    }
+                    let kind = self.parse_stage()?;
+                    stages.push(Stage {
+                        kind,
+                        span: token.span.to(self.previous_span()),
+                    });
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_line_filter(&mut self) -> Result<Stage, ParseError> {
+        let operator = self.advance();
+        let op = match operator.kind {
+            TokenKind::PipeContains => LineFilterOp::Contains,
+            TokenKind::NotEq => LineFilterOp::NotContains,
+            TokenKind::PipeMatch => LineFilterOp::Regex,
+            TokenKind::NotMatch => LineFilterOp::NotRegex,
+            TokenKind::PipePattern => LineFilterOp::Pattern,
+            _ => LineFilterOp::NotPattern,
+        };
+
+        let mut values = vec![self.parse_line_filter_value(op)?];
+        while self.eat_keyword("or") {
+            values.push(self.parse_line_filter_value(op)?);
+        }
+
+        Ok(Stage {
+            kind: StageKind::LineFilter(LineFilter { op, values }),
+            span: operator.span.to(self.previous_span()),
+        })
+    }
+
+    fn parse_line_filter_value(&mut self, op: LineFilterOp) -> Result<LineFilterValue, ParseError> {
+        match self.peek().clone() {
+            TokenKind::String(value) => {
+                let span = self.advance().span;
+                Ok(LineFilterValue::Text { value, span })
+            }
+            TokenKind::Ident(name)
+                if name == "ip"
+                    && matches!(op, LineFilterOp::Contains | LineFilterOp::NotContains) =>
+            {
+                let start = self.advance().span;
+                self.expect(&TokenKind::LeftParen, "`(` after ip")?;
+                let (cidr, _) = self.expect_string("an IP address or range")?;
+                let end = self.expect(&TokenKind::RightParen, "`)`")?.span;
+                Ok(LineFilterValue::Ip {
+                    cidr,
+                    span: start.to(end),
+                })
+            }
+            _ => Err(self.unexpected(&format!("a quoted string after `{}`", op.symbol()))),
+        }
+    }
+
+    fn parse_stage(&mut self) -> Result<StageKind, ParseError> {
+        let keyword = match self.peek() {
+            TokenKind::Ident(name) => name.clone(),
+            TokenKind::LeftParen => return Ok(StageKind::LabelFilter(self.parse_label_filter()?)),
+            _ => return Err(self.unexpected_stage()),
+        };
+
+        let kind = match keyword.as_str() {
+            "json" => {
+                self.advance();
+                StageKind::Parser(ParserStage::Json(self.parse_extract_params()?))
+            }
+            "logfmt" => {
+                self.advance();
+                let mut flags = Vec::new();
+                while let TokenKind::Flag(flag) = self.peek().clone() {
+                    if !LOGFMT_FLAGS.contains(&flag.as_str()) {
+                        return Err(self.error_here(format!(
+                            "unknown logfmt flag `--{flag}`; expected --strict or --keep-empty"
+                        )));
+                    }
+                    self.advance();
+                    flags.push(flag);
+                }
+                StageKind::Parser(ParserStage::Logfmt {
+                    flags,
+                    params: self.parse_extract_params()?,
+                })
+            }
+            "regexp" => {
+                self.advance();
+                let (expression, _) =
+                    self.expect_string("a quoted regular expression with named groups")?;
+                StageKind::Parser(ParserStage::Regexp { expression })
+            }
+            "pattern" => {
+                self.advance();
+                let (expression, _) =
+                    self.expect_string("a quoted pattern such as \"<ip> - <_>\"")?;
+                StageKind::Parser(ParserStage::Pattern { expression })
+            }
+            "unpack" => {
+                self.advance();
+                StageKind::Parser(ParserStage::Unpack)
+            }
+            "line_format" => {
+                self.advance();
+                let (template, _) = self.expect_string("a quoted template")?;
+                StageKind::LineFormat { template }
+            }
+            "label_format" => {
+                self.advance();
+                StageKind::LabelFormat(self.parse_label_format()?)
+            }
+            "decolorize" => {
+                self.advance();
+                StageKind::Decolorize
+            }
+            "drop" => {
+                self.advance();
+                StageKind::Drop(self.parse_label_selections()?)
+            }
+            "keep" => {
+                self.advance();
+                StageKind::Keep(self.parse_label_selections()?)
+            }
+            "distinct" => {
+                self.advance();
+                let mut labels = vec![self.expect_ident("a label name")?.0];
+                while self.eat(&TokenKind::Comma) {
+                    labels.push(self.expect_ident("a label name")?.0);
+                }
+                StageKind::Distinct(labels)
+            }
+            _ => StageKind::LabelFilter(self.parse_label_filter()?),
+        };
+
+        Ok(kind)
+    }
+
+    fn unexpected_stage(&self) -> ParseError {
+        self.unexpected(
+            "a parser (json, logfmt, regexp, pattern, unpack), a formatter (line_format, label_format), drop, keep, distinct, decolorize or a label filter after `|`",
+        )
+    }
+
+    /// `label` or `label="expression"`, comma-separated; may be empty.
+    fn parse_extract_params(&mut self) -> Result<Vec<ExtractParam>, ParseError> {
+        let mut params = Vec::new();
+        while let TokenKind::Ident(label) = self.peek().clone() {
+            self.advance();
+            let expression = if self.eat(&TokenKind::Eq) {
+                Some(self.expect_string("a quoted field expression")?.0)
+            } else {
+                None
+            };
+            params.push(ExtractParam { label, expression });
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        Ok(params)
+    }
+
+    fn parse_label_format(&mut self) -> Result<Vec<LabelFormatOp>, ParseError> {
+        let mut operations = Vec::new();
+        loop {
+            let (label, _) = self.expect_ident("a label name")?;
+            self.expect(&TokenKind::Eq, &format!("`=` after `{label}`"))?;
+            let operation = match self.peek().clone() {
+                TokenKind::Ident(from) => {
+                    self.advance();
+                    LabelFormatOp::Rename { label, from }
+                }
+                TokenKind::String(template) => {
+                    self.advance();
+                    LabelFormatOp::Template { label, template }
+                }
+                _ => return Err(self.unexpected("a label to rename from or a quoted template")),
+            };
+            operations.push(operation);
+            if !self.eat(&TokenKind::Comma) {
+                return Ok(operations);
+            }
+        }
+    }
+
+    fn parse_label_selections(&mut self) -> Result<Vec<LabelSelection>, ParseError> {
+        let mut selections = Vec::new();
+        loop {
+            let (label, _) = self.expect_ident("a label name")?;
+            let op = match self.peek() {
+                TokenKind::Eq => Some(MatchOp::Eq),
+                TokenKind::NotEq => Some(MatchOp::NotEq),
+                TokenKind::Match => Some(MatchOp::Regex),
+                TokenKind::NotMatch => Some(MatchOp::NotRegex),
+                _ => None,
+            };
+            let matcher = match op {
+                Some(op) => {
+                    self.advance();
+                    Some((op, self.expect_string("a quoted label value")?.0))
+                }
+                None => None,
+            };
+            selections.push(LabelSelection { label, matcher });
+            if !self.eat(&TokenKind::Comma) {
+                return Ok(selections);
+            }
+        }
+    }
+
+    fn parse_unwrap(&mut self, pipe: Span) -> Result<Unwrap, ParseError> {
+        self.advance();
+        let (name, _) = self.expect_ident("a label to unwrap")?;
+        let (label, conversion) =
+            if UNWRAP_CONVERSIONS.contains(&name.as_str()) && self.check(&TokenKind::LeftParen) {
+                self.advance();
+                let (label, _) = self.expect_ident("a label to convert")?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                (label, Some(name))
+            } else {
+                (name, None)
+            };
+
+        Ok(Unwrap {
+            label,
+            conversion,
+            filters: Vec::new(),
+            span: pipe.to(self.previous_span()),
+        })
+    }
+
+    fn starts_label_filter(&self) -> bool {
+        matches!(self.peek(), TokenKind::Ident(_) | TokenKind::LeftParen)
+    }
+
+    /// `or` binds loosest; `and`, `,` and plain juxtaposition all mean `and`.
+    fn parse_label_filter(&mut self) -> Result<LabelFilter, ParseError> {
+        let mut left = self.parse_label_filter_and()?;
+        while self.eat_keyword("or") {
+            let right = self.parse_label_filter_and()?;
+            left = LabelFilter::Or(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_label_filter_and(&mut self) -> Result<LabelFilter, ParseError> {
+        let mut left = self.parse_label_filter_primary()?;
+        loop {
+            let joined = self.eat_keyword("and")
+                || self.eat(&TokenKind::Comma)
+                || match self.peek() {
+                    TokenKind::Ident(name) => !matches!(name.as_str(), "or" | "offset"),
+                    TokenKind::LeftParen => true,
+                    _ => false,
+                };
+            if !joined {
+                return Ok(left);
+            }
+            let right = self.parse_label_filter_primary()?;
+            left = LabelFilter::And(Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_label_filter_primary(&mut self) -> Result<LabelFilter, ParseError> {
+        if self.eat(&TokenKind::LeftParen) {
+            let inner = self.parse_label_filter()?;
+            self.expect(&TokenKind::RightParen, "`)`")?;
+            return Ok(inner);
+        }
+
+        let (label, label_span) = match self.peek() {
+            TokenKind::Ident(_) => self.expect_ident("a label name")?,
+            _ => return Err(self.unexpected_stage()),
+        };
+        let op = match self.peek() {
+            TokenKind::Eq | TokenKind::EqEq => CompareOp::Eq,
+            TokenKind::NotEq => CompareOp::NotEq,
+            TokenKind::Match => CompareOp::Regex,
+            TokenKind::NotMatch => CompareOp::NotRegex,
+            TokenKind::Gt => CompareOp::Gt,
+            TokenKind::Gte => CompareOp::Gte,
+            TokenKind::Lt => CompareOp::Lt,
+            TokenKind::Lte => CompareOp::Lte,
+            _ => {
+                return Err(self.unexpected(&format!(
+                    "a comparison such as `=`, `=~` or `>` after label `{label}`"
+                )));
+            }
+        };
+        self.advance();
+
+        let ordering = matches!(
+            op,
+            CompareOp::Gt | CompareOp::Gte | CompareOp::Lt | CompareOp::Lte
+        );
+        let regex = matches!(op, CompareOp::Regex | CompareOp::NotRegex);
+        let value = match self.peek().clone() {
+            TokenKind::String(_) if ordering => {
+                return Err(self.error_here(format!(
+                    "`{}` compares numbers, durations or sizes; remove the quotes",
+                    op.symbol()
+                )));
+            }
+            TokenKind::String(value) => {
+                self.advance();
+                FilterValue::String(value)
+            }
+            _ if regex => return Err(self.unexpected("a quoted regular expression")),
+            TokenKind::Number(value) => {
+                self.advance();
+                FilterValue::Number(value)
+            }
+            TokenKind::Sub if matches!(self.peek_nth(1), TokenKind::Number(_)) => {
+                self.advance();
+                let TokenKind::Number(value) = self.advance().kind else {
+                    unreachable!("checked above");
+                };
+                FilterValue::Number(-value)
+            }
+            TokenKind::Duration(value) => {
+                self.advance();
+                FilterValue::Duration(value)
+            }
+            TokenKind::Bytes(value) => {
+                self.advance();
+                FilterValue::Bytes(value)
+            }
+            TokenKind::Ident(name)
+                if name == "ip" && matches!(op, CompareOp::Eq | CompareOp::NotEq) =>
+            {
+                self.advance();
+                self.expect(&TokenKind::LeftParen, "`(` after ip")?;
+                let (cidr, _) = self.expect_string("an IP address or range")?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                FilterValue::Ip(cidr)
+            }
+            _ => return Err(self.unexpected("a quoted string, number, duration or size")),
+        };
+
+        Ok(LabelFilter::Comparison(LabelComparison {
+            label,
+            op,
+            value,
+            span: label_span.to(self.previous_span()),
+        }))
+    }
+
+    fn peek_token(&self) -> &Token {
+        &self.tokens[self.position.min(self.tokens.len() - 1)]
+    }
+
+    fn peek(&self) -> &TokenKind {
+        &self.peek_token().kind
+    }
+
+    fn peek_nth(&self, offset: usize) -> &TokenKind {
+        &self.tokens[(self.position + offset).min(self.tokens.len() - 1)].kind
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek_token().clone();
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn previous_span(&self) -> Span {
+        self.position
+            .checked_sub(1)
+            .map_or(Span::new(0, 0), |index| self.tokens[index].span)
+    }
+
+    fn check(&self, kind: &TokenKind) -> bool {
+        self.peek() == kind
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        let matched = self.check(kind);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let matched = matches!(self.peek(), TokenKind::Ident(name) if name == keyword);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
+    fn expect(&mut self, kind: &TokenKind, expected: &str) -> Result<Token, ParseError> {
+        if self.check(kind) {
+            Ok(self.advance())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn expect_ident(&mut self, expected: &str) -> Result<(String, Span), ParseError> {
+        match self.peek().clone() {
+            TokenKind::Ident(name) => Ok((name, self.advance().span)),
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn expect_string(&mut self, expected: &str) -> Result<(String, Span), ParseError> {
+        match self.peek().clone() {
+            TokenKind::String(value) => Ok((value, self.advance().span)),
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn expect_number(&mut self, expected: &str) -> Result<f64, ParseError> {
+        match self.parse_unary() {
+            Ok(Expr::Literal { value, .. }) => Ok(value),
+            Ok(other) => Err(self.error(format!("expected {expected}"), other.span())),
+            Err(_) => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn expect_duration(&mut self, expected: &str) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Duration(value) => {
+                self.advance();
+                Ok(value)
+            }
+            TokenKind::Number(_) => Err(self.error_here(format!(
+                "expected {expected}; durations need a unit such as s, m or h"
+            ))),
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>, span: Span) -> ParseError {
+        ParseError::new(self.source, message, span)
+    }
+
+    fn error_here(&self, message: impl Into<String>) -> ParseError {
+        self.error(message, self.peek_token().span)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        self.error_here(format!(
+            "expected {expected}, found {}",
+            self.peek().describe()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logql::{
+        BinaryOp, Expr, LabelFilter, LineFilterOp, ParserStage, RangeOp, StageKind, VectorOp, parse,
+    };
+
+    #[test]
+    fn parses_log_pipelines_without_splitting_quoted_operators() {
+        let query = r#"{app="api", env=~"prod|stage"} |= "a |= b" or "c" != `x` | logfmt --strict level, msg="message" | status >= 500, level="error" or duration > 1.5s | line_format "{{.msg}}""#;
+        let Expr::Log(log) = parse(query).unwrap() else {
+            panic!("expected a log query");
+        };
+
+        assert_eq!(log.selector.matchers.len(), 2);
+        assert_eq!(log.pipeline.len(), 5);
+        let StageKind::LineFilter(filter) = &log.pipeline[0].kind else {
+            panic!("expected a line filter");
+        };
+        assert_eq!(filter.op, LineFilterOp::Contains);
+        assert_eq!(
+            filter
+                .values
+                .iter()
+                .map(|value| value.text())
+                .collect::<Vec<_>>(),
+            ["a |= b", "c"]
+        );
+        assert_eq!(log.pipeline[1].span.text(query), "!= `x`");
+        let StageKind::Parser(ParserStage::Logfmt { flags, params }) = &log.pipeline[2].kind else {
+            panic!("expected logfmt");
+        };
+        assert_eq!(flags, &["strict"]);
+        assert_eq!(params[1].expression.as_deref(), Some("message"));
+        let StageKind::LabelFilter(LabelFilter::Or(left, _)) = &log.pipeline[3].kind else {
+            panic!("expected `or` to bind loosest");
+        };
+        assert!(matches!(**left, LabelFilter::And(..)));
+        assert!(matches!(log.pipeline[4].kind, StageKind::LineFormat { .. }));
+    }
+
+    #[test]
+    fn parses_nested_aggregations_and_operator_precedence() {
+        let query = r#"topk(5, sum by (path) (quantile_over_time(0.99, {app="api"} | json | unwrap duration(latency) | __error__="" [5m]) by (path))) / 2 + 1 > bool 10 or vector(0)"#;
+        let Expr::Binary(or) = parse(query).unwrap() else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(or.op, BinaryOp::Or);
+        let Expr::Binary(comparison) = *or.left else {
+            panic!("expected the comparison on the left of `or`");
+        };
+        assert!(comparison.return_bool);
+        let Expr::Binary(add) = *comparison.left else {
+            panic!("expected `+` under `>`");
+        };
+        assert_eq!(add.op, BinaryOp::Add);
+        let Expr::Binary(div) = *add.left else {
+            panic!("expected `/` to bind tighter than `+`");
+        };
+        let Expr::VectorAggregation(topk) = *div.left else {
+            panic!("expected topk");
+        };
+        assert_eq!((topk.op, topk.parameter), (VectorOp::Topk, Some(5.0)));
+
+        let ranges = parse(query).unwrap();
+        let ranges = ranges.range_aggregations();
+        assert_eq!(ranges[0].op, RangeOp::QuantileOverTime);
+        let unwrap = ranges[0].range.unwrap.as_ref().unwrap();
+        assert_eq!(
+            (unwrap.label.as_str(), unwrap.conversion.as_deref()),
+            ("latency", Some("duration"))
+        );
+        assert_eq!(unwrap.filters.len(), 1);
+        assert_eq!(ranges[0].range.range, "5m");
+
+        let Expr::Binary(pow) = parse("2 ^ 3 ^ 2").unwrap() else {
+            panic!("expected a binary expression");
+        };
+        assert!(
+            matches!(*pow.right, Expr::Binary(_)),
+            "`^` is right-associative"
+        );
+    }
+
+    #[test]
+    fn parses_approx_topk_and_distinct() {
+        let query = r#"approx_topk(5, sum by (app) (rate({app="api"}[5m])))"#;
+        let Expr::VectorAggregation(approx) = parse(query).unwrap() else {
+            panic!("expected a vector aggregation");
+        };
+        assert_eq!(
+            (approx.op, approx.parameter),
+            (VectorOp::ApproxTopk, Some(5.0))
+        );
+        assert!(matches!(*approx.expr, Expr::VectorAggregation(_)));
+
+        let Expr::Log(log) = parse(r#"{app="api"} | logfmt | distinct level, pod"#).unwrap() else {
+            panic!("expected a log query");
+        };
+        assert_eq!(
+            log.pipeline[1].kind,
+            StageKind::Distinct(vec!["level".to_string(), "pod".to_string()])
+        );
+
+        let error = parse(r#"{app="api"} | distinct"#).unwrap_err();
+        assert!(
+            error.to_string().contains("expected a label name"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_positions() {
+        let cases = [
+            (
+                r#"{app="api"} | json | status >= "500""#,
+                1,
+                32,
+                "compares numbers",
+            ),
+            (
+                "{app=api}",
+                1,
+                6,
+                "expected a quoted label value, found `api`",
+            ),
+            ("{}", 1, 1, "at least one label matcher"),
+            (
+                r#"{app="api"} |= "x" [5m]"#,
+                1,
+                20,
+                "only valid inside a range aggregation",
+            ),
+            (
+                r#"sum_over_time({app="api"} [5m])"#,
+                1,
+                1,
+                "needs an `| unwrap <label>`",
+            ),
+            (
+                r#"sum({app="api"})"#,
+                1,
+                5,
+                "wrap the log query in a range aggregation",
+            ),
+            (
+                "rate({app=\"api\"}\n  | json [5])",
+                2,
+                11,
+                "durations need a unit",
+            ),
+            (
+                r#"count_over_time({app="api"} [5m]"#,
+                1,
+                33,
+                "expected `)`, found end of query",
+            ),
+        ];
+
+        for (query, line, column, message) in cases {
+            let error = parse(query).unwrap_err();
+            assert!(
+                error.message.contains(message),
+                "{query}: unexpected message {:?}",
+                error.message
+            );
+            assert_eq!(
+                (error.line, error.column),
+                (line, column),
+                "{query}: {error}"
+            );
+        }
+    }
+}
@@ -1,19 +1,35 @@
 #![allow(dead_code)]
 
-use anyhow::{Result, bail};
-use reqwest::RequestBuilder;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::config::LokiConfig;
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use reqwest::{
+    Client, Request,
+    header::{AUTHORIZATION, HeaderValue},
+};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::config::{LokiConfig, LokiOAuth2Config};
+
+/// Tokens are refreshed this long before they expire, or at half their lifetime if shorter.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub enum LokiAuth {
     None,
     Basic { username: String, password: String },
     Bearer { token: String },
+    OAuth2(OAuth2TokenSource),
 }
 
 impl LokiAuth {
-    pub fn from_config(config: &LokiConfig) -> Result<Self> {
+    /// `http_client` is used to reach the OAuth2 token endpoint, so it shares Loki's CA and timeout.
+    pub fn from_config(config: &LokiConfig, http_client: &Client) -> Result<Self> {
         match config.auth_type.as_str() {
             "none" => Ok(Self::None),
             "basic" => {
@@ -33,15 +49,307 @@ impl LokiAuth {
 
                 Ok(Self::Bearer { token })
             }
+            "oauth2" => {
+                let Some(oauth2) = config.oauth2.as_ref() else {
+                    bail!("loki.oauth2 is required when loki.auth_type=oauth2");
+                };
+
+                Ok(Self::OAuth2(OAuth2TokenSource::new(
+                    oauth2,
+                    http_client.clone(),
+                )))
+            }
             other => bail!("unsupported loki auth type: {other}"),
         }
     }
 
-    pub fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+    /// Sets the `Authorization` header, fetching or refreshing an OAuth2 token when needed.
+    pub async fn apply(&self, request: &mut Request) -> Result<()> {
+        let value = match self {
+            Self::None => return Ok(()),
+            Self::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{username}:{password}"))
+                )
+            }
+            Self::Bearer { token } => format!("Bearer {token}"),
+            Self::OAuth2(source) => format!("Bearer {}", source.token().await?),
+        };
+
+        let mut value =
+            HeaderValue::from_str(&value).context("Loki credentials are not a valid header")?;
+        value.set_sensitive(true);
+        request.headers_mut().insert(AUTHORIZATION, value);
+        Ok(())
+    }
+
+    /// Drops a cached token Loki rejected, given the `Authorization` header the rejected request
+    /// carried. Returns whether a retry may succeed with new credentials.
+    pub async fn invalidate(&self, rejected: Option<&HeaderValue>) -> bool {
         match self {
-            Self::None => builder,
-            Self::Basic { username, password } => builder.basic_auth(username, Some(password)),
-            Self::Bearer { token } => builder.bearer_auth(token),
+            Self::OAuth2(source) => {
+                if let Some(token) = rejected
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                {
+                    source.invalidate(token).await;
+                }
+                true
+            }
+            Self::None | Self::Basic { .. } | Self::Bearer { .. } => false,
         }
     }
 }
+
+/// Fetches access tokens with the OAuth2 client-credentials grant and caches them until shortly
+/// before they expire. Clones share the cache.
+#[derive(Clone)]
+pub struct OAuth2TokenSource {
+    http_client: Client,
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scopes: Vec<String>,
+    cached: Arc<Mutex<Option<CachedToken>>>,
+}
+
+impl std::fmt::Debug for OAuth2TokenSource {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("OAuth2TokenSource")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+struct CachedToken {
+    access_token: String,
+    /// `None` when the token endpoint did not say; such tokens live until Loki rejects them.
+    refresh_at: Option<Instant>,
+}
+
+impl CachedToken {
+    fn is_fresh(&self, now: Instant) -> bool {
+        self.refresh_at.is_none_or(|refresh_at| now < refresh_at)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl OAuth2TokenSource {
+    pub fn new(config: &LokiOAuth2Config, http_client: Client) -> Self {
+        Self {
+            http_client,
+            token_url: config.token_url.clone(),
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret.clone(),
+            scopes: config.scopes.clone(),
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns a valid access token. Concurrent callers wait on a single refresh.
+    pub async fn token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref()
+            && token.is_fresh(Instant::now())
+        {
+            return Ok(token.access_token.clone());
+        }
+
+        let token = self.fetch().await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    /// Drops the cached token if it is still `rejected`. A token another caller fetched after
+    /// the rejected request went out is kept, so concurrent 401s lead to a single refresh.
+    pub async fn invalidate(&self, rejected: &str) {
+        let mut cached = self.cached.lock().await;
+        if cached
+            .as_ref()
+            .is_some_and(|token| token.access_token == rejected)
+        {
+            cached.take();
+        }
+    }
+
+    async fn fetch(&self) -> Result<CachedToken> {
+        let mut form = vec![("grant_type", "client_credentials".to_string())];
+        if !self.scopes.is_empty() {
+            form.push(("scope", self.scopes.join(" ")));
+        }
+
+        let requested_at = Instant::now();
+        let response = self
+            .http_client
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .with_context(|| format!("OAuth2 token request to {} failed", self.token_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response
+                .json::<TokenError>()
+                .await
+                .map(|error| match error.error_description {
+                    Some(description) => format!("{}: {description}", error.error),
+                    None => error.error,
+                })
+                .unwrap_or_else(|_| "no error details".to_string());
+            bail!(
+                "OAuth2 token endpoint {} returned {status}: {detail}",
+                self.token_url
+            );
+        }
+
+        let token = response
+            .json::<TokenResponse>()
+            .await
+            .context("failed to decode OAuth2 token response")?;
+
+        Ok(CachedToken {
+            access_token: token.access_token,
+            refresh_at: refresh_at(requested_at, token.expires_in),
+        })
+    }
+}
+
+/// When a token requested at `requested_at` and valid for `expires_in` seconds should be replaced.
+fn refresh_at(requested_at: Instant, expires_in: Option<u64>) -> Option<Instant> {
+    expires_in.map(|expires_in| {
+        let lifetime = Duration::from_secs(expires_in);
+        requested_at + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN.min(lifetime / 2))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use axum::{Json, Router, http::HeaderMap, routing::post};
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use reqwest::Client;
+    use serde_json::json;
+
+    use super::{OAuth2TokenSource, refresh_at};
+    use crate::config::LokiOAuth2Config;
+
+    /// Issues `token-1`, `token-2`, ... valid for an hour, recording each request's
+    /// `Authorization` header and form body.
+    async fn token_source() -> (OAuth2TokenSource, Arc<Mutex<Vec<(String, String)>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = Router::new().route(
+            "/oauth/token",
+            post(move |headers: HeaderMap, body: String| {
+                let recorded = recorded.clone();
+                async move {
+                    let mut recorded = recorded.lock().expect("lock");
+                    let authorization = headers
+                        .get("authorization")
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    recorded.push((authorization, body));
+                    Json(json!({
+                        "access_token": format!("token-{}", recorded.len()),
+                        "token_type": "Bearer",
+                        "expires_in": 3600,
+                    }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind stand-in");
+        let address = listener.local_addr().expect("local addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        let config = LokiOAuth2Config {
+            token_url: format!("http://{address}/oauth/token"),
+            client_id: "loki-mcp".to_string(),
+            client_secret: "s3cret".to_string(),
+            scopes: vec!["logs:read".to_string(), "logs:query".to_string()],
+        };
+        (OAuth2TokenSource::new(&config, Client::new()), requests)
+    }
+
+    #[tokio::test]
+    async fn caches_tokens_and_refreshes_them_once_due() {
+        let (source, requests) = token_source().await;
+
+        assert_eq!(source.token().await.expect("token"), "token-1");
+        assert_eq!(source.token().await.expect("token"), "token-1");
+        assert_eq!(requests.lock().expect("lock").len(), 1);
+
+        // Past its refresh point the cached token is replaced.
+        if let Some(token) = source.cached.lock().await.as_mut() {
+            token.refresh_at = Some(Instant::now());
+        }
+        assert_eq!(source.token().await.expect("token"), "token-2");
+
+        let requests = requests.lock().expect("lock");
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].0,
+            format!("Basic {}", STANDARD.encode("loki-mcp:s3cret"))
+        );
+        assert_eq!(
+            requests[0].1,
+            "grant_type=client_credentials&scope=logs%3Aread+logs%3Aquery"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalidates_only_the_rejected_token() {
+        let (source, requests) = token_source().await;
+        assert_eq!(source.token().await.expect("token"), "token-1");
+
+        source.invalidate("token-1").await;
+        assert_eq!(source.token().await.expect("token"), "token-2");
+
+        // A second 401 for the old token must not discard the one that replaced it.
+        source.invalidate("token-1").await;
+        assert_eq!(source.token().await.expect("token"), "token-2");
+        assert_eq!(requests.lock().expect("lock").len(), 2);
+    }
+
+    #[test]
+    fn refreshes_by_the_margin_or_at_half_the_lifetime() {
+        let requested_at = Instant::now();
+
+        assert_eq!(
+            refresh_at(requested_at, Some(3600)),
+            Some(requested_at + Duration::from_secs(3570))
+        );
+        assert_eq!(
+            refresh_at(requested_at, Some(40)),
+            Some(requested_at + Duration::from_secs(20))
+        );
+        assert_eq!(refresh_at(requested_at, Some(0)), Some(requested_at));
+        assert_eq!(refresh_at(requested_at, None), None);
+    }
+}
@@ -0,0 +1,621 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::Response;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+
+use crate::response::{LogLineEntry, stream_labels};
+
+/// Body chunks queued between the connection and the decoder thread.
+const DECODE_QUEUE_CHUNKS: usize = 8;
+
+/// Receives log entries as a streamed response is decoded.
+pub trait LogEntrySink {
+    /// `stream` is the entry's label set as Loki sent it; `timestamp_nanos` is the raw timestamp.
+    fn push(&mut self, stream: &Map<String, Value>, timestamp_nanos: &str, entry: LogLineEntry);
+}
+
+/// A decoded `query_range` response whose entries went to `sink` instead of memory.
+pub struct DecodedLogs<S> {
+    pub sink: S,
+    pub result_type: Option<String>,
+    pub entries: usize,
+}
+
+/// Reads a whole body, failing as soon as it grows past `max_bytes`.
+pub async fn read_limited(mut response: Response, max_bytes: u64) -> Result<Vec<u8>> {
+    ensure_declared_length(&response, max_bytes)?;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = next_chunk(&mut response, body.len() as u64).await? {
+        ensure_within_limit(body.len() as u64 + chunk.len() as u64, max_bytes)?;
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+/// Decodes a log query response while it downloads, handing each entry to `sink` so neither
+/// the body nor the entries are ever held in full. Fails once more than `max_bytes` arrive.
+pub async fn decode_log_stream<S>(
+    mut response: Response,
+    max_bytes: u64,
+    sink: S,
+) -> Result<DecodedLogs<S>>
+where
+    S: LogEntrySink + Send + 'static,
+{
+    ensure_declared_length(&response, max_bytes)?;
+
+    let (sender, receiver) = mpsc::channel(DECODE_QUEUE_CHUNKS);
+    let decoder =
+        tokio::task::spawn_blocking(move || decode_streams(ChunkReader::new(receiver), sink));
+
+    let mut received = 0_u64;
+    let pumped = async {
+        while let Some(chunk) = next_chunk(&mut response, received).await? {
+            received += chunk.len() as u64;
+            ensure_within_limit(received, max_bytes)?;
+            if sender.send(chunk).await.is_err() {
+                // The decoder gave up early; its error says why.
+                break;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+    drop(sender);
+
+    let decoded = decoder.await.context("Loki response decoder failed")?;
+    // A failed download leaves the decoder with a truncated body; the download error is the cause.
+    pumped?;
+    let (envelope, decoded) = decoded.map_err(|error| {
+        if error.is_eof() {
+            anyhow!("Loki response was cut off after {received} bytes, before the JSON ended")
+        } else {
+            anyhow!(error).context("failed to decode Loki JSON response")
+        }
+    })?;
+
+    match envelope.status.as_deref() {
+        Some("success") => Ok(decoded),
+        _ => bail!(
+            "loki api error ({}): {}",
+            envelope.error_type.as_deref().unwrap_or("unknown_error"),
+            envelope
+                .error
+                .as_deref()
+                .unwrap_or("Loki returned an error response")
+        ),
+    }
+}
+
+fn ensure_declared_length(response: &Response, max_bytes: u64) -> Result<()> {
+    match response.content_length() {
+        Some(length) => ensure_within_limit(length, max_bytes),
+        None => Ok(()),
+    }
+}
+
+fn ensure_within_limit(bytes: u64, max_bytes: u64) -> Result<()> {
+    if bytes > max_bytes {
+        bail!(
+            "Loki response was cut off: it is larger than max_response_bytes ({max_bytes} bytes); \
+             narrow the time range or lower the limit"
+        );
+    }
+
+    Ok(())
+}
+
+async fn next_chunk(response: &mut Response, received: u64) -> Result<Option<Vec<u8>>> {
+    let chunk = response
+        .chunk()
+        .await
+        .with_context(|| format!("Loki response was cut off after {received} bytes"))?;
+    Ok(chunk.map(Vec::from))
+}
+
+/// Blocking reader over body chunks sent from the async side.
+struct ChunkReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    offset: usize,
+}
+
+impl ChunkReader {
+    fn new(receiver: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.chunk.len() {
+            let Some(chunk) = self.receiver.blocking_recv() else {
+                return Ok(0);
+            };
+            self.chunk = chunk;
+            self.offset = 0;
+        }
+
+        let count = buffer.len().min(self.chunk.len() - self.offset);
+        buffer[..count].copy_from_slice(&self.chunk[self.offset..self.offset + count]);
+        self.offset += count;
+        Ok(count)
+    }
+}
+
+#[derive(Default)]
+struct Envelope {
+    status: Option<String>,
+    error: Option<String>,
+    error_type: Option<String>,
+}
+
+struct DecodeState<S> {
+    sink: S,
+    result_type: Option<String>,
+    entries: usize,
+}
+
+impl<S: LogEntrySink> DecodeState<S> {
+    fn emit(&mut self, stream: &StreamLabels, timestamp_nanos: String, line: String) {
+        self.entries += 1;
+        let entry = LogLineEntry::new(stream.labels.clone(), &timestamp_nanos, line);
+        self.sink.push(&stream.raw, &timestamp_nanos, entry);
+    }
+}
+
+struct StreamLabels {
+    raw: Map<String, Value>,
+    labels: BTreeMap<String, String>,
+}
+
+impl StreamLabels {
+    fn new(raw: Map<String, Value>) -> Self {
+        Self {
+            labels: stream_labels(&raw),
+            raw,
+        }
+    }
+}
+
+fn decode_streams<R: Read, S: LogEntrySink>(
+    reader: R,
+    sink: S,
+) -> serde_json::Result<(Envelope, DecodedLogs<S>)> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut state = DecodeState {
+        sink,
+        result_type: None,
+        entries: 0,
+    };
+    let envelope = deserializer.deserialize_map(EnvelopeVisitor { state: &mut state })?;
+    deserializer.end()?;
+
+    Ok((
+        envelope,
+        DecodedLogs {
+            sink: state.sink,
+            result_type: state.result_type,
+            entries: state.entries,
+        },
+    ))
+}
+
+/// `{"status": ..., "data": {...}, "error": ..., "errorType": ...}`
+struct EnvelopeVisitor<'a, S> {
+    state: &'a mut DecodeState<S>,
+}
+
+impl<'de, S: LogEntrySink> Visitor<'de> for EnvelopeVisitor<'_, S> {
+    type Value = Envelope;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Loki API response object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Envelope, A::Error> {
+        let mut envelope = Envelope::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "status" => envelope.status = map.next_value()?,
+                "error" => envelope.error = map.next_value()?,
+                "errorType" => envelope.error_type = map.next_value()?,
+                "data" => map.next_value_seed(DataSeed {
+                    state: &mut *self.state,
+                })?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(envelope)
+    }
+}
+
+/// `{"resultType": ..., "result": [...], "stats": {...}}`; `null` when Loki failed.
+struct DataSeed<'a, S> {
+    state: &'a mut DecodeState<S>,
+}
+
+impl<'de, S: LogEntrySink> DeserializeSeed<'de> for DataSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de, S: LogEntrySink> Visitor<'de> for DataSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Loki query result object")
+    }
+
+    fn visit_none<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "resultType" => self.state.result_type = map.next_value()?,
+                "result" => map.next_value_seed(ResultSeed {
+                    state: &mut *self.state,
+                })?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The list of streams.
+struct ResultSeed<'a, S> {
+    state: &'a mut DecodeState<S>,
+}
+
+impl<'de, S: LogEntrySink> DeserializeSeed<'de> for ResultSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, S: LogEntrySink> Visitor<'de> for ResultSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of streams")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq
+            .next_element_seed(StreamSeed {
+                state: &mut *self.state,
+            })?
+            .is_some()
+        {}
+
+        Ok(())
+    }
+}
+
+/// `{"stream": {...labels}, "values": [[timestamp, line], ...]}`
+struct StreamSeed<'a, S> {
+    state: &'a mut DecodeState<S>,
+}
+
+impl<'de, S: LogEntrySink> DeserializeSeed<'de> for StreamSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        // Scalar and string results are a bare `[timestamp, value]` array, not stream objects.
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, S: LogEntrySink> Visitor<'de> for StreamSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a stream object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut labels = None;
+        // Values that arrive before their labels wait here; Loki normally sends labels first.
+        let mut pending = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "stream" => {
+                    let stream = StreamLabels::new(map.next_value()?);
+                    for (timestamp, line) in pending.drain(..) {
+                        self.state.emit(&stream, timestamp, line);
+                    }
+                    labels = Some(stream);
+                }
+                "values" => map.next_value_seed(ValuesSeed {
+                    state: &mut *self.state,
+                    labels: labels.as_ref(),
+                    pending: &mut pending,
+                })?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            let stream = StreamLabels::new(Map::new());
+            for (timestamp, line) in pending {
+                self.state.emit(&stream, timestamp, line);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct ValuesSeed<'a, 'b, S> {
+    state: &'a mut DecodeState<S>,
+    labels: Option<&'b StreamLabels>,
+    pending: &'b mut Vec<(String, String)>,
+}
+
+impl<'de, S: LogEntrySink> DeserializeSeed<'de> for ValuesSeed<'_, '_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, S: LogEntrySink> Visitor<'de> for ValuesSeed<'_, '_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of [timestamp, line] pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element::<Value>()? {
+            // Matrix samples and malformed pairs are skipped, as `format_log_result` does.
+            let Some((timestamp, line)) = log_pair(value) else {
+                continue;
+            };
+            match self.labels {
+                Some(labels) => self.state.emit(labels, timestamp, line),
+                None => self.pending.push((timestamp, line)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn log_pair(value: Value) -> Option<(String, String)> {
+    let Value::Array(pair) = value else {
+        return None;
+    };
+    let [Value::String(timestamp), Value::String(line)] = <[Value; 2]>::try_from(pair).ok()? else {
+        return None;
+    };
+
+    Some((timestamp, line))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use axum::{Router, body::Body, routing::get};
+    use serde_json::{Map, Value};
+
+    use crate::{
+        loki::body::{LogEntrySink, decode_log_stream, decode_streams, read_limited},
+        response::LogLineEntry,
+    };
+
+    /// Serves `chunks` as a chunked body, so no `Content-Length` is declared up front.
+    async fn chunked_response(chunks: Vec<&'static str>) -> reqwest::Response {
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                let chunks = chunks.clone();
+                async move {
+                    Body::from_stream(futures::stream::iter(
+                        chunks.into_iter().map(Ok::<_, io::Error>),
+                    ))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind stand-in");
+        let address = listener.local_addr().expect("local addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        let response = reqwest::get(format!("http://{address}/"))
+            .await
+            .expect("response");
+        assert_eq!(response.content_length(), None);
+        response
+    }
+
+    const FIRST_CHUNK: &str = r#"{"status":"success","data":{"resultType":"streams","result":[{"stream":{"app":"api"},"values":[["1700000000000000000","first"]"#;
+
+    #[derive(Default)]
+    struct Collected(Vec<(String, String, LogLineEntry)>);
+
+    impl LogEntrySink for Collected {
+        fn push(
+            &mut self,
+            stream: &Map<String, Value>,
+            timestamp_nanos: &str,
+            entry: LogLineEntry,
+        ) {
+            self.0.push((
+                Value::Object(stream.clone()).to_string(),
+                timestamp_nanos.to_string(),
+                entry,
+            ));
+        }
+    }
+
+    #[test]
+    fn decodes_entries_in_order_whatever_the_key_order() {
+        let body = br#"{
+            "data": {
+                "stats": {"summary": {"bytesProcessedPerSecond": 1}},
+                "result": [
+                    {"values": [["1700000000000000000", "before labels"]], "stream": {"app": "api"}},
+                    {"stream": {"app": "web"}, "values": [["1700000001000000000", "after labels"], [1, "skipped"]]}
+                ],
+                "resultType": "streams"
+            },
+            "status": "success"
+        }"#;
+
+        let (envelope, decoded) = decode_streams(&body[..], Collected::default()).expect("decodes");
+        assert_eq!(envelope.status.as_deref(), Some("success"));
+        assert_eq!(decoded.result_type.as_deref(), Some("streams"));
+        assert_eq!(decoded.entries, 2);
+
+        let (stream, timestamp, entry) = &decoded.sink.0[0];
+        assert_eq!(stream, r#"{"app":"api"}"#);
+        assert_eq!(timestamp, "1700000000000000000");
+        assert_eq!(entry.line, "before labels");
+        assert_eq!(entry.timestamp, "2023-11-14T22:13:20+00:00");
+        assert_eq!(entry.stream["app"], "api");
+        assert_eq!(decoded.sink.0[1].2.line, "after labels");
+
+        let truncated = &body[..body.len() / 2];
+        let error = decode_streams(truncated, Collected::default())
+            .err()
+            .expect("truncated body fails");
+        assert!(error.is_eof());
+
+        let (envelope, decoded) = decode_streams(
+            &br#"{"status":"error","errorType":"bad_data","error":"parse error","data":null}"#[..],
+            Collected::default(),
+        )
+        .expect("decodes errors");
+        assert_eq!(envelope.error.as_deref(), Some("parse error"));
+        assert_eq!(decoded.entries, 0);
+    }
+
+    #[tokio::test]
+    async fn stops_streaming_once_the_body_passes_the_size_limit() {
+        let rest = r#",["1700000001000000000","second"]]}]}}"#;
+        let response = chunked_response(vec![FIRST_CHUNK, rest]).await;
+        let error = decode_log_stream(response, FIRST_CHUNK.len() as u64 + 4, Collected::default())
+            .await
+            .err()
+            .expect("oversized stream fails");
+        assert!(
+            error.to_string().contains("larger than max_response_bytes"),
+            "{error:#}"
+        );
+
+        let response = chunked_response(vec![FIRST_CHUNK, rest]).await;
+        let error = read_limited(response, FIRST_CHUNK.len() as u64)
+            .await
+            .expect_err("oversized body fails");
+        assert!(error.to_string().contains("larger than max_response_bytes"));
+
+        let response = chunked_response(vec![FIRST_CHUNK, rest]).await;
+        let decoded = decode_log_stream(response, 1024, Collected::default())
+            .await
+            .expect("body within the limit decodes");
+        assert_eq!(decoded.entries, 2);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_after_valid_entries_is_a_decode_error() {
+        let response = chunked_response(vec![FIRST_CHUNK, r#",["17", oops]]}]}}"#]).await;
+        let error = decode_log_stream(response, 1024, Collected::default())
+            .await
+            .err()
+            .expect("malformed body fails");
+        assert!(
+            format!("{error:#}").contains("failed to decode Loki JSON response"),
+            "{error:#}"
+        );
+
+        let body = format!("{FIRST_CHUNK}]}}]}}}} trailing");
+        let error = decode_streams(body.as_bytes(), Collected::default())
+            .err()
+            .expect("trailing data fails");
+        assert!(error.is_syntax());
+    }
+
+    #[test]
+    fn other_result_types_decode_without_entries() {
+        for (body, result_type) in [
+            (
+                r#"{"status":"success","data":{"resultType":"matrix","result":[{"metric":{"app":"api"},"values":[[1700000000,"3"]]}]}}"#,
+                "matrix",
+            ),
+            (
+                r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{"app":"api"},"value":[1700000000,"3"]}]}}"#,
+                "vector",
+            ),
+            (
+                r#"{"status":"success","data":{"resultType":"scalar","result":[1700000000.5,"1"]}}"#,
+                "scalar",
+            ),
+        ] {
+            let (envelope, decoded) =
+                decode_streams(body.as_bytes(), Collected::default()).expect(result_type);
+            assert_eq!(envelope.status.as_deref(), Some("success"));
+            assert_eq!(decoded.result_type.as_deref(), Some(result_type));
+            assert_eq!(decoded.entries, 0, "{result_type}");
+        }
+    }
+}
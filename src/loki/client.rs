@@ -1,17 +1,41 @@
 #![allow(dead_code)]
 
+use std::{sync::Arc, time::Duration};
+
 use anyhow::{Context, Result, anyhow, bail};
 use chrono::{DateTime, Utc};
-use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use futures::StreamExt;
+use reqwest::{
+    Client, Method, RequestBuilder, Response, StatusCode,
+    header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue},
+};
+use rustls::{
+    ClientConfig, RootCertStore,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    Connector, MaybeTlsStream, WebSocketStream, connect_async_tls_with_config,
+    tungstenite::{Message, client::IntoClientRequest},
+};
+use tracing::debug;
 
 use crate::{
     config::LokiConfig,
+    guardrails::parse_byte_size,
     loki::{
         auth::LokiAuth,
-        types::{LokiApiResponse, LokiHealth, LokiQueryStats},
+        body::{DecodedLogs, LogEntrySink, decode_log_stream, read_limited},
+        resilience::{
+            CircuitBreakers, OpenCircuit, RetryPolicy, endpoint_key, is_retryable_error,
+            is_retryable_status, retry_after,
+        },
+        types::{LokiApiResponse, LokiHealth, LokiQueryStats, LokiTailBatch},
     },
+    metrics::MetricsRegistry,
     time::parse_std_duration,
 };
 
@@ -21,12 +45,64 @@ pub struct LokiClient {
     base_url: String,
     tenant_id: Option<String>,
     auth: LokiAuth,
+    /// Configured `headers`, sent with every request.
+    headers: HeaderMap,
+    /// Lower-cased names of inbound MCP headers that may be copied onto Loki requests.
+    forward_headers: Arc<[String]>,
+    /// Allow-listed inbound headers of the current call.
+    forwarded: HeaderMap,
+    timeout: Duration,
+    max_response_bytes: u64,
+    websocket_tls: Option<Arc<ClientConfig>>,
+    retry: RetryPolicy,
+    breakers: CircuitBreakers,
+    metrics: Option<(String, MetricsRegistry)>,
+}
+
+/// An open `/loki/api/v1/tail` WebSocket.
+pub struct LokiTailStream {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl LokiTailStream {
+    /// Waits for the next batch of entries. Returns `None` once Loki closes the connection.
+    pub async fn next_batch(&mut self) -> Result<Option<LokiTailBatch>> {
+        while let Some(message) = self.socket.next().await {
+            match message.context("Loki tail connection failed")? {
+                Message::Text(text) => {
+                    return serde_json::from_str(text.as_str())
+                        .map(Some)
+                        .context("failed to decode Loki tail message");
+                }
+                Message::Binary(bytes) => {
+                    return serde_json::from_slice(&bytes)
+                        .map(Some)
+                        .context("failed to decode Loki tail message");
+                }
+                Message::Close(_) => return Ok(None),
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.socket.close(None).await;
+    }
 }
 
 impl LokiClient {
     pub fn new(config: &LokiConfig) -> Result<Self> {
         let timeout = parse_std_duration(&config.timeout)
             .with_context(|| format!("invalid loki.timeout: {}", config.timeout))?;
+        let max_response_bytes =
+            parse_byte_size(&config.max_response_bytes).with_context(|| {
+                format!(
+                    "invalid loki.max_response_bytes: {}",
+                    config.max_response_bytes
+                )
+            })?;
 
         let mut builder = Client::builder()
             .timeout(timeout)
@@ -40,20 +116,103 @@ impl LokiClient {
             builder = builder.add_root_certificate(certificate);
         }
 
+        if let (Some(cert_path), Some(key_path)) =
+            (config.client_cert.as_deref(), config.client_key.as_deref())
+        {
+            let mut pem = std::fs::read(cert_path)
+                .with_context(|| format!("failed to read client certificate from {cert_path}"))?;
+            pem.push(b'\n');
+            pem.extend(
+                std::fs::read(key_path)
+                    .with_context(|| format!("failed to read client key from {key_path}"))?,
+            );
+            let identity = reqwest::Identity::from_pem(&pem).with_context(|| {
+                format!("invalid PEM client certificate/key at {cert_path} and {key_path}")
+            })?;
+            builder = builder.identity(identity);
+        }
+
         let client = builder
             .build()
             .context("failed to build Loki HTTP client")?;
 
-        let auth = LokiAuth::from_config(config)?;
+        let auth = LokiAuth::from_config(config, &client)?;
+        let websocket_tls = if config.url.starts_with("https://") {
+            Some(websocket_tls_config(config)?)
+        } else {
+            None
+        };
 
         Ok(Self {
             client,
             base_url: config.url.trim_end_matches('/').to_string(),
             tenant_id: config.tenant_id.clone(),
             auth,
+            headers: static_headers(config)?,
+            forward_headers: config.forward_headers.iter().cloned().collect(),
+            forwarded: HeaderMap::new(),
+            timeout,
+            max_response_bytes,
+            websocket_tls,
+            retry: RetryPolicy::from_config(&config.retry)?,
+            breakers: CircuitBreakers::from_config(&config.circuit_breaker)?,
+            metrics: None,
         })
     }
 
+    /// Reports retries and circuit breaker state for this client under `datasource`.
+    pub fn with_metrics(self, datasource: &str, metrics: MetricsRegistry) -> Self {
+        Self {
+            breakers: self.breakers.with_metrics(datasource, metrics.clone()),
+            metrics: Some((datasource.to_string(), metrics)),
+            ..self
+        }
+    }
+
+    /// Endpoints whose circuit breaker is currently rejecting calls.
+    pub fn open_circuits(&self) -> Vec<OpenCircuit> {
+        self.breakers.open_circuits()
+    }
+
+    /// Returns a client that also sends the allow-listed (`forward_headers`) subset of `inbound`.
+    pub fn with_forwarded_headers(&self, inbound: &HeaderMap) -> Self {
+        let mut forwarded = HeaderMap::new();
+        for name in self.forward_headers.iter() {
+            for value in inbound.get_all(name.as_str()) {
+                if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                    forwarded.append(name, value.clone());
+                }
+            }
+        }
+
+        Self {
+            forwarded,
+            ..self.clone()
+        }
+    }
+
+    /// Whether any inbound headers can be forwarded by [`Self::with_forwarded_headers`].
+    pub fn forwards_headers(&self) -> bool {
+        !self.forward_headers.is_empty()
+    }
+
+    /// The forwarded headers as `name=value` pairs, so cached responses stay scoped to them.
+    pub fn forwarded_headers_key(&self) -> String {
+        self.forwarded
+            .iter()
+            .map(|(name, value)| format!("{name}={}", String::from_utf8_lossy(value.as_bytes())))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    /// Returns a client that sends `tenant_id` as `X-Scope-OrgID` instead of the configured tenant.
+    pub fn with_tenant(&self, tenant_id: Option<String>) -> Self {
+        Self {
+            tenant_id,
+            ..self.clone()
+        }
+    }
+
     pub async fn check_health(&self) -> Result<LokiHealth> {
         let readiness = self.probe_readiness().await;
         let build_info = self
@@ -157,6 +316,33 @@ impl LokiClient {
         self.send_api_data(request).await
     }
 
+    /// Runs a log query like [`Self::query_logs`], but hands entries to `sink` as the response
+    /// downloads instead of returning the decoded body.
+    pub async fn stream_logs<S>(
+        &self,
+        query: &str,
+        (start, end): (DateTime<Utc>, DateTime<Utc>),
+        limit: u32,
+        direction: Option<&str>,
+        sink: S,
+    ) -> Result<DecodedLogs<S>>
+    where
+        S: LogEntrySink + Send + 'static,
+    {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        append_time_range(&mut params, Some(start), Some(end))?;
+        params.push(("limit".to_string(), limit.to_string()));
+        if let Some(direction) = direction {
+            params.push(("direction".to_string(), direction.to_string()));
+        }
+
+        let request = self
+            .request(Method::GET, "/loki/api/v1/query_range")
+            .query(&params);
+        let response = self.send(request).await?;
+        decode_log_stream(response, self.max_response_bytes, sink).await
+    }
+
     pub async fn query_metrics(
         &self,
         query: &str,
@@ -177,6 +363,19 @@ impl LokiClient {
         self.send_api_data(request).await
     }
 
+    /// Evaluates a query at a single instant via `/loki/api/v1/query` (Loki defaults `time` to now).
+    pub async fn query_instant(&self, query: &str, time: Option<DateTime<Utc>>) -> Result<Value> {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        if let Some(time) = time {
+            params.push(("time".to_string(), timestamp_nanos(time)?));
+        }
+
+        let request = self
+            .request(Method::GET, "/loki/api/v1/query")
+            .query(&params);
+        self.send_api_data(request).await
+    }
+
     pub async fn query_stats(
         &self,
         query: &str,
@@ -194,6 +393,84 @@ impl LokiClient {
         Ok(LokiQueryStats::from_value(data))
     }
 
+    /// Per-stream byte totals from `/loki/api/v1/index/volume`, aggregated by `aggregate_by`
+    /// (`series` or `labels`) and optionally grouped by `target_labels`.
+    pub async fn volume(
+        &self,
+        query: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: Option<u32>,
+        target_labels: &[String],
+        aggregate_by: Option<&str>,
+    ) -> Result<Value> {
+        let params = volume_params(query, start, end, limit, target_labels, aggregate_by)?;
+        let request = self
+            .request(Method::GET, "/loki/api/v1/index/volume")
+            .query(&params);
+        self.send_api_data(request).await
+    }
+
+    /// Like [`Self::volume`], but bucketed by `step` via `/loki/api/v1/index/volume_range`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn volume_range(
+        &self,
+        query: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        step: &str,
+        limit: Option<u32>,
+        target_labels: &[String],
+        aggregate_by: Option<&str>,
+    ) -> Result<Value> {
+        let mut params = volume_params(query, start, end, limit, target_labels, aggregate_by)?;
+        params.push(("step".to_string(), step.to_string()));
+        let request = self
+            .request(Method::GET, "/loki/api/v1/index/volume_range")
+            .query(&params);
+        self.send_api_data(request).await
+    }
+
+    /// Fields Loki finds inside matching lines (parsed JSON/logfmt keys and structured metadata).
+    pub async fn detected_fields(
+        &self,
+        query: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        line_limit: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<Value> {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        append_time_range(&mut params, start, end)?;
+        if let Some(line_limit) = line_limit {
+            params.push(("line_limit".to_string(), line_limit.to_string()));
+        }
+        if let Some(limit) = limit {
+            params.push(("limit".to_string(), limit.to_string()));
+        }
+
+        let request = self
+            .request(Method::GET, "/loki/api/v1/detected_fields")
+            .query(&params);
+        self.send_api_data_or_raw(request).await
+    }
+
+    /// Labels of the streams matching `query`, with their cardinality.
+    pub async fn detected_labels(
+        &self,
+        query: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> Result<Value> {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        append_time_range(&mut params, start, end)?;
+
+        let request = self
+            .request(Method::GET, "/loki/api/v1/detected_labels")
+            .query(&params);
+        self.send_api_data_or_raw(request).await
+    }
+
     pub async fn detect_patterns(
         &self,
         query: &str,
@@ -214,6 +491,40 @@ impl LokiClient {
         self.send_api_data(request).await
     }
 
+    /// Rule groups as the ruler stores them: YAML keyed by namespace, optionally just one namespace.
+    pub async fn rules(&self, namespace: Option<&str>) -> Result<String> {
+        let path = match namespace {
+            Some(namespace) => {
+                validate_rule_namespace(namespace)?;
+                format!("/loki/api/v1/rules/{namespace}")
+            }
+            None => "/loki/api/v1/rules".to_string(),
+        };
+        let response = self.send(self.request(Method::GET, &path)).await?;
+        let body = read_limited(response, self.max_response_bytes).await?;
+
+        String::from_utf8(body).context("failed to read Loki rules response")
+    }
+
+    /// Evaluated rule groups in the Prometheus-compatible format, with rule health and state.
+    pub async fn prometheus_rules(&self, rule_type: Option<&str>) -> Result<Value> {
+        let mut params = Vec::new();
+        if let Some(rule_type) = rule_type {
+            params.push(("type".to_string(), rule_type.to_string()));
+        }
+
+        let request = self
+            .request(Method::GET, "/prometheus/api/v1/rules")
+            .query(&params);
+        self.send_api_data(request).await
+    }
+
+    /// Alerts currently pending or firing in the ruler.
+    pub async fn alerts(&self) -> Result<Value> {
+        let request = self.request(Method::GET, "/prometheus/api/v1/alerts");
+        self.send_api_data(request).await
+    }
+
     pub async fn query_runtime_stats(
         &self,
         query: &str,
@@ -247,30 +558,149 @@ impl LokiClient {
         })
     }
 
+    /// Opens a live tail over `/loki/api/v1/tail`, starting at `start` (Loki defaults to one hour ago).
+    pub async fn tail(
+        &self,
+        query: &str,
+        start: Option<DateTime<Utc>>,
+        limit: Option<u32>,
+    ) -> Result<LokiTailStream> {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        append_time_range(&mut params, start, None)?;
+        if let Some(limit) = limit {
+            params.push(("limit".to_string(), limit.to_string()));
+        }
+
+        // Build through the regular request path so auth and tenant headers stay in one place.
+        let mut request = self
+            .request(Method::GET, "/loki/api/v1/tail")
+            .query(&params)
+            .build()
+            .context("failed to build Loki tail request")?;
+        self.auth.apply(&mut request).await?;
+        let mut url = request.url().clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_| anyhow!("invalid Loki URL for tail: {url}"))?;
+
+        let mut websocket_request = url
+            .as_str()
+            .into_client_request()
+            .context("failed to build Loki tail request")?;
+        websocket_request
+            .headers_mut()
+            .extend(request.headers().clone());
+
+        let connector = self.websocket_tls.clone().map(Connector::Rustls);
+        let (socket, _) = tokio::time::timeout(
+            self.timeout,
+            connect_async_tls_with_config(websocket_request, None, false, connector),
+        )
+        .await
+        .map_err(|_| anyhow!("timed out connecting to Loki tail endpoint"))?
+        .context("failed to open Loki tail WebSocket")?;
+
+        Ok(LokiTailStream { socket })
+    }
+
     fn request(&self, method: Method, path: &str) -> RequestBuilder {
         let endpoint = format!("{}{}", self.base_url, path);
-        let builder = self.client.request(method, endpoint);
+        // Configured headers first, so forwarded values and the tenant take precedence.
+        let mut headers = self.headers.clone();
+        headers.extend(self.forwarded.clone());
+        let builder = self.client.request(method, endpoint).headers(headers);
 
-        let builder = if let Some(tenant_id) = self.tenant_id.as_deref() {
+        if let Some(tenant_id) = self.tenant_id.as_deref() {
             builder.header("X-Scope-OrgID", tenant_id)
         } else {
             builder
-        };
+        }
+    }
 
-        self.auth.apply(builder)
+    /// Sends a request once, outside retries and circuit breakers, with credentials attached.
+    async fn send_once(&self, builder: RequestBuilder) -> Result<Response> {
+        let mut request = builder.build().context("failed to build Loki request")?;
+        self.auth.apply(&mut request).await?;
+        self.client
+            .execute(request)
+            .await
+            .context("request to Loki failed")
     }
 
     async fn send_json<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T> {
-        let response = builder.send().await.context("request to Loki failed")?;
+        let response = self.send(builder).await?;
+        let body = read_limited(response, self.max_response_bytes).await?;
 
-        let response = response
-            .error_for_status()
-            .context("Loki returned non-success status")?;
+        serde_json::from_slice(&body).context("failed to decode Loki JSON response")
+    }
 
-        response
-            .json::<T>()
-            .await
-            .context("failed to decode Loki JSON response")
+    /// Sends a request through the endpoint's circuit breaker, retrying idempotent GETs on
+    /// transient failures with jittered backoff and honouring `Retry-After`. A 401 is retried
+    /// once with fresh credentials when the auth mode can renew them.
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let mut request = builder.build().context("failed to build Loki request")?;
+        let endpoint = endpoint_key(request.url().path());
+        self.breakers.acquire(&endpoint)?;
+
+        let idempotent = request.method() == Method::GET;
+        let mut attempt = 0;
+        let mut reauthorized = false;
+        let result = loop {
+            let Some(mut attempt_request) = request.try_clone() else {
+                // Streaming bodies cannot be replayed, so they get a single attempt.
+                self.auth.apply(&mut request).await?;
+                break self.client.execute(request).await;
+            };
+            self.auth.apply(&mut attempt_request).await?;
+            let authorization = attempt_request.headers().get(AUTHORIZATION).cloned();
+            let result = self.client.execute(attempt_request).await;
+            if !reauthorized
+                && result
+                    .as_ref()
+                    .is_ok_and(|response| response.status() == StatusCode::UNAUTHORIZED)
+                && self.auth.invalidate(authorization.as_ref()).await
+            {
+                reauthorized = true;
+                debug!(endpoint = %endpoint, "Loki rejected the access token; fetching a new one");
+                continue;
+            }
+            let transient = match &result {
+                Ok(response) => is_retryable_status(response.status()),
+                Err(error) => is_retryable_error(error),
+            };
+            if !transient || !idempotent || attempt >= self.retry.max_retries() {
+                break result;
+            }
+
+            let requested_wait = result
+                .as_ref()
+                .ok()
+                .and_then(|response| retry_after(response.headers(), Utc::now()));
+            let Some(delay) = self.retry.delay(attempt, requested_wait) else {
+                break result;
+            };
+            attempt += 1;
+            if let Some((datasource, metrics)) = self.metrics.as_ref() {
+                metrics.inc_loki_retry(datasource, &endpoint);
+            }
+            debug!(endpoint = %endpoint, attempt, delay_ms = delay.as_millis() as u64, "retrying Loki request");
+            tokio::time::sleep(delay).await;
+        };
+
+        let healthy = match &result {
+            Ok(response) => !response.status().is_server_error(),
+            Err(_) => false,
+        };
+        if healthy {
+            self.breakers.record_success(&endpoint);
+        } else {
+            self.breakers.record_failure(&endpoint);
+        }
+
+        result
+            .context("request to Loki failed")?
+            .error_for_status()
+            .context("Loki returned non-success status")
     }
 
     async fn send_api_data<T: DeserializeOwned + Default>(
@@ -316,7 +746,7 @@ impl LokiClient {
     }
 
     async fn get_optional_json(&self, path: &str) -> Option<Value> {
-        let response = self.request(Method::GET, path).send().await.ok()?;
+        let response = self.send_once(self.request(Method::GET, path)).await.ok()?;
         if !response.status().is_success() {
             return None;
         }
@@ -325,17 +755,16 @@ impl LokiClient {
     }
 
     async fn probe_readiness(&self) -> ReadinessProbe {
-        match self.request(Method::GET, "/ready").send().await {
+        match self.send_once(self.request(Method::GET, "/ready")).await {
             Ok(response) if response.status().is_success() => ReadinessProbe::Ready,
             Ok(response) => ReadinessProbe::Status(response.status()),
-            Err(error) => ReadinessProbe::Error(error.to_string()),
+            Err(error) => ReadinessProbe::Error(format!("{error:#}")),
         }
     }
 
     async fn is_loki_api_reachable(&self) -> bool {
         match self
-            .request(Method::GET, "/loki/api/v1/labels")
-            .send()
+            .send_once(self.request(Method::GET, "/loki/api/v1/labels"))
             .await
         {
             Ok(response) => response.status().is_success(),
@@ -365,6 +794,57 @@ fn evaluate_health(readiness: ReadinessProbe, api_reachable: bool) -> (bool, Opt
     }
 }
 
+fn websocket_tls_config(config: &LokiConfig) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Some(ca_cert_path) = config.ca_cert.as_deref() {
+        let certificates = CertificateDer::pem_file_iter(ca_cert_path)
+            .with_context(|| format!("failed to read CA certificate from {ca_cert_path}"))?
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .with_context(|| format!("invalid PEM CA certificate at {ca_cert_path}"))?;
+        for certificate in certificates {
+            roots
+                .add(certificate)
+                .with_context(|| format!("invalid CA certificate at {ca_cert_path}"))?;
+        }
+    }
+
+    let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("failed to select TLS protocol versions")?
+        .with_root_certificates(roots);
+    let tls_config = match (config.client_cert.as_deref(), config.client_key.as_deref()) {
+        (Some(cert_path), Some(key_path)) => {
+            let certificates = CertificateDer::pem_file_iter(cert_path)
+                .with_context(|| format!("failed to read client certificate from {cert_path}"))?
+                .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+                .with_context(|| format!("invalid PEM client certificate at {cert_path}"))?;
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .with_context(|| format!("invalid PEM client key at {key_path}"))?;
+            builder
+                .with_client_auth_cert(certificates, key)
+                .context("client certificate does not match its key")?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(tls_config))
+}
+
+fn static_headers(config: &LokiConfig) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("invalid loki header name: {name}"))?;
+        let header_value = HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for loki header {name}"))?;
+        headers.insert(header_name, header_value);
+    }
+
+    Ok(headers)
+}
+
 fn append_time_range(
     params: &mut Vec<(String, String)>,
     start: Option<DateTime<Utc>>,
@@ -381,6 +861,32 @@ fn append_time_range(
     Ok(())
 }
 
+fn volume_params(
+    query: &str,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<u32>,
+    target_labels: &[String],
+    aggregate_by: Option<&str>,
+) -> Result<Vec<(String, String)>> {
+    let mut params = vec![("query".to_string(), query.to_string())];
+    append_time_range(&mut params, start, end)?;
+    if let Some(limit) = limit {
+        params.push(("limit".to_string(), limit.to_string()));
+    }
+    if !target_labels.is_empty() {
+        for label in target_labels {
+            validate_label_name(label)?;
+        }
+        params.push(("targetLabels".to_string(), target_labels.join(",")));
+    }
+    if let Some(aggregate_by) = aggregate_by {
+        params.push(("aggregateBy".to_string(), aggregate_by.to_string()));
+    }
+
+    Ok(params)
+}
+
 fn timestamp_nanos(value: DateTime<Utc>) -> Result<String> {
     let nanos = value
         .timestamp_nanos_opt()
@@ -389,6 +895,21 @@ fn timestamp_nanos(value: DateTime<Utc>) -> Result<String> {
     Ok(nanos.to_string())
 }
 
+fn validate_rule_namespace(namespace: &str) -> Result<()> {
+    if namespace.is_empty() {
+        bail!("namespace must not be empty");
+    }
+
+    if namespace
+        .chars()
+        .any(|character| matches!(character, '/' | '?' | '#' | '%') || character.is_whitespace())
+    {
+        bail!("namespace contains unsupported characters: {namespace}");
+    }
+
+    Ok(())
+}
+
 fn validate_label_name(label: &str) -> Result<()> {
     if label.is_empty() {
         bail!("label must not be empty");
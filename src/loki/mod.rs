@@ -1,3 +1,5 @@
 pub mod auth;
+pub mod body;
 pub mod client;
+pub mod resilience;
 pub mod types;
@@ -0,0 +1,417 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, RandomState},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, header::HeaderMap};
+use serde::Serialize;
+
+use crate::{
+    config::{CircuitBreakerConfig, LokiRetryConfig},
+    metrics::MetricsRegistry,
+    time::parse_std_duration,
+};
+
+/// Jittered exponential backoff for transient Loki failures.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &LokiRetryConfig) -> Result<Self> {
+        Ok(Self {
+            max_retries: config.max_retries,
+            initial_backoff: parse_std_duration(&config.initial_backoff).with_context(|| {
+                format!("invalid retry.initial_backoff: {}", config.initial_backoff)
+            })?,
+            max_backoff: parse_std_duration(&config.max_backoff)
+                .with_context(|| format!("invalid retry.max_backoff: {}", config.max_backoff))?,
+        })
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Delay before retry number `attempt` (zero-based), or `None` when Loki asked us to wait
+    /// longer than `max_backoff` and the call should fail instead.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        // Equal jitter: never less than half the exponential step, so retries stay spread out.
+        let half = ceiling / 2;
+        let jittered = half + jitter(ceiling - half);
+
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_backoff => None,
+            Some(retry_after) => Some(jittered.max(retry_after)),
+            None => Some(jittered),
+        }
+    }
+}
+
+fn jitter(range: Duration) -> Duration {
+    let nanos = u64::try_from(range.as_nanos()).unwrap_or(u64::MAX);
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+
+    Duration::from_nanos(RandomState::new().hash_one(Instant::now()) % (nanos + 1))
+}
+
+/// Status codes Loki returns while overloaded or mid-rollout.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Connection failures and resets are retried; timeouts are not, since each already took the
+/// full `loki.timeout`.
+pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+    !error.is_timeout() && (error.is_connect() || error.is_request())
+}
+
+/// Parses `Retry-After` as delay-seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((at - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Groups request paths into breaker endpoints, folding path parameters so label names and rule
+/// namespaces do not each get their own breaker (and metric series).
+pub fn endpoint_key(path: &str) -> String {
+    match path
+        .strip_prefix("/loki/api/v1/label/")
+        .and_then(|rest| rest.strip_suffix("/values"))
+    {
+        Some(_) => "/loki/api/v1/label/{name}/values".to_string(),
+        None if path.starts_with("/loki/api/v1/rules/") => {
+            "/loki/api/v1/rules/{namespace}".to_string()
+        }
+        None => path.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    HalfOpen,
+    Open,
+}
+
+impl CircuitState {
+    fn metric_value(self) -> i64 {
+        match self {
+            Self::Closed => 0,
+            Self::HalfOpen => 1,
+            Self::Open => 2,
+        }
+    }
+}
+
+/// An endpoint whose breaker is currently rejecting calls.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenCircuit {
+    pub endpoint: String,
+    pub consecutive_failures: u32,
+    pub retry_in_seconds: u64,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trial_started: Option<Instant>,
+}
+
+impl Breaker {
+    /// A trial abandoned mid-flight (e.g. the tool call was cancelled) expires after
+    /// `open_duration` so the endpoint cannot stay half-open forever.
+    fn trial_in_flight(&self, open_duration: Duration) -> bool {
+        self.trial_started
+            .is_some_and(|started| started.elapsed() < open_duration)
+    }
+}
+
+/// Per-endpoint circuit breakers for one Loki datasource, shared by every clone of its client.
+///
+/// After `failure_threshold` consecutive failed calls (connection errors, timeouts or 5xx) an
+/// endpoint rejects calls for `open_duration`, then lets a single trial call through: success
+/// closes it, failure opens it again.
+#[derive(Clone)]
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    open_duration: Duration,
+    enabled: bool,
+    endpoints: Arc<Mutex<HashMap<String, Breaker>>>,
+    observer: Option<(String, MetricsRegistry)>,
+}
+
+impl CircuitBreakers {
+    pub fn from_config(config: &CircuitBreakerConfig) -> Result<Self> {
+        let open_duration = if config.enabled {
+            parse_std_duration(&config.open_duration).with_context(|| {
+                format!(
+                    "invalid circuit_breaker.open_duration: {}",
+                    config.open_duration
+                )
+            })?
+        } else {
+            Duration::ZERO
+        };
+
+        Ok(Self {
+            failure_threshold: config.failure_threshold.max(1),
+            open_duration,
+            enabled: config.enabled,
+            endpoints: Arc::default(),
+            observer: None,
+        })
+    }
+
+    /// Reports state changes as the `loki_circuit_breaker_state` gauge for `datasource`.
+    pub fn with_metrics(self, datasource: &str, metrics: MetricsRegistry) -> Self {
+        Self {
+            observer: Some((datasource.to_string(), metrics)),
+            ..self
+        }
+    }
+
+    /// Admits a call to `endpoint`, or fails fast while its breaker is open.
+    pub fn acquire(&self, endpoint: &str) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let mut endpoints = self.lock();
+        let breaker = endpoints.entry(endpoint.to_string()).or_default();
+        let Some(opened_at) = breaker.opened_at else {
+            return Ok(());
+        };
+
+        let elapsed = opened_at.elapsed();
+        if elapsed < self.open_duration || breaker.trial_in_flight(self.open_duration) {
+            let failures = breaker.consecutive_failures;
+            let retry_in = self.open_duration.saturating_sub(elapsed).as_secs().max(1);
+            drop(endpoints);
+            if let Some((datasource, metrics)) = self.observer.as_ref() {
+                metrics.inc_loki_circuit_rejection(datasource, endpoint);
+            }
+            bail!(
+                "circuit breaker open for Loki {endpoint} after {failures} consecutive failures; Loki looks unhealthy, retry in {retry_in}s"
+            );
+        }
+
+        breaker.trial_started = Some(Instant::now());
+        drop(endpoints);
+        self.report(endpoint, CircuitState::HalfOpen);
+        Ok(())
+    }
+
+    pub fn record_success(&self, endpoint: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let changed = {
+            let mut endpoints = self.lock();
+            let breaker = endpoints.entry(endpoint.to_string()).or_default();
+            let changed = breaker.opened_at.is_some();
+            *breaker = Breaker::default();
+            changed
+        };
+        if changed {
+            self.report(endpoint, CircuitState::Closed);
+        }
+    }
+
+    pub fn record_failure(&self, endpoint: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let opened = {
+            let mut endpoints = self.lock();
+            let breaker = endpoints.entry(endpoint.to_string()).or_default();
+            breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+            let opened = breaker.trial_started.is_some()
+                || (breaker.opened_at.is_none()
+                    && breaker.consecutive_failures >= self.failure_threshold);
+            if opened {
+                breaker.opened_at = Some(Instant::now());
+                breaker.trial_started = None;
+            }
+            opened
+        };
+        if opened {
+            self.report(endpoint, CircuitState::Open);
+        }
+    }
+
+    pub fn state(&self, endpoint: &str) -> CircuitState {
+        let endpoints = self.lock();
+        match endpoints.get(endpoint) {
+            Some(breaker) if breaker.trial_started.is_some() => CircuitState::HalfOpen,
+            Some(breaker) if breaker.opened_at.is_some() => CircuitState::Open,
+            _ => CircuitState::Closed,
+        }
+    }
+
+    /// Endpoints that are open or probing, sorted by endpoint.
+    pub fn open_circuits(&self) -> Vec<OpenCircuit> {
+        let endpoints = self.lock();
+        let mut open = endpoints
+            .iter()
+            .filter_map(|(endpoint, breaker)| {
+                let opened_at = breaker.opened_at?;
+                Some(OpenCircuit {
+                    endpoint: endpoint.clone(),
+                    consecutive_failures: breaker.consecutive_failures,
+                    retry_in_seconds: self
+                        .open_duration
+                        .saturating_sub(opened_at.elapsed())
+                        .as_secs(),
+                })
+            })
+            .collect::<Vec<OpenCircuit>>();
+        open.sort_by(|left, right| left.endpoint.cmp(&right.endpoint));
+        open
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Breaker>> {
+        self.endpoints
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn report(&self, endpoint: &str, state: CircuitState) {
+        if let Some((datasource, metrics)) = self.observer.as_ref() {
+            metrics.set_loki_circuit_state(datasource, endpoint, state.metric_value());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    use crate::{
+        config::{CircuitBreakerConfig, LokiRetryConfig},
+        loki::resilience::{CircuitBreakers, CircuitState, RetryPolicy, endpoint_key, retry_after},
+    };
+
+    #[test]
+    fn backoff_grows_with_jitter_and_honours_retry_after() {
+        let policy = RetryPolicy::from_config(&LokiRetryConfig {
+            max_retries: 3,
+            initial_backoff: "100ms".to_string(),
+            max_backoff: "1s".to_string(),
+        })
+        .expect("policy");
+
+        for attempt in 0..6 {
+            let ceiling =
+                Duration::from_millis(100 * 2_u64.pow(attempt)).min(Duration::from_secs(1));
+            let delay = policy.delay(attempt, None).expect("delay");
+            assert!(
+                delay >= ceiling / 2 && delay <= ceiling,
+                "{delay:?} for {attempt}"
+            );
+        }
+
+        assert!(
+            policy
+                .delay(0, Some(Duration::from_millis(800)))
+                .expect("delay")
+                >= Duration::from_millis(800)
+        );
+        assert!(policy.delay(0, Some(Duration::from_secs(2))).is_none());
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, now), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(3)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Thu, 01 Jan 2026 00:00:05 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(5)));
+
+        assert_eq!(
+            endpoint_key("/loki/api/v1/label/app/values"),
+            "/loki/api/v1/label/{name}/values"
+        );
+        assert_eq!(
+            endpoint_key("/loki/api/v1/rules/team-a"),
+            "/loki/api/v1/rules/{namespace}"
+        );
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_after_threshold_and_closes_after_a_good_trial() {
+        let breakers = CircuitBreakers::from_config(&CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 2,
+            open_duration: "50ms".to_string(),
+        })
+        .expect("breakers");
+        let endpoint = "/loki/api/v1/query_range";
+
+        breakers.acquire(endpoint).expect("closed");
+        breakers.record_failure(endpoint);
+        assert_eq!(breakers.state(endpoint), CircuitState::Closed);
+        breakers.record_failure(endpoint);
+        assert_eq!(breakers.state(endpoint), CircuitState::Open);
+        assert!(breakers.acquire(endpoint).is_err());
+        assert!(breakers.acquire("/loki/api/v1/labels").is_ok());
+        assert_eq!(breakers.open_circuits().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        breakers.acquire(endpoint).expect("trial call");
+        assert_eq!(breakers.state(endpoint), CircuitState::HalfOpen);
+        assert!(breakers.acquire(endpoint).is_err(), "one trial at a time");
+        breakers.record_failure(endpoint);
+        assert_eq!(breakers.state(endpoint), CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        breakers.acquire(endpoint).expect("second trial");
+        breakers.record_success(endpoint);
+        assert_eq!(breakers.state(endpoint), CircuitState::Closed);
+        assert!(breakers.open_circuits().is_empty());
+    }
+}
@@ -18,6 +18,16 @@ pub struct LokiApiResponse<T> {
     pub error_type: Option<String>,
 }
 
+/// One message from the `/loki/api/v1/tail` WebSocket.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LokiTailBatch {
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub streams: Vec<Value>,
+    // Loki sends `"dropped_entries": null` when nothing was dropped.
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub dropped_entries: Vec<Value>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LokiHealth {
     pub healthy: bool,
@@ -62,6 +72,13 @@ impl LokiQueryStats {
 
 pub type LabelSet = BTreeMap<String, String>;
 
+fn null_as_empty<'de, D>(deserializer: D) -> Result<Vec<Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<Vec<Value>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 fn extract_u64(value: &Value, keys: &[&str]) -> Option<u64> {
     if let Some(object) = value.as_object() {
         for key in keys {
@@ -3,6 +3,7 @@ use std::{
     future::{self, Future},
     hash::{Hash, Hasher},
     net::SocketAddr,
+    sync::{Arc, RwLock},
     time::Instant,
 };
 
@@ -12,32 +13,48 @@ use rmcp::{
     ErrorData as McpError, RoleServer, ServerHandler,
     handler::server::tool::schema_for_type,
     model::{
-        CallToolRequestParams, CallToolResult, ListToolsResult, PaginatedRequestParams,
-        ServerCapabilities, ServerInfo, Tool, ToolAnnotations,
+        CallToolRequestParams, CallToolResult, GetPromptRequestParams, GetPromptResult,
+        ListPromptsResult, ListResourceTemplatesResult, ListResourcesResult, ListToolsResult,
+        LoggingLevel, LoggingMessageNotificationParam, PaginatedRequestParams,
+        ProgressNotificationParam, ReadResourceRequestParams, ReadResourceResult,
+        ServerCapabilities, ServerInfo, SetLevelRequestParams, Tool, ToolAnnotations,
     },
     service::RequestContext,
 };
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde_json::{Map, Value, json};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::debug;
 
 use crate::{
+    auth::AuthenticatedIdentity,
     config::Config,
     metrics::MetricsRegistry,
+    prompts::PromptCatalog,
     rate_limit::ToolRateLimiter,
     recent_actions::{ActionOutcome, RecentActionInput, RecentActionsStore},
-    tools::ToolRouter,
+    resources::{self, ResourceCatalog},
+    tenants::TenantAccess,
+    tools::{self, LintedError, ProgressSender, ToolCallContext, ToolProgress, ToolRouter},
 };
 
+/// Metrics, rate-limit and recent-action name for `resources/read` calls.
+const RESOURCE_READ: &str = "resources/read";
+
 #[derive(Clone)]
 pub struct LokiMcpServer {
     tool_router: ToolRouter,
     tools: Vec<Tool>,
+    resources: ResourceCatalog,
+    prompts: PromptCatalog,
     metrics: MetricsRegistry,
     rate_limiter: Option<ToolRateLimiter>,
     identity_header: Option<String>,
-    tenant_id: Option<String>,
+    static_identity: Option<String>,
+    tenant_access: TenantAccess,
     recent_actions: Option<RecentActionsStore>,
+    logging_level: Arc<RwLock<LoggingLevel>>,
 }
 
 impl LokiMcpServer {
@@ -52,7 +69,11 @@ impl LokiMcpServer {
             None
         };
         let identity_header = config.server.identity_header.clone();
-        let tenant_id = config.loki.tenant_id.clone();
+        let static_identity = config.server.static_identity.clone();
+        let tenant_access = TenantAccess::new(&config.tenants);
+        let resources =
+            ResourceCatalog::new(config.clone()).context("failed to create resource catalog")?;
+        let prompts = PromptCatalog::new(&config);
         let tool_router = ToolRouter::new_with_metrics(config, Some(metrics.clone()))
             .context("failed to create tool router")?;
         let tools = build_tools();
@@ -60,19 +81,34 @@ impl LokiMcpServer {
         Ok(Self {
             tool_router,
             tools,
+            resources,
+            prompts,
             metrics,
             rate_limiter,
             identity_header,
-            tenant_id,
+            static_identity,
+            tenant_access,
             recent_actions,
+            logging_level: Arc::new(RwLock::new(LoggingLevel::Info)),
         })
     }
 
-    fn resolve_identity(&self, context: &RequestContext<RoleServer>) -> String {
-        let Some(parts) = context.extensions.get::<Parts>() else {
-            return "unknown".to_string();
+    pub fn tool_router(&self) -> &ToolRouter {
+        &self.tool_router
+    }
+
+    /// The caller identity used for rate limiting and recent-action records. Identity headers and
+    /// `x-forwarded-for` are client-controlled, so this is never used to pick a tenant.
+    fn resolve_identity(&self, parts: Option<&Parts>) -> String {
+        let Some(parts) = parts else {
+            // Non-HTTP transports (stdio) carry no request headers.
+            return self.fallback_identity();
         };
 
+        if let Some(authenticated) = parts.extensions.get::<AuthenticatedIdentity>() {
+            return authenticated.identity.clone();
+        }
+
         if let Some(identity_header) = self.identity_header.as_deref()
             && let Some(identity) = header_value(parts, identity_header)
         {
@@ -93,7 +129,64 @@ impl LokiMcpServer {
             return remote_address.ip().to_string();
         }
 
-        "unknown".to_string()
+        self.fallback_identity()
+    }
+
+    /// The identity and groups tenant mapping trusts: the authenticated identity on HTTP, or the
+    /// configured static identity on transports without request headers (stdio).
+    fn tenant_identity(&self, parts: Option<&Parts>) -> Option<(String, Vec<String>)> {
+        match parts {
+            Some(parts) => parts
+                .extensions
+                .get::<AuthenticatedIdentity>()
+                .map(|authenticated| {
+                    (authenticated.identity.clone(), authenticated.groups.clone())
+                }),
+            None => Some((self.fallback_identity(), Vec::new())),
+        }
+    }
+
+    /// Picks the tenant for a tool call from the caller's trusted identity.
+    fn resolve_tenant(
+        &self,
+        tool_name: &str,
+        parts: Option<&Parts>,
+        requested_tenant: Option<&str>,
+        requested_tenants: Option<&[String]>,
+        datasource_tenant: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        let (identity, groups) = match self.tenant_identity(parts) {
+            Some(trusted) => trusted,
+            None if self.tenant_access.enabled() => {
+                return Err(
+                    "tenant mapping requires an authenticated identity; send credentials accepted by server.auth or server.jwt"
+                        .to_string(),
+                );
+            }
+            None => (String::new(), Vec::new()),
+        };
+
+        match requested_tenants {
+            Some(_) if !tools::supports_federation(tool_name) => Err(format!(
+                "{tool_name} does not accept `tenants`; query one tenant at a time with `tenant`"
+            )),
+            Some(_) if requested_tenant.is_some() => {
+                Err("pass either `tenant` or `tenants`, not both".to_string())
+            }
+            Some(tenants) => self
+                .tenant_access
+                .resolve_federated(&identity, &groups, tenants),
+            None => {
+                self.tenant_access
+                    .resolve(&identity, &groups, requested_tenant, datasource_tenant)
+            }
+        }
+    }
+
+    fn fallback_identity(&self) -> String {
+        self.static_identity
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string())
     }
 
     fn resolve_request_id(&self, context: &RequestContext<RoleServer>) -> Option<String> {
@@ -101,6 +194,82 @@ impl LokiMcpServer {
         header_value(parts, "x-request-id")
     }
 
+    /// Relays tool progress to the client as progress notifications when the request carries a
+    /// progress token, otherwise as `info` logging messages.
+    fn forward_progress(
+        &self,
+        tool_name: &str,
+        context: &RequestContext<RoleServer>,
+    ) -> (ProgressSender, JoinHandle<()>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<ToolProgress>();
+        let peer = context.peer.clone();
+        let progress_token = context.meta.get_progress_token();
+        let logging_level = self.logging_level.clone();
+        let logger = tool_name.to_string();
+
+        let handle = tokio::spawn(async move {
+            while let Some(update) = receiver.recv().await {
+                let result = match progress_token.clone() {
+                    Some(progress_token) => {
+                        peer.notify_progress(ProgressNotificationParam {
+                            progress_token,
+                            progress: update.progress as f64,
+                            total: update.total.map(|total| total as f64),
+                            message: Some(update.message),
+                        })
+                        .await
+                    }
+                    None if logging_enabled(&logging_level, LoggingLevel::Info) => {
+                        peer.notify_logging_message(LoggingMessageNotificationParam {
+                            level: LoggingLevel::Info,
+                            logger: Some(logger.clone()),
+                            data: update.data,
+                        })
+                        .await
+                    }
+                    None => Ok(()),
+                };
+                if let Err(error) = result {
+                    debug!(tool = %logger, error = %error, "failed to send tool progress notification");
+                    break;
+                }
+            }
+        });
+
+        (sender, handle)
+    }
+
+    /// Admits a `resources/read` call. Live Loki catalogs resolve the caller's tenant the same
+    /// way tool calls do, and every read is rate limited. Returns the tenant to query.
+    fn admit_resource_read(
+        &self,
+        uri: &str,
+        parts: Option<&Parts>,
+        identity: &str,
+    ) -> Result<Option<String>, (ActionOutcome, &'static str, String)> {
+        let tenant_id = if resources::is_live_uri(uri) {
+            self.resolve_tenant(
+                RESOURCE_READ,
+                parts,
+                None,
+                None,
+                self.tool_router.datasource_tenant_id(None).as_deref(),
+            )
+            .map_err(|message| (ActionOutcome::TenantDenied, "tenant_denied", message))?
+        } else {
+            None
+        };
+
+        if let Some(rate_limiter) = self.rate_limiter.as_ref()
+            && let Err(message) = rate_limiter.check(RESOURCE_READ, identity, tenant_id.as_deref())
+        {
+            self.metrics.inc_tool_rate_limited(RESOURCE_READ);
+            return Err((ActionOutcome::RateLimited, "rate_limited", message));
+        }
+
+        Ok(tenant_id)
+    }
+
     async fn record_action(&self, input: RecentActionInput) {
         if let Some(recent_actions) = self.recent_actions.as_ref() {
             recent_actions.record(input).await;
@@ -111,7 +280,12 @@ impl LokiMcpServer {
 impl ServerHandler for LokiMcpServer {
     fn get_info(&self) -> ServerInfo {
         let mut info = ServerInfo {
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_prompts()
+                .enable_logging()
+                .build(),
             instructions: Some(
                 "Query Grafana Loki. Start with loki_describe_schema, then use query tools."
                     .to_string(),
@@ -136,6 +310,136 @@ impl ServerHandler for LokiMcpServer {
         self.tools.iter().find(|tool| tool.name == name).cloned()
     }
 
+    fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
+        future::ready(Ok(ListResourcesResult::with_all_items(
+            self.resources.list(),
+        )))
+    }
+
+    fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListResourceTemplatesResult, McpError>> + Send + '_ {
+        future::ready(Ok(ListResourceTemplatesResult::with_all_items(
+            self.resources.templates(),
+        )))
+    }
+
+    fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListPromptsResult, McpError>> + Send + '_ {
+        future::ready(Ok(ListPromptsResult::with_all_items(self.prompts.list())))
+    }
+
+    fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<(), McpError>> + Send + '_ {
+        if let Ok(mut level) = self.logging_level.write() {
+            *level = request.level;
+        }
+        future::ready(Ok(()))
+    }
+
+    fn get_prompt(
+        &self,
+        request: GetPromptRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<GetPromptResult, McpError>> + Send + '_ {
+        let arguments = request.arguments.unwrap_or_default();
+        let result = self
+            .prompts
+            .get(&request.name, &arguments)
+            .map_err(|error| {
+                McpError::invalid_params(error.to_string(), Some(json!({"name": request.name})))
+            });
+        future::ready(result)
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let started = Instant::now();
+        let uri = request.uri;
+        let parts = context.extensions.get::<Parts>();
+        let identity = self.resolve_identity(parts);
+        let action = RecentActionInput {
+            request_id: self.resolve_request_id(&context),
+            tool: RESOURCE_READ.to_string(),
+            outcome: ActionOutcome::Success,
+            duration_ms: 0,
+            identity_hash: hash_string(&identity),
+            tenant_id: None,
+            query: Some(uri.clone()),
+            error_class: None,
+            error: None,
+        };
+
+        let tenant_id = match self.admit_resource_read(&uri, parts, &identity) {
+            Ok(tenant_id) => tenant_id,
+            Err((outcome, error_class, message)) => {
+                self.metrics.inc_tool_call(RESOURCE_READ, error_class);
+                self.record_action(RecentActionInput {
+                    outcome,
+                    duration_ms: elapsed_millis(started),
+                    error_class: Some(error_class.to_string()),
+                    error: Some(message.clone()),
+                    ..action
+                })
+                .await;
+                return Err(McpError::invalid_request(
+                    message,
+                    Some(json!({"uri": uri, "identity": identity})),
+                ));
+            }
+        };
+
+        let result = self.resources.read(&uri, tenant_id.as_deref()).await;
+        let action = RecentActionInput {
+            duration_ms: elapsed_millis(started),
+            tenant_id,
+            ..action
+        };
+        match result {
+            Ok(contents) => {
+                self.metrics.inc_tool_call(RESOURCE_READ, "success");
+                self.record_action(action).await;
+                Ok(ReadResourceResult {
+                    contents: vec![contents],
+                })
+            }
+            Err(error) => {
+                let message = format!("{error:#}");
+                self.metrics.inc_tool_call(RESOURCE_READ, "error");
+                self.record_action(RecentActionInput {
+                    outcome: ActionOutcome::Error,
+                    error_class: Some("resource_error".to_string()),
+                    error: Some(message.clone()),
+                    ..action
+                })
+                .await;
+                if resources::is_not_found_error(&error) {
+                    Err(McpError::resource_not_found(
+                        error.to_string(),
+                        Some(json!({"uri": uri})),
+                    ))
+                } else {
+                    Err(McpError::internal_error(message, Some(json!({"uri": uri}))))
+                }
+            }
+        }
+    }
+
     async fn call_tool(
         &self,
         request: CallToolRequestParams,
@@ -143,11 +447,20 @@ impl ServerHandler for LokiMcpServer {
     ) -> Result<CallToolResult, McpError> {
         let started = Instant::now();
         let tool_name = request.name.into_owned();
-        let identity = self.resolve_identity(&context);
+        let identity = self.resolve_identity(context.extensions.get::<Parts>());
         let request_id = self.resolve_request_id(&context);
         let identity_hash = hash_string(&identity);
-        let arguments_map = request.arguments.unwrap_or_default();
+        let mut arguments_map = request.arguments.unwrap_or_default();
         let query_text = extract_query_text(&arguments_map);
+        let requested_tenant = take_string_argument(&mut arguments_map, "tenant");
+        let requested_tenants = take_string_list_argument(&mut arguments_map, "tenants");
+        let datasource_tenant = self.tool_router.datasource_tenant_id(
+            arguments_map
+                .get("datasource")
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty()),
+        );
 
         if self.get_tool(&tool_name).is_none() {
             self.metrics.inc_tool_call(&tool_name, "invalid_tool");
@@ -157,7 +470,7 @@ impl ServerHandler for LokiMcpServer {
                 outcome: ActionOutcome::InvalidTool,
                 duration_ms: elapsed_millis(started),
                 identity_hash: identity_hash.clone(),
-                tenant_id: self.tenant_id.clone(),
+                tenant_id: datasource_tenant.clone(),
                 query: query_text.clone(),
                 error_class: Some("invalid_tool".to_string()),
                 error: Some(format!("unknown tool: {tool_name}")),
@@ -169,9 +482,46 @@ impl ServerHandler for LokiMcpServer {
             ));
         }
 
+        let tenant_id = if tools::uses_datasource(&tool_name) {
+            let resolved = self.resolve_tenant(
+                &tool_name,
+                context.extensions.get::<Parts>(),
+                requested_tenant.as_deref(),
+                requested_tenants.as_deref(),
+                datasource_tenant.as_deref(),
+            );
+            match resolved {
+                Ok(tenant_id) => tenant_id,
+                Err(error_message) => {
+                    self.metrics.inc_tool_call(&tool_name, "tenant_denied");
+                    self.record_action(RecentActionInput {
+                        request_id: request_id.clone(),
+                        tool: tool_name.clone(),
+                        outcome: ActionOutcome::TenantDenied,
+                        duration_ms: elapsed_millis(started),
+                        identity_hash: identity_hash.clone(),
+                        tenant_id: requested_tenant.clone().or_else(|| {
+                            requested_tenants.as_ref().map(|tenants| tenants.join("|"))
+                        }),
+                        query: query_text.clone(),
+                        error_class: Some("tenant_denied".to_string()),
+                        error: Some(error_message.clone()),
+                    })
+                    .await;
+                    return Ok(CallToolResult::structured_error(json!({
+                        "error": error_message,
+                        "tool": tool_name,
+                        "identity": identity,
+                    })));
+                }
+            }
+        } else {
+            datasource_tenant
+        };
+
         if let Some(rate_limiter) = self.rate_limiter.as_ref()
             && let Err(error_message) =
-                rate_limiter.check(&tool_name, &identity, self.tenant_id.as_deref())
+                rate_limiter.check(&tool_name, &identity, tenant_id.as_deref())
         {
             self.metrics.inc_tool_rate_limited(&tool_name);
             self.metrics.inc_tool_call(&tool_name, "rate_limited");
@@ -181,7 +531,7 @@ impl ServerHandler for LokiMcpServer {
                 outcome: ActionOutcome::RateLimited,
                 duration_ms: elapsed_millis(started),
                 identity_hash: identity_hash.clone(),
-                tenant_id: self.tenant_id.clone(),
+                tenant_id: tenant_id.clone(),
                 query: query_text.clone(),
                 error_class: Some("rate_limited".to_string()),
                 error: Some(error_message.clone()),
@@ -195,7 +545,33 @@ impl ServerHandler for LokiMcpServer {
         }
 
         let arguments = Value::Object(arguments_map);
-        match self.tool_router.call(&tool_name, arguments).await {
+        let (progress, progress_forwarder) = if tools::streams_progress(&tool_name) {
+            let (sender, handle) = self.forward_progress(&tool_name, &context);
+            (Some(sender), Some(handle))
+        } else {
+            (None, None)
+        };
+        let result = self
+            .tool_router
+            .call_with_context(
+                &tool_name,
+                arguments,
+                ToolCallContext {
+                    tenant_id: tenant_id.clone(),
+                    progress,
+                    inbound_headers: context
+                        .extensions
+                        .get::<Parts>()
+                        .map(|parts| parts.headers.clone()),
+                },
+            )
+            .await;
+        if let Some(progress_forwarder) = progress_forwarder {
+            // Deliver every progress notification before the final result.
+            let _ = progress_forwarder.await;
+        }
+
+        match result {
             Ok(value) => {
                 self.metrics.inc_tool_call(&tool_name, "success");
                 self.record_action(RecentActionInput {
@@ -204,7 +580,7 @@ impl ServerHandler for LokiMcpServer {
                     outcome: ActionOutcome::Success,
                     duration_ms: elapsed_millis(started),
                     identity_hash: identity_hash.clone(),
-                    tenant_id: self.tenant_id.clone(),
+                    tenant_id: tenant_id.clone(),
                     query: query_text.clone(),
                     error_class: None,
                     error: None,
@@ -222,16 +598,20 @@ impl ServerHandler for LokiMcpServer {
                     outcome,
                     duration_ms: elapsed_millis(started),
                     identity_hash: identity_hash.clone(),
-                    tenant_id: self.tenant_id.clone(),
+                    tenant_id: tenant_id.clone(),
                     query: query_text.clone(),
                     error_class: Some(error_class),
                     error: Some(message.clone()),
                 })
                 .await;
-                Ok(CallToolResult::structured_error(json!({
+                let mut body = json!({
                     "error": message,
                     "tool": tool_name,
-                })))
+                });
+                if let Some(linted) = error.downcast_ref::<LintedError>() {
+                    body["lint"] = linted.lint.clone();
+                }
+                Ok(CallToolResult::structured_error(body))
             }
         }
     }
@@ -255,25 +635,45 @@ fn build_tools() -> Vec<Tool> {
             "loki_series",
             "List matching series (unique label sets) for one or more LogQL matchers.",
         ),
+        readonly_tool::<VolumeParams>(
+            "loki_volume",
+            "Rank streams or label groups by bytes ingested using Loki's index volume APIs. Use it to find the noisiest apps or namespaces before writing a query.",
+        ),
+        readonly_tool::<DetectedFieldsParams>(
+            "loki_detected_fields",
+            "List fields inside matching log lines (JSON/logfmt keys, structured metadata) with type and cardinality, to build `json_fields` filters. Falls back to sampling lines when Loki lacks the API.",
+        ),
+        readonly_tool::<DetectedLabelsParams>(
+            "loki_detected_labels",
+            "List stream labels for a selector with their cardinality. Falls back to sampling streams when Loki lacks the API.",
+        ),
         readonly_tool::<QueryLogsParams>(
             "loki_query_logs",
-            "Run a LogQL log query with optional time range and result controls.",
+            "Run a LogQL log query with optional time range and result controls. Pass `next_cursor` back as `cursor` to fetch the next page.",
         ),
         readonly_tool::<QueryMetricsParams>(
             "loki_query_metrics",
             "Run a LogQL metric query and return numeric series data.",
         ),
+        readonly_tool::<QueryInstantParams>(
+            "loki_query_instant",
+            "Evaluate a LogQL metric query at a single instant (`time`, default now) and return vector samples sorted by value, highest first.",
+        ),
         readonly_tool::<BuildQueryParams>(
             "loki_build_query",
-            "Build LogQL from structured filters, then execute and return results.",
+            "Build LogQL from structured matchers, line filters, a parser, label filters, formatters and aggregations (unwrap, quantile_over_time, sum by, topk), then execute it and return the results. Log results page via `next_cursor`.",
         ),
-        readonly_tool::<TailParams>(
+        live_tool::<TailParams>(
             "loki_tail",
-            "Fetch the latest log lines for a required label set.",
+            "Live-tail new log lines for a required label set, streaming them as progress or logging notifications until `lines` arrive or `duration` elapses.",
         ),
         readonly_tool::<RunSavedQueryParams>(
             "loki_run_saved_query",
-            "Run a configured saved query by name with optional range override.",
+            "Run a configured saved query by name with typed `params` (declared in loki_describe_schema) and an optional range override. Log queries page via `next_cursor`; metric and instant saved queries return series.",
+        ),
+        readonly_tool::<ListSavedQueriesParams>(
+            "loki_list_saved_queries",
+            "List configured saved queries with their kind, range, params and tags, optionally filtered to those carrying every given tag.",
         ),
         readonly_tool::<QueryStatsParams>(
             "loki_query_stats",
@@ -289,16 +689,36 @@ fn build_tools() -> Vec<Tool> {
         ),
         readonly_tool::<ExplainQueryParams>(
             "loki_explain_query",
-            "Explain key parts of a LogQL query (selector, stages, aggregation).",
+            "Parse a LogQL query and explain it as a tree of described nodes; syntax errors include the line and column.",
+        ),
+        readonly_tool::<ExplainQueryParams>(
+            "loki_lint_query",
+            "Check a LogQL query for expensive or incorrect patterns; findings carry a rule id, severity, position and suggestion.",
         ),
         readonly_tool::<SuggestMetricRuleParams>(
             "loki_suggest_metric_rule",
             "Generate a recording or alerting rule from a LogQL query.",
         ),
-        readonly_tool::<NoParams>(
+        readonly_tool::<ListRulesParams>(
+            "loki_list_rules",
+            "List ruler rule groups with each rule's LogQL, health and state, or the raw rules YAML with `format: yaml`. Check here before suggesting a new rule.",
+        ),
+        readonly_tool::<ListAlertsParams>(
+            "loki_list_alerts",
+            "List pending and firing Loki ruler alerts, firing first, with the LogQL expression of each alert's rule.",
+        ),
+        readonly_tool::<AlertQueryParams>(
+            "loki_get_alert_query",
+            "Fetch the LogQL expression behind an alert and a ready-to-run loki_query_logs call for the logs that made it fire.",
+        ),
+        readonly_tool::<DatasourceParams>(
             "loki_check_health",
             "Check Loki readiness/build/ring health status through the configured endpoint.",
         ),
+        readonly_tool::<NoParams>(
+            "loki_list_datasources",
+            "List configured Loki datasources, the default one, and whether each is healthy.",
+        ),
     ]
 }
 
@@ -310,6 +730,15 @@ where
         .annotate(ToolAnnotations::new().read_only(true).idempotent(true))
 }
 
+/// A read-only tool whose result depends on when it is called, so it is not idempotent.
+fn live_tool<T>(name: &'static str, description: &'static str) -> Tool
+where
+    T: JsonSchema + std::any::Any,
+{
+    Tool::new(name, description, schema_for_type::<T>())
+        .annotate(ToolAnnotations::new().read_only(true).idempotent(false))
+}
+
 fn header_value(parts: &Parts, name: &str) -> Option<String> {
     parts
         .headers
@@ -320,6 +749,13 @@ fn header_value(parts: &Parts, name: &str) -> Option<String> {
         .map(str::to_string)
 }
 
+fn logging_enabled(minimum: &RwLock<LoggingLevel>, level: LoggingLevel) -> bool {
+    minimum
+        .read()
+        .map(|minimum| level as u8 >= *minimum as u8)
+        .unwrap_or(true)
+}
+
 fn elapsed_millis(started: Instant) -> u64 {
     u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)
 }
@@ -337,6 +773,31 @@ fn extract_query_text(arguments: &Map<String, Value>) -> Option<String> {
         .map(str::to_string)
 }
 
+fn take_string_argument(arguments: &mut Map<String, Value>, name: &str) -> Option<String> {
+    arguments
+        .remove(name)
+        .and_then(|value| value.as_str().map(str::trim).map(str::to_string))
+        .filter(|value| !value.is_empty())
+}
+
+/// Removes a list argument; a single string is accepted as a one-element list.
+fn take_string_list_argument(
+    arguments: &mut Map<String, Value>,
+    name: &str,
+) -> Option<Vec<String>> {
+    match arguments.remove(name)? {
+        Value::Array(values) => Some(
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        ),
+        Value::String(value) => Some(vec![value]),
+        _ => None,
+    }
+}
+
 fn classify_error(message: &str) -> (ActionOutcome, String) {
     let normalized = message.to_ascii_lowercase();
     if normalized.contains("guardrail") {
@@ -350,12 +811,22 @@ fn classify_error(message: &str) -> (ActionOutcome, String) {
 #[serde(deny_unknown_fields)]
 struct NoParams {}
 
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct DatasourceParams {
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct ListLabelsParams {
     start: Option<String>,
     end: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -366,6 +837,8 @@ struct LabelValuesParams {
     start: Option<String>,
     end: Option<String>,
     query: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -376,6 +849,47 @@ struct SeriesParams {
     r#match: Vec<String>,
     start: Option<String>,
     end: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct VolumeParams {
+    query: Option<String>,
+    target_labels: Option<Vec<String>>,
+    aggregate_by: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    step: Option<String>,
+    limit: Option<u32>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct DetectedFieldsParams {
+    query: String,
+    start: Option<String>,
+    end: Option<String>,
+    line_limit: Option<u32>,
+    limit: Option<u32>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct DetectedLabelsParams {
+    query: String,
+    start: Option<String>,
+    end: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -387,7 +901,11 @@ struct QueryLogsParams {
     end: Option<String>,
     limit: Option<u32>,
     direction: Option<String>,
+    cursor: Option<String>,
     response_mode: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
 }
 
 #[allow(dead_code)]
@@ -398,6 +916,21 @@ struct QueryMetricsParams {
     start: Option<String>,
     end: Option<String>,
     step: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct QueryInstantParams {
+    query: String,
+    time: Option<String>,
+    limit: Option<usize>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
 }
 
 #[allow(dead_code)]
@@ -410,12 +943,68 @@ struct BuildQueryParams {
     line_filter_regex: Option<String>,
     exclude: Option<String>,
     json_fields: Option<BTreeMap<String, String>>,
+    /// Stream matchers beyond the equality `labels`, e.g. `{"label": "env", "op": "=~", "value": "prod|stage"}`.
+    matchers: Option<Vec<MatcherParams>>,
+    /// Line filters applied in order; `op` is `|=` (default), `!=`, `|~`, `!~`, `|>` or `!>`.
+    line_filters: Option<Vec<LineFilterParams>>,
+    /// `json`, `logfmt`, `regexp` or `pattern`.
+    parser: Option<String>,
+    /// The regex or pattern for the `regexp` and `pattern` parsers.
+    parser_expression: Option<String>,
+    /// Filters on extracted labels; numbers, durations (`1.5s`) and sizes (`10KB`) compare numerically with `>`, `>=`, `<`, `<=`, `==`.
+    label_filters: Option<Vec<LabelFilterParams>>,
+    /// Label name to template, e.g. `{"route": "{{.method}} {{.path}}"}`.
+    label_format: Option<BTreeMap<String, String>>,
+    line_format: Option<String>,
+    /// Label whose values range aggregations such as `avg_over_time` and `quantile_over_time` read.
+    unwrap: Option<String>,
+    /// `bytes`, `duration` or `duration_seconds`.
+    unwrap_conversion: Option<String>,
     aggregation: Option<String>,
     aggregation_range: Option<String>,
+    /// The quantile for `quantile_over_time`, between 0 and 1.
+    quantile: Option<f64>,
+    /// `sum`, `avg`, `min`, `max`, `count`, `stddev` or `stdvar` over the range aggregation; defaults to `sum` with `group_by`.
+    vector_aggregation: Option<String>,
+    group_by: Option<Vec<String>>,
+    /// Keep only the `topk` highest series.
+    topk: Option<u32>,
     start: Option<String>,
     end: Option<String>,
     limit: Option<u32>,
+    cursor: Option<String>,
     response_mode: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct MatcherParams {
+    label: String,
+    /// `=` (default), `!=`, `=~` or `!~`.
+    op: Option<String>,
+    value: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct LineFilterParams {
+    op: Option<String>,
+    value: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct LabelFilterParams {
+    label: String,
+    /// `=` (default), `!=`, `=~`, `!~`, `==`, `>`, `>=`, `<` or `<=`.
+    op: Option<String>,
+    value: Value,
 }
 
 #[allow(dead_code)]
@@ -424,7 +1013,11 @@ struct BuildQueryParams {
 struct TailParams {
     labels: BTreeMap<String, String>,
     lines: Option<u32>,
+    duration: Option<String>,
+    since: Option<String>,
     response_mode: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -432,8 +1025,22 @@ struct TailParams {
 #[serde(deny_unknown_fields)]
 struct RunSavedQueryParams {
     name: String,
+    /// Values for the saved query's declared params, keyed by name. Omitted params use their default.
+    params: Option<BTreeMap<String, Value>>,
     override_range: Option<String>,
+    cursor: Option<String>,
     response_mode: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct ListSavedQueriesParams {
+    /// Only list saved queries carrying every one of these tags.
+    tags: Option<Vec<String>>,
 }
 
 #[allow(dead_code)]
@@ -443,6 +1050,8 @@ struct QueryStatsParams {
     query: String,
     start: Option<String>,
     end: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -453,6 +1062,8 @@ struct DetectPatternsParams {
     start: Option<String>,
     end: Option<String>,
     step: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -464,6 +1075,9 @@ struct CompareRangesParams {
     baseline_end: String,
     compare_start: String,
     compare_end: String,
+    datasource: Option<String>,
+    tenant: Option<String>,
+    tenants: Option<Vec<String>>,
 }
 
 #[allow(dead_code)]
@@ -485,16 +1099,118 @@ struct SuggestMetricRuleParams {
     alert_for: Option<String>,
 }
 
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct ListRulesParams {
+    rule_type: Option<String>,
+    namespace: Option<String>,
+    group: Option<String>,
+    format: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct ListAlertsParams {
+    state: Option<String>,
+    alert: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct AlertQueryParams {
+    alert: String,
+    namespace: Option<String>,
+    group: Option<String>,
+    datasource: Option<String>,
+    tenant: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeSet;
 
-    use crate::mcp::build_tools;
+    use axum::http::{Request, request::Parts};
+
+    use crate::{
+        auth::AuthenticatedIdentity,
+        config::{Config, TenantMappingConfig, TenantsConfig},
+        mcp::{LokiMcpServer, build_tools},
+        metrics::MetricsRegistry,
+        recent_actions::ActionOutcome,
+    };
+
+    fn request_parts(header: (&str, &str)) -> Parts {
+        let (parts, ()) = Request::builder()
+            .header(header.0, header.1)
+            .body(())
+            .expect("request")
+            .into_parts();
+        parts
+    }
+
+    #[test]
+    fn tenant_mapping_ignores_spoofable_identity_headers() {
+        let config = Config {
+            tenants: TenantsConfig {
+                enabled: true,
+                mappings: vec![TenantMappingConfig {
+                    identities: vec!["alice".to_string()],
+                    tenants: vec!["payments".to_string()],
+                    ..Default::default()
+                }],
+            },
+            ..Default::default()
+        };
+        let server = LokiMcpServer::new(
+            config,
+            MetricsRegistry::new("tenant_test").expect("metrics"),
+            None,
+        )
+        .expect("server");
+
+        let spoofed = request_parts(("x-forwarded-for", "alice"));
+        assert_eq!(server.resolve_identity(Some(&spoofed)), "alice");
+        let error = server
+            .resolve_tenant("loki_list_labels", Some(&spoofed), None, None, None)
+            .expect_err("a forwarded-for identity must not select a tenant");
+        assert!(error.contains("requires an authenticated identity"));
+
+        let mut authenticated = request_parts(("x-forwarded-for", "mallory"));
+        authenticated
+            .extensions
+            .insert(AuthenticatedIdentity::new("alice"));
+        assert_eq!(
+            server.resolve_tenant("loki_list_labels", Some(&authenticated), None, None, None),
+            Ok(Some("payments".to_string()))
+        );
+
+        // Live resources take the same path; static ones need no tenant.
+        let (outcome, error_class, _) = server
+            .admit_resource_read("loki://labels/app/values", Some(&spoofed), "alice")
+            .expect_err("a forwarded-for identity must not read live resources");
+        assert_eq!(outcome, ActionOutcome::TenantDenied);
+        assert_eq!(error_class, "tenant_denied");
+        assert_eq!(
+            server.admit_resource_read("loki://labels", Some(&authenticated), "alice"),
+            Ok(Some("payments".to_string()))
+        );
+        assert_eq!(
+            server.admit_resource_read("loki://schema", Some(&spoofed), "alice"),
+            Ok(None)
+        );
+    }
 
     #[test]
     fn registers_all_spec_tools_with_unique_names() {
         let tools = build_tools();
-        assert_eq!(tools.len(), 15);
+        assert_eq!(tools.len(), 25);
 
         let unique_count = tools
             .iter()
@@ -502,6 +1218,16 @@ mod tests {
             .collect::<BTreeSet<String>>()
             .len();
 
-        assert_eq!(unique_count, 15);
+        assert_eq!(unique_count, 25);
+
+        let idempotent = |name: &str| {
+            tools
+                .iter()
+                .find(|tool| tool.name == name)
+                .and_then(|tool| tool.annotations.as_ref())
+                .and_then(|annotations| annotations.idempotent_hint)
+        };
+        assert_eq!(idempotent("loki_tail"), Some(false));
+        assert_eq!(idempotent("loki_query_logs"), Some(true));
     }
 }
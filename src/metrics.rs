@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use prometheus::{IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use prometheus::{IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
 
 #[derive(Clone)]
 pub struct MetricsRegistry {
@@ -10,6 +10,9 @@ pub struct MetricsRegistry {
     tool_guardrail_rejections_total: IntCounterVec,
     tool_rate_limited_total: IntCounterVec,
     readiness_cache_total: IntCounterVec,
+    loki_request_retries_total: IntCounterVec,
+    loki_circuit_breaker_state: IntGaugeVec,
+    loki_circuit_breaker_rejections_total: IntCounterVec,
 }
 
 impl MetricsRegistry {
@@ -66,6 +69,33 @@ impl MetricsRegistry {
         )
         .context("failed to create readiness_cache_total metric")?;
 
+        let loki_request_retries_total = IntCounterVec::new(
+            Opts::new(
+                format!("{prefix}_loki_request_retries_total"),
+                "Total retried Loki requests partitioned by datasource and endpoint",
+            ),
+            &["datasource", "endpoint"],
+        )
+        .context("failed to create loki_request_retries_total metric")?;
+
+        let loki_circuit_breaker_state = IntGaugeVec::new(
+            Opts::new(
+                format!("{prefix}_loki_circuit_breaker_state"),
+                "Loki circuit breaker state per datasource and endpoint (0 closed, 1 half-open, 2 open)",
+            ),
+            &["datasource", "endpoint"],
+        )
+        .context("failed to create loki_circuit_breaker_state metric")?;
+
+        let loki_circuit_breaker_rejections_total = IntCounterVec::new(
+            Opts::new(
+                format!("{prefix}_loki_circuit_breaker_rejections_total"),
+                "Total Loki requests rejected by an open circuit breaker partitioned by datasource and endpoint",
+            ),
+            &["datasource", "endpoint"],
+        )
+        .context("failed to create loki_circuit_breaker_rejections_total metric")?;
+
         registry
             .register(Box::new(http_requests_total.clone()))
             .context("failed to register http_requests_total metric")?;
@@ -84,6 +114,15 @@ impl MetricsRegistry {
         registry
             .register(Box::new(readiness_cache_total.clone()))
             .context("failed to register readiness_cache_total metric")?;
+        registry
+            .register(Box::new(loki_request_retries_total.clone()))
+            .context("failed to register loki_request_retries_total metric")?;
+        registry
+            .register(Box::new(loki_circuit_breaker_state.clone()))
+            .context("failed to register loki_circuit_breaker_state metric")?;
+        registry
+            .register(Box::new(loki_circuit_breaker_rejections_total.clone()))
+            .context("failed to register loki_circuit_breaker_rejections_total metric")?;
 
         Ok(Self {
             registry,
@@ -93,6 +132,9 @@ impl MetricsRegistry {
             tool_guardrail_rejections_total,
             tool_rate_limited_total,
             readiness_cache_total,
+            loki_request_retries_total,
+            loki_circuit_breaker_state,
+            loki_circuit_breaker_rejections_total,
         })
     }
 
@@ -140,6 +182,24 @@ impl MetricsRegistry {
             .inc();
     }
 
+    pub fn inc_loki_retry(&self, datasource: &str, endpoint: &str) {
+        self.loki_request_retries_total
+            .with_label_values(&[datasource, endpoint])
+            .inc();
+    }
+
+    pub fn set_loki_circuit_state(&self, datasource: &str, endpoint: &str, state: i64) {
+        self.loki_circuit_breaker_state
+            .with_label_values(&[datasource, endpoint])
+            .set(state);
+    }
+
+    pub fn inc_loki_circuit_rejection(&self, datasource: &str, endpoint: &str) {
+        self.loki_circuit_breaker_rejections_total
+            .with_label_values(&[datasource, endpoint])
+            .inc();
+    }
+
     pub fn render(&self) -> Result<String> {
         let metric_families = self.registry.gather();
         let mut body = String::new();
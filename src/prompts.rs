@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
+use rmcp::model::{
+    GetPromptResult, Prompt, PromptArgument as McpPromptArgument, PromptMessage, PromptMessageRole,
+};
+use serde_json::{Map, Value};
+
+use crate::config::{Config, PromptArgument, PromptTemplate, SavedQuery, SchemaField};
+
+/// Renders configured `[[prompts]]` into MCP prompt messages grounded in saved queries and schema.
+#[derive(Clone)]
+pub struct PromptCatalog {
+    prompts: Vec<PromptTemplate>,
+    saved_queries: Vec<SavedQuery>,
+    fields: Vec<SchemaField>,
+}
+
+impl PromptCatalog {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            prompts: config.prompts.clone(),
+            saved_queries: config.saved_queries.clone(),
+            fields: config
+                .labels
+                .iter()
+                .chain(config.structured_metadata.iter())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Prompt> {
+        self.prompts
+            .iter()
+            .map(|prompt| {
+                let arguments = prompt
+                    .arguments
+                    .iter()
+                    .map(|argument| McpPromptArgument {
+                        name: argument.name.clone(),
+                        title: None,
+                        description: Some(argument_description(argument)),
+                        required: Some(argument.required && argument.default.is_none()),
+                    })
+                    .collect::<Vec<McpPromptArgument>>();
+
+                Prompt::new(&prompt.name, Some(&prompt.description), Some(arguments))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str, arguments: &Map<String, Value>) -> Result<GetPromptResult> {
+        let Some(prompt) = self.prompts.iter().find(|prompt| prompt.name == name) else {
+            bail!("prompt not found: {name}");
+        };
+
+        for key in arguments.keys() {
+            if !prompt
+                .arguments
+                .iter()
+                .any(|argument| &argument.name == key)
+            {
+                bail!("unknown argument for prompt {name}: {key}");
+            }
+        }
+
+        let mut values = BTreeMap::<String, String>::new();
+        for argument in &prompt.arguments {
+            let value = match arguments.get(&argument.name) {
+                Some(value) => coerce_argument(argument, value)?,
+                None => match argument.default.as_deref() {
+                    Some(default) => default.to_string(),
+                    None if argument.required => {
+                        bail!(
+                            "missing required argument for prompt {name}: {}",
+                            argument.name
+                        )
+                    }
+                    None => String::new(),
+                },
+            };
+            values.insert(argument.name.clone(), value);
+        }
+
+        let mut text = render_template(&prompt.template, &values);
+        self.append_saved_queries(prompt, &mut text);
+        self.append_fields(prompt, &mut text);
+
+        Ok(GetPromptResult {
+            description: Some(prompt.description.clone()),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+
+    fn append_saved_queries(&self, prompt: &PromptTemplate, text: &mut String) {
+        let saved_queries = prompt
+            .saved_queries
+            .iter()
+            .filter_map(|name| self.saved_queries.iter().find(|query| &query.name == name))
+            .collect::<Vec<&SavedQuery>>();
+        if saved_queries.is_empty() {
+            return;
+        }
+
+        text.push_str("\n\nSaved queries (run with loki_run_saved_query):");
+        for saved_query in saved_queries {
+            text.push_str(&format!(
+                "\n- {}: {}\n  kind: {}\n  query: {}",
+                saved_query.name, saved_query.description, saved_query.kind, saved_query.query
+            ));
+            if !saved_query.range.is_empty() {
+                text.push_str(&format!("\n  range: {}", saved_query.range));
+            }
+            if !saved_query.tags.is_empty() {
+                text.push_str(&format!("\n  tags: {}", saved_query.tags.join(", ")));
+            }
+            for param in &saved_query.params {
+                let requirement = match &param.default {
+                    Some(default) => format!("default {default}"),
+                    None => "required".to_string(),
+                };
+                text.push_str(&format!(
+                    "\n  param {} ({}, {requirement}): {}",
+                    param.name, param.kind, param.description
+                ));
+            }
+        }
+    }
+
+    fn append_fields(&self, prompt: &PromptTemplate, text: &mut String) {
+        let fields = prompt
+            .fields
+            .iter()
+            .filter_map(|name| self.fields.iter().find(|field| &field.name == name))
+            .collect::<Vec<&SchemaField>>();
+        if fields.is_empty() {
+            return;
+        }
+
+        text.push_str("\n\nRelevant fields:");
+        for field in fields {
+            text.push_str(&format!("\n- {}: {}", field.name, field.description));
+            if !field.common_values.is_empty() {
+                text.push_str(&format!(
+                    " (common values: {})",
+                    field.common_values.join(", ")
+                ));
+            }
+        }
+    }
+}
+
+/// Returns the `{{name}}` placeholders referenced by a prompt template, in order of appearance.
+pub fn template_placeholders(template: &str) -> Vec<String> {
+    let mut placeholders = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            break;
+        };
+
+        let name = after_open[..end].trim();
+        if !name.is_empty() {
+            placeholders.push(name.to_string());
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    placeholders
+}
+
+fn render_template(template: &str, values: &BTreeMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            break;
+        };
+
+        rendered.push_str(&rest[..start]);
+        let name = after_open[..end].trim();
+        match values.get(name) {
+            Some(value) => rendered.push_str(value),
+            None => rendered.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn coerce_argument(argument: &PromptArgument, value: &Value) -> Result<String> {
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        _ => bail!("argument {} must be a scalar value", argument.name),
+    };
+
+    match argument.kind.as_str() {
+        "number" => {
+            text.parse::<f64>()
+                .map_err(|_| anyhow!("argument {} must be a number, got {text}", argument.name))?;
+        }
+        "boolean" => {
+            text.parse::<bool>().map_err(|_| {
+                anyhow!(
+                    "argument {} must be true or false, got {text}",
+                    argument.name
+                )
+            })?;
+        }
+        _ => {}
+    }
+
+    Ok(text)
+}
+
+fn argument_description(argument: &PromptArgument) -> String {
+    let mut description = if argument.description.is_empty() {
+        format!("({})", argument.kind)
+    } else {
+        format!("{} ({})", argument.description, argument.kind)
+    };
+    if let Some(default) = argument.default.as_deref() {
+        description.push_str(&format!(", default: {default}"));
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::PromptMessageContent;
+    use serde_json::{Map, Value, json};
+
+    use crate::{
+        config::{Config, PromptArgument, PromptTemplate, SavedQuery, SchemaField},
+        prompts::{PromptCatalog, template_placeholders},
+    };
+
+    fn catalog() -> PromptCatalog {
+        let config = Config {
+            labels: vec![SchemaField {
+                name: "namespace".to_string(),
+                description: "Kubernetes namespace".to_string(),
+                common_values: vec!["production".to_string()],
+            }],
+            saved_queries: vec![SavedQuery {
+                name: "recent_errors".to_string(),
+                description: "Error logs in last 15 minutes".to_string(),
+                query: "{level=\"error\"}".to_string(),
+                range: "15m".to_string(),
+                ..Default::default()
+            }],
+            prompts: vec![PromptTemplate {
+                name: "triage".to_string(),
+                description: "Investigate errors in a namespace".to_string(),
+                template: "Investigate errors in {{namespace}} above {{threshold}} per minute."
+                    .to_string(),
+                arguments: vec![
+                    PromptArgument {
+                        name: "namespace".to_string(),
+                        description: "Namespace to investigate".to_string(),
+                        kind: "string".to_string(),
+                        required: true,
+                        default: None,
+                    },
+                    PromptArgument {
+                        name: "threshold".to_string(),
+                        description: String::new(),
+                        kind: "number".to_string(),
+                        required: false,
+                        default: Some("10".to_string()),
+                    },
+                ],
+                saved_queries: vec!["recent_errors".to_string()],
+                fields: vec!["namespace".to_string()],
+            }],
+            ..Default::default()
+        };
+
+        PromptCatalog::new(&config)
+    }
+
+    fn arguments(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn extracts_template_placeholders() {
+        assert_eq!(
+            template_placeholders("a {{ one }} b {{two}} {{unterminated"),
+            vec!["one".to_string(), "two".to_string()]
+        );
+    }
+
+    #[test]
+    fn renders_prompt_with_defaults_and_grounding() {
+        let result = catalog()
+            .get("triage", &arguments(json!({"namespace": "production"})))
+            .expect("prompt should render");
+
+        let PromptMessageContent::Text { text } = &result.messages[0].content else {
+            panic!("expected text content");
+        };
+        assert!(text.starts_with("Investigate errors in production above 10 per minute."));
+        assert!(text.contains("- recent_errors: Error logs in last 15 minutes"));
+        assert!(text.contains("- namespace: Kubernetes namespace (common values: production)"));
+    }
+
+    #[test]
+    fn rejects_missing_and_mistyped_arguments() {
+        let catalog = catalog();
+
+        let error = catalog
+            .get("triage", &Map::new())
+            .expect_err("missing namespace should fail");
+        assert!(error.to_string().contains("missing required argument"));
+
+        let error = catalog
+            .get(
+                "triage",
+                &arguments(json!({"namespace": "production", "threshold": "lots"})),
+            )
+            .expect_err("non-numeric threshold should fail");
+        assert!(error.to_string().contains("must be a number"));
+    }
+}
@@ -4,13 +4,14 @@ use chrono::{DateTime, Duration, Utc};
 use serde::Serialize;
 use tokio::sync::RwLock;
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ActionOutcome {
     Success,
     Error,
     RateLimited,
     GuardrailReject,
+    TenantDenied,
     InvalidTool,
 }
 
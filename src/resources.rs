@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use rmcp::model::{
+    AnnotateAble, RawResource, RawResourceTemplate, Resource, ResourceContents, ResourceTemplate,
+};
+use serde_json::{Value, json};
+use thiserror::Error;
+
+use crate::{
+    cache::QueryCache, config::Config, loki::client::LokiClient, time::parse_std_duration,
+    tools::discovery,
+};
+
+const SCHEMA_URI: &str = "loki://schema";
+const LABELS_URI: &str = "loki://labels";
+const SAVED_QUERY_URI_PREFIX: &str = "loki://saved-queries/";
+const LABELS_URI_PREFIX: &str = "loki://labels/";
+const JSON_MIME_TYPE: &str = "application/json";
+
+/// A resource URI that names nothing this server serves; reported as `resource_not_found`.
+#[derive(Debug, Error)]
+pub enum ResourceNotFound {
+    #[error("unknown resource uri: {0}")]
+    Uri(String),
+    #[error("saved query not found: {0}")]
+    SavedQuery(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ResourceUri {
+    Schema,
+    SavedQuery(String),
+    Labels,
+    LabelValues(String),
+}
+
+impl ResourceUri {
+    fn parse(uri: &str) -> Result<Self> {
+        if uri == SCHEMA_URI {
+            return Ok(Self::Schema);
+        }
+        if uri == LABELS_URI {
+            return Ok(Self::Labels);
+        }
+
+        if let Some(name) = uri.strip_prefix(SAVED_QUERY_URI_PREFIX)
+            && !name.is_empty()
+            && !name.contains('/')
+        {
+            return Ok(Self::SavedQuery(name.to_string()));
+        }
+
+        if let Some(rest) = uri.strip_prefix(LABELS_URI_PREFIX)
+            && let Some(label) = rest.strip_suffix("/values")
+            && !label.is_empty()
+            && !label.contains('/')
+        {
+            return Ok(Self::LabelValues(label.to_string()));
+        }
+
+        Err(ResourceNotFound::Uri(uri.to_string()).into())
+    }
+
+    fn is_live(&self) -> bool {
+        matches!(self, Self::Labels | Self::LabelValues(_))
+    }
+}
+
+/// Serves configured schema data and live Loki label catalogs as MCP resources.
+#[derive(Clone)]
+pub struct ResourceCatalog {
+    config: Config,
+    loki_client: LokiClient,
+    cache: Option<QueryCache>,
+}
+
+impl ResourceCatalog {
+    pub fn new(config: Config) -> Result<Self> {
+        // Live catalogs are read from the default datasource.
+        let datasource = config.default_resolved_datasource();
+        let loki_client = LokiClient::new(&datasource.loki)?;
+        let cache = if datasource.cache.enabled {
+            let ttl = parse_std_duration(&datasource.cache.ttl)
+                .with_context(|| format!("invalid cache.ttl: {}", datasource.cache.ttl))?;
+            Some(QueryCache::new(datasource.cache.max_entries, ttl))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config,
+            loki_client,
+            cache,
+        })
+    }
+
+    pub fn list(&self) -> Vec<Resource> {
+        let mut resources = vec![
+            json_resource(
+                SCHEMA_URI,
+                "schema",
+                "Configured labels, structured metadata, and saved queries.",
+            ),
+            json_resource(LABELS_URI, "labels", "Label names currently known to Loki."),
+        ];
+
+        for saved_query in &self.config.saved_queries {
+            resources.push(json_resource(
+                &format!("{SAVED_QUERY_URI_PREFIX}{}", saved_query.name),
+                &format!("saved-query:{}", saved_query.name),
+                &saved_query.description,
+            ));
+        }
+
+        for label in &self.config.labels {
+            resources.push(json_resource(
+                &format!("{LABELS_URI_PREFIX}{}/values", label.name),
+                &format!("label-values:{}", label.name),
+                &format!("Values Loki reports for label `{}`.", label.name),
+            ));
+        }
+
+        resources
+    }
+
+    pub fn templates(&self) -> Vec<ResourceTemplate> {
+        vec![
+            json_template(
+                "loki://saved-queries/{name}",
+                "saved-query",
+                "A configured saved query definition by name.",
+            ),
+            json_template(
+                "loki://labels/{label}/values",
+                "label-values",
+                "Values Loki reports for any label name.",
+            ),
+        ]
+    }
+
+    /// Reads a resource. Live catalogs query Loki as `tenant_id` when given, otherwise as the
+    /// default datasource's tenant, and are cached per tenant.
+    pub async fn read(&self, uri: &str, tenant_id: Option<&str>) -> Result<ResourceContents> {
+        let parsed = ResourceUri::parse(uri)?;
+
+        let cache = self.cache.as_ref().filter(|_| parsed.is_live());
+        let cache_key = format!("{}|resource:{uri}", tenant_id.unwrap_or_default());
+        if let Some(cache) = cache
+            && let Some(cached) = cache.get(&cache_key).await
+        {
+            return json_contents(uri, &cached);
+        }
+
+        let client = match tenant_id {
+            Some(tenant_id) => self.loki_client.with_tenant(Some(tenant_id.to_string())),
+            None => self.loki_client.clone(),
+        };
+        let value = self.resolve(&client, parsed).await?;
+        if let Some(cache) = cache {
+            cache.insert(cache_key, value.clone()).await;
+        }
+
+        json_contents(uri, &value)
+    }
+
+    async fn resolve(&self, client: &LokiClient, uri: ResourceUri) -> Result<Value> {
+        match uri {
+            ResourceUri::Schema => Ok(discovery::describe_schema(&self.config)),
+            ResourceUri::SavedQuery(name) => {
+                let Some(saved_query) = self
+                    .config
+                    .saved_queries
+                    .iter()
+                    .find(|saved_query| saved_query.name == name)
+                else {
+                    return Err(ResourceNotFound::SavedQuery(name).into());
+                };
+
+                serde_json::to_value(saved_query).context("failed to serialize saved query")
+            }
+            ResourceUri::Labels => {
+                let labels = client.labels(None, None).await?;
+                Ok(json!({ "labels": labels }))
+            }
+            ResourceUri::LabelValues(label) => {
+                let values = client.label_values(&label, None, None, None).await?;
+                Ok(json!({
+                    "label": label,
+                    "values": values,
+                }))
+            }
+        }
+    }
+}
+
+/// Whether reading `uri` queries Loki, and so needs the caller's tenant.
+pub fn is_live_uri(uri: &str) -> bool {
+    ResourceUri::parse(uri).is_ok_and(|parsed| parsed.is_live())
+}
+
+pub fn is_not_found_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<ResourceNotFound>().is_some()
+}
+
+fn json_resource(uri: &str, name: &str, description: &str) -> Resource {
+    let mut resource = RawResource::new(uri, name);
+    resource.description = Some(description.to_string());
+    resource.mime_type = Some(JSON_MIME_TYPE.to_string());
+    resource.no_annotation()
+}
+
+fn json_template(uri_template: &str, name: &str, description: &str) -> ResourceTemplate {
+    RawResourceTemplate {
+        uri_template: uri_template.to_string(),
+        name: name.to_string(),
+        title: None,
+        description: Some(description.to_string()),
+        mime_type: Some(JSON_MIME_TYPE.to_string()),
+        icons: None,
+    }
+    .no_annotation()
+}
+
+fn json_contents(uri: &str, value: &Value) -> Result<ResourceContents> {
+    let text = serde_json::to_string_pretty(value).context("failed to serialize resource")?;
+    Ok(ResourceContents::TextResourceContents {
+        uri: uri.to_string(),
+        mime_type: Some(JSON_MIME_TYPE.to_string()),
+        text,
+        meta: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, http::HeaderMap, routing::get};
+    use rmcp::model::ResourceContents;
+    use serde_json::{Value, json};
+
+    use crate::{
+        config::{Config, SavedQuery},
+        resources::{ResourceCatalog, ResourceUri, is_live_uri, is_not_found_error},
+    };
+
+    fn config_with_saved_query() -> Config {
+        Config {
+            saved_queries: vec![SavedQuery {
+                name: "recent_errors".to_string(),
+                description: "Error logs in last 15 minutes".to_string(),
+                query: "{level=\"error\"}".to_string(),
+                range: "15m".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_supported_resource_uris() {
+        assert_eq!(
+            ResourceUri::parse("loki://schema").expect("valid"),
+            ResourceUri::Schema
+        );
+        assert_eq!(
+            ResourceUri::parse("loki://saved-queries/recent_errors").expect("valid"),
+            ResourceUri::SavedQuery("recent_errors".to_string())
+        );
+        assert_eq!(
+            ResourceUri::parse("loki://labels/namespace/values").expect("valid"),
+            ResourceUri::LabelValues("namespace".to_string())
+        );
+        assert!(ResourceUri::parse("loki://labels//values").is_err());
+        let error = ResourceUri::parse("file:///etc/passwd").expect_err("unknown scheme");
+        assert!(is_not_found_error(&error));
+    }
+
+    #[test]
+    fn lists_saved_queries_as_resources() {
+        let catalog = ResourceCatalog::new(config_with_saved_query()).expect("catalog");
+        let uris = catalog
+            .list()
+            .into_iter()
+            .map(|resource| resource.raw.uri)
+            .collect::<Vec<String>>();
+
+        assert!(uris.contains(&"loki://schema".to_string()));
+        assert!(uris.contains(&"loki://saved-queries/recent_errors".to_string()));
+    }
+
+    #[tokio::test]
+    async fn live_resources_query_and_cache_per_tenant() {
+        // Echoes the X-Scope-OrgID header back as the only label name.
+        let app = Router::new().route(
+            "/loki/api/v1/labels",
+            get(|headers: HeaderMap| async move {
+                let tenant = headers
+                    .get("x-scope-orgid")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("none")
+                    .to_string();
+                Json(json!({"status": "success", "data": [tenant]}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind stand-in");
+        let address = listener.local_addr().expect("local addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        let mut config = config_with_saved_query();
+        config.loki.url = format!("http://{address}");
+        let catalog = ResourceCatalog::new(config).expect("catalog");
+
+        for tenant in ["payments", "billing", "payments"] {
+            let contents = catalog
+                .read("loki://labels", Some(tenant))
+                .await
+                .expect("labels");
+            let ResourceContents::TextResourceContents { text, .. } = contents else {
+                panic!("expected text contents");
+            };
+            let value: Value = serde_json::from_str(&text).expect("json");
+            assert_eq!(value["labels"], json!([tenant]));
+        }
+        assert!(is_live_uri("loki://labels/app/values"));
+        assert!(!is_live_uri("loki://schema"));
+    }
+
+    #[tokio::test]
+    async fn reads_saved_query_resource_from_config() {
+        let catalog = ResourceCatalog::new(config_with_saved_query()).expect("catalog");
+        let contents = catalog
+            .read("loki://saved-queries/recent_errors", None)
+            .await
+            .expect("resource should resolve");
+
+        let ResourceContents::TextResourceContents { text, .. } = contents else {
+            panic!("expected text contents");
+        };
+        let value: Value = serde_json::from_str(&text).expect("json");
+        assert_eq!(value["query"], "{level=\"error\"}");
+
+        let error = catalog
+            .read("loki://saved-queries/missing", None)
+            .await
+            .expect_err("unknown saved query should fail");
+        assert!(is_not_found_error(&error));
+
+        let error = anyhow::anyhow!("saved query not found: but not typed");
+        assert!(!is_not_found_error(&error));
+    }
+}
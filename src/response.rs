@@ -4,7 +4,7 @@ use std::collections::{BTreeMap, HashMap};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, json};
+use serde_json::{Map, Value, json};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
@@ -90,10 +90,133 @@ pub fn format_log_result(requested_mode: ResponseMode, raw_data: Value) -> (Resp
 }
 
 #[derive(Debug, Clone, Serialize)]
-struct LogLineEntry {
-    timestamp: String,
-    line: String,
-    stream: BTreeMap<String, String>,
+pub struct LogLineEntry {
+    pub timestamp: String,
+    pub line: String,
+    pub stream: BTreeMap<String, String>,
+}
+
+impl LogLineEntry {
+    pub fn new(stream: BTreeMap<String, String>, timestamp_nanos: &str, line: String) -> Self {
+        let timestamp = nanos_to_rfc3339(timestamp_nanos).unwrap_or_else(|| {
+            // Keep timestamp info even if conversion fails.
+            timestamp_nanos.to_string()
+        });
+
+        Self {
+            timestamp,
+            line,
+            stream,
+        }
+    }
+}
+
+/// A stream's labels with non-string values blanked.
+pub fn stream_labels(stream: &Map<String, Value>) -> BTreeMap<String, String> {
+    stream
+        .iter()
+        .map(|(key, value)| (key.clone(), value.as_str().unwrap_or("").to_string()))
+        .collect()
+}
+
+/// Builds the `summary` response one entry at a time, so callers that stream entries never
+/// hold them all.
+#[derive(Debug, Default)]
+pub struct LogSummary {
+    total_lines: usize,
+    level_counts: BTreeMap<String, u64>,
+    pattern_counts: HashMap<String, u64>,
+    pattern_sample: HashMap<String, LogLineEntry>,
+    time_buckets: BTreeMap<String, u64>,
+    first_timestamp: Option<DateTime<Utc>>,
+    last_timestamp: Option<DateTime<Utc>>,
+}
+
+impl LogSummary {
+    pub fn push(&mut self, entry: &LogLineEntry) {
+        self.total_lines += 1;
+        if let Some(level) = detect_level(&entry.line) {
+            *self.level_counts.entry(level).or_insert(0) += 1;
+        }
+
+        if let Some(timestamp) = parse_entry_timestamp(&entry.timestamp) {
+            self.first_timestamp = Some(
+                self.first_timestamp
+                    .map_or(timestamp, |current| current.min(timestamp)),
+            );
+            self.last_timestamp = Some(
+                self.last_timestamp
+                    .map_or(timestamp, |current| current.max(timestamp)),
+            );
+
+            let bucket = time_bucket_5m(timestamp);
+            *self.time_buckets.entry(bucket).or_insert(0) += 1;
+        }
+
+        let pattern = normalize_pattern(&entry.line);
+        *self.pattern_counts.entry(pattern.clone()).or_insert(0) += 1;
+        self.pattern_sample
+            .entry(pattern)
+            .or_insert_with(|| entry.clone());
+    }
+
+    pub fn total_lines(&self) -> usize {
+        self.total_lines
+    }
+
+    pub fn into_payload(self, include_samples: bool) -> Value {
+        let mut top_patterns = self
+            .pattern_counts
+            .into_iter()
+            .collect::<Vec<(String, u64)>>();
+        top_patterns.sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
+        if top_patterns.len() > 10 {
+            top_patterns.truncate(10);
+        }
+
+        let patterns = if include_samples {
+            top_patterns
+                .iter()
+                .map(|(pattern, count)| {
+                    let sample = self
+                        .pattern_sample
+                        .get(pattern)
+                        .map(|entry| {
+                            json!({
+                                "timestamp": entry.timestamp,
+                                "line": entry.line,
+                            })
+                        })
+                        .unwrap_or(Value::Null);
+                    json!({
+                        "pattern": pattern,
+                        "count": count,
+                        "sample": sample,
+                    })
+                })
+                .collect::<Vec<Value>>()
+        } else {
+            top_patterns
+                .iter()
+                .map(|(pattern, count)| {
+                    json!({
+                        "pattern": pattern,
+                        "count": count,
+                    })
+                })
+                .collect::<Vec<Value>>()
+        };
+
+        json!({
+            "mode": "summary",
+            "total_lines": self.total_lines,
+            "first_timestamp": self.first_timestamp.map(|value| value.to_rfc3339()),
+            "last_timestamp": self.last_timestamp.map(|value| value.to_rfc3339()),
+            "level_breakdown": self.level_counts,
+            "top_patterns": patterns,
+            "time_distribution_5m": self.time_buckets,
+        })
+    }
 }
 
 fn flatten_log_entries(raw_data: &Value) -> Vec<LogLineEntry> {
@@ -107,12 +230,7 @@ fn flatten_log_entries(raw_data: &Value) -> Vec<LogLineEntry> {
         let stream_labels = stream
             .get("stream")
             .and_then(Value::as_object)
-            .map(|object| {
-                object
-                    .iter()
-                    .map(|(key, value)| (key.clone(), value.as_str().unwrap_or("").to_string()))
-                    .collect::<BTreeMap<String, String>>()
-            })
+            .map(stream_labels)
             .unwrap_or_default();
 
         let Some(values) = stream.get("values").and_then(Value::as_array) else {
@@ -134,16 +252,11 @@ fn flatten_log_entries(raw_data: &Value) -> Vec<LogLineEntry> {
                 continue;
             };
 
-            let timestamp = nanos_to_rfc3339(timestamp_nanos).unwrap_or_else(|| {
-                // Keep timestamp info even if conversion fails.
-                timestamp_nanos.to_string()
-            });
-
-            entries.push(LogLineEntry {
-                timestamp,
-                line: line.to_string(),
-                stream: stream_labels.clone(),
-            });
+            entries.push(LogLineEntry::new(
+                stream_labels.clone(),
+                timestamp_nanos,
+                line.to_string(),
+            ));
         }
     }
 
@@ -169,83 +282,12 @@ fn truncate_lines(entries: &[LogLineEntry], edge_count: usize) -> (Vec<LogLineEn
 }
 
 fn summary_payload(entries: &[LogLineEntry], include_samples: bool) -> Value {
-    let mut level_counts = BTreeMap::<String, u64>::new();
-    let mut pattern_counts = HashMap::<String, u64>::new();
-    let mut pattern_sample = HashMap::<String, LogLineEntry>::new();
-    let mut time_buckets = BTreeMap::<String, u64>::new();
-
-    let mut first_timestamp: Option<DateTime<Utc>> = None;
-    let mut last_timestamp: Option<DateTime<Utc>> = None;
-
+    let mut summary = LogSummary::default();
     for entry in entries {
-        if let Some(level) = detect_level(&entry.line) {
-            *level_counts.entry(level).or_insert(0) += 1;
-        }
-
-        let pattern = normalize_pattern(&entry.line);
-        *pattern_counts.entry(pattern.clone()).or_insert(0) += 1;
-        pattern_sample
-            .entry(pattern)
-            .or_insert_with(|| entry.clone());
-
-        if let Some(timestamp) = parse_entry_timestamp(&entry.timestamp) {
-            first_timestamp =
-                Some(first_timestamp.map_or(timestamp, |current| current.min(timestamp)));
-            last_timestamp =
-                Some(last_timestamp.map_or(timestamp, |current| current.max(timestamp)));
-
-            let bucket = time_bucket_5m(timestamp);
-            *time_buckets.entry(bucket).or_insert(0) += 1;
-        }
-    }
-
-    let mut top_patterns = pattern_counts.into_iter().collect::<Vec<(String, u64)>>();
-    top_patterns.sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
-    if top_patterns.len() > 10 {
-        top_patterns.truncate(10);
+        summary.push(entry);
     }
 
-    let patterns = if include_samples {
-        top_patterns
-            .iter()
-            .map(|(pattern, count)| {
-                let sample = pattern_sample
-                    .get(pattern)
-                    .map(|entry| {
-                        json!({
-                            "timestamp": entry.timestamp,
-                            "line": entry.line,
-                        })
-                    })
-                    .unwrap_or(Value::Null);
-                json!({
-                    "pattern": pattern,
-                    "count": count,
-                    "sample": sample,
-                })
-            })
-            .collect::<Vec<Value>>()
-    } else {
-        top_patterns
-            .iter()
-            .map(|(pattern, count)| {
-                json!({
-                    "pattern": pattern,
-                    "count": count,
-                })
-            })
-            .collect::<Vec<Value>>()
-    };
-
-    json!({
-        "mode": "summary",
-        "total_lines": entries.len(),
-        "first_timestamp": first_timestamp.map(|value| value.to_rfc3339()),
-        "last_timestamp": last_timestamp.map(|value| value.to_rfc3339()),
-        "level_breakdown": level_counts,
-        "top_patterns": patterns,
-        "time_distribution_5m": time_buckets,
-    })
+    summary.into_payload(include_samples)
 }
 
 fn nanos_to_rfc3339(timestamp_nanos: &str) -> Option<String> {
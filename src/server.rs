@@ -11,14 +11,19 @@ use anyhow::{Context, Result};
 use axum::{
     Json, Router,
     extract::{Extension, Query, Request, State},
-    http::{HeaderValue, StatusCode},
+    http::{HeaderValue, StatusCode, header::WWW_AUTHENTICATE},
     middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::get,
 };
-use rmcp::transport::{
-    StreamableHttpServerConfig,
-    streamable_http_server::{session::local::LocalSessionManager, tower::StreamableHttpService},
+use rmcp::{
+    ServiceExt,
+    transport::{
+        StreamableHttpServerConfig, stdio,
+        streamable_http_server::{
+            session::local::LocalSessionManager, tower::StreamableHttpService,
+        },
+    },
 };
 use serde::Deserialize;
 use serde_json::json;
@@ -27,8 +32,15 @@ use tokio::sync::RwLock;
 use tracing::{Instrument, info, warn};
 
 use crate::{
-    config::Config, loki::client::LokiClient, mcp::LokiMcpServer, metrics::MetricsRegistry,
-    recent_actions::RecentActionsStore, time::parse_std_duration,
+    auth::InboundAuth,
+    config::Config,
+    loki::client::LokiClient,
+    mcp::LokiMcpServer,
+    metrics::MetricsRegistry,
+    recent_actions::RecentActionsStore,
+    time::parse_std_duration,
+    tls::{TlsFiles, load_rustls_config, spawn_reload_task},
+    tools::{ToolRouter, saved},
 };
 
 const READINESS_CACHE_TTL: StdDuration = StdDuration::from_secs(3);
@@ -50,20 +62,62 @@ struct AppState {
     loki_client: LokiClient,
     readiness_cache: Arc<RwLock<Option<CachedReadiness>>>,
     recent_actions: Option<RecentActionsStore>,
+    inbound_auth: Option<InboundAuth>,
+    tool_router: ToolRouter,
 }
 
 pub async fn run(config: Config) -> Result<()> {
-    init_tracing(&config.server.log_level);
+    match config.server.transport.as_str() {
+        "stdio" => run_stdio(config).await,
+        _ => run_http(config).await,
+    }
+}
+
+async fn run_stdio(config: Config) -> Result<()> {
+    // stdout carries the MCP protocol stream, so logs must go to stderr.
+    init_tracing(&config.server.log_level, true);
+    saved::warn_unparsable(&config);
+    let recent_actions = build_recent_actions_store(&config)?;
+    let metrics = MetricsRegistry::new(&config.metrics.prefix)?;
+
+    let mcp_server = LokiMcpServer::new(config, metrics, recent_actions)?;
+    info!("loki-mcp server started on stdio");
+
+    let service = mcp_server
+        .serve(stdio())
+        .await
+        .context("failed to initialize stdio MCP session")?;
+    service
+        .waiting()
+        .await
+        .context("stdio MCP session exited unexpectedly")?;
+
+    Ok(())
+}
+
+async fn run_http(config: Config) -> Result<()> {
+    init_tracing(&config.server.log_level, false);
+    saved::warn_unparsable(&config);
     let recent_actions = build_recent_actions_store(&config)?;
 
+    let metrics_registry = MetricsRegistry::new(&config.metrics.prefix)?;
+    let mcp_server = LokiMcpServer::new(
+        config.clone(),
+        metrics_registry.clone(),
+        recent_actions.clone(),
+    )?;
+
     let state = AppState {
-        metrics: MetricsRegistry::new(&config.metrics.prefix)?,
-        loki_client: LokiClient::new(&config.loki)?,
+        metrics: metrics_registry,
+        loki_client: LokiClient::new(&config.default_resolved_datasource().loki)?,
         readiness_cache: Arc::new(RwLock::new(None)),
-        recent_actions: recent_actions.clone(),
+        recent_actions,
+        inbound_auth: InboundAuth::from_config(&config.server.auth, &config.server.jwt)
+            .await
+            .context("failed to configure inbound authentication")?,
+        tool_router: mcp_server.tool_router().clone(),
     };
 
-    let mcp_server = LokiMcpServer::new(config.clone(), state.metrics.clone(), recent_actions)?;
     let mcp_service: StreamableHttpService<LokiMcpServer, LocalSessionManager> =
         StreamableHttpService::new(
             move || Ok(mcp_server.clone()),
@@ -93,6 +147,19 @@ pub async fn run(config: Config) -> Result<()> {
         .parse()
         .with_context(|| format!("invalid listen address: {}", config.server.listen))?;
 
+    if let Some(tls_files) = TlsFiles::from_config(&config.server) {
+        let rustls_config = load_rustls_config(&tls_files)?;
+        let reload_interval = parse_std_duration(&config.server.tls_reload_interval)?;
+        spawn_reload_task(rustls_config.clone(), tls_files.clone(), reload_interval);
+
+        info!(%address, mutual_tls = tls_files.requires_client_cert(), "loki-mcp server started with TLS");
+
+        return axum_server::bind_rustls(address, rustls_config)
+            .serve(app.into_make_service())
+            .await
+            .context("server exited unexpectedly");
+    }
+
     let listener = TcpListener::bind(address)
         .await
         .with_context(|| format!("failed to bind to {address}"))?;
@@ -104,15 +171,23 @@ pub async fn run(config: Config) -> Result<()> {
         .context("server exited unexpectedly")
 }
 
-fn init_tracing(log_level: &str) {
+fn init_tracing(log_level: &str, use_stderr: bool) {
     let filter = tracing_subscriber::EnvFilter::try_new(log_level)
         .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
 
-    let _ = tracing_subscriber::fmt()
+    let builder = tracing_subscriber::fmt()
         .with_env_filter(filter)
         .with_target(true)
-        .with_level(true)
-        .try_init();
+        .with_level(true);
+
+    let _ = if use_stderr {
+        builder
+            .with_writer(std::io::stderr)
+            .with_ansi(false)
+            .try_init()
+    } else {
+        builder.try_init()
+    };
 }
 
 async fn healthz(State(state): State<AppState>) -> impl IntoResponse {
@@ -124,9 +199,36 @@ async fn readyz(
     State(state): State<AppState>,
     request_id: Option<Extension<RequestId>>,
 ) -> impl IntoResponse {
+    // Breaker state is local and cheap, so it is checked on every probe rather than cached.
+    let open_circuits = state
+        .tool_router
+        .open_circuits()
+        .into_iter()
+        .map(|(datasource, circuit)| json!({"datasource": datasource, "endpoint": circuit.endpoint, "consecutive_failures": circuit.consecutive_failures, "retry_in_seconds": circuit.retry_in_seconds}))
+        .collect::<Vec<serde_json::Value>>();
+    let default_datasource = state.tool_router.default_datasource_name();
+    if open_circuits
+        .iter()
+        .any(|circuit| circuit["datasource"] == default_datasource)
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "not_ready",
+                "message": format!("circuit breaker open for datasource {default_datasource}"),
+                "open_circuits": open_circuits,
+            })),
+        )
+            .into_response();
+    }
+
     if let Some(cached) = read_cached_readiness(&state).await {
         state.metrics.inc_readiness_cache_hit();
-        return (cached.status, Json(cached.body)).into_response();
+        return (
+            cached.status,
+            Json(with_open_circuits(cached.body, open_circuits)),
+        )
+            .into_response();
     }
 
     state.metrics.inc_readiness_cache_miss();
@@ -161,7 +263,25 @@ async fn readyz(
     };
 
     write_cached_readiness(&state, resolved.clone()).await;
-    (resolved.status, Json(resolved.body)).into_response()
+    (
+        resolved.status,
+        Json(with_open_circuits(resolved.body, open_circuits)),
+    )
+        .into_response()
+}
+
+/// Lists open breakers of non-default datasources without failing readiness for them.
+fn with_open_circuits(
+    mut body: serde_json::Value,
+    open_circuits: Vec<serde_json::Value>,
+) -> serde_json::Value {
+    if !open_circuits.is_empty()
+        && let Some(object) = body.as_object_mut()
+    {
+        object.insert("open_circuits".to_string(), json!(open_circuits));
+    }
+
+    body
 }
 
 async fn metrics(
@@ -235,6 +355,29 @@ async fn request_context_middleware(
     let method = request.method().clone();
     let path = request.uri().path().to_string();
     let span = tracing::info_span!("http_request", request_id = %request_id, method = %method, path = %path);
+
+    if let Some(inbound_auth) = state.inbound_auth.as_ref()
+        && requires_auth(&path)
+    {
+        match inbound_auth.authenticate(request.headers()).await {
+            Ok(identity) => {
+                request.extensions_mut().insert(identity);
+            }
+            Err(message) => {
+                warn!(request_id = %request_id, path = %path, error = %message, "rejected unauthenticated request");
+                let mut response =
+                    (StatusCode::UNAUTHORIZED, Json(json!({"error": message}))).into_response();
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                if let Ok(header_value) = HeaderValue::from_str(&request_id) {
+                    response.headers_mut().insert("x-request-id", header_value);
+                }
+                return response;
+            }
+        }
+    }
+
     let mut response = next.run(request).instrument(span).await;
 
     if let Ok(header_value) = HeaderValue::from_str(&request_id) {
@@ -244,6 +387,10 @@ async fn request_context_middleware(
     response
 }
 
+fn requires_auth(path: &str) -> bool {
+    path.starts_with("/mcp") || path.starts_with("/debug")
+}
+
 fn next_request_id() -> String {
     let id = REQUEST_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
     format!("req-{id}")
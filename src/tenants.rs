@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+
+use crate::config::TenantsConfig;
+
+const WILDCARD: &str = "*";
+/// Separates tenants in a federated `X-Scope-OrgID` header.
+pub const TENANT_SEPARATOR: char = '|';
+
+/// Decides which Loki tenant (`X-Scope-OrgID`) a caller may query on a given tool call.
+#[derive(Debug, Clone, Default)]
+pub struct TenantAccess {
+    enabled: bool,
+    mappings: Vec<TenantMapping>,
+}
+
+#[derive(Debug, Clone)]
+struct TenantMapping {
+    identities: Vec<String>,
+    groups: Vec<String>,
+    tenants: Vec<String>,
+}
+
+impl TenantMapping {
+    fn matches(&self, identity: &str, groups: &[String]) -> bool {
+        self.identities
+            .iter()
+            .any(|candidate| candidate == WILDCARD || candidate == identity)
+            || self
+                .groups
+                .iter()
+                .any(|candidate| groups.iter().any(|group| group == candidate))
+    }
+}
+
+impl TenantAccess {
+    pub fn new(config: &TenantsConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            mappings: config
+                .mappings
+                .iter()
+                .map(|mapping| TenantMapping {
+                    identities: mapping.identities.clone(),
+                    groups: mapping.groups.clone(),
+                    tenants: mapping.tenants.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the union of tenants mapped to the identity or any of its groups.
+    pub fn allowed_tenants(&self, identity: &str, groups: &[String]) -> BTreeSet<String> {
+        self.mappings
+            .iter()
+            .filter(|mapping| mapping.matches(identity, groups))
+            .flat_map(|mapping| mapping.tenants.iter().cloned())
+            .collect()
+    }
+
+    /// Picks the tenant for a tool call.
+    ///
+    /// An explicit `requested` tenant must be allowed for the caller. Without one, the
+    /// datasource tenant is used when allowed, then the caller's only allowed tenant.
+    /// With tenant mapping disabled the datasource tenant is always used.
+    pub fn resolve(
+        &self,
+        identity: &str,
+        groups: &[String],
+        requested: Option<&str>,
+        datasource_tenant: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        if !self.enabled {
+            if let Some(requested) = requested {
+                return Err(format!(
+                    "tenant selection is not enabled; remove `tenant` ({requested}) or configure [tenants]"
+                ));
+            }
+            return Ok(datasource_tenant.map(str::to_string));
+        }
+
+        let allowed = self.allowed_tenants(identity, groups);
+        if allowed.is_empty() {
+            return Err(format!(
+                "identity {identity} is not mapped to any Loki tenant"
+            ));
+        }
+        let any_tenant = allowed.contains(WILDCARD);
+        let is_allowed = |tenant: &str| any_tenant || allowed.contains(tenant);
+
+        if let Some(requested) = requested {
+            if requested.contains(TENANT_SEPARATOR) {
+                return Err(format!(
+                    "tenant {requested} names several tenants; pass them as a `tenants` list instead"
+                ));
+            }
+            if is_allowed(requested) {
+                return Ok(Some(requested.to_string()));
+            }
+            return Err(format!(
+                "tenant {requested} is not allowed for identity {identity}. allowed tenants: {}",
+                describe(&allowed)
+            ));
+        }
+
+        if let Some(datasource_tenant) = datasource_tenant
+            && is_allowed(datasource_tenant)
+        {
+            return Ok(Some(datasource_tenant.to_string()));
+        }
+
+        match (any_tenant, allowed.len()) {
+            (false, 1) => Ok(allowed.into_iter().next()),
+            _ => Err(format!(
+                "identity {identity} may query several tenants; pass `tenant` (allowed tenants: {})",
+                describe(&allowed)
+            )),
+        }
+    }
+
+    /// Picks the tenant set for a federated query, joined as Loki expects (`a|b`).
+    ///
+    /// Every requested tenant must be allowed for the caller, so federation needs tenant
+    /// mapping enabled.
+    pub fn resolve_federated(
+        &self,
+        identity: &str,
+        groups: &[String],
+        requested: &[String],
+    ) -> Result<Option<String>, String> {
+        if !self.enabled {
+            return Err(
+                "tenant selection is not enabled; remove `tenants` or configure [tenants]"
+                    .to_string(),
+            );
+        }
+
+        let tenants = requested
+            .iter()
+            .map(|tenant| tenant.trim())
+            .filter(|tenant| !tenant.is_empty())
+            .collect::<BTreeSet<&str>>();
+        if tenants.is_empty() {
+            return Err("`tenants` must list at least one tenant".to_string());
+        }
+        if let Some(tenant) = tenants
+            .iter()
+            .find(|tenant| tenant.contains(TENANT_SEPARATOR) || **tenant == WILDCARD)
+        {
+            return Err(format!(
+                "invalid tenant {tenant} in `tenants`; list each tenant by name"
+            ));
+        }
+
+        let allowed = self.allowed_tenants(identity, groups);
+        if allowed.is_empty() {
+            return Err(format!(
+                "identity {identity} is not mapped to any Loki tenant"
+            ));
+        }
+        if !allowed.contains(WILDCARD) {
+            let denied = tenants
+                .iter()
+                .filter(|tenant| !allowed.contains(**tenant))
+                .copied()
+                .collect::<Vec<&str>>();
+            if !denied.is_empty() {
+                return Err(format!(
+                    "tenants {} are not allowed for identity {identity}. allowed tenants: {}",
+                    denied.join(", "),
+                    describe(&allowed)
+                ));
+            }
+        }
+
+        Ok(Some(
+            tenants
+                .into_iter()
+                .collect::<Vec<&str>>()
+                .join(&TENANT_SEPARATOR.to_string()),
+        ))
+    }
+}
+
+/// Splits a resolved tenant into its members; a single tenant yields itself.
+pub fn federated_tenants(tenant_id: &str) -> Vec<&str> {
+    tenant_id.split(TENANT_SEPARATOR).collect()
+}
+
+fn describe(allowed: &BTreeSet<String>) -> String {
+    if allowed.contains(WILDCARD) {
+        return "any".to_string();
+    }
+
+    allowed.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{TenantMappingConfig, TenantsConfig},
+        tenants::TenantAccess,
+    };
+
+    fn access() -> TenantAccess {
+        TenantAccess::new(&TenantsConfig {
+            enabled: true,
+            mappings: vec![
+                TenantMappingConfig {
+                    identities: vec!["alice".to_string()],
+                    tenants: vec!["payments".to_string()],
+                    ..Default::default()
+                },
+                TenantMappingConfig {
+                    groups: vec!["sre".to_string()],
+                    tenants: vec!["payments".to_string(), "platform".to_string()],
+                    ..Default::default()
+                },
+                TenantMappingConfig {
+                    identities: vec!["admin".to_string()],
+                    tenants: vec!["*".to_string()],
+                    ..Default::default()
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn disabled_mapping_uses_datasource_tenant_and_rejects_overrides() {
+        let access = TenantAccess::default();
+
+        assert_eq!(
+            access.resolve("alice", &[], None, Some("prod")),
+            Ok(Some("prod".to_string()))
+        );
+        assert!(access.resolve("alice", &[], Some("prod"), None).is_err());
+    }
+
+    #[test]
+    fn resolves_tenants_from_identities_and_groups() {
+        let access = access();
+        let sre = vec!["sre".to_string()];
+
+        assert_eq!(
+            access.resolve("alice", &[], None, None),
+            Ok(Some("payments".to_string()))
+        );
+        assert!(
+            access
+                .resolve("alice", &[], Some("platform"), None)
+                .is_err()
+        );
+
+        assert_eq!(
+            access.resolve("bob", &sre, Some("platform"), None),
+            Ok(Some("platform".to_string()))
+        );
+        assert_eq!(
+            access.resolve("bob", &sre, None, Some("platform")),
+            Ok(Some("platform".to_string()))
+        );
+        assert!(access.resolve("bob", &sre, None, Some("billing")).is_err());
+
+        assert!(
+            access
+                .resolve("mallory", &[], None, Some("payments"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn wildcard_tenant_allows_any_explicit_tenant() {
+        let access = access();
+
+        assert_eq!(
+            access.resolve("admin", &[], Some("billing"), None),
+            Ok(Some("billing".to_string()))
+        );
+        assert_eq!(
+            access.resolve("admin", &[], None, Some("prod")),
+            Ok(Some("prod".to_string()))
+        );
+        assert!(access.resolve("admin", &[], None, None).is_err());
+    }
+
+    #[test]
+    fn federated_tenants_must_all_be_allowed() {
+        let access = access();
+        let sre = vec!["sre".to_string()];
+        let tenants = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            access.resolve_federated("bob", &sre, &tenants(&["platform", "payments", "platform"])),
+            Ok(Some("payments|platform".to_string()))
+        );
+        let error = access
+            .resolve_federated("bob", &sre, &tenants(&["payments", "billing"]))
+            .expect_err("billing is not mapped to sre");
+        assert!(error.contains("tenants billing are not allowed"), "{error}");
+
+        assert_eq!(
+            access.resolve_federated("admin", &[], &tenants(&["a", "b"])),
+            Ok(Some("a|b".to_string()))
+        );
+        assert!(
+            access
+                .resolve_federated("admin", &[], &tenants(&["a|b"]))
+                .is_err()
+        );
+        assert!(access.resolve("admin", &[], Some("a|b"), None).is_err());
+        assert!(
+            TenantAccess::default()
+                .resolve_federated("bob", &sre, &tenants(&["payments"]))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, bail};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config;
+
+/// Certificate, private key and optional client CA files for the HTTPS listener.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    cert: String,
+    key: String,
+    client_ca: Option<String>,
+}
+
+impl TlsFiles {
+    pub fn from_config(config: &config::ServerConfig) -> Option<Self> {
+        Some(Self {
+            cert: config.tls_cert.clone()?,
+            key: config.tls_key.clone()?,
+            client_ca: config.tls_client_ca.clone(),
+        })
+    }
+
+    pub fn requires_client_cert(&self) -> bool {
+        self.client_ca.is_some()
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &str> {
+        [Some(self.cert.as_str()), Some(self.key.as_str())]
+            .into_iter()
+            .chain([self.client_ca.as_deref()])
+            .flatten()
+    }
+
+    fn modified_times(&self) -> Vec<Option<SystemTime>> {
+        self.paths()
+            .map(|path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+pub fn build_server_config(files: &TlsFiles) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(default_provider());
+    let certs = load_certs(&files.cert, "server.tls_cert")?;
+    let key = PrivateKeyDer::from_pem_file(&files.key)
+        .with_context(|| format!("failed to read server.tls_key from {}", files.key))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("failed to select TLS protocol versions")?;
+    let builder = match files.client_ca.as_deref() {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path, "server.tls_client_ca")? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid certificate in {path}"))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("failed to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("server.tls_cert does not match server.tls_key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+pub fn load_rustls_config(files: &TlsFiles) -> Result<RustlsConfig> {
+    Ok(RustlsConfig::from_config(build_server_config(files)?))
+}
+
+/// Polls the certificate files and swaps in a fresh server config when any of them change.
+///
+/// A failed reload keeps serving the previous certificates and is retried on the next change.
+pub fn spawn_reload_task(
+    rustls_config: RustlsConfig,
+    files: TlsFiles,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_seen = files.modified_times();
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            let current = files.modified_times();
+            if current == last_seen {
+                continue;
+            }
+            last_seen = current;
+
+            match build_server_config(&files) {
+                Ok(server_config) => {
+                    rustls_config.reload_from_config(server_config);
+                    info!(cert = %files.cert, "reloaded TLS certificates");
+                }
+                Err(error) => {
+                    warn!(cert = %files.cert, error = %format!("{error:#}"), "failed to reload TLS certificates, keeping previous ones");
+                }
+            }
+        }
+    })
+}
+
+fn load_certs(path: &str, field: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .with_context(|| format!("failed to read {field} from {path}"))?
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .with_context(|| format!("invalid PEM in {field} at {path}"))?;
+    if certs.is_empty() {
+        bail!("{field} at {path} contains no certificates");
+    }
+
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use axum::{Json, Router, routing::get};
+    use rcgen::{CertifiedKey, generate_simple_self_signed};
+    use serde_json::json;
+
+    use crate::{
+        config::{LokiConfig, LokiRetryConfig},
+        loki::client::LokiClient,
+        tls::{TlsFiles, build_server_config, load_rustls_config, spawn_reload_task},
+    };
+
+    static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    struct TempTlsDir {
+        path: PathBuf,
+    }
+
+    impl TempTlsDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "loki-mcp-tls-{}-{}",
+                std::process::id(),
+                TEMP_DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir_all(&path).expect("create temp dir");
+            Self { path }
+        }
+
+        fn write_self_signed(&self) -> (TlsFiles, String) {
+            let CertifiedKey { cert, key_pair } =
+                generate_simple_self_signed(vec!["localhost".to_string()]).expect("generate cert");
+            let cert_path = self.path.join("tls.crt");
+            let key_path = self.path.join("tls.key");
+            std::fs::write(&cert_path, cert.pem()).expect("write cert");
+            std::fs::write(&key_path, key_pair.serialize_pem()).expect("write key");
+
+            (
+                TlsFiles {
+                    cert: cert_path.display().to_string(),
+                    key: key_path.display().to_string(),
+                    client_ca: None,
+                },
+                cert.pem(),
+            )
+        }
+    }
+
+    impl Drop for TempTlsDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_certificate_and_key() {
+        let first = TempTlsDir::new();
+        let second = TempTlsDir::new();
+        let (files, _) = first.write_self_signed();
+        let (other, _) = second.write_self_signed();
+
+        build_server_config(&files).expect("matching pair should load");
+
+        let mismatched = TlsFiles {
+            key: other.key,
+            ..files
+        };
+        assert!(build_server_config(&mismatched).is_err());
+    }
+
+    #[test]
+    fn client_ca_enables_mutual_tls() {
+        let dir = TempTlsDir::new();
+        let (files, _) = dir.write_self_signed();
+        let files = TlsFiles {
+            client_ca: Some(files.cert.clone()),
+            ..files
+        };
+
+        assert!(files.requires_client_cert());
+        build_server_config(&files).expect("client ca should load");
+
+        let missing = TlsFiles {
+            client_ca: Some(dir.path.join("missing.crt").display().to_string()),
+            ..files
+        };
+        assert!(build_server_config(&missing).is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_https_and_reloads_rotated_certificates() {
+        let dir = TempTlsDir::new();
+        let (files, first_pem) = dir.write_self_signed();
+        let rustls_config = load_rustls_config(&files).expect("tls config");
+        let reload_task = spawn_reload_task(
+            rustls_config.clone(),
+            files.clone(),
+            Duration::from_millis(20),
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let address = listener.local_addr().expect("local addr");
+        let app = Router::new().route("/healthz", get(|| async { "ok" }));
+        let server = tokio::spawn(
+            axum_server::from_tcp_rustls(listener, rustls_config.clone())
+                .serve(app.into_make_service()),
+        );
+
+        let get_healthz = |pem: String| async move {
+            reqwest::Client::builder()
+                .add_root_certificate(reqwest::Certificate::from_pem(pem.as_bytes())?)
+                .build()?
+                .get(format!("https://localhost:{}/healthz", address.port()))
+                .send()
+                .await?
+                .text()
+                .await
+        };
+        assert_eq!(get_healthz(first_pem.clone()).await.expect("https"), "ok");
+
+        let initial = rustls_config.get_inner();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let (_, second_pem) = dir.write_self_signed();
+        for _ in 0..100 {
+            if !std::sync::Arc::ptr_eq(&initial, &rustls_config.get_inner()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(get_healthz(second_pem).await.expect("reloaded https"), "ok");
+        assert!(get_healthz(first_pem).await.is_err());
+
+        reload_task.abort();
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn loki_client_presents_its_certificate_to_mutual_tls_servers() {
+        let server_dir = TempTlsDir::new();
+        let client_dir = TempTlsDir::new();
+        let (server_files, _) = server_dir.write_self_signed();
+        let (client_files, _) = client_dir.write_self_signed();
+        let rustls_config = load_rustls_config(&TlsFiles {
+            client_ca: Some(client_files.cert.clone()),
+            ..server_files.clone()
+        })
+        .expect("tls config");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let address = listener.local_addr().expect("local addr");
+        let app = Router::new().route(
+            "/loki/api/v1/labels",
+            get(|| async { Json(json!({"status": "success", "data": ["app"]})) }),
+        );
+        let server = tokio::spawn(
+            axum_server::from_tcp_rustls(listener, rustls_config).serve(app.into_make_service()),
+        );
+
+        let loki = |client_cert: Option<String>, client_key: Option<String>| LokiConfig {
+            url: format!("https://localhost:{}", address.port()),
+            ca_cert: Some(server_files.cert.clone()),
+            client_cert,
+            client_key,
+            timeout: "2s".to_string(),
+            retry: LokiRetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let client = LokiClient::new(&loki(
+            Some(client_files.cert.clone()),
+            Some(client_files.key.clone()),
+        ))
+        .expect("client with certificate");
+        assert_eq!(
+            client.labels(None, None).await.expect("mutual TLS"),
+            vec!["app".to_string()]
+        );
+
+        let anonymous = LokiClient::new(&loki(None, None)).expect("client without certificate");
+        assert!(anonymous.labels(None, None).await.is_err());
+
+        server.abort();
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    logql::{self, RangeOp, VectorOp},
+    tools::query::BuildQueryInput,
+};
+
+const DEFAULT_AGGREGATION_RANGE: &str = "5m";
+const UNWRAP_CONVERSIONS: [&str; 3] = ["bytes", "duration", "duration_seconds"];
+/// Aggregations `vector_aggregation` accepts; `topk` has its own parameter.
+const VECTOR_AGGREGATIONS: [VectorOp; 7] = [
+    VectorOp::Sum,
+    VectorOp::Avg,
+    VectorOp::Min,
+    VectorOp::Max,
+    VectorOp::Count,
+    VectorOp::Stddev,
+    VectorOp::Stdvar,
+];
+
+/// A stream selector matcher: `label op "value"`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatcherInput {
+    pub label: String,
+    /// `=` (default), `!=`, `=~` or `!~`.
+    pub op: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LineFilterInput {
+    /// `|=` (default), `!=`, `|~`, `!~`, `|>` or `!>`.
+    pub op: Option<String>,
+    pub value: String,
+}
+
+/// A filter on an extracted label. Strings are quoted; numbers, durations (`1.5s`) and
+/// sizes (`10KB`) are compared numerically.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelFilterInput {
+    pub label: String,
+    /// `=` (default), `!=`, `=~`, `!~`, `==`, `>`, `>=`, `<` or `<=`.
+    pub op: Option<String>,
+    pub value: Value,
+}
+
+/// Builds the LogQL for `loki_build_query`, including any aggregation, and checks that it
+/// parses. Stages are emitted in a fixed order: line filters, parser, label filters,
+/// `label_format`, `line_format`, `unwrap`.
+pub(crate) fn build_query_string(input: &BuildQueryInput) -> Result<String> {
+    let mut parts = vec![selector(input)?];
+
+    for (field, value) in input.structured_metadata.iter().flatten() {
+        parts.push(format!(
+            "| {}=\"{}\"",
+            label_name(field)?,
+            escape_logql_value(value)
+        ));
+    }
+
+    let legacy_filters = [
+        ("|=", input.line_filter.as_ref()),
+        ("|~", input.line_filter_regex.as_ref()),
+        ("!=", input.exclude.as_ref()),
+    ];
+    for (op, value) in legacy_filters {
+        if let Some(value) = value {
+            parts.push(format!("{op} \"{}\"", escape_logql_value(value)));
+        }
+    }
+    for filter in input.line_filters.iter().flatten() {
+        let op = filter.op.as_deref().unwrap_or("|=");
+        if !matches!(op, "|=" | "!=" | "|~" | "!~" | "|>" | "!>") {
+            bail!("unsupported line filter op: {op}. expected one of |=, !=, |~, !~, |>, !>");
+        }
+        parts.push(format!("{op} \"{}\"", escape_logql_value(&filter.value)));
+    }
+
+    let json_fields = input
+        .json_fields
+        .as_ref()
+        .filter(|fields| !fields.is_empty());
+    if let Some(parser) = parser_stage(input, json_fields.is_some())? {
+        parts.push(parser);
+    }
+    for (field, value) in json_fields.into_iter().flatten() {
+        parts.push(format!(
+            "| {}=\"{}\"",
+            label_name(field)?,
+            escape_logql_value(value)
+        ));
+    }
+    for filter in input.label_filters.iter().flatten() {
+        parts.push(format!("| {}", label_filter(filter)?));
+    }
+
+    if let Some(label_format) = input.label_format.as_ref().filter(|ops| !ops.is_empty()) {
+        let operations = label_format
+            .iter()
+            .map(|(label, template)| {
+                Ok(format!(
+                    "{}=\"{}\"",
+                    label_name(label)?,
+                    escape_logql_value(template)
+                ))
+            })
+            .collect::<Result<Vec<String>>>()?;
+        parts.push(format!("| label_format {}", operations.join(", ")));
+    }
+    if let Some(template) = input.line_format.as_deref() {
+        parts.push(format!(
+            "| line_format \"{}\"",
+            escape_logql_value(template)
+        ));
+    }
+    if let Some(unwrap) = input.unwrap.as_deref() {
+        if input.aggregation.is_none() {
+            bail!("unwrap needs an aggregation such as avg_over_time or quantile_over_time");
+        }
+        let label = label_name(unwrap)?;
+        let unwrapped = match input.unwrap_conversion.as_deref() {
+            None => label.to_string(),
+            Some(conversion) if UNWRAP_CONVERSIONS.contains(&conversion) => {
+                format!("{conversion}({label})")
+            }
+            Some(conversion) => bail!(
+                "unsupported unwrap_conversion: {conversion}. expected one of bytes, duration, duration_seconds"
+            ),
+        };
+        // Values that fail to convert would otherwise fail the whole query.
+        parts.push(format!("| unwrap {unwrapped} | __error__=\"\""));
+    }
+
+    let log_query = parts.join(" ");
+    let query = match input.aggregation.as_deref() {
+        Some(aggregation) => metric_query(input, aggregation, &log_query)?,
+        None => {
+            if input.vector_aggregation.is_some()
+                || input.group_by.is_some()
+                || input.topk.is_some()
+                || input.quantile.is_some()
+            {
+                bail!(
+                    "vector_aggregation, group_by, topk and quantile need an aggregation such as count_over_time"
+                );
+            }
+            log_query
+        }
+    };
+
+    logql::parse(&query).map_err(|error| anyhow!("built query is not valid LogQL: {error}"))?;
+    Ok(query)
+}
+
+fn selector(input: &BuildQueryInput) -> Result<String> {
+    let mut matchers = Vec::new();
+    for (label, value) in input.labels.iter().flatten() {
+        matchers.push(format!(
+            "{}=\"{}\"",
+            label_name(label)?,
+            escape_logql_value(value)
+        ));
+    }
+    for matcher in input.matchers.iter().flatten() {
+        let op = matcher.op.as_deref().unwrap_or("=");
+        if !matches!(op, "=" | "!=" | "=~" | "!~") {
+            bail!("unsupported matcher op: {op}. expected one of =, !=, =~, !~");
+        }
+        matchers.push(format!(
+            "{}{op}\"{}\"",
+            label_name(&matcher.label)?,
+            escape_logql_value(&matcher.value)
+        ));
+    }
+    if matchers.is_empty() {
+        bail!("labels or matchers must select at least one stream label");
+    }
+
+    Ok(format!("{{{}}}", matchers.join(",")))
+}
+
+fn parser_stage(input: &BuildQueryInput, has_json_fields: bool) -> Result<Option<String>> {
+    let expression = input.parser_expression.as_deref();
+    let stage = match (input.parser.as_deref(), expression) {
+        (None, None) if has_json_fields => "| json".to_string(),
+        (None, None) => return Ok(None),
+        (None, Some(_)) => bail!("parser_expression needs parser set to regexp or pattern"),
+        (Some(parser), _) if has_json_fields && parser != "json" => {
+            bail!("json_fields need the json parser, not {parser}")
+        }
+        (Some(parser @ ("json" | "logfmt")), None) => format!("| {parser}"),
+        (Some(parser @ ("regexp" | "pattern")), Some(expression)) => {
+            format!("| {parser} \"{}\"", escape_logql_value(expression))
+        }
+        (Some(parser @ ("regexp" | "pattern")), None) => {
+            bail!("the {parser} parser needs parser_expression")
+        }
+        (Some(parser @ ("json" | "logfmt")), Some(_)) => {
+            bail!("parser_expression only applies to regexp and pattern, not {parser}")
+        }
+        (Some(parser), _) => {
+            bail!("unsupported parser: {parser}. expected one of json, logfmt, regexp, pattern")
+        }
+    };
+
+    Ok(Some(stage))
+}
+
+fn label_filter(filter: &LabelFilterInput) -> Result<String> {
+    let label = label_name(&filter.label)?;
+    let op = filter.op.as_deref().unwrap_or("=");
+    let value = match (op, &filter.value) {
+        ("=" | "!=" | "=~" | "!~", Value::String(value)) => {
+            format!("\"{}\"", escape_logql_value(value))
+        }
+        ("=" | "!=" | "==" | ">" | ">=" | "<" | "<=", Value::Number(number)) => number.to_string(),
+        ("==" | ">" | ">=" | "<" | "<=", Value::String(value)) if is_numeric_literal(value) => {
+            value.clone()
+        }
+        ("==" | ">" | ">=" | "<" | "<=", Value::String(value)) => bail!(
+            "label filter on {label} compares numerically; {value:?} is not a number, duration or size"
+        ),
+        ("=~" | "!~", _) => bail!("label filter on {label} with {op} needs a string regex"),
+        ("=" | "!=" | "==" | ">" | ">=" | "<" | "<=", _) => {
+            bail!("label filter on {label} needs a string or number value")
+        }
+        _ => bail!(
+            "unsupported label filter op: {op}. expected one of =, !=, =~, !~, ==, >, >=, <, <="
+        ),
+    };
+
+    Ok(format!("{label}{op}{value}"))
+}
+
+fn metric_query(input: &BuildQueryInput, aggregation: &str, log_query: &str) -> Result<String> {
+    let op = validate_aggregation(aggregation)?;
+    if op.requires_unwrap() && input.unwrap.is_none() {
+        bail!("{aggregation} needs unwrap: the label whose values it aggregates");
+    }
+    if !op.allows_unwrap() && input.unwrap.is_some() {
+        bail!(
+            "{aggregation} counts lines and does not take unwrap; use an aggregation such as avg_over_time"
+        );
+    }
+    let parameter = match (op, input.quantile) {
+        (RangeOp::QuantileOverTime, Some(quantile)) if (0.0..=1.0).contains(&quantile) => {
+            format!("{quantile}, ")
+        }
+        (RangeOp::QuantileOverTime, Some(quantile)) => {
+            bail!("quantile must be between 0 and 1, got {quantile}")
+        }
+        (RangeOp::QuantileOverTime, None) => bail!("quantile_over_time needs quantile, e.g. 0.99"),
+        (_, Some(_)) => bail!("quantile only applies to quantile_over_time"),
+        (_, None) => String::new(),
+    };
+    let range = input
+        .aggregation_range
+        .as_deref()
+        .unwrap_or(DEFAULT_AGGREGATION_RANGE);
+    // The range is interpolated unquoted, so anything but a plain duration could add expressions.
+    if logql::parse_duration(range).is_none() {
+        bail!("invalid aggregation_range: {range:?}. expected a duration such as 5m or 1h30m");
+    }
+    let mut query = format!("{aggregation}({parameter}{log_query} [{range}])");
+
+    let grouping = match input.group_by.as_ref() {
+        Some(labels) => {
+            let labels = labels
+                .iter()
+                .map(|label| label_name(label))
+                .collect::<Result<Vec<&str>>>()?;
+            Some(format!("by ({})", labels.join(", ")))
+        }
+        None => None,
+    };
+    let vector = match input.vector_aggregation.as_deref() {
+        Some(name) => Some(
+            VectorOp::from_name(name)
+                .filter(|op| VECTOR_AGGREGATIONS.contains(op))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "unsupported vector_aggregation: {name}. expected one of sum, avg, min, max, count, stddev, stdvar"
+                    )
+                })?
+                .name(),
+        ),
+        // `group_by` alone means a sum per group.
+        None => grouping.as_ref().map(|_| VectorOp::Sum.name()),
+    };
+    if let Some(vector) = vector {
+        query = match grouping {
+            Some(grouping) => format!("{vector} {grouping} ({query})"),
+            None => format!("{vector}({query})"),
+        };
+    }
+    if let Some(k) = input.topk {
+        if k == 0 {
+            bail!("topk must be greater than zero");
+        }
+        query = format!("topk({k}, {query})");
+    }
+
+    Ok(query)
+}
+
+pub(crate) fn validate_aggregation(aggregation: &str) -> Result<RangeOp> {
+    RangeOp::from_name(aggregation).ok_or_else(|| {
+        let expected = RangeOp::ALL
+            .iter()
+            .map(|op| op.name())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        anyhow!("unsupported aggregation: {aggregation}. expected one of {expected}")
+    })
+}
+
+pub(crate) fn selector_from_labels(labels: &BTreeMap<String, String>) -> Result<String> {
+    if labels.is_empty() {
+        return Ok("{}".to_string());
+    }
+
+    let pairs = labels
+        .iter()
+        .map(|(key, value)| {
+            Ok(format!(
+                "{}=\"{}\"",
+                label_name(key)?,
+                escape_logql_value(value)
+            ))
+        })
+        .collect::<Result<Vec<String>>>()?;
+
+    Ok(format!("{{{}}}", pairs.join(",")))
+}
+
+/// Label names are interpolated unquoted, so only LogQL identifiers are accepted.
+fn label_name(name: &str) -> Result<&str> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if !valid {
+        bail!("invalid label name: {name:?}. label names use letters, digits and underscores");
+    }
+
+    Ok(name)
+}
+
+/// `500`, `1.5`, `250ms`, `10KB`: emitted unquoted for numeric comparisons.
+fn is_numeric_literal(value: &str) -> bool {
+    value.starts_with(|ch: char| ch.is_ascii_digit())
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '.')
+}
+
+/// Escapes a value for a double-quoted LogQL string.
+pub(crate) fn escape_logql_value(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for ch in input.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::build_query_string;
+    use crate::tools::query::BuildQueryInput;
+
+    fn build(params: Value) -> anyhow::Result<String> {
+        let input: BuildQueryInput = serde_json::from_value(params).expect("valid input");
+        build_query_string(&input)
+    }
+
+    #[test]
+    fn builds_full_pipelines_and_aggregations() {
+        let query = build(json!({
+            "labels": {"app": "api"},
+            "matchers": [{"label": "env", "op": "=~", "value": "prod|stage"}, {"label": "pod", "op": "!=", "value": ""}],
+            "line_filters": [{"value": "GET"}, {"op": "!~", "value": "health(z)?"}],
+            "parser": "logfmt",
+            "label_filters": [
+                {"label": "status", "op": ">=", "value": 500},
+                {"label": "latency", "op": ">", "value": "250ms"},
+                {"label": "path", "op": "=~", "value": "/api/.*"},
+            ],
+            "label_format": {"route": "{{.method}} {{.path}}"},
+            "unwrap": "latency",
+            "unwrap_conversion": "duration",
+            "aggregation": "quantile_over_time",
+            "quantile": 0.99,
+            "aggregation_range": "10m",
+            "group_by": ["path"],
+            "topk": 5,
+        }))
+        .expect("query should build");
+
+        assert_eq!(
+            query,
+            r#"topk(5, sum by (path) (quantile_over_time(0.99, {app="api",env=~"prod|stage",pod!=""} |= "GET" !~ "health(z)?" | logfmt | status>=500 | latency>250ms | path=~"/api/.*" | label_format route="{{.method}} {{.path}}" | unwrap duration(latency) | __error__="" [10m])))"#
+        );
+
+        assert_eq!(
+            build(json!({
+                "labels": {"app": "api"},
+                "parser": "regexp",
+                "parser_expression": r#"(?P<ip>\S+) "(?P<method>\w+)"#,
+                "line_format": "{{.ip}}",
+            }))
+            .expect("query should build"),
+            r#"{app="api"} | regexp "(?P<ip>\\S+) \"(?P<method>\\w+)" | line_format "{{.ip}}""#
+        );
+        assert_eq!(
+            build(json!({
+                "labels": {"app": "api"},
+                "json_fields": {"level": "error"},
+                "aggregation": "avg_over_time",
+                "unwrap": "bytes_sent",
+                "vector_aggregation": "max",
+            }))
+            .expect("query should build"),
+            r#"max(avg_over_time({app="api"} | json | level="error" | unwrap bytes_sent | __error__="" [5m]))"#
+        );
+    }
+
+    #[test]
+    fn escapes_values_and_rejects_unsafe_or_inconsistent_input() {
+        assert_eq!(
+            build(json!({
+                "labels": {"app": "a\"} |= \"x"},
+                "line_filter": "line\nbreak \\ end",
+            }))
+            .expect("query should build"),
+            r#"{app="a\"} |= \"x"} |= "line\nbreak \\ end""#
+        );
+
+        for (params, message) in [
+            (json!({"labels": {"app\"}": "x"}}), "invalid label name"),
+            (json!({}), "at least one stream label"),
+            (
+                json!({"labels": {"app": "api"}, "matchers": [{"label": "env", "op": "==", "value": "x"}]}),
+                "unsupported matcher op",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "label_filters": [{"label": "status", "op": ">", "value": "5xx) or {"}]}),
+                "compares numerically",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "parser": "regexp"}),
+                "needs parser_expression",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "aggregation": "avg_over_time"}),
+                "needs unwrap",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "aggregation": "count_over_time", "unwrap": "latency"}),
+                "does not take unwrap",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "aggregation": "quantile_over_time", "unwrap": "latency"}),
+                "needs quantile",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "topk": 5}),
+                "need an aggregation",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "aggregation": "rate", "aggregation_range": "5m]) or vector(1"}),
+                "invalid aggregation_range",
+            ),
+            (
+                json!({
+                    "labels": {"app": "api"},
+                    "aggregation": "rate",
+                    "aggregation_range": "5m]) or vector(1) or count_over_time({a=\"b\"}[1h",
+                }),
+                "invalid aggregation_range",
+            ),
+            (
+                json!({"labels": {"app": "api"}, "aggregation": "rate", "aggregation_range": "5m "}),
+                "invalid aggregation_range",
+            ),
+        ] {
+            let error = build(params.clone()).expect_err("input should be rejected");
+            assert!(error.to_string().contains(message), "{params}: {error}");
+        }
+    }
+}
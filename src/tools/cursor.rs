@@ -0,0 +1,449 @@
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::tools::split::merge_log_results;
+
+const CURSOR_VERSION: u8 = 1;
+
+/// Continuation point for a paginated log query, handed to callers as an opaque string.
+///
+/// It carries the remaining time window, the timestamp of the last returned entry and
+/// fingerprints of every entry already returned at that timestamp, so the next page can
+/// re-include the boundary nanosecond without repeating lines.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LogCursor {
+    #[serde(rename = "v")]
+    version: u8,
+    #[serde(rename = "q")]
+    query: u64,
+    #[serde(rename = "f")]
+    forward: bool,
+    #[serde(rename = "s")]
+    start: i64,
+    #[serde(rename = "e")]
+    end: i64,
+    #[serde(rename = "t")]
+    timestamp: i64,
+    #[serde(rename = "k")]
+    seen: Vec<u64>,
+}
+
+/// One page of log entries and the cursor for the next one, if the page was full.
+#[derive(Debug)]
+pub(crate) struct LogPage {
+    pub data: Value,
+    pub next_cursor: Option<String>,
+}
+
+impl LogCursor {
+    pub(crate) fn decode(cursor: &str, query: &str) -> Result<Self> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(cursor.trim())
+            .context("invalid cursor: not a cursor returned by a previous page")?;
+        let cursor = serde_json::from_slice::<Self>(&bytes)
+            .context("invalid cursor: not a cursor returned by a previous page")?;
+        if cursor.version != CURSOR_VERSION {
+            bail!(
+                "invalid cursor: unsupported cursor version {}",
+                cursor.version
+            );
+        }
+        if cursor.query != fingerprint(query) {
+            bail!("invalid cursor: it was issued for a different query");
+        }
+
+        Ok(cursor)
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let bytes = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub(crate) fn forward(&self) -> bool {
+        self.forward
+    }
+
+    /// The window still to be read: everything before the boundary (inclusive) for backward
+    /// queries, everything after it (inclusive) for forward ones.
+    pub(crate) fn window(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            DateTime::from_timestamp_nanos(self.start),
+            DateTime::from_timestamp_nanos(self.end),
+        )
+    }
+
+    /// How many extra entries to request so that dropping already returned ones still
+    /// leaves a full page.
+    pub(crate) fn overlap(&self) -> u32 {
+        u32::try_from(self.seen.len()).unwrap_or(u32::MAX)
+    }
+}
+
+/// A log entry position used to order and fingerprint page contents.
+struct Entry {
+    timestamp: i64,
+    fingerprint: u64,
+}
+
+/// Turns a raw `streams` result into a page of at most `limit` entries.
+///
+/// `fetched_limit` is the limit sent to Loki: a result that reached it may have more entries
+/// behind it, so a cursor is issued pointing just past the last entry kept.
+pub(crate) fn paginate(
+    query: &str,
+    data: Value,
+    limit: u32,
+    fetched_limit: u32,
+    forward: bool,
+    (start, end): (DateTime<Utc>, DateTime<Utc>),
+    previous: Option<&LogCursor>,
+) -> Result<LogPage> {
+    if data.get("resultType").and_then(Value::as_str) != Some("streams") {
+        return Ok(LogPage {
+            data,
+            next_cursor: None,
+        });
+    }
+
+    let fetched = entries(&data).len();
+    let mut data = match previous {
+        Some(previous) => drop_seen(data, previous),
+        None => data,
+    };
+    if entries(&data).len() > limit as usize {
+        data = merge_log_results(vec![data], Some(limit as usize), forward);
+    }
+
+    let mut kept = entries(&data);
+    if fetched < fetched_limit as usize || kept.is_empty() {
+        return Ok(LogPage {
+            data,
+            next_cursor: None,
+        });
+    }
+
+    kept.sort_by_key(|entry| entry.timestamp);
+    let boundary = if forward {
+        kept[kept.len() - 1].timestamp
+    } else {
+        kept[0].timestamp
+    };
+    let mut seen = kept
+        .iter()
+        .filter(|entry| entry.timestamp == boundary)
+        .map(|entry| entry.fingerprint)
+        .collect::<Vec<u64>>();
+    if let Some(previous) = previous.filter(|previous| previous.timestamp == boundary) {
+        seen.extend(previous.seen.iter().copied());
+    }
+
+    Ok(LogPage {
+        data,
+        next_cursor: Some(cursor_at(query, forward, (start, end), boundary, seen)?),
+    })
+}
+
+/// Tracks where a page ends while its entries stream past, for pages that are never held
+/// whole. Entries the previous cursor already returned are recognised as they arrive.
+#[derive(Debug)]
+pub(crate) struct PageBoundary {
+    forward: bool,
+    previous: Option<LogCursor>,
+    fetched: usize,
+    timestamp: Option<i64>,
+    seen: Vec<u64>,
+}
+
+impl PageBoundary {
+    pub(crate) fn new(forward: bool, previous: Option<LogCursor>) -> Self {
+        Self {
+            forward,
+            previous,
+            fetched: 0,
+            timestamp: None,
+            seen: Vec::new(),
+        }
+    }
+
+    /// Records an entry and returns whether it belongs on the page, i.e. was not returned
+    /// by an earlier page. Skipped entries still count towards the fetched limit.
+    pub(crate) fn observe(
+        &mut self,
+        stream: &Map<String, Value>,
+        timestamp_nanos: &str,
+        line: &str,
+    ) -> bool {
+        let Ok(timestamp) = timestamp_nanos.parse::<i64>() else {
+            return true;
+        };
+        self.fetched += 1;
+
+        // Only entries at a boundary timestamp need their fingerprint.
+        let fingerprint = || {
+            let labels = serde_json::to_string(stream).unwrap_or_default();
+            entry_fingerprint(&labels, line)
+        };
+        if let Some(previous) = self.previous.as_ref()
+            && previous.timestamp == timestamp
+            && previous.seen.contains(&fingerprint())
+        {
+            return false;
+        }
+
+        let beyond = self.timestamp.is_none_or(|boundary| {
+            if self.forward {
+                timestamp > boundary
+            } else {
+                timestamp < boundary
+            }
+        });
+        if beyond {
+            self.timestamp = Some(timestamp);
+            self.seen.clear();
+        }
+        if self.timestamp == Some(timestamp) {
+            self.seen.push(fingerprint());
+        }
+
+        true
+    }
+
+    /// The cursor for the next page, when the page was full.
+    pub(crate) fn next_cursor(
+        self,
+        query: &str,
+        fetched_limit: u32,
+        window: (DateTime<Utc>, DateTime<Utc>),
+    ) -> Result<Option<String>> {
+        let Some(boundary) = self.timestamp else {
+            return Ok(None);
+        };
+        if self.fetched < fetched_limit as usize {
+            return Ok(None);
+        }
+        let mut seen = self.seen;
+        if let Some(previous) = self
+            .previous
+            .filter(|previous| previous.timestamp == boundary)
+        {
+            seen.extend(previous.seen);
+        }
+
+        cursor_at(query, self.forward, window, boundary, seen).map(Some)
+    }
+}
+
+fn cursor_at(
+    query: &str,
+    forward: bool,
+    (start, end): (DateTime<Utc>, DateTime<Utc>),
+    boundary: i64,
+    mut seen: Vec<u64>,
+) -> Result<String> {
+    seen.sort_unstable();
+    seen.dedup();
+
+    let start = nanos(start)?;
+    let end = nanos(end)?;
+    let (start, end) = if forward {
+        (boundary, end)
+    } else {
+        // Loki treats `end` as exclusive; step one nanosecond past the boundary to keep it.
+        (start, boundary.saturating_add(1))
+    };
+    let cursor = LogCursor {
+        version: CURSOR_VERSION,
+        query: fingerprint(query),
+        forward,
+        start,
+        end,
+        timestamp: boundary,
+        seen,
+    };
+
+    Ok(cursor.encode())
+}
+
+fn drop_seen(mut data: Value, cursor: &LogCursor) -> Value {
+    let Some(streams) = data.get_mut("result").and_then(Value::as_array_mut) else {
+        return data;
+    };
+
+    for stream in streams.iter_mut() {
+        let labels = stream
+            .get("stream")
+            .map(Value::to_string)
+            .unwrap_or_default();
+        if let Some(values) = stream.get_mut("values").and_then(Value::as_array_mut) {
+            values.retain(|value| {
+                let Some(entry) = entry(&labels, value) else {
+                    return true;
+                };
+                entry.timestamp != cursor.timestamp || !cursor.seen.contains(&entry.fingerprint)
+            });
+        }
+    }
+    streams.retain(|stream| {
+        stream
+            .get("values")
+            .and_then(Value::as_array)
+            .is_some_and(|values| !values.is_empty())
+    });
+
+    data
+}
+
+fn entries(data: &Value) -> Vec<Entry> {
+    data.get("result")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .flat_map(|stream| {
+            let labels = stream
+                .get("stream")
+                .map(Value::to_string)
+                .unwrap_or_default();
+            stream
+                .get("values")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(move |value| entry(&labels, value))
+        })
+        .collect()
+}
+
+fn entry(labels: &str, value: &Value) -> Option<Entry> {
+    let timestamp = value.get(0)?.as_str()?.parse::<i64>().ok()?;
+    let line = value.get(1).and_then(Value::as_str).unwrap_or_default();
+
+    Some(Entry {
+        timestamp,
+        fingerprint: entry_fingerprint(labels, line),
+    })
+}
+
+fn entry_fingerprint(labels: &str, line: &str) -> u64 {
+    fingerprint(&format!("{labels}\u{0}{line}"))
+}
+
+fn nanos(time: DateTime<Utc>) -> Result<i64> {
+    time.timestamp_nanos_opt()
+        .context("timestamp is outside the supported nanosecond range")
+}
+
+/// FNV-1a: stable across builds, unlike `DefaultHasher`, so cursors survive restarts.
+fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::{Value, json};
+
+    use crate::tools::cursor::{LogCursor, paginate};
+
+    fn streams(entries: &[(&str, &str, &str)]) -> Value {
+        json!({
+            "resultType": "streams",
+            "result": entries
+                .iter()
+                .map(|(app, timestamp, line)| json!({
+                    "stream": {"app": app},
+                    "values": [[timestamp, line]],
+                }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    #[test]
+    fn pages_backward_through_shared_timestamps_without_duplicates() {
+        let window = (
+            Utc.timestamp_opt(0, 0).unwrap(),
+            Utc.timestamp_opt(100, 0).unwrap(),
+        );
+        let first = paginate(
+            "{app=~\".+\"}",
+            streams(&[("api", "30", "c"), ("web", "20", "b")]),
+            2,
+            2,
+            false,
+            window,
+            None,
+        )
+        .expect("first page");
+        let cursor = LogCursor::decode(
+            first
+                .next_cursor
+                .as_deref()
+                .expect("full page has a cursor"),
+            "{app=~\".+\"}",
+        )
+        .expect("cursor should decode");
+        assert_eq!(cursor.window().1.timestamp_nanos_opt(), Some(21));
+        assert_eq!(cursor.overlap(), 1);
+
+        // Loki returns the boundary line again plus a second line at the same nanosecond.
+        let second = paginate(
+            "{app=~\".+\"}",
+            streams(&[("web", "20", "b"), ("api", "20", "b2"), ("api", "10", "a")]),
+            2,
+            3,
+            false,
+            cursor.window(),
+            Some(&cursor),
+        )
+        .expect("second page");
+        let lines = second.data["result"]
+            .as_array()
+            .expect("streams")
+            .iter()
+            .flat_map(|stream| stream["values"].as_array().cloned().unwrap_or_default())
+            .map(|value| value[1].as_str().unwrap_or_default().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(lines, vec!["b2", "a"]);
+
+        let last = paginate(
+            "{app=~\".+\"}",
+            streams(&[]),
+            2,
+            2,
+            false,
+            cursor.window(),
+            Some(&cursor),
+        )
+        .expect("last page");
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[test]
+    fn rejects_cursors_for_other_queries_or_garbage() {
+        let page = paginate(
+            "{app=\"api\"}",
+            streams(&[("api", "5", "a")]),
+            1,
+            1,
+            true,
+            (
+                Utc.timestamp_opt(0, 0).unwrap(),
+                Utc.timestamp_opt(10, 0).unwrap(),
+            ),
+            None,
+        )
+        .expect("page");
+        let next_cursor = page.next_cursor.expect("cursor");
+        let cursor = LogCursor::decode(&next_cursor, "{app=\"api\"}").expect("same query");
+        assert!(cursor.forward());
+        assert_eq!(cursor.window().0.timestamp_nanos_opt(), Some(5));
+
+        assert!(LogCursor::decode(&next_cursor, "{app=\"web\"}").is_err());
+        assert!(LogCursor::decode("not-a-cursor", "{app=\"api\"}").is_err());
+    }
+}
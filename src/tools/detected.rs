@@ -0,0 +1,481 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use chrono::Utc;
+use chrono_tz::Tz;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{loki::client::LokiClient, time::resolve_time_range};
+
+const DEFAULT_LINE_LIMIT: u32 = 500;
+const MAX_LINE_LIMIT: u32 = 5000;
+const DEFAULT_FIELD_LIMIT: u32 = 100;
+/// Distinct values kept per field while sampling; cardinality is reported as a lower bound past it.
+const MAX_TRACKED_VALUES: usize = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetectedFieldsInput {
+    pub query: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub line_limit: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetectedLabelsInput {
+    pub query: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+/// Lists fields found inside matching log lines via `/detected_fields`, or by sampling lines and
+/// parsing them as JSON or logfmt when this Loki does not serve the endpoint.
+pub async fn detected_fields(
+    client: &LokiClient,
+    timezone: Tz,
+    input: DetectedFieldsInput,
+) -> Result<Value> {
+    let (start, end) = resolve_time_range(
+        input.start.as_deref(),
+        input.end.as_deref(),
+        timezone,
+        Utc::now(),
+    )?;
+    let line_limit = input
+        .line_limit
+        .unwrap_or(DEFAULT_LINE_LIMIT)
+        .clamp(1, MAX_LINE_LIMIT);
+    let limit = input.limit.unwrap_or(DEFAULT_FIELD_LIMIT).max(1);
+
+    let (source, fallback_reason, sampled_lines, mut fields) = match client
+        .detected_fields(
+            &input.query,
+            Some(start),
+            Some(end),
+            Some(line_limit),
+            Some(limit),
+        )
+        .await
+    {
+        Ok(data) => ("loki", None, None, fields_from_loki(&data)),
+        Err(error) if !endpoint_unsupported(&error) => return Err(error),
+        Err(error) => {
+            let data = client
+                .query_logs(
+                    &input.query,
+                    Some(start),
+                    Some(end),
+                    Some(line_limit),
+                    Some("backward"),
+                )
+                .await?;
+            let (lines, fields) = sample_fields(&data);
+            ("sampled", Some(format!("{error:#}")), Some(lines), fields)
+        }
+    };
+    fields.truncate(limit as usize);
+
+    Ok(json!({
+        "query": input.query,
+        "start": start,
+        "end": end,
+        "source": source,
+        "fallback_reason": fallback_reason,
+        "sampled_lines": sampled_lines,
+        "fields": fields,
+        "usage": "fields parsed by `json` can be filtered with `json_fields` in loki_build_query; for logfmt fields set `parser: \"logfmt\"` and filter with `label_filters`",
+    }))
+}
+
+/// Lists stream labels for a selector via `/detected_labels`, or from sampled streams when this
+/// Loki does not serve the endpoint.
+pub async fn detected_labels(
+    client: &LokiClient,
+    timezone: Tz,
+    input: DetectedLabelsInput,
+) -> Result<Value> {
+    let (start, end) = resolve_time_range(
+        input.start.as_deref(),
+        input.end.as_deref(),
+        timezone,
+        Utc::now(),
+    )?;
+
+    let (source, fallback_reason, labels) = match client
+        .detected_labels(&input.query, Some(start), Some(end))
+        .await
+    {
+        Ok(data) => ("loki", None, labels_from_loki(&data)),
+        Err(error) if !endpoint_unsupported(&error) => return Err(error),
+        Err(error) => {
+            let data = client
+                .query_logs(
+                    &input.query,
+                    Some(start),
+                    Some(end),
+                    Some(DEFAULT_LINE_LIMIT),
+                    Some("backward"),
+                )
+                .await?;
+            ("sampled", Some(format!("{error:#}")), sample_labels(&data))
+        }
+    };
+
+    Ok(json!({
+        "query": input.query,
+        "start": start,
+        "end": end,
+        "source": source,
+        "fallback_reason": fallback_reason,
+        "labels": labels,
+    }))
+}
+
+/// Whether Loki rejected the request because it lacks the API (older releases, disabled
+/// features). Other failures would only repeat against `query_range`, so they are returned.
+fn endpoint_unsupported(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .filter_map(reqwest::Error::status)
+        .any(|status| {
+            matches!(
+                status,
+                StatusCode::NOT_FOUND
+                    | StatusCode::METHOD_NOT_ALLOWED
+                    | StatusCode::NOT_IMPLEMENTED
+            )
+        })
+}
+
+fn fields_from_loki(data: &Value) -> Vec<Value> {
+    data.get("fields")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|field| {
+            json!({
+                "name": field.get("label").cloned().unwrap_or(Value::Null),
+                "type": field.get("type").cloned().unwrap_or_else(|| json!("string")),
+                "cardinality": field.get("cardinality").cloned().unwrap_or(Value::Null),
+                "parsers": field.get("parsers").cloned().unwrap_or_else(|| json!([])),
+            })
+        })
+        .collect()
+}
+
+fn labels_from_loki(data: &Value) -> Vec<Value> {
+    data.get("detectedLabels")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|label| {
+            json!({
+                "name": label.get("label").cloned().unwrap_or(Value::Null),
+                "cardinality": label.get("cardinality").cloned().unwrap_or(Value::Null),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FieldType {
+    Boolean,
+    Int,
+    Float,
+    Duration,
+    Bytes,
+    String,
+}
+
+impl FieldType {
+    fn of(value: &str) -> Self {
+        if value == "true" || value == "false" {
+            Self::Boolean
+        } else if value.parse::<i64>().is_ok() {
+            Self::Int
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+            Self::Float
+        } else if has_numeric_prefix(value, &["ns", "us", "µs", "ms", "s", "m", "h"]) {
+            Self::Duration
+        } else if has_numeric_prefix(
+            value,
+            &["b", "kb", "mb", "gb", "tb", "kib", "mib", "gib", "tib"],
+        ) {
+            Self::Bytes
+        } else {
+            Self::String
+        }
+    }
+
+    /// The narrowest type that still describes values of both types.
+    fn widen(self, other: Self) -> Self {
+        match (self, other) {
+            (left, right) if left == right => left,
+            (Self::Int, Self::Float) | (Self::Float, Self::Int) => Self::Float,
+            _ => Self::String,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Duration => "duration",
+            Self::Bytes => "bytes",
+            Self::String => "string",
+        }
+    }
+}
+
+fn has_numeric_prefix(value: &str, units: &[&str]) -> bool {
+    let lower = value.to_ascii_lowercase();
+    units.iter().any(|unit| {
+        lower
+            .strip_suffix(unit)
+            .is_some_and(|number| !number.is_empty() && number.parse::<f64>().is_ok())
+    })
+}
+
+#[derive(Debug, Default)]
+struct FieldStats {
+    field_type: Option<FieldType>,
+    values: BTreeSet<String>,
+    parsers: BTreeSet<&'static str>,
+}
+
+impl FieldStats {
+    fn observe(&mut self, value: &str, parser: &'static str) {
+        let value_type = FieldType::of(value);
+        self.field_type = Some(
+            self.field_type
+                .map_or(value_type, |current| current.widen(value_type)),
+        );
+        if self.values.len() < MAX_TRACKED_VALUES {
+            self.values.insert(value.to_string());
+        }
+        self.parsers.insert(parser);
+    }
+}
+
+/// Parses sampled lines as JSON objects or logfmt and summarises each field found.
+fn sample_fields(data: &Value) -> (usize, Vec<Value>) {
+    let mut stats = BTreeMap::<String, FieldStats>::new();
+    let mut lines = 0;
+    for line in log_lines(data) {
+        lines += 1;
+        let (parser, pairs) = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(object)) => ("json", flatten_json(&object)),
+            _ => ("logfmt", parse_logfmt(line)),
+        };
+        for (name, value) in pairs {
+            stats.entry(name).or_default().observe(&value, parser);
+        }
+    }
+
+    let fields = stats
+        .into_iter()
+        .map(|(name, stats)| {
+            json!({
+                "name": name,
+                "type": stats.field_type.unwrap_or(FieldType::String).name(),
+                "cardinality": stats.values.len(),
+                "parsers": stats.parsers,
+            })
+        })
+        .collect();
+    (lines, fields)
+}
+
+fn sample_labels(data: &Value) -> Vec<Value> {
+    let mut values = BTreeMap::<String, BTreeSet<String>>::new();
+    for stream in data
+        .get("result")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        for (name, value) in stream
+            .get("stream")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            values
+                .entry(name.clone())
+                .or_default()
+                .insert(value.as_str().unwrap_or_default().to_string());
+        }
+    }
+
+    values
+        .into_iter()
+        .map(|(name, values)| json!({"name": name, "cardinality": values.len()}))
+        .collect()
+}
+
+fn log_lines(data: &Value) -> impl Iterator<Item = &str> {
+    data.get("result")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|stream| stream.get("values").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|value| value.get(1).and_then(Value::as_str))
+}
+
+/// Flattens nested objects the way Loki's `json` parser names them (`parent_child`); arrays
+/// and nulls are skipped.
+fn flatten_json(object: &Map<String, Value>) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    flatten_json_into(object, "", &mut pairs);
+    pairs
+}
+
+fn flatten_json_into(object: &Map<String, Value>, prefix: &str, pairs: &mut Vec<(String, String)>) {
+    for (key, value) in object {
+        let name = if prefix.is_empty() {
+            sanitize_field_name(key)
+        } else {
+            format!("{prefix}_{}", sanitize_field_name(key))
+        };
+        match value {
+            Value::Object(nested) => flatten_json_into(nested, &name, pairs),
+            Value::String(text) => pairs.push((name, text.clone())),
+            Value::Number(number) => pairs.push((name, number.to_string())),
+            Value::Bool(flag) => pairs.push((name, flag.to_string())),
+            Value::Array(_) | Value::Null => {}
+        }
+    }
+}
+
+/// Loki replaces characters that are not valid in label names with `_`.
+fn sanitize_field_name(name: &str) -> String {
+    name.chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() || character == '_' {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Extracts `key=value` pairs, honouring double-quoted values with backslash escapes.
+fn parse_logfmt(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut characters = line.chars().peekable();
+    loop {
+        while characters
+            .next_if(|character| character.is_whitespace())
+            .is_some()
+        {}
+        let mut key = String::new();
+        while let Some(character) =
+            characters.next_if(|character| !character.is_whitespace() && *character != '=')
+        {
+            key.push(character);
+        }
+        if key.is_empty() && characters.peek().is_none() {
+            break;
+        }
+        if characters.next_if_eq(&'=').is_none() {
+            // A bare word is not a field; skip it.
+            if key.is_empty() {
+                characters.next();
+            }
+            continue;
+        }
+
+        let mut value = String::new();
+        if characters.next_if_eq(&'"').is_some() {
+            while let Some(character) = characters.next() {
+                match character {
+                    '\\' => value.extend(characters.next()),
+                    '"' => break,
+                    other => value.push(other),
+                }
+            }
+        } else {
+            while let Some(character) = characters.next_if(|character| !character.is_whitespace()) {
+                value.push(character);
+            }
+        }
+        if !key.is_empty() {
+            pairs.push((sanitize_field_name(&key), value));
+        }
+    }
+
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::tools::detected::{parse_logfmt, sample_fields, sample_labels};
+
+    #[test]
+    fn parses_logfmt_with_quotes_and_bare_words() {
+        assert_eq!(
+            parse_logfmt(r#"level=info msg="request \"done\" ok" bare duration=12ms ="#),
+            vec![
+                ("level".to_string(), "info".to_string()),
+                ("msg".to_string(), "request \"done\" ok".to_string()),
+                ("duration".to_string(), "12ms".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_field_types_cardinality_and_parsers_from_sampled_lines() {
+        let data = json!({
+            "resultType": "streams",
+            "result": [
+                {
+                    "stream": {"app": "api", "env": "prod"},
+                    "values": [
+                        ["3", r#"{"status":200,"latency":"12ms","http":{"method":"GET"},"tags":["a"]}"#],
+                        ["2", r#"{"status":503,"latency":"1.5s","http":{"method":"POST"},"ok":false}"#],
+                    ],
+                },
+                {
+                    "stream": {"app": "web", "env": "prod"},
+                    "values": [["1", "level=warn status=2.5 size=10KB user=alice"]],
+                },
+            ],
+        });
+
+        let (lines, fields) = sample_fields(&data);
+        assert_eq!(lines, 3);
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|field| field["name"] == name)
+                .cloned()
+                .unwrap_or_else(|| panic!("missing field {name}"))
+        };
+        assert_eq!(field("status")["type"], "float");
+        assert_eq!(field("status")["cardinality"], 3);
+        assert_eq!(field("status")["parsers"], json!(["json", "logfmt"]));
+        assert_eq!(field("latency")["type"], "duration");
+        assert_eq!(field("http_method")["type"], "string");
+        assert_eq!(field("http_method")["cardinality"], 2);
+        assert_eq!(field("ok")["type"], "boolean");
+        assert_eq!(field("size")["type"], "bytes");
+        assert!(fields.iter().all(|field| field["name"] != "tags"));
+
+        assert_eq!(
+            sample_labels(&data),
+            vec![
+                json!({"name": "app", "cardinality": 2}),
+                json!({"name": "env", "cardinality": 1}),
+            ]
+        );
+    }
+}
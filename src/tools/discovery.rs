@@ -3,9 +3,28 @@
 use anyhow::{Result, bail};
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
+use serde::Deserialize;
 use serde_json::{Value, json};
 
-use crate::{config::Config, loki::client::LokiClient, time::parse_time_reference};
+use crate::{
+    config::Config,
+    loki::client::LokiClient,
+    time::{parse_time_reference, resolve_time_range},
+};
+
+const DEFAULT_VOLUME_LIMIT: u32 = 100;
+const MAX_VOLUME_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VolumeInput {
+    pub query: Option<String>,
+    pub target_labels: Option<Vec<String>>,
+    pub aggregate_by: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub step: Option<String>,
+    pub limit: Option<u32>,
+}
 
 type OptionalRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
 
@@ -17,6 +36,7 @@ pub fn describe_schema(config: &Config) -> Value {
         "notes": {
             "label_selector_syntax": "{label=\"value\"}",
             "structured_metadata_filter_syntax": "{label=\"value\"} | field=\"value\"",
+            "saved_query_params": "pass values as loki_run_saved_query params. params without a default are required; a param with a label accepts only that label's common_values",
         }
     })
 }
@@ -65,6 +85,135 @@ pub async fn series(
     Ok(json!({ "series": series }))
 }
 
+/// Ranks streams (or label groups) by bytes ingested, using Loki's index volume APIs.
+///
+/// With `step` the range endpoint is used and each entry also carries its per-step samples.
+pub async fn volume(client: &LokiClient, timezone: Tz, input: VolumeInput) -> Result<Value> {
+    let target_labels = input.target_labels.unwrap_or_default();
+    let query = volume_selector(input.query.as_deref(), &target_labels)?;
+    let aggregate_by = input.aggregate_by.as_deref().map(str::trim);
+    if let Some(aggregate_by) = aggregate_by
+        && !matches!(aggregate_by, "series" | "labels")
+    {
+        bail!("unsupported aggregate_by: {aggregate_by}. expected one of series, labels");
+    }
+    let limit = input
+        .limit
+        .unwrap_or(DEFAULT_VOLUME_LIMIT)
+        .clamp(1, MAX_VOLUME_LIMIT);
+    let (start, end) = resolve_time_range(
+        input.start.as_deref(),
+        input.end.as_deref(),
+        timezone,
+        Utc::now(),
+    )?;
+
+    let data = match input.step.as_deref() {
+        Some(step) => {
+            client
+                .volume_range(
+                    &query,
+                    Some(start),
+                    Some(end),
+                    step,
+                    Some(limit),
+                    &target_labels,
+                    aggregate_by,
+                )
+                .await?
+        }
+        None => {
+            client
+                .volume(
+                    &query,
+                    Some(start),
+                    Some(end),
+                    Some(limit),
+                    &target_labels,
+                    aggregate_by,
+                )
+                .await?
+        }
+    };
+
+    let mut streams = data
+        .get("result")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|entry| {
+            let labels = entry.get("metric").cloned().unwrap_or_else(|| json!({}));
+            let (bytes, values) = match entry.get("values") {
+                Some(values) => (
+                    values
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(sample_bytes)
+                        .sum::<u64>(),
+                    Some(values.clone()),
+                ),
+                None => (
+                    entry.get("value").map(sample_bytes).unwrap_or_default(),
+                    None,
+                ),
+            };
+            (labels, bytes, values)
+        })
+        .collect::<Vec<(Value, u64, Option<Value>)>>();
+    streams.sort_by_key(|(_, bytes, _)| std::cmp::Reverse(*bytes));
+    let total_bytes = streams.iter().map(|(_, bytes, _)| bytes).sum::<u64>();
+
+    let ranked = streams
+        .into_iter()
+        .map(|(labels, bytes, values)| {
+            let mut entry = json!({
+                "labels": labels,
+                "bytes": bytes,
+                "share": if total_bytes == 0 { 0.0 } else { bytes as f64 / total_bytes as f64 },
+            });
+            if let (Some(values), Some(object)) = (values, entry.as_object_mut()) {
+                object.insert("values".to_string(), values);
+            }
+            entry
+        })
+        .collect::<Vec<Value>>();
+
+    Ok(json!({
+        "query": query,
+        "start": start,
+        "end": end,
+        "step": input.step,
+        "target_labels": target_labels,
+        "aggregate_by": aggregate_by.unwrap_or("series"),
+        "total_bytes": total_bytes,
+        "streams": ranked,
+    }))
+}
+
+/// Uses `query` when given, otherwise matches every stream that has the first target label.
+pub(crate) fn volume_selector(query: Option<&str>, target_labels: &[String]) -> Result<String> {
+    if let Some(query) = query.map(str::trim).filter(|query| !query.is_empty()) {
+        return Ok(query.to_string());
+    }
+
+    match target_labels.first() {
+        Some(label) => Ok(format!("{{{label}=~\".+\"}}")),
+        None => bail!("volume needs a `query` selector or at least one entry in `target_labels`"),
+    }
+}
+
+fn sample_bytes(sample: &Value) -> u64 {
+    sample
+        .get(1)
+        .and_then(|value| match value {
+            Value::String(text) => text.parse::<f64>().ok(),
+            other => other.as_f64(),
+        })
+        .map(|bytes| bytes.max(0.0) as u64)
+        .unwrap_or_default()
+}
+
 fn parse_optional_range(
     start: Option<&str>,
     end: Option<&str>,
@@ -1,33 +1,101 @@
 #![allow(dead_code)]
 
 pub mod analysis;
+pub mod builder;
+pub mod cursor;
+pub mod detected;
 pub mod discovery;
 pub mod query;
+pub mod rules;
+pub mod saved;
+pub mod split;
 pub mod utility;
 
-use std::{collections::BTreeMap, time::Duration as StdDuration};
+use std::{borrow::Cow, collections::BTreeMap, fmt, time::Duration as StdDuration};
 
 use anyhow::{Context, Result, bail};
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
+use reqwest::header::HeaderMap;
 use serde::{Deserialize, de::DeserializeOwned};
 use serde_json::{Map, Value, json};
+use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
     cache::QueryCache,
-    config::Config,
+    config::{Config, ResolvedDatasource},
     guardrails::{self, GuardrailDecision},
-    loki::{client::LokiClient, types::LokiQueryStats},
+    logql::{self, lint::LabelSchema},
+    loki::{client::LokiClient, resilience::OpenCircuit, types::LokiQueryStats},
     metrics::MetricsRegistry,
+    tenants::{TENANT_SEPARATOR, federated_tenants},
     time::{parse_std_duration, parse_time_reference, resolve_time_range},
+    tools::split::QuerySplit,
 };
 
+/// Incremental output from a long-running tool, such as new lines from `loki_tail`.
+#[derive(Debug, Clone)]
+pub struct ToolProgress {
+    pub progress: u64,
+    pub total: Option<u64>,
+    pub message: String,
+    pub data: Value,
+}
+
+pub type ProgressSender = UnboundedSender<ToolProgress>;
+
+/// Per-call settings resolved by the MCP layer.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallContext {
+    /// Sent as `X-Scope-OrgID` instead of the datasource tenant when set.
+    pub tenant_id: Option<String>,
+    /// Receives incremental output from tools that stream.
+    pub progress: Option<ProgressSender>,
+    /// Headers of the inbound MCP request; datasources forward their `forward_headers` from it.
+    pub inbound_headers: Option<HeaderMap>,
+}
+
+/// A failed tool call together with the lint pre-check findings for its query.
+/// Displays as the underlying error; the MCP layer adds `lint` to the error result.
+#[derive(Debug)]
+pub struct LintedError {
+    pub error: anyhow::Error,
+    pub lint: Value,
+}
+
+impl fmt::Display for LintedError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only the outermost message; the rest of the chain comes from `source`.
+        write!(formatter, "{}", self.error)
+    }
+}
+
+impl std::error::Error for LintedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
 #[derive(Clone)]
 pub struct ToolRouter {
     config: Config,
-    loki_client: LokiClient,
     timezone: Tz,
     metrics: Option<MetricsRegistry>,
+    datasources: Vec<Datasource>,
+    default_datasource: String,
+    split: QuerySplit,
+    stream_labels: Vec<String>,
+    structured_metadata: Vec<String>,
+}
+
+/// A Loki backend with its own client, cache and guardrail settings.
+#[derive(Clone)]
+pub(crate) struct Datasource {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) url: String,
+    pub(crate) tenant_id: Option<String>,
+    pub(crate) loki_client: LokiClient,
     cache: Option<QueryCache>,
     cache_skip_if_range_shorter_than: StdDuration,
     guardrails: GuardrailSettings,
@@ -58,72 +126,166 @@ impl ToolRouter {
             .timezone
             .parse::<Tz>()
             .with_context(|| format!("invalid timezone: {}", config.server.timezone))?;
-        let loki_client = LokiClient::new(&config.loki)?;
-        let cache = if config.cache.enabled {
-            let ttl = parse_std_duration(&config.cache.ttl)
-                .with_context(|| format!("invalid cache.ttl: {}", config.cache.ttl))?;
-            Some(QueryCache::new(config.cache.max_entries, ttl))
-        } else {
-            None
-        };
-        let cache_skip_if_range_shorter_than =
-            parse_std_duration(&config.cache.skip_if_range_shorter_than).with_context(|| {
-                format!(
-                    "invalid cache.skip_if_range_shorter_than: {}",
-                    config.cache.skip_if_range_shorter_than
-                )
-            })?;
-
-        let max_bytes = guardrails::parse_byte_size(&config.guardrails.max_bytes_scanned)
-            .with_context(|| {
-                format!(
-                    "invalid guardrails.max_bytes_scanned: {}",
-                    config.guardrails.max_bytes_scanned
-                )
-            })?;
-        let max_bytes_scanned = if max_bytes == 0 {
-            None
-        } else {
-            Some(max_bytes)
-        };
-        let max_streams = if config.guardrails.max_streams == 0 {
-            None
-        } else {
-            Some(config.guardrails.max_streams)
-        };
-        let skip_stats_if_streams_below = config.guardrails.skip_stats_if_streams_below;
-        let skip_stats_if_range_shorter_than = parse_std_duration(
-            &config.guardrails.skip_stats_if_range_shorter_than,
-        )
-        .with_context(|| {
-            format!(
-                "invalid guardrails.skip_stats_if_range_shorter_than: {}",
-                config.guardrails.skip_stats_if_range_shorter_than
-            )
-        })?;
+        let datasources = config
+            .resolved_datasources()
+            .iter()
+            .map(|datasource| {
+                Datasource::new(datasource, metrics.as_ref())
+                    .with_context(|| format!("invalid datasource: {}", datasource.name))
+            })
+            .collect::<Result<Vec<Datasource>>>()?;
+        let default_datasource = config.default_datasource_name();
+        let split = QuerySplit::from_config(&config.query_split)?;
+        let stream_labels = config
+            .labels
+            .iter()
+            .map(|field| field.name.clone())
+            .collect();
+        let structured_metadata = config
+            .structured_metadata
+            .iter()
+            .map(|field| field.name.clone())
+            .collect();
 
         Ok(Self {
             config,
-            loki_client,
             timezone,
             metrics,
-            cache,
-            cache_skip_if_range_shorter_than,
-            guardrails: GuardrailSettings {
-                max_bytes_scanned,
-                max_streams,
-                skip_stats_if_streams_below,
-                skip_stats_if_range_shorter_than,
-            },
+            datasources,
+            default_datasource,
+            split,
+            stream_labels,
+            structured_metadata,
         })
     }
 
+    fn label_schema(&self) -> LabelSchema<'_> {
+        LabelSchema {
+            stream_labels: &self.stream_labels,
+            structured_metadata: &self.structured_metadata,
+        }
+    }
+
+    /// Returns the Loki tenant of the named datasource (or the default one when omitted).
+    pub fn datasource_tenant_id(&self, datasource: Option<&str>) -> Option<String> {
+        self.select_datasource(datasource)
+            .ok()
+            .and_then(|datasource| datasource.tenant_id.clone())
+    }
+
+    /// Lists circuit breakers that are rejecting calls, by datasource.
+    pub fn open_circuits(&self) -> Vec<(String, OpenCircuit)> {
+        self.datasources
+            .iter()
+            .flat_map(|datasource| {
+                datasource
+                    .loki_client
+                    .open_circuits()
+                    .into_iter()
+                    .map(|circuit| (datasource.name.clone(), circuit))
+            })
+            .collect()
+    }
+
+    pub fn default_datasource_name(&self) -> &str {
+        &self.default_datasource
+    }
+
+    fn select_datasource(&self, name: Option<&str>) -> Result<&Datasource> {
+        let name = name.unwrap_or(&self.default_datasource);
+        self.datasources
+            .iter()
+            .find(|datasource| datasource.name == name)
+            .ok_or_else(|| {
+                let known = self
+                    .datasources
+                    .iter()
+                    .map(|datasource| datasource.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ");
+                anyhow::anyhow!("unknown datasource: {name}. configured datasources: {known}")
+            })
+    }
+
     pub async fn call(&self, tool_name: &str, params: Value) -> Result<Value> {
-        let normalized_params = normalize_params(params);
-        let should_use_cache = self.should_use_cache(tool_name, &normalized_params);
+        self.call_with_context(tool_name, params, ToolCallContext::default())
+            .await
+    }
+
+    /// Runs a tool against the selected datasource. Cache entries are keyed per tenant.
+    pub async fn call_with_context(
+        &self,
+        tool_name: &str,
+        params: Value,
+        context: ToolCallContext,
+    ) -> Result<Value> {
+        let mut normalized_params = normalize_params(params);
+        let requested_datasource = take_datasource_param(&mut normalized_params)?;
+        let datasource = self.select_datasource(requested_datasource.as_deref())?;
+        if let Some(tenant_id) = context.tenant_id.as_deref()
+            && tenant_id.contains(TENANT_SEPARATOR)
+            && !supports_federation(tool_name)
+        {
+            bail!("{tool_name} does not support querying several tenants at once");
+        }
+        let mut datasource = Cow::Borrowed(datasource);
+        if let Some(tenant_id) = context.tenant_id.as_deref()
+            && datasource.tenant_id.as_deref() != Some(tenant_id)
+        {
+            datasource = Cow::Owned(datasource.for_tenant(tenant_id));
+        }
+        if let Some(inbound_headers) = context.inbound_headers.as_ref()
+            && datasource.loki_client.forwards_headers()
+        {
+            datasource = Cow::Owned(datasource.with_forwarded_headers(inbound_headers));
+        }
+        let datasource = datasource.as_ref();
+
+        if tool_name == "loki_list_datasources" {
+            return utility::list_datasources(&self.datasources, &self.default_datasource).await;
+        }
+
+        let lint = self
+            .config
+            .lint
+            .pre_check
+            .then(|| self.pre_check_lint(tool_name, &normalized_params))
+            .flatten();
+        let result = self
+            .call_datasource(
+                datasource,
+                tool_name,
+                normalized_params,
+                context.progress.as_ref(),
+            )
+            .await;
+
+        match (result, lint) {
+            (result, None) => result,
+            (Ok(mut response), Some(lint)) => {
+                if let Some(object) = response.as_object_mut() {
+                    object.insert("lint".to_string(), lint);
+                }
+                Ok(response)
+            }
+            (Err(error), Some(lint)) => Err(LintedError { error, lint }.into()),
+        }
+    }
+
+    /// Serves a call from the cache or runs it against Loki behind the guardrails.
+    async fn call_datasource(
+        &self,
+        datasource: &Datasource,
+        tool_name: &str,
+        normalized_params: Value,
+        progress: Option<&ProgressSender>,
+    ) -> Result<Value> {
+        let should_use_cache = self.should_use_cache(datasource, tool_name, &normalized_params);
 
         if should_use_cache
-            && let Some(cached) = self.try_cache_get(tool_name, &normalized_params).await?
+            && let Some(cached) = self
+                .try_cache_get(datasource, tool_name, &normalized_params)
+                .await?
         {
             if let Some(metrics) = self.metrics.as_ref() {
                 metrics.inc_tool_cache_hit(tool_name);
@@ -134,7 +296,10 @@ impl ToolRouter {
             metrics.inc_tool_cache_miss(tool_name);
         }
 
-        if let Err(error) = self.enforce_guardrails(tool_name, &normalized_params).await {
+        if let Err(error) = self
+            .enforce_guardrails(datasource, tool_name, &normalized_params)
+            .await
+        {
             if let Some(metrics) = self.metrics.as_ref()
                 && is_guardrail_error(&error)
             {
@@ -143,23 +308,67 @@ impl ToolRouter {
             return Err(error);
         }
 
-        let response = self.dispatch(tool_name, normalized_params.clone()).await?;
-
+        let mut response = self
+            .dispatch(datasource, tool_name, normalized_params.clone(), progress)
+            .await?;
+        if uses_datasource(tool_name)
+            && let Some(object) = response.as_object_mut()
+        {
+            object.insert("datasource".to_string(), json!(datasource.name));
+            if let Some(tenant_id) = datasource.tenant_id.as_deref()
+                && tenant_id.contains(TENANT_SEPARATOR)
+            {
+                object.insert("tenants".to_string(), json!(federated_tenants(tenant_id)));
+            }
+        }
         if should_use_cache {
-            self.try_cache_put(tool_name, &normalized_params, &response)
+            self.try_cache_put(datasource, tool_name, &normalized_params, &response)
                 .await?;
         }
 
         Ok(response)
     }
 
-    async fn dispatch(&self, tool_name: &str, params: Value) -> Result<Value> {
+    /// Lint findings for the query a tool is about to run, or `None` when there are none.
+    /// Invalid input and unparsable queries are left for the tool or Loki to reject.
+    fn pre_check_lint(&self, tool_name: &str, params: &Value) -> Option<Value> {
+        let query = match tool_name {
+            "loki_build_query" => {
+                let input: query::BuildQueryInput = parse_params(params.clone()).ok()?;
+                builder::build_query_string(&input).ok()?
+            }
+            "loki_run_saved_query" => {
+                let input: query::RunSavedQueryInput = parse_params(params.clone()).ok()?;
+                saved_query_run(&self.config, &input, self.timezone).ok()?.0
+            }
+            _ if lints_query_param(tool_name) => params.get("query")?.as_str()?.to_string(),
+            _ => return None,
+        };
+        let query = query.trim();
+        let expr = logql::parse(query).ok()?;
+        let findings = logql::lint::lint(&expr, self.label_schema());
+
+        (!findings.is_empty()).then(|| utility::lint_findings_json(&findings, query))
+    }
+
+    async fn dispatch(
+        &self,
+        datasource: &Datasource,
+        tool_name: &str,
+        params: Value,
+        progress: Option<&ProgressSender>,
+    ) -> Result<Value> {
+        let loki_client = &datasource.loki_client;
         match tool_name {
             "loki_describe_schema" => Ok(discovery::describe_schema(&self.config)),
+            "loki_list_saved_queries" => {
+                let input: ListSavedQueriesParams = parse_params(params)?;
+                Ok(saved::list(&self.config, &input.tags.unwrap_or_default()))
+            }
             "loki_list_labels" => {
                 let input: StartEndParams = parse_params(params)?;
                 discovery::list_labels(
-                    &self.loki_client,
+                    loki_client,
                     self.timezone,
                     input.start.as_deref(),
                     input.end.as_deref(),
@@ -169,7 +378,7 @@ impl ToolRouter {
             "loki_label_values" => {
                 let input: LabelValuesParams = parse_params(params)?;
                 discovery::label_values(
-                    &self.loki_client,
+                    loki_client,
                     self.timezone,
                     &input.label,
                     input.start.as_deref(),
@@ -181,7 +390,7 @@ impl ToolRouter {
             "loki_series" => {
                 let input: SeriesParams = parse_params(params)?;
                 discovery::series(
-                    &self.loki_client,
+                    loki_client,
                     self.timezone,
                     &input.r#match,
                     input.start.as_deref(),
@@ -189,42 +398,75 @@ impl ToolRouter {
                 )
                 .await
             }
+            "loki_volume" => {
+                let input: discovery::VolumeInput = parse_params(params)?;
+                discovery::volume(loki_client, self.timezone, input).await
+            }
+            "loki_detected_fields" => {
+                let input: detected::DetectedFieldsInput = parse_params(params)?;
+                detected::detected_fields(loki_client, self.timezone, input).await
+            }
+            "loki_detected_labels" => {
+                let input: detected::DetectedLabelsInput = parse_params(params)?;
+                detected::detected_labels(loki_client, self.timezone, input).await
+            }
             "loki_query_logs" => {
                 let input: query::QueryLogsInput = parse_params(params)?;
-                query::query_logs(&self.loki_client, self.timezone, input).await
+                query::query_logs(loki_client, &self.split, self.timezone, input).await
             }
             "loki_query_metrics" => {
                 let input: query::QueryMetricsInput = parse_params(params)?;
-                query::query_metrics(&self.loki_client, self.timezone, input).await
+                query::query_metrics(loki_client, &self.split, self.timezone, input).await
+            }
+            "loki_query_instant" => {
+                let input: query::QueryInstantInput = parse_params(params)?;
+                query::query_instant(loki_client, self.timezone, input).await
             }
             "loki_build_query" => {
                 let input: query::BuildQueryInput = parse_params(params)?;
-                query::build_query(&self.loki_client, self.timezone, input).await
+                query::build_query(loki_client, &self.split, self.timezone, input).await
             }
             "loki_tail" => {
                 let input: query::TailInput = parse_params(params)?;
-                query::tail(&self.loki_client, self.timezone, input).await
+                query::tail(loki_client, self.timezone, input, progress).await
             }
             "loki_run_saved_query" => {
                 let input: query::RunSavedQueryInput = parse_params(params)?;
-                query::run_saved_query(&self.loki_client, &self.config, self.timezone, input).await
+                query::run_saved_query(loki_client, &self.split, &self.config, self.timezone, input)
+                    .await
             }
             "loki_query_stats" => {
                 let input: analysis::QueryStatsInput = parse_params(params)?;
-                analysis::query_stats(&self.loki_client, self.timezone, input).await
+                analysis::query_stats(loki_client, self.timezone, input).await
             }
             "loki_detect_patterns" => {
                 let input: analysis::DetectPatternsInput = parse_params(params)?;
-                analysis::detect_patterns(&self.loki_client, self.timezone, input).await
+                analysis::detect_patterns(loki_client, self.timezone, input).await
             }
             "loki_compare_ranges" => {
                 let input: analysis::CompareRangesInput = parse_params(params)?;
-                analysis::compare_ranges(&self.loki_client, self.timezone, input).await
+                analysis::compare_ranges(loki_client, self.timezone, input).await
+            }
+            "loki_list_rules" => {
+                let input: rules::ListRulesInput = parse_params(params)?;
+                rules::list_rules(loki_client, input).await
+            }
+            "loki_list_alerts" => {
+                let input: rules::ListAlertsInput = parse_params(params)?;
+                rules::list_alerts(loki_client, input).await
+            }
+            "loki_get_alert_query" => {
+                let input: rules::AlertQueryInput = parse_params(params)?;
+                rules::alert_query(loki_client, input).await
             }
             "loki_explain_query" => {
                 let input: ExplainQueryParams = parse_params(params)?;
                 utility::explain_query(&input.query)
             }
+            "loki_lint_query" => {
+                let input: ExplainQueryParams = parse_params(params)?;
+                utility::lint_query(&input.query, self.label_schema())
+            }
             "loki_suggest_metric_rule" => {
                 let input: SuggestMetricRuleParams = parse_params(params)?;
                 utility::suggest_metric_rule(
@@ -236,13 +478,13 @@ impl ToolRouter {
                     input.alert_for.as_deref(),
                 )
             }
-            "loki_check_health" => utility::check_health(&self.loki_client).await,
+            "loki_check_health" => utility::check_health(loki_client).await,
             _ => bail!("unknown tool: {tool_name}"),
         }
     }
 
-    fn should_use_cache(&self, tool_name: &str, params: &Value) -> bool {
-        if self.cache.is_none() || !is_cacheable_tool(tool_name) {
+    fn should_use_cache(&self, datasource: &Datasource, tool_name: &str, params: &Value) -> bool {
+        if datasource.cache.is_none() || !is_cacheable_tool(tool_name) {
             return false;
         }
 
@@ -252,33 +494,50 @@ impl ToolRouter {
         };
 
         if let Some(duration) = range_duration {
-            duration >= self.cache_skip_if_range_shorter_than
+            duration >= datasource.cache_skip_if_range_shorter_than
         } else {
             true
         }
     }
 
-    async fn try_cache_get(&self, tool_name: &str, params: &Value) -> Result<Option<Value>> {
-        let Some(cache) = self.cache.as_ref() else {
+    async fn try_cache_get(
+        &self,
+        datasource: &Datasource,
+        tool_name: &str,
+        params: &Value,
+    ) -> Result<Option<Value>> {
+        let Some(cache) = datasource.cache.as_ref() else {
             return Ok(None);
         };
 
-        let key = cache_key(tool_name, params)?;
+        let key = tenant_cache_key(datasource, tool_name, params)?;
         Ok(cache.get(&key).await)
     }
 
-    async fn try_cache_put(&self, tool_name: &str, params: &Value, value: &Value) -> Result<()> {
-        let Some(cache) = self.cache.as_ref() else {
+    async fn try_cache_put(
+        &self,
+        datasource: &Datasource,
+        tool_name: &str,
+        params: &Value,
+        value: &Value,
+    ) -> Result<()> {
+        let Some(cache) = datasource.cache.as_ref() else {
             return Ok(());
         };
 
-        let key = cache_key(tool_name, params)?;
+        let key = tenant_cache_key(datasource, tool_name, params)?;
         cache.insert(key, value.clone()).await;
         Ok(())
     }
 
-    async fn enforce_guardrails(&self, tool_name: &str, params: &Value) -> Result<()> {
-        if !self.guardrails.enabled() || !is_guardrailed_tool(tool_name) {
+    async fn enforce_guardrails(
+        &self,
+        datasource: &Datasource,
+        tool_name: &str,
+        params: &Value,
+    ) -> Result<()> {
+        let guardrails = datasource.guardrails;
+        if !guardrails.enabled() || !is_guardrailed_tool(tool_name) {
             return Ok(());
         }
 
@@ -286,31 +545,17 @@ impl ToolRouter {
         for guardrail_query in guardrail_queries {
             for (start, end) in &guardrail_query.ranges {
                 let range_duration = duration_between(*start, *end)?;
-                if range_duration < self.guardrails.skip_stats_if_range_shorter_than {
+                if range_duration < guardrails.skip_stats_if_range_shorter_than {
                     continue;
                 }
 
-                let mut stats = self
-                    .loki_client
-                    .query_stats(&guardrail_query.query, Some(*start), Some(*end))
+                let stats = estimate_query_stats(datasource, &guardrail_query.query, *start, *end)
                     .await
                     .with_context(|| {
                         format!(
                             "guardrail pre-check failed for query. narrow the query or use a shorter range (start={start}, end={end})"
                         )
                     })?;
-                if needs_runtime_stats_fallback(&stats) {
-                    let runtime_stats = self
-                        .loki_client
-                        .query_runtime_stats(&guardrail_query.query, Some(*start), Some(*end))
-                        .await
-                        .with_context(|| {
-                            format!(
-                                "guardrail pre-check failed for query. narrow the query or use a shorter range (start={start}, end={end})"
-                            )
-                        })?;
-                    stats = merge_stats(stats, runtime_stats);
-                }
 
                 let estimated_streams = stats.streams.ok_or_else(|| {
                     anyhow::anyhow!(
@@ -318,7 +563,7 @@ impl ToolRouter {
                     )
                 })?;
 
-                if estimated_streams < self.guardrails.skip_stats_if_streams_below {
+                if estimated_streams < guardrails.skip_stats_if_streams_below {
                     continue;
                 }
 
@@ -331,20 +576,22 @@ impl ToolRouter {
                 match guardrails::evaluate(
                     estimated_bytes,
                     estimated_streams,
-                    self.guardrails.max_bytes_scanned,
-                    self.guardrails.max_streams,
+                    guardrails.max_bytes_scanned,
+                    guardrails.max_streams,
                 ) {
                     GuardrailDecision::Allow => {}
                     GuardrailDecision::RejectBytes => {
-                        let limit = self.guardrails.max_bytes_scanned.unwrap_or_default();
+                        let limit = guardrails.max_bytes_scanned.unwrap_or_default();
                         bail!(
-                            "query rejected by guardrail: estimated bytes scanned ({estimated_bytes}) exceeds configured limit ({limit}). narrow labels or shorten the time range"
+                            "query rejected by guardrail: estimated bytes scanned ({estimated_bytes}) exceeds configured limit ({limit}). narrow labels or shorten the time range. {}",
+                            volume_hint(&guardrail_query.query)
                         );
                     }
                     GuardrailDecision::RejectStreams => {
-                        let limit = self.guardrails.max_streams.unwrap_or_default();
+                        let limit = guardrails.max_streams.unwrap_or_default();
                         bail!(
-                            "query rejected by guardrail: estimated streams ({estimated_streams}) exceeds configured limit ({limit}). add narrower label selectors or shorten the time range"
+                            "query rejected by guardrail: estimated streams ({estimated_streams}) exceeds configured limit ({limit}). add narrower label selectors or shorten the time range. {}",
+                            volume_hint(&guardrail_query.query)
                         );
                     }
                 }
@@ -382,14 +629,43 @@ impl ToolRouter {
                     ranges: vec![range],
                 }])
             }
+            "loki_detected_fields" => {
+                let input: detected::DetectedFieldsInput = parse_params(params.clone())?;
+                let range = resolve_time_range(
+                    input.start.as_deref(),
+                    input.end.as_deref(),
+                    self.timezone,
+                    Utc::now(),
+                )?;
+                Ok(vec![GuardrailQuery {
+                    query: input.query,
+                    ranges: vec![range],
+                }])
+            }
+            "loki_detected_labels" => {
+                let input: detected::DetectedLabelsInput = parse_params(params.clone())?;
+                let range = resolve_time_range(
+                    input.start.as_deref(),
+                    input.end.as_deref(),
+                    self.timezone,
+                    Utc::now(),
+                )?;
+                Ok(vec![GuardrailQuery {
+                    query: input.query,
+                    ranges: vec![range],
+                }])
+            }
+            "loki_query_instant" => {
+                let input: query::QueryInstantInput = parse_params(params.clone())?;
+                let range = instant_range(&input, self.timezone)?;
+                Ok(vec![GuardrailQuery {
+                    query: input.query,
+                    ranges: vec![range],
+                }])
+            }
             "loki_build_query" => {
                 let input: query::BuildQueryInput = parse_params(params.clone())?;
-                let mut built_query = query::build_query_string(&input)?;
-                if let Some(aggregation) = input.aggregation.as_deref() {
-                    query::validate_aggregation(aggregation)?;
-                    let aggregation_range = input.aggregation_range.as_deref().unwrap_or("5m");
-                    built_query = format!("{aggregation}({built_query}[{aggregation_range}])");
-                }
+                let built_query = builder::build_query_string(&input)?;
 
                 let range = resolve_time_range(
                     input.start.as_deref(),
@@ -405,11 +681,10 @@ impl ToolRouter {
             }
             "loki_tail" => {
                 let input: query::TailInput = parse_params(params.clone())?;
-                if input.labels.is_empty() {
-                    bail!("tail labels must not be empty");
-                }
-                let selector = query::selector_from_labels(&input.labels);
-                let range = resolve_time_range(None, None, self.timezone, Utc::now())?;
+                // `since` replays history before following new lines, so it is what gets scanned.
+                let now = Utc::now();
+                let (selector, start) = query::tail_start(&input, self.timezone, now)?;
+                let range = (start, now);
 
                 Ok(vec![GuardrailQuery {
                     query: selector,
@@ -418,29 +693,9 @@ impl ToolRouter {
             }
             "loki_run_saved_query" => {
                 let input: query::RunSavedQueryInput = parse_params(params.clone())?;
-                let Some(saved_query) = self
-                    .config
-                    .saved_queries
-                    .iter()
-                    .find(|saved_query| saved_query.name == input.name)
-                else {
-                    bail!("saved query not found: {}", input.name);
-                };
-
-                let range = resolve_time_range(
-                    Some(
-                        input
-                            .override_range
-                            .as_deref()
-                            .unwrap_or(saved_query.range.as_str()),
-                    ),
-                    None,
-                    self.timezone,
-                    Utc::now(),
-                )?;
-
+                let (query, range) = saved_query_run(&self.config, &input, self.timezone)?;
                 Ok(vec![GuardrailQuery {
-                    query: saved_query.query.clone(),
+                    query,
                     ranges: vec![range],
                 }])
             }
@@ -480,6 +735,33 @@ impl ToolRouter {
 
     fn cache_range_duration(&self, tool_name: &str, params: &Value) -> Result<Option<StdDuration>> {
         match tool_name {
+            "loki_volume" => {
+                let input: discovery::VolumeInput = parse_params(params.clone())?;
+                range_duration_from_bounds(
+                    input.start.as_deref(),
+                    input.end.as_deref(),
+                    self.timezone,
+                )
+                .map(Some)
+            }
+            "loki_detected_fields" => {
+                let input: detected::DetectedFieldsInput = parse_params(params.clone())?;
+                range_duration_from_bounds(
+                    input.start.as_deref(),
+                    input.end.as_deref(),
+                    self.timezone,
+                )
+                .map(Some)
+            }
+            "loki_detected_labels" => {
+                let input: detected::DetectedLabelsInput = parse_params(params.clone())?;
+                range_duration_from_bounds(
+                    input.start.as_deref(),
+                    input.end.as_deref(),
+                    self.timezone,
+                )
+                .map(Some)
+            }
             "loki_query_logs" => {
                 let input: query::QueryLogsInput = parse_params(params.clone())?;
                 range_duration_from_bounds(
@@ -498,6 +780,11 @@ impl ToolRouter {
                 )
                 .map(Some)
             }
+            "loki_query_instant" => {
+                let input: query::QueryInstantInput = parse_params(params.clone())?;
+                let (start, end) = instant_range(&input, self.timezone)?;
+                duration_between(start, end).map(Some)
+            }
             "loki_build_query" => {
                 let input: query::BuildQueryInput = parse_params(params.clone())?;
                 range_duration_from_bounds(
@@ -507,29 +794,9 @@ impl ToolRouter {
                 )
                 .map(Some)
             }
-            "loki_tail" => range_duration_from_bounds(None, None, self.timezone).map(Some),
             "loki_run_saved_query" => {
                 let input: query::RunSavedQueryInput = parse_params(params.clone())?;
-                let Some(saved_query) = self
-                    .config
-                    .saved_queries
-                    .iter()
-                    .find(|saved_query| saved_query.name == input.name)
-                else {
-                    bail!("saved query not found: {}", input.name);
-                };
-
-                let (start, end) = resolve_time_range(
-                    Some(
-                        input
-                            .override_range
-                            .as_deref()
-                            .unwrap_or(saved_query.range.as_str()),
-                    ),
-                    None,
-                    self.timezone,
-                    Utc::now(),
-                )?;
+                let (_, (start, end)) = saved_query_run(&self.config, &input, self.timezone)?;
                 duration_between(start, end).map(Some)
             }
             "loki_query_stats" => {
@@ -594,12 +861,107 @@ impl ToolRouter {
     }
 }
 
-fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T> {
-    serde_json::from_value(normalize_params(params)).context("invalid tool parameters")
-}
+impl Datasource {
+    fn new(config: &ResolvedDatasource, metrics: Option<&MetricsRegistry>) -> Result<Self> {
+        let mut loki_client = LokiClient::new(&config.loki)?;
+        if let Some(metrics) = metrics {
+            loki_client = loki_client.with_metrics(&config.name, metrics.clone());
+        }
+        let cache = if config.cache.enabled {
+            let ttl = parse_std_duration(&config.cache.ttl)
+                .with_context(|| format!("invalid cache.ttl: {}", config.cache.ttl))?;
+            Some(QueryCache::new(config.cache.max_entries, ttl))
+        } else {
+            None
+        };
+        let cache_skip_if_range_shorter_than =
+            parse_std_duration(&config.cache.skip_if_range_shorter_than).with_context(|| {
+                format!(
+                    "invalid cache.skip_if_range_shorter_than: {}",
+                    config.cache.skip_if_range_shorter_than
+                )
+            })?;
 
-fn normalize_params(params: Value) -> Value {
-    if params.is_null() { json!({}) } else { params }
+        let max_bytes = guardrails::parse_byte_size(&config.guardrails.max_bytes_scanned)
+            .with_context(|| {
+                format!(
+                    "invalid guardrails.max_bytes_scanned: {}",
+                    config.guardrails.max_bytes_scanned
+                )
+            })?;
+        let max_bytes_scanned = if max_bytes == 0 {
+            None
+        } else {
+            Some(max_bytes)
+        };
+        let max_streams = if config.guardrails.max_streams == 0 {
+            None
+        } else {
+            Some(config.guardrails.max_streams)
+        };
+        let skip_stats_if_streams_below = config.guardrails.skip_stats_if_streams_below;
+        let skip_stats_if_range_shorter_than = parse_std_duration(
+            &config.guardrails.skip_stats_if_range_shorter_than,
+        )
+        .with_context(|| {
+            format!(
+                "invalid guardrails.skip_stats_if_range_shorter_than: {}",
+                config.guardrails.skip_stats_if_range_shorter_than
+            )
+        })?;
+
+        Ok(Self {
+            name: config.name.clone(),
+            description: config.description.clone(),
+            url: config.loki.url.clone(),
+            tenant_id: config.loki.tenant_id.clone(),
+            loki_client,
+            cache,
+            cache_skip_if_range_shorter_than,
+            guardrails: GuardrailSettings {
+                max_bytes_scanned,
+                max_streams,
+                skip_stats_if_streams_below,
+                skip_stats_if_range_shorter_than,
+            },
+        })
+    }
+
+    fn for_tenant(&self, tenant_id: &str) -> Self {
+        Self {
+            tenant_id: Some(tenant_id.to_string()),
+            loki_client: self.loki_client.with_tenant(Some(tenant_id.to_string())),
+            ..self.clone()
+        }
+    }
+
+    fn with_forwarded_headers(&self, inbound_headers: &HeaderMap) -> Self {
+        Self {
+            loki_client: self.loki_client.with_forwarded_headers(inbound_headers),
+            ..self.clone()
+        }
+    }
+}
+
+fn take_datasource_param(params: &mut Value) -> Result<Option<String>> {
+    let Some(object) = params.as_object_mut() else {
+        return Ok(None);
+    };
+
+    match object.remove("datasource") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(name)) if name.trim().is_empty() => Ok(None),
+        Some(Value::String(name)) => Ok(Some(name.trim().to_string())),
+        Some(_) => bail!("datasource must be a string"),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T> {
+    serde_json::from_value(normalize_params(params)).context("invalid tool parameters")
+}
+
+fn normalize_params(params: Value) -> Value {
+    if params.is_null() { json!({}) } else { params }
 }
 
 fn is_cacheable_tool(tool_name: &str) -> bool {
@@ -608,10 +970,13 @@ fn is_cacheable_tool(tool_name: &str) -> bool {
         "loki_list_labels"
             | "loki_label_values"
             | "loki_series"
+            | "loki_volume"
+            | "loki_detected_fields"
+            | "loki_detected_labels"
             | "loki_query_logs"
             | "loki_query_metrics"
+            | "loki_query_instant"
             | "loki_build_query"
-            | "loki_tail"
             | "loki_run_saved_query"
             | "loki_query_stats"
             | "loki_detect_patterns"
@@ -619,11 +984,59 @@ fn is_cacheable_tool(tool_name: &str) -> bool {
     )
 }
 
+pub(crate) fn uses_datasource(tool_name: &str) -> bool {
+    !matches!(
+        tool_name,
+        "loki_describe_schema"
+            | "loki_list_saved_queries"
+            | "loki_explain_query"
+            | "loki_lint_query"
+            | "loki_suggest_metric_rule"
+            | "loki_list_datasources"
+    )
+}
+
+/// Tools that can query several tenants at once (`X-Scope-OrgID: a|b`).
+pub(crate) fn supports_federation(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        "loki_query_logs"
+            | "loki_query_metrics"
+            | "loki_query_instant"
+            | "loki_build_query"
+            | "loki_run_saved_query"
+            | "loki_compare_ranges"
+    )
+}
+
+/// Tools whose `query` parameter runs against Loki as written, so the lint pre-check applies.
+fn lints_query_param(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        "loki_query_logs"
+            | "loki_query_metrics"
+            | "loki_query_instant"
+            | "loki_query_stats"
+            | "loki_volume"
+            | "loki_detected_fields"
+            | "loki_detect_patterns"
+            | "loki_compare_ranges"
+    )
+}
+
+/// Tools that report incremental output through [`ToolCallContext::progress`].
+pub(crate) fn streams_progress(tool_name: &str) -> bool {
+    tool_name == "loki_tail"
+}
+
 fn is_guardrailed_tool(tool_name: &str) -> bool {
     matches!(
         tool_name,
         "loki_query_logs"
             | "loki_query_metrics"
+            | "loki_query_instant"
+            | "loki_detected_fields"
+            | "loki_detected_labels"
             | "loki_build_query"
             | "loki_tail"
             | "loki_run_saved_query"
@@ -632,6 +1045,91 @@ fn is_guardrailed_tool(tool_name: &str) -> bool {
     )
 }
 
+/// Points a rejected caller at `loki_volume` for the query's stream selector.
+fn volume_hint(query: &str) -> String {
+    match stream_selector(query) {
+        Some(selector) => format!(
+            "run loki_volume with query {selector} and target_labels to see which streams produce the most bytes"
+        ),
+        None => "run loki_volume with target_labels to see which streams produce the most bytes"
+            .to_string(),
+    }
+}
+
+/// Returns the first `{...}` stream selector in a LogQL query, skipping braces inside strings.
+fn stream_selector(query: &str) -> Option<&str> {
+    let start = query.find('{')?;
+    let mut quote = None;
+    let mut escaped = false;
+    for (offset, character) in query[start..].char_indices() {
+        match (quote, character) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(open), character) if character == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '`') => quote = Some(character),
+            (None, '}') => return Some(&query[start..=start + offset]),
+            (None, _) => {}
+        }
+    }
+
+    None
+}
+
+/// Index stats for a guardrail pre-check. Federated tenants are estimated one by one and
+/// summed, so the limits apply to the whole tenant set.
+async fn estimate_query_stats(
+    datasource: &Datasource,
+    query: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<LokiQueryStats> {
+    let clients = match datasource.tenant_id.as_deref() {
+        Some(tenant_id) if tenant_id.contains(TENANT_SEPARATOR) => federated_tenants(tenant_id)
+            .into_iter()
+            .map(|tenant| datasource.loki_client.with_tenant(Some(tenant.to_string())))
+            .collect(),
+        _ => vec![datasource.loki_client.clone()],
+    };
+
+    let mut total: Option<LokiQueryStats> = None;
+    for client in clients {
+        let mut stats = client.query_stats(query, Some(start), Some(end)).await?;
+        if needs_runtime_stats_fallback(&stats) {
+            let runtime_stats = client
+                .query_runtime_stats(query, Some(start), Some(end))
+                .await?;
+            stats = merge_stats(stats, runtime_stats);
+        }
+        total = Some(match total {
+            Some(total) => sum_stats(total, stats),
+            None => stats,
+        });
+    }
+
+    total.context("no tenant to estimate")
+}
+
+fn sum_stats(left: LokiQueryStats, right: LokiQueryStats) -> LokiQueryStats {
+    let add = |left: Option<u64>, right: Option<u64>| match (left, right) {
+        (Some(left), Some(right)) => Some(left.saturating_add(right)),
+        _ => None,
+    };
+    let mut raw = match left.raw {
+        Value::Array(raw) => raw,
+        raw => vec![raw],
+    };
+    raw.push(right.raw);
+
+    LokiQueryStats {
+        bytes_processed: add(left.bytes_processed, right.bytes_processed),
+        streams: add(left.streams, right.streams),
+        chunks: add(left.chunks, right.chunks),
+        entries: add(left.entries, right.entries),
+        raw: Value::Array(raw),
+    }
+}
+
 fn is_guardrail_error(error: &anyhow::Error) -> bool {
     error.to_string().to_ascii_lowercase().contains("guardrail")
 }
@@ -682,6 +1180,17 @@ fn cache_key(tool_name: &str, params: &Value) -> Result<String> {
     Ok(format!("{tool_name}:{serialized}"))
 }
 
+fn tenant_cache_key(datasource: &Datasource, tool_name: &str, params: &Value) -> Result<String> {
+    let key = cache_key(tool_name, params)?;
+    let tenant = datasource.tenant_id.as_deref().unwrap_or_default();
+    let forwarded = datasource.loki_client.forwarded_headers_key();
+    if forwarded.is_empty() {
+        Ok(format!("{tenant}|{key}"))
+    } else {
+        Ok(format!("{tenant}|{forwarded}|{key}"))
+    }
+}
+
 fn canonicalize_json(value: &Value) -> Value {
     match value {
         Value::Object(object) => {
@@ -711,6 +1220,43 @@ fn range_duration_from_bounds(
     duration_between(start, end)
 }
 
+/// An instant query reads its widest range selector back from the evaluation time.
+fn instant_range(
+    input: &query::QueryInstantInput,
+    timezone: Tz,
+) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let time = query::instant_time(input.time.as_deref(), timezone)?;
+    let lookback = chrono::Duration::from_std(query::instant_lookback(&input.query))
+        .context("instant query range is too large")?;
+
+    Ok((time - lookback, time))
+}
+
+type TimeRange = (DateTime<Utc>, DateTime<Utc>);
+
+/// The rendered query a saved-query call runs and the window it reads, by the query's kind.
+fn saved_query_run(
+    config: &Config,
+    input: &query::RunSavedQueryInput,
+    timezone: Tz,
+) -> Result<(String, TimeRange)> {
+    let saved_query = saved::find(config, &input.name)?;
+    let rendered = saved::render(config, saved_query, input.params.as_ref())?;
+    let range = match saved::run_range(saved_query, input.override_range.as_deref())? {
+        Some(range) => resolve_time_range(Some(range), None, timezone, Utc::now())?,
+        None => {
+            let instant = query::QueryInstantInput {
+                query: rendered.query.clone(),
+                time: None,
+                limit: None,
+            };
+            instant_range(&instant, timezone)?
+        }
+    };
+
+    Ok((rendered.query, range))
+}
+
 fn optional_discovery_range(
     start: Option<&str>,
     end: Option<&str>,
@@ -763,6 +1309,11 @@ struct SeriesParams {
     end: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct ListSavedQueriesParams {
+    tags: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct ExplainQueryParams {
     query: String,
@@ -780,13 +1331,151 @@ struct SuggestMetricRuleParams {
 
 #[cfg(test)]
 mod tests {
-    use serde_json::json;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use axum::{
+        Json, Router,
+        body::Body,
+        extract::Query,
+        http::{HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::{get, post},
+    };
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio_tungstenite::{
+        accept_hdr_async,
+        tungstenite::{
+            Message,
+            handshake::server::{Request, Response},
+        },
+    };
 
     use crate::{
-        config::Config,
-        tools::{ToolRouter, cache_key},
+        config::{
+            CacheConfig, CircuitBreakerConfig, Config, DatasourceConfig, GuardrailsConfig,
+            LintConfig, LokiConfig, LokiOAuth2Config, LokiRetryConfig, QuerySplitConfig,
+            SavedQuery, SavedQueryParam, SchemaField,
+        },
+        metrics::MetricsRegistry,
+        response::{ResponseMode, format_log_result},
+        tools::{LintedError, ToolCallContext, ToolRouter, cache_key},
     };
 
+    fn datasource(name: &str, url: &str) -> DatasourceConfig {
+        DatasourceConfig {
+            name: name.to_string(),
+            description: format!("{name} cluster"),
+            loki: LokiConfig {
+                url: url.to_string(),
+                timeout: "2s".to_string(),
+                ..Default::default()
+            },
+            cache: None,
+            guardrails: None,
+        }
+    }
+
+    async fn spawn_loki_stand_in() -> String {
+        spawn_stand_in(
+            Router::new()
+                .route("/ready", get(|| async { "ready" }))
+                .route(
+                    "/loki/api/v1/labels",
+                    get(|| async { Json(json!({"status": "success", "data": ["namespace"]})) }),
+                ),
+        )
+        .await
+    }
+
+    async fn spawn_stand_in(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind stand-in");
+        let address = listener.local_addr().expect("local addr");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        format!("http://{address}")
+    }
+
+    /// Accepts one tail WebSocket, records the request URI and tenant header, sends `messages`,
+    /// then keeps the connection open without sending anything else.
+    #[allow(clippy::result_large_err)] // The handshake callback signature is fixed by tungstenite.
+    async fn spawn_tail_stand_in(messages: Vec<Value>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind stand-in");
+        let address = listener.local_addr().expect("local addr");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let callback = |request: &Request, response: Response| {
+                let tenant = request
+                    .headers()
+                    .get("x-scope-orgid")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("none");
+                let mut recorded = recorded.lock().expect("lock");
+                recorded.push(request.uri().to_string());
+                recorded.push(tenant.to_string());
+                Ok(response)
+            };
+            let mut socket = accept_hdr_async(stream, callback)
+                .await
+                .expect("websocket handshake");
+            for message in messages {
+                socket
+                    .send(Message::text(message.to_string()))
+                    .await
+                    .expect("send tail message");
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        });
+
+        (format!("http://{address}"), seen)
+    }
+
+    /// A `prod` datasource with guardrails disabled, for stand-ins that do not answer stats queries.
+    fn test_datasource(url: &str) -> DatasourceConfig {
+        DatasourceConfig {
+            guardrails: Some(GuardrailsConfig {
+                max_bytes_scanned: "0".to_string(),
+                max_streams: 0,
+                ..Default::default()
+            }),
+            ..datasource("prod", url)
+        }
+    }
+
+    fn tail_message(app: &str, lines: &[&str]) -> Value {
+        let values = lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| json!([format!("17000000000000000{index:02}"), line]))
+            .collect::<Vec<Value>>();
+        json!({
+            "streams": [{"stream": {"app": app}, "values": values}],
+            "dropped_entries": null,
+        })
+    }
+
+    fn unused_local_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let address = listener.local_addr().expect("local addr");
+        drop(listener);
+
+        format!("http://{address}")
+    }
+
     #[tokio::test]
     async fn describe_schema_tool_returns_configured_schema() {
         let router = ToolRouter::new(Config::default()).expect("router should build");
@@ -799,6 +1488,339 @@ mod tests {
         assert!(response.get("structured_metadata").is_some());
     }
 
+    #[tokio::test]
+    async fn saved_queries_interpolate_validated_params() {
+        // Echoes the query Loki received back as the only log line.
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/query_range",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                Json(json!({
+                    "status": "success",
+                    "data": {
+                        "resultType": "streams",
+                        "result": [{"stream": {"app": "api"}, "values": [["10", params["query"]]]}],
+                    },
+                }))
+            }),
+        ))
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            labels: vec![SchemaField {
+                name: "namespace".to_string(),
+                description: "Kubernetes namespace".to_string(),
+                common_values: vec!["checkout".to_string()],
+            }],
+            saved_queries: vec![SavedQuery {
+                name: "slow".to_string(),
+                description: "Slow requests".to_string(),
+                query: r#"{namespace="${namespace}"} |= "${text}" | logfmt | duration > ${min}"#
+                    .to_string(),
+                range: "1h".to_string(),
+                params: vec![
+                    SavedQueryParam {
+                        name: "namespace".to_string(),
+                        description: String::new(),
+                        kind: "string".to_string(),
+                        label: Some("namespace".to_string()),
+                        default: None,
+                    },
+                    SavedQueryParam {
+                        name: "text".to_string(),
+                        description: String::new(),
+                        kind: "string".to_string(),
+                        label: None,
+                        default: Some("timeout".to_string()),
+                    },
+                    SavedQueryParam {
+                        name: "min".to_string(),
+                        description: "Seconds".to_string(),
+                        kind: "number".to_string(),
+                        label: None,
+                        default: Some("2".to_string()),
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let schema = router
+            .call("loki_describe_schema", json!({}))
+            .await
+            .expect("schema");
+        assert_eq!(schema["saved_queries"][0]["params"][2]["type"], "number");
+        assert_eq!(
+            schema["saved_queries"][0]["params"][0]["label"],
+            "namespace"
+        );
+
+        let response = router
+            .call(
+                "loki_run_saved_query",
+                json!({
+                    "name": "slow",
+                    "params": {"namespace": "checkout", "text": "a\" | drop x", "min": 5},
+                    "response_mode": "truncated",
+                }),
+            )
+            .await
+            .expect("saved query should run");
+        let expected = r#"{namespace="checkout"} |= "a\" | drop x" | logfmt | duration > 5"#;
+        assert_eq!(response["query"], expected);
+        assert_eq!(response["params"]["min"], "5");
+        assert_eq!(response["data"]["lines"][0]["line"], expected);
+
+        for (params, message) in [
+            (
+                json!({"namespace": "payments"}),
+                "not a known value of label namespace",
+            ),
+            (
+                json!({"namespace": "checkout", "min": "1 or vector(1)"}),
+                "expected a number",
+            ),
+            (json!({}), "requires param namespace"),
+        ] {
+            let error = router
+                .call(
+                    "loki_run_saved_query",
+                    json!({"name": "slow", "params": params}),
+                )
+                .await
+                .expect_err("params should be rejected");
+            assert!(format!("{error:#}").contains(message), "{error:#}");
+        }
+    }
+
+    #[tokio::test]
+    async fn saved_queries_run_by_kind_and_list_by_tag() {
+        // Echoes the query and step back as series labels.
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/query_range",
+                    get(|Query(params): Query<HashMap<String, String>>| async move {
+                        let metric = json!({"query": params["query"], "step": params["step"]});
+                        Json(json!({
+                            "status": "success",
+                            "data": {
+                                "resultType": "matrix",
+                                "result": [{"metric": metric, "values": [[1767225600, "3"]]}],
+                            },
+                        }))
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/query",
+                    get(|| async {
+                        let sample = |app: &str, value: &str| {
+                            json!({"metric": {"app": app}, "value": [1767225600, value]})
+                        };
+                        Json(json!({
+                            "status": "success",
+                            "data": {
+                                "resultType": "vector",
+                                "result": [sample("api", "4"), sample("web", "17"), sample("db", "9")],
+                            },
+                        }))
+                    }),
+                ),
+        )
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            saved_queries: vec![
+                SavedQuery {
+                    name: "error_rate".to_string(),
+                    description: "Error rate by app".to_string(),
+                    query: r#"sum by (app) (rate({level="error"}[5m]))"#.to_string(),
+                    kind: "metrics".to_string(),
+                    range: "1h".to_string(),
+                    step: Some("1m".to_string()),
+                    tags: vec!["errors".to_string(), "slo".to_string()],
+                    ..Default::default()
+                },
+                SavedQuery {
+                    name: "top_error_apps".to_string(),
+                    description: "Apps with the most errors in the last hour".to_string(),
+                    query: r#"sum by (app) (count_over_time({level="error"}[1h]))"#.to_string(),
+                    kind: "instant".to_string(),
+                    limit: Some(2),
+                    tags: vec!["errors".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let response = router
+            .call("loki_run_saved_query", json!({"name": "error_rate"}))
+            .await
+            .expect("metric saved query should run");
+        assert_eq!(response["kind"], "metrics");
+        assert_eq!(response["step"], "1m");
+        assert_eq!(
+            response["data"]["result"][0]["metric"],
+            json!({"query": r#"sum by (app) (rate({level="error"}[5m]))"#, "step": "1m"})
+        );
+
+        let response = router
+            .call("loki_run_saved_query", json!({"name": "top_error_apps"}))
+            .await
+            .expect("instant saved query should run");
+        assert_eq!(response["kind"], "instant");
+        assert_eq!(response["total_series"], 3);
+        assert_eq!(response["data"]["result"][1]["metric"]["app"], "db");
+        assert_eq!(response["data"]["result"].as_array().map(Vec::len), Some(2));
+
+        let error = router
+            .call(
+                "loki_run_saved_query",
+                json!({"name": "top_error_apps", "override_range": "6h"}),
+            )
+            .await
+            .expect_err("instant saved queries have no range");
+        assert!(error.to_string().contains("does not take override_range"));
+
+        let listed = router
+            .call("loki_list_saved_queries", json!({"tags": ["slo"]}))
+            .await
+            .expect("list");
+        assert_eq!(listed["total"], 1);
+        assert_eq!(listed["saved_queries"][0]["name"], "error_rate");
+        assert_eq!(listed["tags"], json!(["errors", "slo"]));
+        let listed = router
+            .call("loki_list_saved_queries", json!({}))
+            .await
+            .expect("list");
+        assert_eq!(listed["total"], 2);
+    }
+
+    #[tokio::test]
+    async fn explain_query_returns_the_parsed_tree_and_positioned_errors() {
+        let router = ToolRouter::new(Config::default()).expect("router should build");
+        let response = router
+            .call(
+                "loki_explain_query",
+                json!({"query": r#"sum by (level) (count_over_time({app="api"} |= "a | b" | json [5m]))"#}),
+            )
+            .await
+            .expect("tool should execute");
+
+        assert_eq!(response["kind"], "metric");
+        assert_eq!(response["aggregation"], "sum");
+        assert_eq!(response["selector"], r#"{app="api"}"#);
+        assert_eq!(
+            response["pipeline_stages"],
+            json!([r#"|= "a | b""#, "| json"])
+        );
+        assert_eq!(response["ast"]["type"], "vector_aggregation");
+        let range = &response["ast"]["expr"];
+        assert_eq!(range["operation"], "count_over_time");
+        assert_eq!(
+            range["log"]["pipeline"][0]["description"],
+            "Keeps lines containing \"a | b\"."
+        );
+        assert_eq!(
+            response["description"],
+            "Sums the series, grouped by `level`."
+        );
+
+        let error = router
+            .call(
+                "loki_explain_query",
+                json!({"query": "{app=\"api\"} | json |"}),
+            )
+            .await
+            .expect_err("invalid query should fail");
+        let message = error.to_string();
+        assert!(message.contains("at line 1, column 21"), "{message}");
+        assert!(message.ends_with("^"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn lints_queries_on_request_and_as_a_warning_only_pre_check() {
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/query",
+            get(|| async {
+                Json(json!({
+                    "status": "success",
+                    "data": {"resultType": "vector", "result": []},
+                }))
+            }),
+        ))
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            labels: vec![SchemaField {
+                name: "app".to_string(),
+                description: "Application".to_string(),
+                common_values: Vec::new(),
+            }],
+            ..Default::default()
+        };
+        let query = r#"sum(count_over_time({service="api"} | json |= "timeout" [5m]))"#;
+
+        let router = ToolRouter::new(config.clone()).expect("router should build");
+        let response = router
+            .call("loki_lint_query", json!({"query": query}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(response["valid"], true);
+        assert_eq!(response["counts"]["warning"], 2);
+        let findings = response["findings"].as_array().expect("findings");
+        assert_eq!(findings[0]["rule"], "unknown-label");
+        assert_eq!(findings[0]["text"], r#"service="api""#);
+        assert_eq!(findings[0]["column"], 22);
+        assert_eq!(findings[1]["rule"], "parser-before-line-filter");
+
+        let response = router
+            .call("loki_lint_query", json!({"query": "{app=\"api\"} |~"}))
+            .await
+            .expect("syntax errors are findings");
+        assert_eq!(response["valid"], false);
+        assert_eq!(response["findings"][0]["rule"], "syntax-error");
+
+        let instant = json!({"query": query, "time": "2026-01-01T00:00:00Z"});
+        let response = router
+            .call("loki_query_instant", instant.clone())
+            .await
+            .expect("tool should execute");
+        assert!(
+            response.get("lint").is_none(),
+            "pre-check is off by default"
+        );
+
+        let router = ToolRouter::new(Config {
+            lint: LintConfig { pre_check: true },
+            ..config
+        })
+        .expect("router should build");
+        let response = router
+            .call("loki_query_instant", instant)
+            .await
+            .expect("lint findings never fail the call");
+        assert_eq!(response["result_type"], "vector");
+        assert_eq!(response["lint"][1]["rule"], "parser-before-line-filter");
+
+        // The stand-in has no query_range, so the call fails and keeps its findings.
+        let error = router
+            .call(
+                "loki_build_query",
+                json!({"labels": {"service": "api"}, "line_filter": "timeout"}),
+            )
+            .await
+            .expect_err("query_range is not served");
+        let linted = error
+            .downcast_ref::<LintedError>()
+            .expect("findings travel with the error");
+        assert_eq!(linted.lint[0]["rule"], "unknown-label");
+        assert_eq!(error.to_string(), linted.error.to_string());
+    }
+
     #[test]
     fn cache_key_is_stable_for_equivalent_json_objects() {
         let first = json!({
@@ -820,4 +1842,1263 @@ mod tests {
         let second_key = cache_key("loki_query_logs", &second).expect("cache key");
         assert_eq!(first_key, second_key);
     }
+
+    #[tokio::test]
+    async fn routes_tool_calls_to_the_selected_datasource() {
+        let config = Config {
+            default_datasource: Some("staging".to_string()),
+            datasources: vec![
+                datasource("prod", &spawn_loki_stand_in().await),
+                datasource("staging", &unused_local_url()),
+            ],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let response = router
+            .call("loki_list_labels", json!({"datasource": "prod"}))
+            .await
+            .expect("prod should answer");
+        assert_eq!(response["datasource"], "prod");
+        assert_eq!(response["labels"], json!(["namespace"]));
+
+        assert!(router.call("loki_list_labels", json!({})).await.is_err());
+
+        let error = router
+            .call("loki_list_labels", json!({"datasource": "edge"}))
+            .await
+            .expect_err("unknown datasource should fail");
+        assert!(error.to_string().contains("unknown datasource: edge"));
+    }
+
+    #[tokio::test]
+    async fn scopes_loki_requests_and_cache_entries_to_the_tenant() {
+        // Echoes the X-Scope-OrgID header back as the only label name.
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/labels",
+            get(|headers: HeaderMap| async move {
+                let tenant = headers
+                    .get("x-scope-orgid")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("none")
+                    .to_string();
+                Json(json!({"status": "success", "data": [tenant]}))
+            }),
+        ))
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.loki.tenant_id = Some("platform".to_string());
+        let config = Config {
+            datasources: vec![prod],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        for tenant in ["payments", "billing", "payments"] {
+            let response = router
+                .call_with_context(
+                    "loki_list_labels",
+                    json!({}),
+                    ToolCallContext {
+                        tenant_id: Some(tenant.to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect("tool should execute");
+            assert_eq!(response["labels"], json!([tenant]));
+        }
+
+        let response = router
+            .call("loki_list_labels", json!({}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(response["labels"], json!(["platform"]));
+    }
+
+    #[tokio::test]
+    async fn sends_configured_and_forwarded_headers_and_scopes_the_cache_to_them() {
+        // Echoes the gateway headers back as label names.
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/labels",
+            get(|headers: HeaderMap| async move {
+                let labels = ["x-grafana-org-id", "x-routing-key", "x-session"]
+                    .into_iter()
+                    .filter_map(|name| headers.get(name))
+                    .filter_map(|value| value.to_str().ok())
+                    .collect::<Vec<&str>>();
+                Json(json!({"status": "success", "data": labels}))
+            }),
+        ))
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.loki.headers = BTreeMap::from([
+            ("X-Grafana-Org-Id".to_string(), "7".to_string()),
+            ("X-Routing-Key".to_string(), "default".to_string()),
+        ]);
+        prod.loki.forward_headers = vec!["x-routing-key".to_string()];
+        let config = Config {
+            datasources: vec![prod],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        for routing_key in ["eu", "us", "eu"] {
+            let mut inbound_headers = HeaderMap::new();
+            inbound_headers.insert("x-routing-key", routing_key.parse().expect("header"));
+            inbound_headers.insert("x-session", "secret".parse().expect("header"));
+            let response = router
+                .call_with_context(
+                    "loki_list_labels",
+                    json!({}),
+                    ToolCallContext {
+                        inbound_headers: Some(inbound_headers),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect("tool should execute");
+            assert_eq!(response["labels"], json!(["7", routing_key]));
+        }
+
+        let response = router
+            .call("loki_list_labels", json!({}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(response["labels"], json!(["7", "default"]));
+    }
+
+    #[tokio::test]
+    async fn federated_tenants_share_one_query_and_are_guardrailed_together() {
+        let stats_tenants = Arc::new(Mutex::new(Vec::new()));
+        let recorded = stats_tenants.clone();
+        // Like Loki, labels each stream of a multi-tenant query with `__tenant_id__`.
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/query_range",
+                    get(|headers: HeaderMap| async move {
+                        let org_id = headers
+                            .get("x-scope-orgid")
+                            .and_then(|value| value.to_str().ok())
+                            .unwrap_or_default()
+                            .to_string();
+                        let result = org_id
+                            .split('|')
+                            .map(|tenant| {
+                                json!({
+                                    "stream": {"app": "api", "__tenant_id__": tenant},
+                                    "values": [["1", format!("line from {tenant}")]],
+                                })
+                            })
+                            .collect::<Vec<Value>>();
+                        Json(json!({
+                            "status": "success",
+                            "data": {"resultType": "streams", "result": result},
+                        }))
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/index/stats",
+                    get(move |headers: HeaderMap| {
+                        let recorded = recorded.clone();
+                        async move {
+                            let tenant = headers
+                                .get("x-scope-orgid")
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default()
+                                .to_string();
+                            recorded.lock().expect("lock").push(tenant);
+                            Json(json!({"streams": 60, "chunks": 1, "entries": 1, "bytes": 600}))
+                        }
+                    }),
+                ),
+        )
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.guardrails = Some(GuardrailsConfig {
+            max_bytes_scanned: "1000".to_string(),
+            max_streams: 0,
+            skip_stats_if_streams_below: 0,
+            skip_stats_if_range_shorter_than: "10m".to_string(),
+        });
+        let config = Config {
+            datasources: vec![prod],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+        let call = |tool: &'static str, params: Value, tenant: &str| {
+            let context = ToolCallContext {
+                tenant_id: Some(tenant.to_string()),
+                ..Default::default()
+            };
+            let router = router.clone();
+            async move { router.call_with_context(tool, params, context).await }
+        };
+        let logs = json!({"query": "{app=\"api\"}", "start": "1h"});
+
+        let response = call("loki_query_logs", logs.clone(), "payments")
+            .await
+            .expect("one tenant stays under the limit");
+        assert!(response.get("tenants").is_none());
+        assert_eq!(stats_tenants.lock().expect("lock").as_slice(), ["payments"]);
+
+        let error = call("loki_query_logs", logs.clone(), "payments|platform")
+            .await
+            .expect_err("two tenants together exceed the limit");
+        assert!(
+            error.to_string().contains("estimated bytes scanned (1200)"),
+            "{error}"
+        );
+        assert_eq!(
+            stats_tenants.lock().expect("lock").as_slice(),
+            ["payments", "payments", "platform"]
+        );
+
+        let error = call("loki_list_labels", json!({}), "payments|platform")
+            .await
+            .expect_err("label APIs are single-tenant");
+        assert!(
+            error
+                .to_string()
+                .contains("does not support querying several tenants")
+        );
+
+        let query = json!({"query": "{app=\"api\"}", "start": "5m"});
+        let response = call("loki_query_logs", query, "payments|platform")
+            .await
+            .expect("short ranges skip the pre-check");
+        assert_eq!(response["tenants"], json!(["payments", "platform"]));
+        let tenants = response["data"]["result"]["result"]
+            .as_array()
+            .expect("streams")
+            .iter()
+            .map(|stream| stream["stream"]["__tenant_id__"].clone())
+            .collect::<Vec<Value>>();
+        assert_eq!(tenants, vec![json!("payments"), json!("platform")]);
+    }
+
+    #[tokio::test]
+    async fn tail_guardrails_cover_the_replayed_since_window() {
+        let stats_calls = Arc::new(AtomicUsize::new(0));
+        let counter = stats_calls.clone();
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/index/stats",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Json(json!({"streams": 60, "chunks": 1, "entries": 1, "bytes": 5000}))
+            }),
+        ))
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.guardrails = Some(GuardrailsConfig {
+            max_bytes_scanned: "1000".to_string(),
+            max_streams: 0,
+            skip_stats_if_streams_below: 0,
+            skip_stats_if_range_shorter_than: "10m".to_string(),
+        });
+        let router = ToolRouter::new(Config {
+            datasources: vec![prod],
+            ..Default::default()
+        })
+        .expect("router should build");
+
+        let error = router
+            .call(
+                "loki_tail",
+                json!({"labels": {"app": "api"}, "since": "30d"}),
+            )
+            .await
+            .expect_err("30 days of replay exceeds the limit");
+        assert!(
+            error.to_string().contains("estimated bytes scanned (5000)"),
+            "{error}"
+        );
+        assert_eq!(stats_calls.load(Ordering::SeqCst), 1);
+
+        // Without `since` nothing is replayed, so the pre-check is skipped.
+        let error = router
+            .call("loki_tail", json!({"labels": {"app": "api"}}))
+            .await
+            .expect_err("the stand-in has no tail endpoint");
+        assert!(!error.to_string().contains("guardrail"), "{error}");
+        assert_eq!(stats_calls.load(Ordering::SeqCst), 1);
+
+        // Label keys cannot rewrite the selector the guardrail estimates.
+        let error = router
+            .call(
+                "loki_tail",
+                json!({"labels": {"app=\"x\"} |= \"y\" or {a": "b"}, "since": "30d"}),
+            )
+            .await
+            .expect_err("label keys must be identifiers");
+        assert!(error.to_string().contains("invalid label name"), "{error}");
+        assert_eq!(stats_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn tail_streams_batches_until_the_line_limit() {
+        let (url, seen) = spawn_tail_stand_in(vec![
+            tail_message("api", &["first", "second"]),
+            tail_message("api", &["third", "fourth", "fifth"]),
+        ])
+        .await;
+        let mut prod = test_datasource(&url);
+        prod.loki.tenant_id = Some("platform".to_string());
+        let router = ToolRouter::new(Config {
+            datasources: vec![prod],
+            ..Default::default()
+        })
+        .expect("router should build");
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let response = router
+            .call_with_context(
+                "loki_tail",
+                json!({"labels": {"app": "api"}, "lines": 4, "duration": "5s", "response_mode": "raw"}),
+                ToolCallContext {
+                    progress: Some(sender),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("tail should succeed");
+
+        assert_eq!(response["stopped_by"], "lines");
+        assert_eq!(response["lines_received"], 4);
+        assert_eq!(response["data"]["total_lines"], 4);
+
+        let first = receiver.recv().await.expect("first batch");
+        assert_eq!((first.progress, first.total), (2, Some(4)));
+        assert_eq!(first.message, "first\nsecond");
+        let second = receiver.recv().await.expect("second batch");
+        assert_eq!(second.progress, 4);
+        assert_eq!(second.message, "third\nfourth");
+        assert!(receiver.recv().await.is_none());
+
+        let seen = seen.lock().expect("lock").clone();
+        assert!(seen[0].starts_with("/loki/api/v1/tail?query=%7Bapp%3D%22api%22%7D"));
+        assert_eq!(seen[1], "platform");
+    }
+
+    #[tokio::test]
+    async fn tail_stops_after_the_duration_and_formats_the_final_batch() {
+        let (url, _) = spawn_tail_stand_in(vec![tail_message("api", &["only"])]).await;
+        let router = ToolRouter::new(Config {
+            datasources: vec![test_datasource(&url)],
+            ..Default::default()
+        })
+        .expect("router should build");
+
+        let response = router
+            .call(
+                "loki_tail",
+                json!({"labels": {"app": "api"}, "duration": "1s", "response_mode": "summary"}),
+            )
+            .await
+            .expect("tail should succeed");
+
+        assert_eq!(response["stopped_by"], "duration");
+        assert_eq!(response["lines_received"], 1);
+        assert_eq!(response["response_mode"], "summary");
+
+        let error = router
+            .call(
+                "loki_tail",
+                json!({"labels": {"app": "api"}, "duration": "10m"}),
+            )
+            .await
+            .expect_err("long tails should be rejected");
+        assert!(error.to_string().contains("at most 5m"));
+    }
+
+    #[tokio::test]
+    async fn lists_datasources_with_health() {
+        let config = Config {
+            datasources: vec![
+                datasource("prod", &spawn_loki_stand_in().await),
+                datasource("edge", &unused_local_url()),
+            ],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let response = router
+            .call("loki_list_datasources", json!({}))
+            .await
+            .expect("tool should execute");
+
+        assert_eq!(response["default_datasource"], "prod");
+        assert_eq!(response["datasources"][0]["name"], "prod");
+        assert_eq!(response["datasources"][0]["default"], true);
+        assert_eq!(response["datasources"][0]["healthy"], true);
+        assert_eq!(response["datasources"][1]["name"], "edge");
+        assert_eq!(response["datasources"][1]["healthy"], false);
+    }
+
+    #[tokio::test]
+    async fn splits_long_log_queries_and_merges_in_direction_order() {
+        // Answers each sub-query with one line stamped at its start, and records the starts.
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = starts.clone();
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/query_range",
+            get(move |Query(params): Query<HashMap<String, String>>| {
+                let recorded = recorded.clone();
+                async move {
+                    let start = params["start"].clone();
+                    recorded.lock().expect("lock").push(start.clone());
+                    Json(json!({
+                        "status": "success",
+                        "data": {
+                            "resultType": "streams",
+                            "result": [{
+                                "stream": {"app": "api"},
+                                "values": [[start.clone(), format!("line at {start}")]],
+                            }],
+                        },
+                    }))
+                }
+            }),
+        ))
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            query_split: QuerySplitConfig {
+                interval: "24h".to_string(),
+                max_concurrency: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+        let params = |direction: &str, limit: u32| {
+            json!({
+                "query": "{app=\"api\"}",
+                "start": "2026-01-01T00:00:00Z",
+                "end": "2026-01-04T00:00:00Z",
+                "limit": limit,
+                "direction": direction,
+                "response_mode": "raw",
+            })
+        };
+        let lines = |response: &Value| {
+            response["data"]["result"]["result"]
+                .as_array()
+                .expect("streams")
+                .iter()
+                .flat_map(|stream| stream["values"].as_array().cloned().unwrap_or_default())
+                .map(|value| value[0].as_str().unwrap_or_default().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        let backward = router
+            .call("loki_query_logs", params("backward", 2))
+            .await
+            .expect("tool should execute");
+        assert_eq!(backward["sub_queries"], 2);
+        assert_eq!(
+            lines(&backward),
+            vec!["1767398400000000000", "1767312000000000000"]
+        );
+
+        starts.lock().expect("lock").clear();
+        let forward = router
+            .call("loki_query_logs", params("forward", 10))
+            .await
+            .expect("tool should execute");
+        assert_eq!(forward["sub_queries"], 3);
+        assert_eq!(
+            lines(&forward),
+            vec![
+                "1767225600000000000",
+                "1767312000000000000",
+                "1767398400000000000"
+            ]
+        );
+        assert_eq!(starts.lock().expect("lock").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_through_log_results_with_cursors() {
+        // Serves a fixed log honouring start (inclusive), end (exclusive), limit and direction.
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/query_range",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                let entries = [
+                    (10, "api", "a"),
+                    (20, "api", "b"),
+                    (20, "web", "c"),
+                    (20, "web", "d"),
+                    (30, "api", "e"),
+                ];
+                let start = params["start"].parse::<i64>().expect("start");
+                let end = params["end"].parse::<i64>().expect("end");
+                let limit = params["limit"].parse::<usize>().expect("limit");
+                let mut matching = entries
+                    .iter()
+                    .filter(|(timestamp, _, _)| (start..end).contains(timestamp))
+                    .collect::<Vec<_>>();
+                if params["direction"] == "backward" {
+                    matching.reverse();
+                }
+                let result = matching
+                    .into_iter()
+                    .take(limit)
+                    .map(|(timestamp, app, line)| {
+                        json!({"stream": {"app": app}, "values": [[timestamp.to_string(), line]]})
+                    })
+                    .collect::<Vec<Value>>();
+                Json(json!({
+                    "status": "success",
+                    "data": {"resultType": "streams", "result": result},
+                }))
+            }),
+        ))
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        for (direction, expected) in [
+            ("backward", vec!["e", "d", "c", "b", "a"]),
+            ("forward", vec!["a", "b", "c", "d", "e"]),
+        ] {
+            let mut cursor = Value::Null;
+            let mut lines = Vec::new();
+            for _ in 0..10 {
+                let response = router
+                    .call(
+                        "loki_query_logs",
+                        json!({
+                            "query": "{app=~\".+\"}",
+                            "start": "1970-01-01T00:00:00Z",
+                            "end": "1970-01-01T00:00:01Z",
+                            "limit": 2,
+                            "direction": direction,
+                            "cursor": cursor,
+                            "response_mode": "raw",
+                        }),
+                    )
+                    .await
+                    .expect("tool should execute");
+                let mut page = response["data"]["result"]["result"]
+                    .as_array()
+                    .expect("streams")
+                    .iter()
+                    .flat_map(|stream| stream["values"].as_array().cloned().unwrap_or_default())
+                    .map(|value| {
+                        (
+                            value[0].as_str().unwrap_or_default().to_string(),
+                            value[1].as_str().unwrap_or_default().to_string(),
+                        )
+                    })
+                    .collect::<Vec<(String, String)>>();
+                page.sort();
+                if direction == "backward" {
+                    page.reverse();
+                }
+                lines.extend(page.into_iter().map(|(_, line)| line));
+                cursor = response["next_cursor"].clone();
+                if cursor.is_null() {
+                    break;
+                }
+            }
+
+            assert_eq!(lines, expected, "{direction} pages");
+        }
+    }
+
+    #[tokio::test]
+    async fn summary_pages_stream_and_oversized_responses_are_cut_off() {
+        // Serves pairs of entries sharing a timestamp, honouring start, end, limit and direction.
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/query_range",
+                    get(|Query(params): Query<HashMap<String, String>>| async move {
+                        let start = params["start"].parse::<i64>().expect("start");
+                        let end = params["end"].parse::<i64>().expect("end");
+                        let limit = params["limit"].parse::<usize>().expect("limit");
+                        let mut matching = (0..40_i64)
+                            .map(|index| ((index / 2) * 40_000_000, index))
+                            .filter(|(timestamp, _)| (start..end).contains(timestamp))
+                            .collect::<Vec<_>>();
+                        if params["direction"] == "backward" {
+                            matching.reverse();
+                        }
+                        let result = matching
+                            .into_iter()
+                            .take(limit)
+                            .map(|(timestamp, index)| {
+                                let app = if index % 2 == 0 { "api" } else { "web" };
+                                let level = ["error", "info", "warn"][index as usize % 3];
+                                let line = format!("{level} request {index} took {}ms", index * 7);
+                                json!({"stream": {"app": app}, "values": [[timestamp.to_string(), line]]})
+                            })
+                            .collect::<Vec<Value>>();
+                        Json(json!({
+                            "status": "success",
+                            "data": {"resultType": "streams", "result": result},
+                        }))
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/series",
+                    get(|| async {
+                        // The connection drops halfway through the body.
+                        let head = futures::stream::once(async {
+                            Ok::<_, std::io::Error>(r#"{"status":"success","data":[{"app":"#)
+                        });
+                        let reset = futures::stream::once(async {
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            Err(std::io::Error::other("connection reset"))
+                        });
+                        Body::from_stream(head.chain(reset))
+                    }),
+                ),
+        )
+        .await;
+        let mut small = test_datasource(&url);
+        small.name = "small".to_string();
+        small.loki.max_response_bytes = "1KB".to_string();
+        let config = Config {
+            datasources: vec![test_datasource(&url), small],
+            query_split: QuerySplitConfig {
+                interval: "250ms".to_string(),
+                ..Default::default()
+            },
+            cache: CacheConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+        let params = |response_mode: &str, datasource: &str| {
+            json!({
+                "query": "{app=~\".+\"}",
+                "start": "1970-01-01T00:00:00Z",
+                "end": "1970-01-01T00:00:01Z",
+                "limit": 25,
+                "response_mode": response_mode,
+                "datasource": datasource,
+            })
+        };
+
+        let raw = router
+            .call("loki_query_logs", params("raw", "prod"))
+            .await
+            .expect("raw page");
+        let summary = router
+            .call("loki_query_logs", params("summary", "prod"))
+            .await
+            .expect("summary page");
+        // The streamed summary matches one built from the buffered page, down to the cursor.
+        let (_, expected) = format_log_result(ResponseMode::Summary, raw["data"]["result"].clone());
+        assert_eq!(summary["data"], expected);
+        assert_eq!(summary["data"]["total_lines"], 25);
+        assert_eq!(summary["sub_queries"], raw["sub_queries"]);
+        assert!(summary["next_cursor"].is_string());
+        assert_eq!(summary["next_cursor"], raw["next_cursor"]);
+
+        // Cursor pages stream too, skipping the boundary entry the first page already returned.
+        let next_page = |response_mode: &str, cursor: &Value| {
+            let mut params = params(response_mode, "prod");
+            params["cursor"] = cursor.clone();
+            params
+        };
+        let raw = router
+            .call("loki_query_logs", next_page("raw", &raw["next_cursor"]))
+            .await
+            .expect("raw cursor page");
+        let summary = router
+            .call(
+                "loki_query_logs",
+                next_page("summary", &summary["next_cursor"]),
+            )
+            .await
+            .expect("summary cursor page");
+        let (_, expected) = format_log_result(ResponseMode::Summary, raw["data"]["result"].clone());
+        assert_eq!(summary["data"], expected);
+        assert_eq!(summary["data"]["total_lines"], 15);
+        assert!(summary["next_cursor"].is_null());
+        assert!(raw["next_cursor"].is_null());
+
+        for response_mode in ["raw", "summary"] {
+            let error = router
+                .call("loki_query_logs", params(response_mode, "small"))
+                .await
+                .expect_err("response over max_response_bytes");
+            assert!(
+                format!("{error:#}").contains("larger than max_response_bytes (1000 bytes)"),
+                "{error:#}"
+            );
+        }
+
+        let error = router
+            .call("loki_series", json!({"match": ["{app=\"api\"}"]}))
+            .await
+            .expect_err("truncated body");
+        assert!(
+            format!("{error:#}").contains("Loki response was cut off after"),
+            "{error:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures_and_opens_the_circuit_on_persistent_ones() {
+        let label_calls = Arc::new(AtomicUsize::new(0));
+        let series_calls = Arc::new(AtomicUsize::new(0));
+        let (labels_counter, series_counter) = (label_calls.clone(), series_calls.clone());
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/labels",
+                    get(move || async move {
+                        // Mid-rollout: two 503s asking for an immediate retry, then success.
+                        if labels_counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                            return (StatusCode::SERVICE_UNAVAILABLE, [("retry-after", "0")], "")
+                                .into_response();
+                        }
+                        Json(json!({"status": "success", "data": ["app"]})).into_response()
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/series",
+                    get(move || async move {
+                        series_counter.fetch_add(1, Ordering::SeqCst);
+                        StatusCode::BAD_GATEWAY
+                    }),
+                ),
+        )
+        .await;
+        let mut prod = test_datasource(&url);
+        prod.loki.retry = LokiRetryConfig {
+            max_retries: 2,
+            initial_backoff: "10ms".to_string(),
+            max_backoff: "50ms".to_string(),
+        };
+        prod.loki.circuit_breaker = CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 2,
+            open_duration: "1m".to_string(),
+        };
+        let config = Config {
+            datasources: vec![prod],
+            ..Default::default()
+        };
+        let metrics = MetricsRegistry::new("loki_mcp").expect("metrics");
+        let router = ToolRouter::new_with_metrics(config, Some(metrics.clone()))
+            .expect("router should build");
+
+        let labels = router
+            .call("loki_list_labels", json!({}))
+            .await
+            .expect("labels should succeed after retries");
+        assert_eq!(labels["labels"], json!(["app"]));
+        assert_eq!(label_calls.load(Ordering::SeqCst), 3);
+
+        let series = json!({"match": ["{app=\"api\"}"]});
+        for _ in 0..2 {
+            let error = router
+                .call("loki_series", series.clone())
+                .await
+                .expect_err("series should fail");
+            assert!(format!("{error:#}").contains("502"), "{error:#}");
+        }
+        // Each failed call made three attempts; the breaker now rejects without calling Loki.
+        assert_eq!(series_calls.load(Ordering::SeqCst), 6);
+        let error = router
+            .call("loki_series", series)
+            .await
+            .expect_err("circuit should be open");
+        assert!(
+            format!("{error:#}").contains("circuit breaker open"),
+            "{error:#}"
+        );
+        assert_eq!(series_calls.load(Ordering::SeqCst), 6);
+
+        let open = router.open_circuits();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].0, "prod");
+        assert_eq!(open[0].1.endpoint, "/loki/api/v1/series");
+        let rendered = metrics.render().expect("render");
+        assert!(rendered.contains(
+            "loki_mcp_loki_circuit_breaker_state{datasource=\"prod\",endpoint=\"/loki/api/v1/series\"} 2"
+        ));
+        assert!(rendered.contains(
+            "loki_mcp_loki_request_retries_total{datasource=\"prod\",endpoint=\"/loki/api/v1/labels\"} 2"
+        ));
+    }
+
+    #[tokio::test]
+    async fn instant_queries_sort_vectors_by_value() {
+        let times = Arc::new(Mutex::new(Vec::new()));
+        let recorded = times.clone();
+        let url = spawn_stand_in(Router::new().route(
+            "/loki/api/v1/query",
+            get(move |Query(params): Query<HashMap<String, String>>| {
+                let recorded = recorded.clone();
+                async move {
+                    recorded
+                        .lock()
+                        .expect("lock")
+                        .push(params.get("time").cloned().unwrap_or_default());
+                    let sample = |service: &str, value: &str| {
+                        json!({"metric": {"service": service}, "value": [1767225600, value]})
+                    };
+                    Json(json!({
+                        "status": "success",
+                        "data": {
+                            "resultType": "vector",
+                            "result": [sample("api", "4"), sample("web", "17"), sample("db", "9.5")],
+                        },
+                    }))
+                }
+            }),
+        ))
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let response = router
+            .call(
+                "loki_query_instant",
+                json!({
+                    "query": "sum by (service) (count_over_time({level=\"error\"}[1h]))",
+                    "time": "2026-01-01T00:00:00Z",
+                    "limit": 2,
+                }),
+            )
+            .await
+            .expect("tool should execute");
+
+        assert_eq!(response["result_type"], "vector");
+        assert_eq!(response["total_series"], 3);
+        let services = response["data"]["result"]
+            .as_array()
+            .expect("vector")
+            .iter()
+            .map(|sample| sample["metric"]["service"].clone())
+            .collect::<Vec<Value>>();
+        assert_eq!(services, vec![json!("web"), json!("db")]);
+        assert_eq!(*times.lock().expect("lock"), vec!["1767225600000000000"]);
+    }
+
+    #[tokio::test]
+    async fn volume_ranks_streams_and_guardrails_suggest_it() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/index/volume",
+                    get(move |Query(params): Query<HashMap<String, String>>| {
+                        let recorded = recorded.clone();
+                        async move {
+                            recorded.lock().expect("lock").push(params);
+                            let entry = |app: &str, bytes: &str| {
+                                json!({"metric": {"app": app}, "value": [1767225600, bytes]})
+                            };
+                            Json(json!({
+                                "status": "success",
+                                "data": {
+                                    "resultType": "vector",
+                                    "result": [entry("api", "100"), entry("web", "300")],
+                                },
+                            }))
+                        }
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/index/stats",
+                    get(|| async {
+                        Json(json!({"streams": 10, "chunks": 1, "entries": 1, "bytes": 9_000_000_000_u64}))
+                    }),
+                ),
+        )
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.guardrails = Some(GuardrailsConfig {
+            skip_stats_if_streams_below: 0,
+            skip_stats_if_range_shorter_than: "0s".to_string(),
+            ..Default::default()
+        });
+        let config = Config {
+            datasources: vec![prod],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let response = router
+            .call(
+                "loki_volume",
+                json!({"target_labels": ["app"], "aggregate_by": "labels", "start": "1h"}),
+            )
+            .await
+            .expect("tool should execute");
+        assert_eq!(response["query"], "{app=~\".+\"}");
+        assert_eq!(response["total_bytes"], 400);
+        assert_eq!(response["streams"][0]["labels"]["app"], "web");
+        assert_eq!(response["streams"][0]["share"], 0.75);
+        assert_eq!(response["streams"][1]["labels"]["app"], "api");
+        {
+            let seen = seen.lock().expect("lock");
+            assert_eq!(seen[0]["targetLabels"], "app");
+            assert_eq!(seen[0]["aggregateBy"], "labels");
+        }
+
+        let error = router
+            .call(
+                "loki_query_logs",
+                json!({"query": "{app=\"api\", msg=~\"{x}\"} |= \"error\"", "start": "1h"}),
+            )
+            .await
+            .expect_err("guardrail should reject");
+        assert!(
+            error
+                .to_string()
+                .contains("run loki_volume with query {app=\"api\", msg=~\"{x}\"}"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn detected_tools_use_loki_and_fall_back_to_sampling() {
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/detected_labels",
+                    get(|| async {
+                        Json(json!({"detectedLabels": [{"label": "app", "cardinality": 4}]}))
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/query_range",
+                    get(|| async {
+                        Json(json!({
+                            "status": "success",
+                            "data": {
+                                "resultType": "streams",
+                                "result": [{
+                                    "stream": {"app": "api"},
+                                    "values": [
+                                        ["2", r#"{"status":500,"user":{"id":"a"}}"#],
+                                        ["1", r#"{"status":200,"user":{"id":"b"}}"#],
+                                    ],
+                                }],
+                            },
+                        }))
+                    }),
+                ),
+        )
+        .await;
+        let config = Config {
+            datasources: vec![test_datasource(&url)],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let labels = router
+            .call(
+                "loki_detected_labels",
+                json!({"query": "{app=~\".+\"}", "start": "1h"}),
+            )
+            .await
+            .expect("tool should execute");
+        assert_eq!(labels["source"], "loki");
+        assert_eq!(labels["labels"], json!([{"name": "app", "cardinality": 4}]));
+
+        // The stand-in has no detected_fields route, so the tool samples lines instead.
+        let fields = router
+            .call(
+                "loki_detected_fields",
+                json!({"query": "{app=\"api\"}", "start": "1h"}),
+            )
+            .await
+            .expect("tool should execute");
+        assert_eq!(fields["source"], "sampled");
+        assert_eq!(fields["sampled_lines"], 2);
+        assert!(fields["fallback_reason"].is_string());
+        assert_eq!(
+            fields["fields"],
+            json!([
+                {"name": "status", "type": "int", "cardinality": 2, "parsers": ["json"]},
+                {"name": "user_id", "type": "string", "cardinality": 2, "parsers": ["json"]},
+            ])
+        );
+
+        // A Loki that has the API but rejects the query is not asked again through sampling.
+        let sampled = Arc::new(AtomicUsize::new(0));
+        let counter = sampled.clone();
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/loki/api/v1/detected_fields",
+                    get(|| async { (StatusCode::BAD_REQUEST, "parse error") }),
+                )
+                .route(
+                    "/loki/api/v1/detected_labels",
+                    get(|| async { (StatusCode::TOO_MANY_REQUESTS, "slow down") }),
+                )
+                .route(
+                    "/loki/api/v1/query_range",
+                    get(move || async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    }),
+                ),
+        )
+        .await;
+        let mut datasource = test_datasource(&url);
+        datasource.loki.retry = LokiRetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let router = ToolRouter::new(Config {
+            datasources: vec![datasource],
+            ..Default::default()
+        })
+        .expect("router should build");
+        for tool in ["loki_detected_fields", "loki_detected_labels"] {
+            let error = router
+                .call(tool, json!({"query": "{app=\"api\"}", "start": "1h"}))
+                .await
+                .expect_err("rejections are returned");
+            assert!(
+                format!("{error:#}").contains("non-success status"),
+                "{error:#}"
+            );
+        }
+        assert_eq!(sampled.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn rule_tools_list_rules_alerts_and_the_query_behind_an_alert() {
+        let rule_types = Arc::new(Mutex::new(Vec::new()));
+        let recorded = rule_types.clone();
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/prometheus/api/v1/rules",
+                    get(move |Query(params): Query<HashMap<String, String>>| {
+                        let recorded = recorded.clone();
+                        async move {
+                            recorded
+                                .lock()
+                                .expect("lock")
+                                .push(params.get("type").cloned().unwrap_or_default());
+                            Json(json!({
+                                "status": "success",
+                                "data": {"groups": [{
+                                    "name": "api",
+                                    "file": "team-a",
+                                    "interval": 60,
+                                    "rules": [
+                                        {
+                                            "type": "alerting",
+                                            "name": "ApiErrors",
+                                            "query": "sum(rate({app=\"api\"} |= \"error\" [5m])) > 1",
+                                            "duration": 300,
+                                            "state": "firing",
+                                            "health": "ok",
+                                            "lastError": "",
+                                            "labels": {"severity": "page"},
+                                            "alerts": [{
+                                                "state": "firing",
+                                                "activeAt": "2026-01-01T00:10:00Z",
+                                                "value": "4e+00",
+                                                "labels": {"alertname": "ApiErrors"},
+                                            }],
+                                        },
+                                        {
+                                            "type": "recording",
+                                            "name": "app:lines:rate5m",
+                                            "query": "sum by (app) (rate({app=~\".+\"}[5m]))",
+                                            "health": "ok",
+                                        },
+                                    ],
+                                }]},
+                            }))
+                        }
+                    }),
+                )
+                .route(
+                    "/prometheus/api/v1/alerts",
+                    get(|| async {
+                        let alert = |name: &str, state: &str, active_at: &str| {
+                            json!({
+                                "labels": {"alertname": name},
+                                "state": state,
+                                "activeAt": active_at,
+                                "value": "1",
+                            })
+                        };
+                        Json(json!({
+                            "status": "success",
+                            "data": {"alerts": [
+                                alert("SlowQueries", "pending", "2026-01-01T00:00:00Z"),
+                                alert("ApiErrors", "firing", "2026-01-01T00:10:00Z"),
+                            ]},
+                        }))
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/rules/team-a",
+                    get(|| async { "team-a:\n  - name: api\n    rules: []\n" }),
+                ),
+        )
+        .await;
+        let config = Config {
+            datasources: vec![datasource("prod", &url)],
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+
+        let rules = router
+            .call("loki_list_rules", json!({"namespace": "team-a"}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(rules["total_groups"], 1);
+        assert_eq!(rules["total_rules"], 2);
+        assert_eq!(rules["groups"][0]["rules"][0]["active_alerts"], 1);
+        assert!(rules["groups"][0]["rules"][0].get("last_error").is_none());
+        assert!(rules["groups"][0]["rules"][1].get("state").is_none());
+
+        let yaml = router
+            .call(
+                "loki_list_rules",
+                json!({"namespace": "team-a", "format": "yaml"}),
+            )
+            .await
+            .expect("tool should execute");
+        assert!(
+            yaml["rules_yaml"]
+                .as_str()
+                .is_some_and(|text| text.starts_with("team-a:"))
+        );
+
+        let alerts = router
+            .call("loki_list_alerts", json!({}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(alerts["firing"], 1);
+        assert_eq!(alerts["pending"], 1);
+        assert_eq!(alerts["alerts"][0]["alert"], "ApiErrors");
+        assert_eq!(
+            alerts["alerts"][0]["query"],
+            "sum(rate({app=\"api\"} |= \"error\" [5m])) > 1"
+        );
+        assert!(alerts["alerts"][1]["query"].is_null());
+
+        let query = router
+            .call("loki_get_alert_query", json!({"alert": "ApiErrors"}))
+            .await
+            .expect("tool should execute");
+        assert_eq!(query["rules"][0]["group"], "api");
+        assert_eq!(
+            query["rules"][0]["query_logs"],
+            json!({
+                "tool": "loki_query_logs",
+                "params": {"query": "{app=\"api\"} |= \"error\"", "start": "2026-01-01T00:05:00Z"},
+            })
+        );
+
+        let error = router
+            .call("loki_get_alert_query", json!({"alert": "Missing"}))
+            .await
+            .expect_err("unknown alert should fail");
+        assert!(
+            error.to_string().contains("known alerts: ApiErrors"),
+            "{error}"
+        );
+        assert_eq!(
+            *rule_types.lock().expect("lock"),
+            vec!["", "alert", "alert", "alert"]
+        );
+    }
+
+    #[tokio::test]
+    async fn oauth2_tokens_are_renewed_when_loki_rejects_them() {
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let issued = token_requests.clone();
+        let accepted = Arc::new(Mutex::new("Bearer token-1".to_string()));
+        let loki_accepts = accepted.clone();
+        let rejections = Arc::new(AtomicUsize::new(0));
+        let rejected = rejections.clone();
+        let url = spawn_stand_in(
+            Router::new()
+                .route(
+                    "/oauth/token",
+                    post(move || {
+                        let issued = issued.clone();
+                        async move {
+                            let count = issued.fetch_add(1, Ordering::SeqCst) + 1;
+                            Json(json!({
+                                "access_token": format!("token-{count}"),
+                                "token_type": "Bearer",
+                                "expires_in": 3600,
+                            }))
+                        }
+                    }),
+                )
+                .route(
+                    "/loki/api/v1/labels",
+                    get(move |headers: HeaderMap| {
+                        let accepted = loki_accepts.clone();
+                        let rejected = rejected.clone();
+                        async move {
+                            let expected = accepted.lock().expect("lock").clone();
+                            let authorization = headers
+                                .get("authorization")
+                                .and_then(|value| value.to_str().ok());
+                            if authorization != Some(expected.as_str()) {
+                                rejected.fetch_add(1, Ordering::SeqCst);
+                                return StatusCode::UNAUTHORIZED.into_response();
+                            }
+                            Json(json!({"status": "success", "data": ["app"]})).into_response()
+                        }
+                    }),
+                ),
+        )
+        .await;
+        let mut prod = datasource("prod", &url);
+        prod.loki.auth_type = "oauth2".to_string();
+        prod.loki.oauth2 = Some(LokiOAuth2Config {
+            token_url: format!("{url}/oauth/token"),
+            client_id: "loki-mcp".to_string(),
+            client_secret: "s3cret".to_string(),
+            scopes: Vec::new(),
+        });
+        let config = Config {
+            datasources: vec![prod],
+            cache: CacheConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let router = ToolRouter::new(config).expect("router should build");
+        let list_labels = || async {
+            router
+                .call("loki_list_labels", json!({}))
+                .await
+                .expect("tool should execute")
+        };
+
+        list_labels().await;
+        assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+
+        // The gateway revokes the token early: Loki rejects it once and a new one is fetched.
+        *accepted.lock().expect("lock") = "Bearer token-2".to_string();
+        assert_eq!(list_labels().await["labels"], json!(["app"]));
+        assert_eq!(rejections.load(Ordering::SeqCst), 1);
+        assert_eq!(token_requests.load(Ordering::SeqCst), 2);
+    }
 }
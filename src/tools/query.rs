@@ -1,20 +1,38 @@
 #![allow(dead_code)]
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration as StdDuration};
 
-use anyhow::{Result, bail};
-use chrono::Utc;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
 use serde::Deserialize;
-use serde_json::{Value, json};
+use serde_json::{Map, Value, json};
 
 use crate::{
     config::Config,
-    loki::client::LokiClient,
-    response::{ResponseMode, format_log_result},
-    time::resolve_time_range,
+    logql,
+    loki::{body::LogEntrySink, client::LokiClient},
+    response::{LogLineEntry, LogSummary, ResponseMode, format_log_result},
+    time::{parse_std_duration, parse_time_reference, resolve_time_range},
+    tools::{
+        ProgressSender, ToolProgress,
+        builder::{
+            LabelFilterInput, LineFilterInput, MatcherInput, build_query_string,
+            selector_from_labels,
+        },
+        cursor::{LogCursor, PageBoundary, paginate},
+        saved,
+        split::QuerySplit,
+    },
 };
 
+const DEFAULT_TAIL_LINES: u32 = 50;
+const MAX_TAIL_LINES: u32 = 1000;
+const DEFAULT_TAIL_DURATION: &str = "10s";
+const MAX_TAIL_DURATION: StdDuration = StdDuration::from_secs(300);
+/// Loki's lookback for instant queries without a range selector.
+const DEFAULT_INSTANT_LOOKBACK: StdDuration = StdDuration::from_secs(300);
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct QueryLogsInput {
     pub query: String,
@@ -22,6 +40,7 @@ pub struct QueryLogsInput {
     pub end: Option<String>,
     pub limit: Option<u32>,
     pub direction: Option<String>,
+    pub cursor: Option<String>,
     pub response_mode: Option<ResponseMode>,
 }
 
@@ -33,6 +52,13 @@ pub struct QueryMetricsInput {
     pub step: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryInstantInput {
+    pub query: String,
+    pub time: Option<String>,
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct BuildQueryInput {
     pub labels: Option<BTreeMap<String, String>>,
@@ -41,11 +67,25 @@ pub struct BuildQueryInput {
     pub line_filter_regex: Option<String>,
     pub exclude: Option<String>,
     pub json_fields: Option<BTreeMap<String, String>>,
+    pub matchers: Option<Vec<MatcherInput>>,
+    pub line_filters: Option<Vec<LineFilterInput>>,
+    pub parser: Option<String>,
+    pub parser_expression: Option<String>,
+    pub label_filters: Option<Vec<LabelFilterInput>>,
+    pub label_format: Option<BTreeMap<String, String>>,
+    pub line_format: Option<String>,
+    pub unwrap: Option<String>,
+    pub unwrap_conversion: Option<String>,
     pub aggregation: Option<String>,
     pub aggregation_range: Option<String>,
+    pub quantile: Option<f64>,
+    pub vector_aggregation: Option<String>,
+    pub group_by: Option<Vec<String>>,
+    pub topk: Option<u32>,
     pub start: Option<String>,
     pub end: Option<String>,
     pub limit: Option<u32>,
+    pub cursor: Option<String>,
     pub response_mode: Option<ResponseMode>,
 }
 
@@ -53,17 +93,36 @@ pub struct BuildQueryInput {
 pub struct TailInput {
     pub labels: BTreeMap<String, String>,
     pub lines: Option<u32>,
+    pub duration: Option<String>,
+    pub since: Option<String>,
     pub response_mode: Option<ResponseMode>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RunSavedQueryInput {
     pub name: String,
+    pub params: Option<Map<String, Value>>,
     pub override_range: Option<String>,
+    pub cursor: Option<String>,
     pub response_mode: Option<ResponseMode>,
 }
 
-pub async fn query_logs(client: &LokiClient, timezone: Tz, input: QueryLogsInput) -> Result<Value> {
+/// One page of a log query as returned to callers.
+struct PagedLogs {
+    response_mode: ResponseMode,
+    data: Value,
+    sub_queries: usize,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    next_cursor: Option<String>,
+}
+
+pub async fn query_logs(
+    client: &LokiClient,
+    split: &QuerySplit,
+    timezone: Tz,
+    input: QueryLogsInput,
+) -> Result<Value> {
     let (start, end) = resolve_time_range(
         input.start.as_deref(),
         input.end.as_deref(),
@@ -72,29 +131,33 @@ pub async fn query_logs(client: &LokiClient, timezone: Tz, input: QueryLogsInput
     )?;
 
     let requested_response_mode = input.response_mode.unwrap_or_default();
-    let data = client
-        .query_logs(
-            &input.query,
-            Some(start),
-            Some(end),
-            Some(input.limit.unwrap_or(100)),
-            input.direction.as_deref(),
-        )
-        .await?;
-    let (response_mode, formatted_data) = format_log_result(requested_response_mode, data);
+    let page = query_log_page(
+        client,
+        split,
+        &input.query,
+        (start, end),
+        input.limit.unwrap_or(100),
+        input.direction.as_deref(),
+        input.cursor.as_deref(),
+        requested_response_mode,
+    )
+    .await?;
 
     Ok(json!({
         "query": input.query,
-        "start": start,
-        "end": end,
+        "start": page.start,
+        "end": page.end,
+        "sub_queries": page.sub_queries,
+        "next_cursor": page.next_cursor,
         "response_mode_requested": requested_response_mode,
-        "response_mode": response_mode,
-        "data": formatted_data,
+        "response_mode": page.response_mode,
+        "data": page.data,
     }))
 }
 
 pub async fn query_metrics(
     client: &LokiClient,
+    split: &QuerySplit,
     timezone: Tz,
     input: QueryMetricsInput,
 ) -> Result<Value> {
@@ -105,8 +168,8 @@ pub async fn query_metrics(
         Utc::now(),
     )?;
 
-    let data = client
-        .query_metrics(&input.query, Some(start), Some(end), input.step.as_deref())
+    let result = split
+        .query_metrics(client, &input.query, start, end, input.step.as_deref())
         .await?;
 
     Ok(json!({
@@ -114,16 +177,92 @@ pub async fn query_metrics(
         "start": start,
         "end": end,
         "step": input.step,
+        "sub_queries": result.sub_queries,
+        "data": result.data,
+    }))
+}
+
+/// Evaluates a metric query at one instant and returns vector samples sorted by value,
+/// highest first, keeping the top `limit` series when set.
+pub async fn query_instant(
+    client: &LokiClient,
+    timezone: Tz,
+    input: QueryInstantInput,
+) -> Result<Value> {
+    let time = instant_time(input.time.as_deref(), timezone)?;
+    let mut data = client.query_instant(&input.query, Some(time)).await?;
+
+    let result_type = data
+        .get("resultType")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let mut total_series = None;
+    if result_type == "vector"
+        && let Some(samples) = data.get_mut("result").and_then(Value::as_array_mut)
+    {
+        samples.sort_by(|left, right| sample_value(right).total_cmp(&sample_value(left)));
+        total_series = Some(samples.len());
+        if let Some(limit) = input.limit {
+            samples.truncate(limit);
+        }
+    }
+
+    Ok(json!({
+        "query": input.query,
+        "time": time,
+        "result_type": result_type,
+        "total_series": total_series,
         "data": data,
     }))
 }
 
+/// Resolves an instant query's `time`, defaulting to now.
+pub(crate) fn instant_time(time: Option<&str>, timezone: Tz) -> Result<DateTime<Utc>> {
+    let now = Utc::now();
+    match time {
+        Some(time) => parse_time_reference(time, timezone, now),
+        None => Ok(now),
+    }
+}
+
+/// The window an instant query reads: the widest `[range]` plus its `offset` across the parsed
+/// range aggregations, or Loki's default lookback for log queries and unparsable text.
+pub(crate) fn instant_lookback(query: &str) -> StdDuration {
+    let Ok(expr) = logql::parse(query) else {
+        return DEFAULT_INSTANT_LOOKBACK;
+    };
+
+    expr.range_aggregations()
+        .into_iter()
+        .filter_map(|aggregation| {
+            let range = logql::parse_duration(&aggregation.range.range)?;
+            let offset = match aggregation.range.offset.as_deref() {
+                Some(offset) => logql::parse_duration(offset)?,
+                None => StdDuration::ZERO,
+            };
+            range.checked_add(offset)
+        })
+        .max()
+        .unwrap_or(DEFAULT_INSTANT_LOOKBACK)
+}
+
+fn sample_value(sample: &Value) -> f64 {
+    sample
+        .get("value")
+        .and_then(|value| value.get(1))
+        .and_then(Value::as_str)
+        .and_then(|value| value.parse::<f64>().ok())
+        .unwrap_or(f64::NEG_INFINITY)
+}
+
 pub async fn build_query(
     client: &LokiClient,
+    split: &QuerySplit,
     timezone: Tz,
     input: BuildQueryInput,
 ) -> Result<Value> {
-    let mut query = build_query_string(&input)?;
+    let query = build_query_string(&input)?;
     let requested_response_mode = input.response_mode.unwrap_or_default();
 
     let (start, end) = resolve_time_range(
@@ -133,174 +272,397 @@ pub async fn build_query(
         Utc::now(),
     )?;
 
-    let (response_mode, data) = if let Some(aggregation) = input.aggregation.as_deref() {
-        validate_aggregation(aggregation)?;
-        let range = input
-            .aggregation_range
-            .as_deref()
-            .unwrap_or("5m")
-            .to_string();
-
-        query = format!("{aggregation}({query}[{range}])");
-        let metrics = client
-            .query_metrics(&query, Some(start), Some(end), None)
+    let (mut start, mut end) = (start, end);
+    let sub_queries;
+    let mut next_cursor = None;
+    let (response_mode, data) = if input.aggregation.is_some() {
+        if input.cursor.is_some() {
+            bail!("cursor applies to log queries only; remove it when using aggregation");
+        }
+        let metrics = split
+            .query_metrics(client, &query, start, end, None)
             .await?;
-        (requested_response_mode, metrics)
+        sub_queries = metrics.sub_queries;
+        (requested_response_mode, metrics.data)
     } else {
-        let logs = client
-            .query_logs(
-                &query,
-                Some(start),
-                Some(end),
-                Some(input.limit.unwrap_or(100)),
-                Some("backward"),
-            )
-            .await?;
-        format_log_result(requested_response_mode, logs)
+        let page = query_log_page(
+            client,
+            split,
+            &query,
+            (start, end),
+            input.limit.unwrap_or(100),
+            Some("backward"),
+            input.cursor.as_deref(),
+            requested_response_mode,
+        )
+        .await?;
+        (start, end) = (page.start, page.end);
+        sub_queries = page.sub_queries;
+        next_cursor = page.next_cursor;
+        (page.response_mode, page.data)
     };
 
     Ok(json!({
         "query": query,
         "start": start,
         "end": end,
+        "sub_queries": sub_queries,
+        "next_cursor": next_cursor,
         "response_mode_requested": requested_response_mode,
         "response_mode": response_mode,
         "data": data,
     }))
 }
 
-pub async fn tail(client: &LokiClient, timezone: Tz, input: TailInput) -> Result<Value> {
+/// The selector a tail follows and where it starts: `since`, for replaying history, or `now`.
+/// A `since` in the future starts now.
+pub(crate) fn tail_start(
+    input: &TailInput,
+    timezone: Tz,
+    now: DateTime<Utc>,
+) -> Result<(String, DateTime<Utc>)> {
     if input.labels.is_empty() {
         bail!("tail labels must not be empty");
     }
+    let selector = selector_from_labels(&input.labels)?;
+    let start = match input.since.as_deref() {
+        Some(since) => parse_time_reference(since, timezone, now)?.min(now),
+        None => now,
+    };
 
-    let selector = selector_from_labels(&input.labels);
+    Ok((selector, start))
+}
+
+/// Streams new lines from Loki's tail WebSocket until `lines` entries arrive or `duration`
+/// elapses, forwarding each batch to `progress` and formatting the collected lines at the end.
+pub async fn tail(
+    client: &LokiClient,
+    timezone: Tz,
+    input: TailInput,
+    progress: Option<&ProgressSender>,
+) -> Result<Value> {
+    let now = Utc::now();
+    let (selector, start) = tail_start(&input, timezone, now)?;
     let requested_response_mode = input.response_mode.unwrap_or_default();
+    let max_lines = input
+        .lines
+        .unwrap_or(DEFAULT_TAIL_LINES)
+        .clamp(1, MAX_TAIL_LINES);
+    let duration_text = input.duration.as_deref().unwrap_or(DEFAULT_TAIL_DURATION);
+    let duration = parse_std_duration(duration_text)
+        .with_context(|| format!("invalid tail duration: {duration_text}"))?;
+    if duration.is_zero() || duration > MAX_TAIL_DURATION {
+        bail!("tail duration must be greater than 0s and at most 5m");
+    }
 
-    let (start, end) = resolve_time_range(None, None, timezone, Utc::now())?;
-    let data = client
-        .query_logs(
-            &selector,
-            Some(start),
-            Some(end),
-            Some(input.lines.unwrap_or(50)),
-            Some("backward"),
-        )
-        .await?;
+    let mut stream = client.tail(&selector, Some(start), Some(max_lines)).await?;
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut streams = Vec::new();
+    let mut received = 0_u32;
+    let mut dropped_entries = 0_usize;
+    let stopped_by = loop {
+        let batch = match tokio::time::timeout_at(deadline, stream.next_batch()).await {
+            Err(_) => break "duration",
+            Ok(batch) => match batch? {
+                Some(batch) => batch,
+                None => break "closed",
+            },
+        };
+
+        dropped_entries += batch.dropped_entries.len();
+        let (batch_streams, batch_lines) = take_tail_lines(batch.streams, max_lines - received);
+        received += batch_lines;
+        if batch_lines > 0
+            && let Some(progress) = progress
+        {
+            let _ = progress.send(ToolProgress {
+                progress: u64::from(received),
+                total: Some(u64::from(max_lines)),
+                message: tail_lines_text(&batch_streams),
+                data: json!({
+                    "query": selector,
+                    "streams": batch_streams,
+                }),
+            });
+        }
+        streams.extend(batch_streams);
+
+        if received >= max_lines {
+            break "lines";
+        }
+    };
+    stream.close().await;
+
+    let data = json!({
+        "resultType": "streams",
+        "result": streams,
+    });
     let (response_mode, formatted_data) = format_log_result(requested_response_mode, data);
 
     Ok(json!({
         "query": selector,
         "start": start,
-        "end": end,
+        "end": Utc::now(),
+        "lines_received": received,
+        "dropped_entries": dropped_entries,
+        "stopped_by": stopped_by,
         "response_mode_requested": requested_response_mode,
         "response_mode": response_mode,
         "data": formatted_data,
     }))
 }
 
+/// Keeps at most `remaining` entries across the streams of one tail message.
+fn take_tail_lines(streams: Vec<Value>, remaining: u32) -> (Vec<Value>, u32) {
+    let mut remaining = remaining as usize;
+    let mut taken = 0;
+    let mut kept = Vec::new();
+
+    for mut stream in streams {
+        if remaining == 0 {
+            break;
+        }
+        let Some(values) = stream.get_mut("values").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        values.truncate(remaining);
+        remaining -= values.len();
+        taken += values.len();
+        if !values.is_empty() {
+            kept.push(stream);
+        }
+    }
+
+    (kept, u32::try_from(taken).unwrap_or(u32::MAX))
+}
+
+fn tail_lines_text(streams: &[Value]) -> String {
+    streams
+        .iter()
+        .filter_map(|stream| stream.get("values").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|value| value.get(1).and_then(Value::as_str))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
 pub async fn run_saved_query(
     client: &LokiClient,
+    split: &QuerySplit,
     config: &Config,
     timezone: Tz,
     input: RunSavedQueryInput,
 ) -> Result<Value> {
-    let Some(saved_query) = config
-        .saved_queries
-        .iter()
-        .find(|query| query.name == input.name)
-    else {
-        bail!("saved query not found: {}", input.name);
+    let saved_query = saved::find(config, &input.name)?;
+    let rendered = saved::render(config, saved_query, input.params.as_ref())?;
+    let range = saved::run_range(saved_query, input.override_range.as_deref())?;
+
+    let mut response = match saved_query.kind.as_str() {
+        "metrics" => {
+            let input = QueryMetricsInput {
+                query: rendered.query,
+                start: range.map(str::to_string),
+                end: None,
+                step: saved_query.step.clone(),
+            };
+            query_metrics(client, split, timezone, input).await?
+        }
+        "instant" => {
+            let input = QueryInstantInput {
+                query: rendered.query,
+                time: None,
+                limit: saved_query.limit.map(|limit| limit as usize),
+            };
+            query_instant(client, timezone, input).await?
+        }
+        _ => {
+            let input = QueryLogsInput {
+                query: rendered.query,
+                start: range.map(str::to_string),
+                end: None,
+                limit: Some(saved_query.limit.unwrap_or(100)),
+                direction: Some(
+                    saved_query
+                        .direction
+                        .clone()
+                        .unwrap_or_else(|| "backward".to_string()),
+                ),
+                cursor: input.cursor,
+                response_mode: input.response_mode.or(saved_query.response_mode),
+            };
+            query_logs(client, split, timezone, input).await?
+        }
     };
 
-    let range = input
-        .override_range
-        .as_deref()
-        .unwrap_or(saved_query.range.as_str());
-
-    let (start, end) = resolve_time_range(Some(range), None, timezone, Utc::now())?;
-
-    let requested_response_mode = input.response_mode.unwrap_or_default();
-    let data = client
-        .query_logs(
-            &saved_query.query,
-            Some(start),
-            Some(end),
-            Some(100),
-            Some("backward"),
-        )
-        .await?;
-    let (response_mode, formatted_data) = format_log_result(requested_response_mode, data);
+    if let Some(object) = response.as_object_mut() {
+        object.insert("name".to_string(), json!(saved_query.name));
+        object.insert("description".to_string(), json!(saved_query.description));
+        object.insert("kind".to_string(), json!(saved_query.kind));
+        object.insert("params".to_string(), Value::Object(rendered.params));
+    }
 
-    Ok(json!({
-        "name": saved_query.name,
-        "query": saved_query.query,
-        "description": saved_query.description,
-        "start": start,
-        "end": end,
-        "response_mode_requested": requested_response_mode,
-        "response_mode": response_mode,
-        "data": formatted_data,
-    }))
+    Ok(response)
 }
 
-pub(crate) fn build_query_string(input: &BuildQueryInput) -> Result<String> {
-    let selector = selector_from_labels(input.labels.as_ref().unwrap_or(&BTreeMap::new()));
-
-    let mut parts = vec![selector];
-
-    if let Some(structured_metadata) = input.structured_metadata.as_ref() {
-        for (field, value) in structured_metadata {
-            parts.push(format!("| {field}=\"{}\"", escape_logql_value(value)));
+/// Runs and formats one page of a log query. A `cursor` from a previous page replaces the time
+/// range and direction, and entries already returned at the page boundary are skipped.
+///
+/// Pages in `summary` mode are summarised while they download, so the raw entries are never
+/// held. When Loki no longer returns some of the cursor's boundary entries, such a page can
+/// summarise up to that many entries beyond `limit`; the next cursor still follows the last one.
+#[allow(clippy::too_many_arguments)]
+async fn query_log_page(
+    client: &LokiClient,
+    split: &QuerySplit,
+    query: &str,
+    range: (DateTime<Utc>, DateTime<Utc>),
+    limit: u32,
+    direction: Option<&str>,
+    cursor: Option<&str>,
+    response_mode: ResponseMode,
+) -> Result<PagedLogs> {
+    let cursor = cursor
+        .map(|cursor| LogCursor::decode(cursor, query))
+        .transpose()?;
+    let forward = match (&cursor, direction) {
+        (Some(cursor), Some(direction))
+            if direction.eq_ignore_ascii_case("forward") != cursor.forward() =>
+        {
+            bail!("invalid cursor: it was issued for a different direction");
         }
+        (Some(cursor), _) => cursor.forward(),
+        (None, direction) => {
+            direction.is_some_and(|direction| direction.eq_ignore_ascii_case("forward"))
+        }
+    };
+    let (start, end) = cursor.as_ref().map(LogCursor::window).unwrap_or(range);
+    let fetched_limit = limit.saturating_add(cursor.as_ref().map(LogCursor::overlap).unwrap_or(0));
+    let direction = Some(if forward { "forward" } else { "backward" });
+
+    if response_mode == ResponseMode::Summary {
+        let streamed = split
+            .stream_logs(
+                client,
+                query,
+                (start, end),
+                fetched_limit,
+                direction,
+                SummarySink::new(forward, cursor),
+            )
+            .await?;
+        let SummarySink { summary, boundary } = streamed.sink;
+        let next_cursor = if streamed.result_type.as_deref() == Some("streams") {
+            boundary.next_cursor(query, fetched_limit, (start, end))?
+        } else {
+            None
+        };
+
+        return Ok(PagedLogs {
+            response_mode,
+            data: summary.into_payload(false),
+            sub_queries: streamed.sub_queries,
+            start,
+            end,
+            next_cursor,
+        });
     }
 
-    if let Some(line_filter) = input.line_filter.as_deref() {
-        parts.push(format!("|= \"{}\"", escape_logql_value(line_filter)));
-    }
+    let result = split
+        .query_logs(client, query, start, end, fetched_limit, direction)
+        .await?;
+    let page = paginate(
+        query,
+        result.data,
+        limit,
+        fetched_limit,
+        forward,
+        (start, end),
+        cursor.as_ref(),
+    )?;
 
-    if let Some(line_filter_regex) = input.line_filter_regex.as_deref() {
-        parts.push(format!("|~ \"{}\"", escape_logql_value(line_filter_regex)));
-    }
+    let (response_mode, data) = format_log_result(response_mode, page.data);
 
-    if let Some(exclude) = input.exclude.as_deref() {
-        parts.push(format!("!= \"{}\"", escape_logql_value(exclude)));
-    }
+    Ok(PagedLogs {
+        response_mode,
+        data,
+        sub_queries: result.sub_queries,
+        start,
+        end,
+        next_cursor: page.next_cursor,
+    })
+}
 
-    if let Some(json_fields) = input.json_fields.as_ref()
-        && !json_fields.is_empty()
-    {
-        parts.push("| json".to_string());
-        for (field, value) in json_fields {
-            parts.push(format!("| {field}=\"{}\"", escape_logql_value(value)));
+/// Folds streamed entries into a summary while following the page boundary.
+struct SummarySink {
+    summary: LogSummary,
+    boundary: PageBoundary,
+}
+
+impl SummarySink {
+    fn new(forward: bool, previous: Option<LogCursor>) -> Self {
+        Self {
+            summary: LogSummary::default(),
+            boundary: PageBoundary::new(forward, previous),
         }
     }
-
-    Ok(parts.join(" "))
 }
 
-pub(crate) fn selector_from_labels(labels: &BTreeMap<String, String>) -> String {
-    if labels.is_empty() {
-        return "{}".to_string();
+impl LogEntrySink for SummarySink {
+    fn push(&mut self, stream: &Map<String, Value>, timestamp_nanos: &str, entry: LogLineEntry) {
+        if self.boundary.observe(stream, timestamp_nanos, &entry.line) {
+            self.summary.push(&entry);
+        }
     }
-
-    let pairs = labels
-        .iter()
-        .map(|(key, value)| format!("{key}=\"{}\"", escape_logql_value(value)))
-        .collect::<Vec<String>>();
-
-    format!("{{{}}}", pairs.join(","))
 }
 
-pub(crate) fn validate_aggregation(aggregation: &str) -> Result<()> {
-    match aggregation {
-        "count_over_time" | "rate" | "bytes_over_time" | "bytes_rate" => Ok(()),
-        _ => bail!(
-            "unsupported aggregation: {aggregation}. expected one of count_over_time, rate, bytes_over_time, bytes_rate"
-        ),
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use super::{TailInput, instant_lookback, tail_start};
+
+    #[test]
+    fn instant_lookback_reads_ranges_and_offsets_from_the_parsed_query() {
+        for (query, seconds) in [
+            (
+                r#"sum(rate({app="api"}[5m])) / sum(rate({app="api"}[1h]))"#,
+                3_600,
+            ),
+            (r#"{app="api"}"#, 300),
+            (r#"count_over_time({app="api"} |~ "[1h]" [5m])"#, 300),
+            (r#"count_over_time({app="api"}[5m] offset 1h)"#, 3_900),
+            (r#"rate({app="api"}[1h30m])"#, 5_400),
+            (
+                r#"max(count_over_time({app="api"} | json [10m] offset 2m))"#,
+                720,
+            ),
+            ("not logql [1h]", 300),
+        ] {
+            assert_eq!(
+                instant_lookback(query),
+                Duration::from_secs(seconds),
+                "{query}"
+            );
+        }
     }
-}
 
-fn escape_logql_value(input: &str) -> String {
-    input.replace('\\', "\\\\").replace('"', "\\\"")
+    #[test]
+    fn tail_start_clamps_a_future_since_to_now() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        let input = |since: &str| -> TailInput {
+            serde_json::from_value(json!({"labels": {"app": "api"}, "since": since}))
+                .expect("tail input")
+        };
+
+        let (selector, start) =
+            tail_start(&input("2026-01-02T00:00:00Z"), chrono_tz::UTC, now).expect("future since");
+        assert_eq!(selector, r#"{app="api"}"#);
+        assert_eq!(start, now);
+
+        let (_, start) = tail_start(&input("1h"), chrono_tz::UTC, now).expect("past since");
+        assert_eq!(start, now - chrono::Duration::hours(1));
+    }
 }
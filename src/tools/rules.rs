@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{loki::client::LokiClient, tools::query::instant_lookback};
+
+/// How many alert names an unknown-alert error lists.
+const MAX_SUGGESTED_ALERTS: usize = 20;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListRulesInput {
+    pub rule_type: Option<String>,
+    pub namespace: Option<String>,
+    pub group: Option<String>,
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListAlertsInput {
+    pub state: Option<String>,
+    pub alert: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertQueryInput {
+    pub alert: String,
+    pub namespace: Option<String>,
+    pub group: Option<String>,
+}
+
+/// Lists ruler rule groups, either summarised from the Prometheus-compatible API (with health
+/// and state) or as the raw YAML the ruler stores.
+pub async fn list_rules(client: &LokiClient, input: ListRulesInput) -> Result<Value> {
+    let rule_type = match input.rule_type.as_deref().map(str::trim) {
+        None => None,
+        Some("alerting") => Some("alert"),
+        Some("recording") => Some("record"),
+        Some(other) => bail!("unsupported rule_type: {other}. expected one of alerting, recording"),
+    };
+
+    match input.format.as_deref().map(str::trim).unwrap_or("summary") {
+        "summary" => {}
+        "yaml" => {
+            if rule_type.is_some() || input.group.is_some() {
+                bail!("rule_type and group filters are not supported with format yaml");
+            }
+            let rules_yaml = client.rules(input.namespace.as_deref()).await?;
+            return Ok(json!({
+                "format": "yaml",
+                "namespace": input.namespace,
+                "rules_yaml": rules_yaml,
+            }));
+        }
+        other => bail!("unsupported format: {other}. expected one of summary, yaml"),
+    }
+
+    let data = client.prometheus_rules(rule_type).await?;
+    let groups = rule_groups(&data)
+        .filter(|group| {
+            input
+                .namespace
+                .as_deref()
+                .is_none_or(|namespace| group_namespace(group) == namespace)
+                && input
+                    .group
+                    .as_deref()
+                    .is_none_or(|name| group_name(group) == name)
+        })
+        .map(|group| {
+            let rules = group_rules(group)
+                .map(summarize_rule)
+                .collect::<Vec<Value>>();
+            json!({
+                "namespace": group_namespace(group),
+                "name": group_name(group),
+                "interval_seconds": group.get("interval"),
+                "rules": rules,
+            })
+        })
+        .collect::<Vec<Value>>();
+    let total_rules = groups
+        .iter()
+        .filter_map(|group| group["rules"].as_array())
+        .map(Vec::len)
+        .sum::<usize>();
+
+    Ok(json!({
+        "format": "summary",
+        "total_groups": groups.len(),
+        "total_rules": total_rules,
+        "groups": groups,
+    }))
+}
+
+/// Lists pending and firing alerts, firing first and longest-active first, each with the
+/// LogQL expression of the rule behind it when the rules API can be read.
+pub async fn list_alerts(client: &LokiClient, input: ListAlertsInput) -> Result<Value> {
+    let state = input.state.as_deref().map(str::trim);
+    if let Some(state) = state
+        && !matches!(state, "firing" | "pending")
+    {
+        bail!("unsupported state: {state}. expected one of firing, pending");
+    }
+
+    let data = client.alerts().await?;
+    // Alerts do not carry their expression; join it from the rules when possible.
+    let (rules, rules_error) = match client.prometheus_rules(Some("alert")).await {
+        Ok(rules) => (rules, None),
+        Err(error) => (Value::Null, Some(format!("{error:#}"))),
+    };
+    let queries = alert_queries(&rules);
+
+    let mut alerts = data
+        .get("alerts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|alert| state.is_none_or(|state| str_field(alert, "state") == state))
+        .filter(|alert| {
+            input
+                .alert
+                .as_deref()
+                .is_none_or(|name| alert_name(alert) == name)
+        })
+        .map(|alert| {
+            let name = alert_name(alert);
+            json!({
+                "alert": name,
+                "state": str_field(alert, "state"),
+                "active_at": alert.get("activeAt"),
+                "value": alert.get("value"),
+                "labels": alert.get("labels"),
+                "annotations": alert.get("annotations"),
+                "query": queries.get(name),
+            })
+        })
+        .collect::<Vec<Value>>();
+    alerts.sort_by(|left, right| {
+        (left["state"] != "firing", left["active_at"].as_str())
+            .cmp(&(right["state"] != "firing", right["active_at"].as_str()))
+    });
+    let firing = alerts
+        .iter()
+        .filter(|alert| alert["state"] == "firing")
+        .count();
+
+    Ok(json!({
+        "total": alerts.len(),
+        "firing": firing,
+        "pending": alerts.len() - firing,
+        "rules_error": rules_error,
+        "alerts": alerts,
+    }))
+}
+
+/// Finds the alerting rule behind `alert` and turns its expression into a ready-to-run
+/// `loki_query_logs` call covering the window that made it fire.
+pub async fn alert_query(client: &LokiClient, input: AlertQueryInput) -> Result<Value> {
+    let alert = input.alert.trim();
+    if alert.is_empty() {
+        bail!("alert must not be empty");
+    }
+
+    let data = client.prometheus_rules(Some("alert")).await?;
+    let mut matches = Vec::new();
+    for group in rule_groups(&data) {
+        if input
+            .namespace
+            .as_deref()
+            .is_some_and(|namespace| group_namespace(group) != namespace)
+            || input
+                .group
+                .as_deref()
+                .is_some_and(|name| group_name(group) != name)
+        {
+            continue;
+        }
+        for rule in group_rules(group).filter(|rule| str_field(rule, "name") == alert) {
+            matches.push(describe_alert_rule(group, rule, Utc::now()));
+        }
+    }
+
+    if matches.is_empty() {
+        let mut known = alert_queries(&data).into_keys().collect::<Vec<&str>>();
+        known.truncate(MAX_SUGGESTED_ALERTS);
+        if known.is_empty() {
+            bail!("no alerting rule named {alert}; the ruler has no alerting rules");
+        }
+        bail!(
+            "no alerting rule named {alert}; known alerts: {}",
+            known.join(", ")
+        );
+    }
+
+    Ok(json!({
+        "alert": alert,
+        "rules": matches,
+    }))
+}
+
+fn describe_alert_rule(group: &Value, rule: &Value, now: DateTime<Utc>) -> Value {
+    let expression = str_field(rule, "query");
+    let instances = rule
+        .get("alerts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|instance| {
+            json!({
+                "state": str_field(instance, "state"),
+                "active_at": instance.get("activeAt"),
+                "value": instance.get("value"),
+                "labels": instance.get("labels"),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    // Start one evaluation window before the earliest instance became active.
+    let lookback = Duration::from_std(instant_lookback(expression)).unwrap_or(Duration::zero());
+    let start = instances
+        .iter()
+        .filter_map(|instance| instance["active_at"].as_str())
+        .filter_map(|active_at| DateTime::parse_from_rfc3339(active_at).ok())
+        .map(|active_at| active_at.with_timezone(&Utc))
+        .min()
+        .unwrap_or(now - Duration::hours(1))
+        - lookback;
+    let query_logs = log_query(expression).map(|query| {
+        json!({
+            "tool": "loki_query_logs",
+            "params": {
+                "query": query,
+                "start": start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            },
+        })
+    });
+
+    json!({
+        "namespace": group_namespace(group),
+        "group": group_name(group),
+        "query": expression,
+        "for_seconds": rule.get("duration"),
+        "state": rule.get("state"),
+        "health": rule.get("health"),
+        "labels": rule.get("labels"),
+        "annotations": rule.get("annotations"),
+        "instances": instances,
+        "query_logs": query_logs,
+    })
+}
+
+fn summarize_rule(rule: &Value) -> Value {
+    let mut summary = Map::new();
+    let rule_type = str_field(rule, "type");
+    summary.insert("type".to_string(), json!(rule_type));
+    summary.insert("name".to_string(), json!(str_field(rule, "name")));
+    summary.insert("query".to_string(), json!(str_field(rule, "query")));
+    for field in ["labels", "annotations", "health"] {
+        if let Some(value) = rule.get(field).filter(|value| !value.is_null()) {
+            summary.insert(field.to_string(), value.clone());
+        }
+    }
+    if let Some(error) = rule
+        .get("lastError")
+        .and_then(Value::as_str)
+        .filter(|error| !error.is_empty())
+    {
+        summary.insert("last_error".to_string(), json!(error));
+    }
+    if rule_type == "alerting" {
+        summary.insert("for_seconds".to_string(), json!(rule.get("duration")));
+        summary.insert("state".to_string(), json!(rule.get("state")));
+        let active = rule
+            .get("alerts")
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len);
+        summary.insert("active_alerts".to_string(), json!(active));
+    }
+
+    Value::Object(summary)
+}
+
+/// Expression of every alerting rule, keyed by alert name.
+fn alert_queries(data: &Value) -> BTreeMap<&str, &str> {
+    rule_groups(data)
+        .flat_map(group_rules)
+        .filter(|rule| str_field(rule, "type") == "alerting")
+        .map(|rule| (str_field(rule, "name"), str_field(rule, "query")))
+        .collect()
+}
+
+fn rule_groups(data: &Value) -> impl Iterator<Item = &Value> {
+    data.get("groups")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+fn group_rules(group: &Value) -> impl Iterator<Item = &Value> {
+    group
+        .get("rules")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// Loki reports the ruler namespace as the group's `file`.
+fn group_namespace(group: &Value) -> &str {
+    str_field(group, "file")
+}
+
+fn group_name(group: &Value) -> &str {
+    str_field(group, "name")
+}
+
+fn alert_name(alert: &Value) -> &str {
+    alert
+        .get("labels")
+        .and_then(|labels| labels.get("alertname"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn str_field<'a>(value: &'a Value, field: &str) -> &'a str {
+    value.get(field).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// The log query inside a metric expression: the first stream selector and its pipeline, up to
+/// the range (`[5m]`) and without an `unwrap` stage, which only metric queries accept.
+fn log_query(expression: &str) -> Option<String> {
+    let start = expression.find('{')?;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut unwrap_at = None;
+    for (offset, character) in expression[start..].char_indices() {
+        let position = start + offset;
+        match (quote, character) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(open), character) if character == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '`') => quote = Some(character),
+            (None, '|')
+                if unwrap_at.is_none()
+                    && expression[position + 1..]
+                        .trim_start()
+                        .starts_with("unwrap") =>
+            {
+                unwrap_at = Some(position);
+            }
+            (None, '[') => {
+                let query = expression[start..unwrap_at.unwrap_or(position)].trim();
+                return Some(query.to_string());
+            }
+            (None, _) => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tools::rules::log_query;
+
+    #[test]
+    fn extracts_the_log_query_behind_a_metric_expression() {
+        assert_eq!(
+            log_query(r#"sum by (app) (rate({app="api"} |= "error [x]" | json [5m])) > 1"#)
+                .as_deref(),
+            Some(r#"{app="api"} |= "error [x]" | json"#)
+        );
+        assert_eq!(
+            log_query(
+                r#"quantile_over_time(0.99, {app="api"} | logfmt | unwrap latency [1m]) > 2"#
+            )
+            .as_deref(),
+            Some(r#"{app="api"} | logfmt"#)
+        );
+        assert_eq!(log_query("vector(1)"), None);
+    }
+}
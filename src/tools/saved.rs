@@ -0,0 +1,443 @@
+//! Saved queries with typed parameters.
+//!
+//! A saved query references its parameters as `${name}`. Values are checked against the declared
+//! type, then escaped for the literal the placeholder sits in, so a value can never close a string
+//! and add pipeline stages of its own.
+
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Map, Value, json};
+use tracing::warn;
+
+use crate::{
+    config::{Config, SavedQuery, SavedQueryParam, SchemaField},
+    logql,
+    tools::builder::escape_logql_value,
+};
+
+pub const KINDS: [&str; 3] = ["logs", "metrics", "instant"];
+pub const PARAM_TYPES: [&str; 3] = ["string", "number", "regex"];
+
+/// Where a placeholder appears in the query text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderContext {
+    /// Outside any literal, for example `| duration > ${min_duration}`.
+    Bare,
+    /// Inside a double-quoted string.
+    Quoted,
+    /// Inside a backtick raw string.
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    pub name: String,
+    pub context: PlaceholderContext,
+    start: usize,
+    end: usize,
+}
+
+/// A saved query with its parameters substituted.
+#[derive(Debug, Clone)]
+pub struct RenderedQuery {
+    pub query: String,
+    pub params: Map<String, Value>,
+}
+
+pub fn find<'a>(config: &'a Config, name: &str) -> Result<&'a SavedQuery> {
+    config
+        .saved_queries
+        .iter()
+        .find(|saved_query| saved_query.name == name)
+        .ok_or_else(|| anyhow!("saved query not found: {name}"))
+}
+
+/// The range a run starts from: `override_range` or the saved `range`. Instant queries have none.
+pub fn run_range<'a>(
+    saved_query: &'a SavedQuery,
+    override_range: Option<&'a str>,
+) -> Result<Option<&'a str>> {
+    if saved_query.kind == "instant" {
+        if override_range.is_some() {
+            bail!(
+                "saved query {} is an instant query and does not take override_range",
+                saved_query.name
+            );
+        }
+        return Ok(None);
+    }
+
+    Ok(Some(override_range.unwrap_or(saved_query.range.as_str())))
+}
+
+/// Lists saved queries carrying every tag in `tags`, with the tags in use across all of them.
+pub fn list(config: &Config, tags: &[String]) -> Value {
+    let saved_queries = config
+        .saved_queries
+        .iter()
+        .filter(|saved_query| tags.iter().all(|tag| saved_query.tags.contains(tag)))
+        .collect::<Vec<&SavedQuery>>();
+    let known_tags = config
+        .saved_queries
+        .iter()
+        .flat_map(|saved_query| saved_query.tags.iter())
+        .collect::<BTreeSet<&String>>();
+
+    json!({
+        "total": saved_queries.len(),
+        "saved_queries": saved_queries,
+        "tags": known_tags,
+    })
+}
+
+/// Finds every `${name}` placeholder and the literal context it appears in.
+pub fn placeholders(query: &str) -> Vec<Placeholder> {
+    let mut found = Vec::new();
+    let mut context = PlaceholderContext::Bare;
+    let mut index = 0;
+    while index < query.len() {
+        let rest = &query[index..];
+        if rest.starts_with("${")
+            && let Some(close) = rest.find('}')
+            && is_identifier(&rest[2..close])
+        {
+            found.push(Placeholder {
+                name: rest[2..close].to_string(),
+                context,
+                start: index,
+                end: index + close + 1,
+            });
+            index += close + 1;
+            continue;
+        }
+
+        let ch = rest.chars().next().unwrap_or_default();
+        match (context, ch) {
+            (PlaceholderContext::Bare, '"') => context = PlaceholderContext::Quoted,
+            (PlaceholderContext::Bare, '`') => context = PlaceholderContext::Raw,
+            (PlaceholderContext::Quoted, '"') | (PlaceholderContext::Raw, '`') => {
+                context = PlaceholderContext::Bare;
+            }
+            (PlaceholderContext::Quoted, '\\') => {
+                index += rest.chars().nth(1).map_or(0, char::len_utf8);
+            }
+            _ => {}
+        }
+        index += ch.len_utf8();
+    }
+    found
+}
+
+/// Checks a value against a parameter declaration and returns its normalized text.
+pub fn check_value(
+    param: &SavedQueryParam,
+    value: &Value,
+    labels: &[SchemaField],
+) -> Result<String> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        _ => bail!("expected a {} value", param.kind),
+    };
+
+    match param.kind.as_str() {
+        "number" => {
+            let text = text.trim();
+            let valid = text.parse::<f64>().is_ok_and(|number| number.is_finite())
+                && text
+                    .chars()
+                    .all(|ch| ch.is_ascii_digit() || matches!(ch, '.' | '-' | 'e' | 'E' | '+'));
+            if !valid {
+                bail!("expected a number, got {text:?}");
+            }
+            Ok(text.to_string())
+        }
+        "regex" => {
+            regex_syntax::Parser::new()
+                .parse(&text)
+                .map_err(|error| anyhow!("invalid regex {text:?}: {error}"))?;
+            Ok(text)
+        }
+        _ => {
+            if let Some(label) = &param.label {
+                let allowed = labels
+                    .iter()
+                    .find(|field| &field.name == label)
+                    .map(|field| field.common_values.as_slice())
+                    .unwrap_or_default();
+                if !allowed.contains(&text) {
+                    bail!(
+                        "{text:?} is not a known value of label {label}. expected one of: {}",
+                        allowed.join(", ")
+                    );
+                }
+            }
+            Ok(text)
+        }
+    }
+}
+
+/// Validates `params` against the saved query's declarations and substitutes them into the query.
+/// Declared parameters fall back to their defaults; a parameter without one is required.
+pub fn render(
+    config: &Config,
+    saved_query: &SavedQuery,
+    params: Option<&Map<String, Value>>,
+) -> Result<RenderedQuery> {
+    let empty = Map::new();
+    let params = params.unwrap_or(&empty);
+    if let Some(unknown) = params
+        .keys()
+        .find(|key| !saved_query.params.iter().any(|param| &param.name == *key))
+    {
+        bail!(
+            "saved query {} has no param {unknown}. declared params: {}",
+            saved_query.name,
+            declared_names(saved_query)
+        );
+    }
+
+    let mut resolved = Map::new();
+    for param in &saved_query.params {
+        let value = match (params.get(&param.name), &param.default) {
+            (Some(value), _) => value.clone(),
+            (None, Some(default)) => Value::String(default.clone()),
+            (None, None) => bail!(
+                "saved query {} requires param {}",
+                saved_query.name,
+                param.name
+            ),
+        };
+        let text = check_value(param, &value, &config.labels).with_context(|| {
+            format!(
+                "invalid value for param {} of saved query {}",
+                param.name, saved_query.name
+            )
+        })?;
+        resolved.insert(param.name.clone(), Value::String(text));
+    }
+
+    let mut query = String::with_capacity(saved_query.query.len());
+    let mut last = 0;
+    for placeholder in placeholders(&saved_query.query) {
+        let Some(Value::String(text)) = resolved.get(&placeholder.name) else {
+            bail!(
+                "saved query {} references undeclared param ${{{}}}",
+                saved_query.name,
+                placeholder.name
+            );
+        };
+        query.push_str(&saved_query.query[last..placeholder.start]);
+        query.push_str(&quote(&placeholder, text)?);
+        last = placeholder.end;
+    }
+    query.push_str(&saved_query.query[last..]);
+
+    // Loki accepts forms the local parser lacks, so a query it cannot read is sent as written;
+    // the substituted values were checked above. [`warn_unparsable`] reports those at startup.
+    if let Ok(expr) = logql::parse(&query) {
+        let is_log_query = matches!(expr, logql::Expr::Log(_));
+        if is_log_query != (saved_query.kind == "logs") {
+            bail!(
+                "saved query {} has kind {} but its query is a {} query",
+                saved_query.name,
+                saved_query.kind,
+                if is_log_query { "log" } else { "metric" }
+            );
+        }
+    }
+
+    Ok(RenderedQuery {
+        query,
+        params: resolved,
+    })
+}
+
+/// Logs saved queries whose default rendering the LogQL parser rejects. They still run, so a
+/// query using a form the parser lacks does not stop the server.
+pub fn warn_unparsable(config: &Config) {
+    for saved_query in &config.saved_queries {
+        if saved_query
+            .params
+            .iter()
+            .any(|param| param.default.is_none())
+        {
+            continue;
+        }
+        let Ok(rendered) = render(config, saved_query, None) else {
+            continue;
+        };
+        if let Err(error) = logql::parse(&rendered.query) {
+            warn!(
+                saved_query = %saved_query.name,
+                error = %error,
+                "saved query does not parse as LogQL; it is sent to Loki as written"
+            );
+        }
+    }
+}
+
+fn quote(placeholder: &Placeholder, text: &str) -> Result<String> {
+    match placeholder.context {
+        PlaceholderContext::Quoted => Ok(escape_logql_value(text)),
+        PlaceholderContext::Raw if text.contains('`') => {
+            bail!("param {} must not contain a backtick", placeholder.name)
+        }
+        PlaceholderContext::Raw | PlaceholderContext::Bare => Ok(text.to_string()),
+    }
+}
+
+fn declared_names(saved_query: &SavedQuery) -> String {
+    if saved_query.params.is_empty() {
+        return "none".to_string();
+    }
+
+    saved_query
+        .params
+        .iter()
+        .map(|param| param.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn param(name: &str, kind: &str, default: Option<&str>) -> SavedQueryParam {
+        SavedQueryParam {
+            name: name.to_string(),
+            description: String::new(),
+            kind: kind.to_string(),
+            label: None,
+            default: default.map(str::to_string),
+        }
+    }
+
+    fn config_with(query: &str, params: Vec<SavedQueryParam>) -> Config {
+        Config {
+            labels: vec![SchemaField {
+                name: "namespace".to_string(),
+                description: "Kubernetes namespace".to_string(),
+                common_values: vec!["checkout".to_string(), "payments".to_string()],
+            }],
+            saved_queries: vec![SavedQuery {
+                name: "slow_requests".to_string(),
+                description: "Slow requests".to_string(),
+                query: query.to_string(),
+                range: "1h".to_string(),
+                params,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn placeholders_record_their_literal_context() {
+        let found =
+            placeholders(r#"{app="${app}"} |~ `${pattern}` | duration > ${min} | x="\"${y}""#);
+        let contexts = found
+            .iter()
+            .map(|placeholder| (placeholder.name.as_str(), placeholder.context))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contexts,
+            vec![
+                ("app", PlaceholderContext::Quoted),
+                ("pattern", PlaceholderContext::Raw),
+                ("min", PlaceholderContext::Bare),
+                ("y", PlaceholderContext::Quoted),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_validates_types_and_escapes_values() {
+        let mut namespace = param("namespace", "string", None);
+        namespace.label = Some("namespace".to_string());
+        let config = config_with(
+            r#"{namespace="${namespace}"} |~ "${pattern}" | logfmt | duration > ${min_duration}"#,
+            vec![
+                namespace,
+                param("pattern", "regex", Some("timeout")),
+                param("min_duration", "number", Some("2")),
+            ],
+        );
+        let saved_query = &config.saved_queries[0];
+
+        let rendered = render(
+            &config,
+            saved_query,
+            json!({"namespace": "checkout", "pattern": "a\"b|c", "min_duration": 5}).as_object(),
+        )
+        .expect("render");
+        assert_eq!(
+            rendered.query,
+            r#"{namespace="checkout"} |~ "a\"b|c" | logfmt | duration > 5"#
+        );
+        assert_eq!(rendered.params["pattern"], "a\"b|c");
+
+        let error = render(&config, saved_query, None).expect_err("namespace is required");
+        assert!(error.to_string().contains("requires param namespace"));
+
+        for (params, expected) in [
+            (
+                json!({"namespace": "unknown"}),
+                "not a known value of label namespace",
+            ),
+            (
+                json!({"namespace": "checkout", "min_duration": "5 or 1"}),
+                "expected a number",
+            ),
+            (
+                json!({"namespace": "checkout", "pattern": "(unclosed"}),
+                "invalid regex",
+            ),
+            (
+                json!({"namespace": "checkout", "limit": 5}),
+                "has no param limit",
+            ),
+        ] {
+            let error = render(&config, saved_query, params.as_object()).expect_err("invalid");
+            assert!(
+                format!("{error:#}").contains(expected),
+                "{error:#} should contain {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn queries_the_parser_rejects_still_render_and_check_their_values() {
+        // `| sample` stands in for a stage Loki has and the local parser does not.
+        let config = config_with(
+            r#"{namespace="${namespace}"} | sample ${ratio}"#,
+            vec![
+                param("namespace", "string", Some("checkout")),
+                param("ratio", "number", Some("0.5")),
+            ],
+        );
+        let saved_query = &config.saved_queries[0];
+
+        let rendered = render(&config, saved_query, None).expect("render");
+        assert_eq!(rendered.query, r#"{namespace="checkout"} | sample 0.5"#);
+        let error = render(&config, saved_query, json!({"ratio": "1 or"}).as_object())
+            .expect_err("values are still checked");
+        assert!(
+            format!("{error:#}").contains("expected a number"),
+            "{error:#}"
+        );
+    }
+}
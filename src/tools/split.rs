@@ -0,0 +1,521 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration as StdDuration,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::{StreamExt, stream};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    config::QuerySplitConfig,
+    loki::{body::LogEntrySink, client::LokiClient},
+    time::parse_std_duration,
+};
+
+/// Loki picks roughly 250 points per range when no step is given; splitting needs one shared step.
+const DEFAULT_POINTS_PER_RANGE: i64 = 250;
+
+/// Splits long `query_range` calls into sub-intervals and merges the partial results.
+#[derive(Debug, Clone, Copy)]
+pub struct QuerySplit {
+    interval: Option<Duration>,
+    max_concurrency: usize,
+}
+
+/// A merged Loki result and the number of `query_range` calls that produced it.
+pub struct SplitResult {
+    pub data: Value,
+    pub sub_queries: usize,
+}
+
+/// A log query streamed into `sink`, and the number of `query_range` calls it took.
+pub struct StreamedLogs<S> {
+    pub sink: S,
+    pub result_type: Option<String>,
+    pub sub_queries: usize,
+}
+
+impl QuerySplit {
+    pub fn from_config(config: &QuerySplitConfig) -> Result<Self> {
+        if !config.enabled {
+            return Ok(Self::disabled());
+        }
+
+        let interval = parse_std_duration(&config.interval)
+            .with_context(|| format!("invalid query_split.interval: {}", config.interval))?;
+        Ok(Self {
+            interval: Some(
+                Duration::from_std(interval).context("query_split.interval is too large")?,
+            ),
+            max_concurrency: config.max_concurrency.max(1),
+        })
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            interval: None,
+            max_concurrency: 1,
+        }
+    }
+
+    /// Runs a log query, walking sub-intervals in `direction` order and stopping once `limit`
+    /// entries are collected.
+    pub async fn query_logs(
+        &self,
+        client: &LokiClient,
+        query: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: u32,
+        direction: Option<&str>,
+    ) -> Result<SplitResult> {
+        let ranges = self.ranges(start, end, None);
+        if ranges.len() <= 1 {
+            let data = client
+                .query_logs(query, Some(start), Some(end), Some(limit), direction)
+                .await?;
+            return Ok(SplitResult {
+                data,
+                sub_queries: 1,
+            });
+        }
+
+        let forward = direction.is_some_and(|direction| direction.eq_ignore_ascii_case("forward"));
+        let ordered = if forward {
+            ranges
+        } else {
+            ranges.into_iter().rev().collect()
+        };
+
+        let mut partials = stream::iter(ordered)
+            .map(|(range_start, range_end)| {
+                client.query_logs(
+                    query,
+                    Some(range_start),
+                    Some(range_end),
+                    Some(limit),
+                    direction,
+                )
+            })
+            .buffered(self.max_concurrency);
+
+        let mut results = Vec::new();
+        let mut collected = 0_usize;
+        while let Some(partial) = partials.next().await {
+            let partial = partial?;
+            collected += count_log_entries(&partial);
+            results.push(partial);
+            // Sub-intervals arrive in direction order, so later ones cannot displace these entries.
+            if collected >= limit as usize {
+                break;
+            }
+        }
+
+        Ok(SplitResult {
+            sub_queries: results.len(),
+            data: merge_log_results(results, Some(limit as usize), forward),
+        })
+    }
+
+    /// Streams a log query into `sink`, one sub-interval at a time in `direction` order. Each
+    /// call asks only for the entries still missing, so the sink sees exactly the entries
+    /// [`Self::query_logs`] would return, without them ever being held together.
+    pub async fn stream_logs<S>(
+        &self,
+        client: &LokiClient,
+        query: &str,
+        (start, end): (DateTime<Utc>, DateTime<Utc>),
+        limit: u32,
+        direction: Option<&str>,
+        mut sink: S,
+    ) -> Result<StreamedLogs<S>>
+    where
+        S: LogEntrySink + Send + 'static,
+    {
+        let mut ranges = self.ranges(start, end, None);
+        if !direction.is_some_and(|direction| direction.eq_ignore_ascii_case("forward")) {
+            ranges.reverse();
+        }
+
+        let mut result_type = None;
+        let mut sub_queries = 0;
+        let mut collected = 0_u32;
+        for range in ranges {
+            let decoded = client
+                .stream_logs(query, range, limit - collected, direction, sink)
+                .await?;
+            sink = decoded.sink;
+            result_type = result_type.or(decoded.result_type);
+            sub_queries += 1;
+            collected =
+                collected.saturating_add(u32::try_from(decoded.entries).unwrap_or(u32::MAX));
+            if collected >= limit {
+                break;
+            }
+        }
+
+        Ok(StreamedLogs {
+            sink,
+            result_type,
+            sub_queries,
+        })
+    }
+
+    /// Runs a metric query over aligned sub-intervals and stitches the matrix samples together.
+    pub async fn query_metrics(
+        &self,
+        client: &LokiClient,
+        query: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Option<&str>,
+    ) -> Result<SplitResult> {
+        let (step_duration, shared_step) = match step {
+            Some(step) => (parse_step(step), step.to_string()),
+            None => {
+                let step_duration = default_step(start, end);
+                let shared_step = format!("{}s", step_duration.num_seconds());
+                (Some(step_duration), shared_step)
+            }
+        };
+        // Unknown step syntax is left for Loki to interpret over the whole window.
+        let ranges = match step_duration {
+            Some(step_duration) => self.ranges(start, end, Some(step_duration)),
+            None => vec![(start, end)],
+        };
+        if ranges.len() <= 1 {
+            let data = client
+                .query_metrics(query, Some(start), Some(end), step)
+                .await?;
+            return Ok(SplitResult {
+                data,
+                sub_queries: 1,
+            });
+        }
+
+        let results = stream::iter(ranges)
+            .map(|(range_start, range_end)| {
+                client.query_metrics(
+                    query,
+                    Some(range_start),
+                    Some(range_end),
+                    Some(&shared_step),
+                )
+            })
+            .buffered(self.max_concurrency)
+            .collect::<Vec<Result<Value>>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<Value>>>()?;
+
+        Ok(SplitResult {
+            sub_queries: results.len(),
+            data: merge_metric_results(results),
+        })
+    }
+
+    /// Cuts `[start, end]` into consecutive sub-ranges no longer than the interval. With a step,
+    /// the interval is rounded down to a whole number of steps so samples stay on one grid.
+    fn ranges(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Option<Duration>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let Some(mut interval) = self.interval else {
+            return vec![(start, end)];
+        };
+        if let Some(step) = step.filter(|step| step.num_milliseconds() > 0) {
+            let steps = (interval.num_milliseconds() / step.num_milliseconds()).max(1);
+            interval = step * i32::try_from(steps).unwrap_or(i32::MAX);
+        }
+        if interval <= Duration::zero() || end - start <= interval {
+            return vec![(start, end)];
+        }
+
+        let mut ranges = Vec::new();
+        let mut range_start = start;
+        while range_start < end {
+            let range_end = (range_start + interval).min(end);
+            ranges.push((range_start, range_end));
+            range_start = range_end;
+        }
+
+        ranges
+    }
+}
+
+fn parse_step(step: &str) -> Option<Duration> {
+    let step = step.trim();
+    let parsed = match step.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => StdDuration::from_secs_f64(seconds),
+        Ok(_) => return None,
+        Err(_) => parse_std_duration(step).ok()?,
+    };
+
+    Duration::from_std(parsed)
+        .ok()
+        .filter(|step| *step > Duration::zero())
+}
+
+fn default_step(start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+    let seconds = (end - start).num_seconds().max(0);
+    let step = (seconds + DEFAULT_POINTS_PER_RANGE - 1) / DEFAULT_POINTS_PER_RANGE;
+    Duration::seconds(step.max(1))
+}
+
+fn count_log_entries(data: &Value) -> usize {
+    data.get("result")
+        .and_then(Value::as_array)
+        .map(|streams| {
+            streams
+                .iter()
+                .filter_map(|stream| stream.get("values").and_then(Value::as_array))
+                .map(Vec::len)
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+/// Merges partial `streams` results: entries a sub-result repeats from the adjacent one before
+/// it (Loki returns boundary entries to both sides) are dropped, entries are ordered by
+/// timestamp in the query direction, and the first `limit` are kept. Identical lines within one
+/// sub-result are all kept.
+pub(crate) fn merge_log_results(results: Vec<Value>, limit: Option<usize>, forward: bool) -> Value {
+    let mut stream_labels = Vec::<Value>::new();
+    let mut stream_index = BTreeMap::<String, usize>::new();
+    let mut entries = Vec::<(i128, usize, Value)>::new();
+    let mut previous = HashMap::<(usize, String), usize>::new();
+
+    for result in results {
+        let mut current = HashMap::<(usize, String), usize>::new();
+        let Some(streams) = result.get("result").and_then(Value::as_array) else {
+            continue;
+        };
+        for stream in streams {
+            let labels = stream.get("stream").cloned().unwrap_or_else(|| json!({}));
+            let key = labels.to_string();
+            let index = *stream_index.entry(key).or_insert_with(|| {
+                stream_labels.push(labels);
+                stream_labels.len() - 1
+            });
+
+            for value in stream
+                .get("values")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let timestamp = value
+                    .get(0)
+                    .and_then(Value::as_str)
+                    .and_then(|timestamp| timestamp.parse::<i128>().ok())
+                    .unwrap_or_default();
+                let key = (index, value.to_string());
+                let repeated = previous.get_mut(&key).filter(|count| **count > 0);
+                *current.entry(key).or_default() += 1;
+                match repeated {
+                    Some(count) => *count -= 1,
+                    None => entries.push((timestamp, index, value.clone())),
+                }
+            }
+        }
+        previous = current;
+    }
+
+    entries.sort_by(|left, right| {
+        let order = left.0.cmp(&right.0);
+        if forward { order } else { order.reverse() }
+    });
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+
+    let mut values_by_stream = vec![Vec::<Value>::new(); stream_labels.len()];
+    for (_, index, value) in entries {
+        values_by_stream[index].push(value);
+    }
+    let streams = stream_labels
+        .into_iter()
+        .zip(values_by_stream)
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(labels, values)| json!({"stream": labels, "values": values}))
+        .collect::<Vec<Value>>();
+
+    json!({
+        "resultType": "streams",
+        "result": streams,
+    })
+}
+
+/// Stitches partial `matrix` results per series, ordering samples by time and dropping the
+/// duplicate sample Loki returns at each shared sub-range boundary.
+pub(crate) fn merge_metric_results(results: Vec<Value>) -> Value {
+    let result_type = results
+        .iter()
+        .find_map(|result| result.get("resultType").and_then(Value::as_str))
+        .unwrap_or("matrix")
+        .to_string();
+    if result_type == "streams" {
+        return merge_log_results(results, None, false);
+    }
+    if result_type != "matrix" {
+        return results.into_iter().last().unwrap_or(Value::Null);
+    }
+
+    let mut series = BTreeMap::<String, (Value, Vec<Value>)>::new();
+    for result in results {
+        let Some(partials) = result.get("result").and_then(Value::as_array) else {
+            continue;
+        };
+        for partial in partials {
+            let metric = partial.get("metric").cloned().unwrap_or_else(|| json!({}));
+            let samples = partial
+                .get("values")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            series
+                .entry(metric.to_string())
+                .or_insert_with(|| (metric, Vec::new()))
+                .1
+                .extend(samples);
+        }
+    }
+
+    let result = series
+        .into_values()
+        .map(|(metric, mut samples)| {
+            samples.sort_by(|left, right| sample_time(left).total_cmp(&sample_time(right)));
+            samples.dedup_by(|left, right| sample_time(left) == sample_time(right));
+            let mut object = Map::new();
+            object.insert("metric".to_string(), metric);
+            object.insert("values".to_string(), Value::Array(samples));
+            Value::Object(object)
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "resultType": "matrix",
+        "result": result,
+    })
+}
+
+fn sample_time(sample: &Value) -> f64 {
+    sample.get(0).and_then(Value::as_f64).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::{
+        config::QuerySplitConfig,
+        tools::split::{QuerySplit, merge_log_results, merge_metric_results},
+    };
+
+    fn split(interval: &str) -> QuerySplit {
+        QuerySplit::from_config(&QuerySplitConfig {
+            enabled: true,
+            interval: interval.to_string(),
+            max_concurrency: 2,
+        })
+        .expect("split config")
+    }
+
+    #[test]
+    fn splits_ranges_on_the_interval_and_step_grid() {
+        let start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let end = start + Duration::hours(60);
+
+        let ranges = split("24h").ranges(start, end, None);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0], (start, start + Duration::hours(24)));
+        assert_eq!(ranges[2], (start + Duration::hours(48), end));
+
+        // 24h is not a multiple of 7h, so sub-ranges shrink to 21h.
+        let ranges = split("24h").ranges(start, end, Some(Duration::hours(7)));
+        assert_eq!(ranges[0].1, start + Duration::hours(21));
+
+        assert_eq!(QuerySplit::disabled().ranges(start, end, None).len(), 1);
+        assert_eq!(
+            split("24h")
+                .ranges(start, start + Duration::hours(2), None)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn merges_log_results_by_direction_and_limit() {
+        let newer = json!({"resultType": "streams", "result": [
+            {"stream": {"app": "api"}, "values": [["300", "c"], ["200", "b"]]},
+        ]});
+        let older = json!({"resultType": "streams", "result": [
+            {"stream": {"app": "api"}, "values": [["200", "b"], ["100", "a"]]},
+            {"stream": {"app": "web"}, "values": [["150", "w"]]},
+        ]});
+
+        let merged = merge_log_results(vec![newer.clone(), older.clone()], Some(3), false);
+        assert_eq!(
+            merged["result"],
+            json!([
+                {"stream": {"app": "api"}, "values": [["300", "c"], ["200", "b"]]},
+                {"stream": {"app": "web"}, "values": [["150", "w"]]},
+            ])
+        );
+
+        let merged = merge_log_results(vec![older, newer], Some(2), true);
+        assert_eq!(
+            merged["result"],
+            json!([
+                {"stream": {"app": "api"}, "values": [["100", "a"]]},
+                {"stream": {"app": "web"}, "values": [["150", "w"]]},
+            ])
+        );
+    }
+
+    #[test]
+    fn drops_only_entries_repeated_across_adjacent_sub_results() {
+        let first = json!({"resultType": "streams", "result": [
+            {"stream": {"app": "api"}, "values": [["100", "a"], ["100", "a"], ["200", "b"]]},
+        ]});
+        let second = json!({"resultType": "streams", "result": [
+            {"stream": {"app": "api"}, "values": [["200", "b"], ["300", "c"], ["300", "c"]]},
+        ]});
+
+        let merged = merge_log_results(vec![first, second], None, true);
+        assert_eq!(
+            merged["result"],
+            json!([
+                {"stream": {"app": "api"}, "values": [
+                    ["100", "a"], ["100", "a"], ["200", "b"], ["300", "c"], ["300", "c"],
+                ]},
+            ])
+        );
+    }
+
+    #[test]
+    fn stitches_matrix_samples_across_sub_ranges() {
+        let first = json!({"resultType": "matrix", "result": [
+            {"metric": {"app": "api"}, "values": [[60, "1"], [120, "2"]]},
+        ]});
+        let second = json!({"resultType": "matrix", "result": [
+            {"metric": {"app": "api"}, "values": [[120, "2"], [180, "3"]]},
+            {"metric": {"app": "web"}, "values": [[180, "9"]]},
+        ]});
+
+        let merged = merge_metric_results(vec![first, second]);
+        assert_eq!(merged["resultType"], "matrix");
+        assert_eq!(
+            merged["result"],
+            json!([
+                {"metric": {"app": "api"}, "values": [[60, "1"], [120, "2"], [180, "3"]]},
+                {"metric": {"app": "web"}, "values": [[180, "9"]]},
+            ])
+        );
+    }
+}
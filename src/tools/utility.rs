@@ -1,9 +1,17 @@
 #![allow(dead_code)]
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use serde_json::{Value, json};
+use tokio::task::JoinSet;
 
-use crate::loki::client::LokiClient;
+use crate::{
+    logql::{
+        self, Expr, ParseError,
+        lint::{self, Finding, LabelSchema, Severity},
+    },
+    loki::client::LokiClient,
+    tools::Datasource,
+};
 
 pub async fn check_health(client: &LokiClient) -> Result<Value> {
     let health = client.check_health().await?;
@@ -16,24 +24,143 @@ pub async fn check_health(client: &LokiClient) -> Result<Value> {
     }))
 }
 
+pub(crate) async fn list_datasources(
+    datasources: &[Datasource],
+    default_datasource: &str,
+) -> Result<Value> {
+    let mut checks = JoinSet::new();
+    for (index, datasource) in datasources.iter().enumerate() {
+        let client = datasource.loki_client.clone();
+        checks.spawn(async move { (index, client.check_health().await) });
+    }
+
+    let mut health = (0..datasources.len()).map(|_| None).collect::<Vec<_>>();
+    while let Some(joined) = checks.join_next().await {
+        if let Ok((index, result)) = joined {
+            health[index] = Some(result);
+        }
+    }
+
+    let entries = datasources
+        .iter()
+        .zip(health)
+        .map(|(datasource, health)| {
+            let (healthy, message) = match health {
+                Some(Ok(health)) => (health.healthy, health.message),
+                Some(Err(error)) => (false, Some(error.to_string())),
+                None => (false, Some("health check did not complete".to_string())),
+            };
+
+            json!({
+                "name": datasource.name,
+                "description": datasource.description,
+                "url": datasource.url,
+                "tenant_id": datasource.tenant_id,
+                "default": datasource.name == default_datasource,
+                "healthy": healthy,
+                "message": message,
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    Ok(json!({
+        "default_datasource": default_datasource,
+        "datasources": entries,
+    }))
+}
+
 pub fn explain_query(query: &str) -> Result<Value> {
     let trimmed = query.trim();
     if trimmed.is_empty() {
         bail!("query must not be empty");
     }
 
-    let selector = extract_selector(trimmed);
-    let pipeline_stages = extract_pipeline_stages(trimmed);
-    let aggregation = extract_aggregation(trimmed);
+    let expr = logql::parse(trimmed)
+        .map_err(|error| anyhow!("invalid LogQL query: {error}\n{}", error.excerpt))?;
+    let log_exprs = expr.log_exprs();
+    let first_log = log_exprs.first();
+    let aggregation = match &expr {
+        Expr::RangeAggregation(aggregation) => Some(aggregation.op.name()),
+        Expr::VectorAggregation(aggregation) => Some(aggregation.op.name()),
+        _ => None,
+    };
+    let tree = logql::explain(&expr, trimmed);
 
     Ok(json!({
         "query": trimmed,
-        "selector": selector,
-        "pipeline_stages": pipeline_stages,
+        "kind": if expr.is_log() { "log" } else { "metric" },
+        "selector": first_log.map(|log| log.selector.span.text(trimmed)),
+        "pipeline_stages": first_log
+            .map(|log| log.pipeline.iter().map(|stage| stage.span.text(trimmed)).collect::<Vec<_>>())
+            .unwrap_or_default(),
         "aggregation": aggregation,
+        "description": tree["description"],
+        "ast": tree,
     }))
 }
 
+pub fn lint_query(query: &str, schema: LabelSchema<'_>) -> Result<Value> {
+    let trimmed = query.trim();
+    if trimmed.is_empty() {
+        bail!("query must not be empty");
+    }
+
+    let findings = match logql::parse(trimmed) {
+        Ok(expr) => lint_findings_json(&lint::lint(&expr, schema), trimmed),
+        Err(error) => json!([syntax_error_json(&error)]),
+    };
+    let count = |severity: &str| {
+        findings.as_array().map_or(0, |findings| {
+            findings
+                .iter()
+                .filter(|finding| finding["severity"] == severity)
+                .count()
+        })
+    };
+
+    Ok(json!({
+        "query": trimmed,
+        "valid": count("error") == 0,
+        "counts": {
+            "error": count("error"),
+            "warning": count("warning"),
+            "info": count("info"),
+        },
+        "findings": findings,
+    }))
+}
+
+/// Findings as returned by `loki_lint_query` and the lint pre-check.
+pub(crate) fn lint_findings_json(findings: &[Finding], query: &str) -> Value {
+    findings
+        .iter()
+        .map(|finding| {
+            let position = ParseError::new(query, "", finding.span);
+            json!({
+                "rule": finding.rule,
+                "severity": finding.severity,
+                "message": finding.message,
+                "suggestion": finding.suggestion,
+                "text": finding.span.text(query),
+                "line": position.line,
+                "column": position.column,
+            })
+        })
+        .collect()
+}
+
+fn syntax_error_json(error: &ParseError) -> Value {
+    json!({
+        "rule": "syntax-error",
+        "severity": Severity::Error,
+        "message": error.message,
+        "suggestion": null,
+        "text": error.excerpt,
+        "line": error.line,
+        "column": error.column,
+    })
+}
+
 pub fn suggest_metric_rule(
     query: &str,
     metric_name: &str,
@@ -75,43 +202,3 @@ pub fn suggest_metric_rule(
         "rule_yaml": yaml,
     }))
 }
-
-fn extract_selector(query: &str) -> Option<String> {
-    let start = query.find('{')?;
-    let end = query[start..].find('}')? + start;
-
-    Some(query[start..=end].to_string())
-}
-
-fn extract_pipeline_stages(query: &str) -> Vec<String> {
-    let mut stages = Vec::new();
-
-    for segment in query.split('|').skip(1) {
-        let trimmed = segment.trim();
-        if !trimmed.is_empty() {
-            stages.push(trimmed.to_string());
-        }
-    }
-
-    stages
-}
-
-fn extract_aggregation(query: &str) -> Option<String> {
-    let normalized = query.trim_start();
-    for name in [
-        "count_over_time",
-        "rate",
-        "bytes_over_time",
-        "bytes_rate",
-        "sum",
-        "avg",
-        "max",
-        "min",
-    ] {
-        if normalized.starts_with(name) {
-            return Some(name.to_string());
-        }
-    }
-
-    None
-}
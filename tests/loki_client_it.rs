@@ -67,6 +67,13 @@ async fn loki_client_endpoints_work_against_real_loki() -> Result<()> {
         .unwrap_or_default();
     assert!(!metric_result.is_empty());
 
+    let instant_query = format!("count_over_time({selector}[30m])");
+    let instant = client.query_instant(&instant_query, Some(end)).await?;
+    assert_eq!(
+        instant.get("resultType").and_then(Value::as_str),
+        Some("vector")
+    );
+
     let stats = client
         .query_stats(&selector, Some(start), Some(end))
         .await?;
@@ -139,6 +139,7 @@ fn router_config(base_url: &str, run_id: &str, disable_guardrails: bool) -> Conf
         description: "Logs seeded by integration test harness".to_string(),
         query: format!("{{test_run_id=\"{run_id}\"}}"),
         range: "30m".to_string(),
+        ..Default::default()
     }];
 
     if disable_guardrails {